@@ -0,0 +1,184 @@
+/* libdeflate.h - C API of the libdeflate crate, compatible with libdeflate 1.25. */
+
+#ifndef LIBDEFLATE_H
+#define LIBDEFLATE_H
+
+/* Generated by cbindgen from src/capi.rs; do not edit. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define LIBDEFLATE_VERSION_STRING "1.25"
+
+#define LIBDEFLATE_VERSION_MAJOR 1
+
+#define LIBDEFLATE_VERSION_MINOR 25
+
+/**
+ * Result of a decompression call. The values match [`DecompressResult`], apart from
+ * `ShortInput`, which libdeflate reports as `LIBDEFLATE_BAD_DATA`.
+ */
+enum libdeflate_result {
+  /**
+   * Decompression was successful.
+   */
+  LIBDEFLATE_SUCCESS = 0,
+  /**
+   * Decompression failed because the compressed data was invalid, corrupt, or
+   * otherwise unsupported.
+   */
+  LIBDEFLATE_BAD_DATA = 1,
+  /**
+   * A NULL `actual_out_nbytes_ret` was provided, but the data would have
+   * decompressed to fewer than `out_nbytes_avail` bytes.
+   */
+  LIBDEFLATE_SHORT_OUTPUT = 2,
+  /**
+   * The data would have decompressed to more than `out_nbytes_avail` bytes.
+   */
+  LIBDEFLATE_INSUFFICIENT_SPACE = 3,
+};
+
+struct libdeflate_compressor;
+
+struct libdeflate_decompressor;
+
+typedef void *(*libdeflate_malloc_func)(size_t);
+
+typedef void (*libdeflate_free_func)(void*);
+
+/**
+ * Advanced options for `libdeflate_alloc_compressor_ex` and
+ * `libdeflate_alloc_decompressor_ex`.
+ */
+struct libdeflate_options {
+  /**
+   * Must be set to `sizeof(struct libdeflate_options)`.
+   */
+  size_t sizeof_options;
+  /**
+   * Allocates the compressor or decompressor; overrides the global allocator.
+   */
+  libdeflate_malloc_func malloc_func;
+  /**
+   * Frees memory allocated by `malloc_func`.
+   */
+  libdeflate_free_func free_func;
+};
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+struct libdeflate_compressor *libdeflate_alloc_compressor(int compression_level);
+
+struct libdeflate_compressor *libdeflate_alloc_compressor_ex(int compression_level,
+                                                             const struct libdeflate_options *options);
+
+size_t libdeflate_deflate_compress(struct libdeflate_compressor *compressor,
+                                   const void *input,
+                                   size_t in_nbytes,
+                                   void *output,
+                                   size_t out_nbytes_avail);
+
+size_t libdeflate_deflate_compress_bound(struct libdeflate_compressor *compressor,
+                                         size_t in_nbytes);
+
+size_t libdeflate_zlib_compress(struct libdeflate_compressor *compressor,
+                                const void *input,
+                                size_t in_nbytes,
+                                void *output,
+                                size_t out_nbytes_avail);
+
+size_t libdeflate_zlib_compress_bound(struct libdeflate_compressor *compressor, size_t in_nbytes);
+
+size_t libdeflate_gzip_compress(struct libdeflate_compressor *compressor,
+                                const void *input,
+                                size_t in_nbytes,
+                                void *output,
+                                size_t out_nbytes_avail);
+
+size_t libdeflate_gzip_compress_bound(struct libdeflate_compressor *compressor, size_t in_nbytes);
+
+void libdeflate_free_compressor(struct libdeflate_compressor *compressor);
+
+struct libdeflate_decompressor *libdeflate_alloc_decompressor(void);
+
+struct libdeflate_decompressor *libdeflate_alloc_decompressor_ex(const struct libdeflate_options *options);
+
+enum libdeflate_result libdeflate_deflate_decompress(struct libdeflate_decompressor *decompressor,
+                                                     const void *input,
+                                                     size_t in_nbytes,
+                                                     void *output,
+                                                     size_t out_nbytes_avail,
+                                                     size_t *actual_out_nbytes_ret);
+
+enum libdeflate_result libdeflate_deflate_decompress_ex(struct libdeflate_decompressor *decompressor,
+                                                        const void *input,
+                                                        size_t in_nbytes,
+                                                        void *output,
+                                                        size_t out_nbytes_avail,
+                                                        size_t *actual_in_nbytes_ret,
+                                                        size_t *actual_out_nbytes_ret);
+
+enum libdeflate_result libdeflate_zlib_decompress(struct libdeflate_decompressor *decompressor,
+                                                  const void *input,
+                                                  size_t in_nbytes,
+                                                  void *output,
+                                                  size_t out_nbytes_avail,
+                                                  size_t *actual_out_nbytes_ret);
+
+enum libdeflate_result libdeflate_zlib_decompress_ex(struct libdeflate_decompressor *decompressor,
+                                                     const void *input,
+                                                     size_t in_nbytes,
+                                                     void *output,
+                                                     size_t out_nbytes_avail,
+                                                     size_t *actual_in_nbytes_ret,
+                                                     size_t *actual_out_nbytes_ret);
+
+/**
+ * Decompresses the first gzip member in `input`; like libdeflate, any data after it
+ * is left for the caller, whose offset `libdeflate_gzip_decompress_ex` reports.
+ */
+enum libdeflate_result libdeflate_gzip_decompress(struct libdeflate_decompressor *decompressor,
+                                                  const void *input,
+                                                  size_t in_nbytes,
+                                                  void *output,
+                                                  size_t out_nbytes_avail,
+                                                  size_t *actual_out_nbytes_ret);
+
+enum libdeflate_result libdeflate_gzip_decompress_ex(struct libdeflate_decompressor *decompressor,
+                                                     const void *input,
+                                                     size_t in_nbytes,
+                                                     void *output,
+                                                     size_t out_nbytes_avail,
+                                                     size_t *actual_in_nbytes_ret,
+                                                     size_t *actual_out_nbytes_ret);
+
+void libdeflate_free_decompressor(struct libdeflate_decompressor *decompressor);
+
+/**
+ * Updates a running Adler-32 checksum. Returns 1, the initial value, if `buffer` is
+ * NULL.
+ */
+uint32_t libdeflate_adler32(uint32_t adler, const void *buffer, size_t len);
+
+/**
+ * Updates a running CRC-32 checksum. Returns 0, the initial value, if `buffer` is
+ * NULL.
+ */
+uint32_t libdeflate_crc32(uint32_t crc, const void *buffer, size_t len);
+
+/**
+ * Sets the allocator used by compressors and decompressors allocated afterwards
+ * without their own in `libdeflate_options`. Passing NULL for both restores the
+ * default.
+ */
+void libdeflate_set_memory_allocator(libdeflate_malloc_func malloc_func,
+                                     libdeflate_free_func free_func);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* LIBDEFLATE_H */
@@ -1,5 +1,8 @@
-use std::cmp::min;
-use std::sync::OnceLock;
+#[cfg(target_arch = "aarch64")]
+use crate::common::aarch64_feature_detected;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crate::common::x86_feature_detected;
+use core::cmp::min;
 
 const DIVISOR: u32 = 65521;
 const MAX_CHUNK_LEN: usize = 4096;
@@ -111,42 +114,66 @@ mod arm;
 
 type Adler32Fn = unsafe fn(u32, &[u8]) -> u32;
 
-#[inline]
-pub fn adler32(adler: u32, slice: &[u8]) -> u32 {
-    static IMPL: OnceLock<Adler32Fn> = OnceLock::new();
-    let func = IMPL.get_or_init(|| {
-        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+/// Picks the fastest implementation the CPU supports.
+fn select_impl() -> Adler32Fn {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if x86_feature_detected!("avx512vl")
+            && x86_feature_detected!("avx512vnni")
+            && x86_feature_detected!("avx512bw")
+            && x86_feature_detected!("avx512f")
         {
-            if is_x86_feature_detected!("avx512vl")
-                && is_x86_feature_detected!("avx512vnni")
-                && is_x86_feature_detected!("avx512bw")
-                && is_x86_feature_detected!("avx512f")
-            {
-                return x86::adler32_x86_avx512_vnni;
-            }
-            if is_x86_feature_detected!("avxvnni") {
-                return x86::adler32_x86_avx2_vnni;
-            }
-            if is_x86_feature_detected!("avx2") {
-                return x86::adler32_x86_avx2;
-            }
-            if is_x86_feature_detected!("sse2") {
-                return x86::adler32_x86_sse2;
-            }
+            return x86::adler32_x86_avx512_vnni;
+        }
+        if x86_feature_detected!("avxvnni") {
+            return x86::adler32_x86_avx2_vnni;
         }
+        if x86_feature_detected!("avx2") {
+            return x86::adler32_x86_avx2;
+        }
+        if x86_feature_detected!("sse2") {
+            return x86::adler32_x86_sse2;
+        }
+    }
 
-        #[cfg(target_arch = "aarch64")]
-        {
-            if std::arch::is_aarch64_feature_detected!("dotprod") {
-                return arm::adler32_arm_neon_dotprod;
-            }
-            if std::arch::is_aarch64_feature_detected!("neon") {
-                return arm::adler32_arm_neon;
-            }
+    #[cfg(target_arch = "aarch64")]
+    {
+        if aarch64_feature_detected!("dotprod") {
+            return arm::adler32_arm_neon_dotprod;
         }
+        if aarch64_feature_detected!("neon") {
+            return arm::adler32_arm_neon;
+        }
+    }
+
+    adler32_generic
+}
 
-        adler32_generic
-    });
+#[inline]
+pub fn adler32(adler: u32, slice: &[u8]) -> u32 {
+    #[cfg(feature = "std")]
+    let func = {
+        static IMPL: std::sync::OnceLock<Adler32Fn> = std::sync::OnceLock::new();
+        *IMPL.get_or_init(select_impl)
+    };
+    #[cfg(not(feature = "std"))]
+    let func = select_impl();
 
     unsafe { func(adler, slice) }
 }
+
+/// Returns the Adler-32 of the concatenation `A || B`, given `adler1 = adler32(1, A)`,
+/// `adler2 = adler32(1, B)` and the length of `B`, without touching the data.
+pub fn adler32_combine(adler1: u32, adler2: u32, len2: u64) -> u32 {
+    let rem = (len2 % DIVISOR as u64) as u32;
+    let a1 = adler1 & 0xffff;
+    let b1 = adler1 >> 16;
+    let a2 = adler2 & 0xffff;
+    let b2 = adler2 >> 16;
+
+    // A = a1 + a2 - 1 and B = b1 + b2 + len2 * (a1 - 1), both mod 65521. Adding
+    // DIVISOR keeps the subtractions from underflowing.
+    let a = (a1 + a2 + DIVISOR - 1) % DIVISOR;
+    let b = (b1 + b2 + rem * a1 % DIVISOR + DIVISOR - rem) % DIVISOR;
+    (b << 16) | a
+}
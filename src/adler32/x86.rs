@@ -41,7 +41,7 @@ macro_rules! adler32_chunk8 {
 macro_rules! adler32_tail {
     ($s1:expr, $s2:expr, $ptr:expr, $len:expr) => {
         if $len > 0 {
-            if $len >= 8 {
+            while $len >= 8 {
                 adler32_chunk8!($s1, $s2, $ptr, $len);
             }
             if $len >= 4 {
@@ -99,7 +99,7 @@ pub unsafe fn adler32_x86_sse2(adler: u32, p: &[u8]) -> u32 {
     let v_zero = _mm_setzero_si128();
 
     while data.len() >= 32 {
-        let mut n = std::cmp::min(data.len(), BLOCK_SIZE);
+        let mut n = core::cmp::min(data.len(), BLOCK_SIZE);
         n &= !31;
 
         s2 = ((s2 as u64 + s1 as u64 * n as u64) % DIVISOR as u64) as u32;
@@ -301,7 +301,7 @@ pub unsafe fn adler32_x86_avx2(adler: u32, p: &[u8]) -> u32 {
     if len > 2048 {
         let align = (ptr as usize) & 31;
         if align != 0 {
-            let original_len_p = std::cmp::min(len, 32 - align);
+            let original_len_p = core::cmp::min(len, 32 - align);
             let mut len_p = original_len_p;
             while len_p >= 8 {
                 adler32_chunk8!(s1, s2, ptr, len_p);
@@ -321,7 +321,7 @@ pub unsafe fn adler32_x86_avx2(adler: u32, p: &[u8]) -> u32 {
     let v_zero = _mm256_setzero_si256();
 
     while len >= 32 {
-        let n = std::cmp::min(len, BLOCK_SIZE);
+        let n = core::cmp::min(len, BLOCK_SIZE);
         let n_rounded = n & !31;
 
         s2 = ((s2 as u64 + s1 as u64 * n_rounded as u64) % DIVISOR as u64) as u32;
@@ -588,7 +588,7 @@ pub unsafe fn adler32_x86_avx2_vnni(adler: u32, p: &[u8]) -> u32 {
     if data.len() > 2048 {
         let align = (data.as_ptr() as usize) & 31;
         if align != 0 {
-            let original_len_p = std::cmp::min(data.len(), 32 - align);
+            let original_len_p = core::cmp::min(data.len(), 32 - align);
             let mut len_p = original_len_p;
             let mut ptr = data.as_ptr();
             while len_p >= 8 {
@@ -608,7 +608,7 @@ pub unsafe fn adler32_x86_avx2_vnni(adler: u32, p: &[u8]) -> u32 {
     );
 
     while data.len() >= 32 {
-        let n = std::cmp::min(data.len(), BLOCK_SIZE) & !31;
+        let n = core::cmp::min(data.len(), BLOCK_SIZE) & !31;
         s2 = ((s2 as u64 + s1 as u64 * n as u64) % DIVISOR as u64) as u32;
 
         let mut v_s1 = _mm256_setzero_si256();
@@ -879,7 +879,7 @@ pub unsafe fn adler32_x86_avx512_vnni(adler: u32, p: &[u8]) -> u32 {
     if data.len() > 2048 {
         let align = (data.as_ptr() as usize) & 63;
         if align != 0 {
-            let original_len_p = std::cmp::min(data.len(), 64 - align);
+            let original_len_p = core::cmp::min(data.len(), 64 - align);
             let mut len_p = original_len_p;
             let mut ptr = data.as_ptr();
             while len_p >= 8 {
@@ -900,7 +900,7 @@ pub unsafe fn adler32_x86_avx512_vnni(adler: u32, p: &[u8]) -> u32 {
     );
 
     while data.len() >= 64 {
-        let n = std::cmp::min(data.len(), BLOCK_SIZE) & !63;
+        let n = core::cmp::min(data.len(), BLOCK_SIZE) & !63;
         s2 = ((s2 as u64 + s1 as u64 * n as u64) % DIVISOR as u64) as u32;
 
         let mut v_s1 = _mm512_setzero_si512();
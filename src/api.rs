@@ -1,25 +1,40 @@
-use crate::compress::{CompressResult, Compressor as InternalCompressor, FlushMode};
-use crate::decompress::Decompressor as InternalDecompressor;
-use std::io::{self};
+use crate::compress::{
+    CompressResult, CompressionOptions, Compressor as InternalCompressor, FlushMode, Strategy,
+};
+use crate::common::{GZIP_MIN_HEADER_SIZE, GZIP_MIN_OVERHEAD};
+use crate::decompress::{DecompressResult, Decompressor as InternalDecompressor};
+use crate::gzip::GzipHeader;
+use crate::error::{Error, Position, Result};
+use alloc::vec::Vec;
 
 pub struct Compressor {
     inner: InternalCompressor,
 }
 
 impl Compressor {
-    pub fn new(level: i32) -> io::Result<Self> {
+    pub fn new(level: i32) -> Result<Self> {
         if !(0..=12).contains(&level) {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Compression level must be between 0 and 12",
-            ));
+            return Err(Error::InvalidLevel(level));
         }
         Ok(Self {
             inner: InternalCompressor::new(level as usize),
         })
     }
 
-    pub fn compress_deflate(&mut self, data: &[u8]) -> io::Result<Vec<u8>> {
+    /// Creates a compressor with parameters beyond those of a level, e.g. to tune for a
+    /// corpus or to keep the output stable across releases.
+    pub fn with_options(options: CompressionOptions) -> Result<Self> {
+        options.validate()?;
+        Ok(Self {
+            inner: InternalCompressor::with_options(options),
+        })
+    }
+
+    pub fn options(&self) -> &CompressionOptions {
+        self.inner.options()
+    }
+
+    pub fn compress_deflate(&mut self, data: &[u8]) -> Result<Vec<u8>> {
         let bound = self.deflate_compress_bound(data.len());
         self.compress_helper(data, bound, |c, data, out| {
             let (res, size, _) = c.compress(data, out, FlushMode::Finish);
@@ -27,57 +42,113 @@ impl Compressor {
         })
     }
 
-    pub fn compress_deflate_into(&mut self, data: &[u8], output: &mut [u8]) -> io::Result<usize> {
-        self.compress_into_helper(data, output, "Insufficient space", |c, data, out| {
+    pub fn compress_deflate_into(&mut self, data: &[u8], output: &mut [u8]) -> Result<usize> {
+        self.compress_into_helper(data, output, |c, data, out| {
             let (res, size, _) = c.compress(data, out, FlushMode::Finish);
             (res, size)
         })
     }
 
-    pub fn compress_zlib(&mut self, data: &[u8]) -> io::Result<Vec<u8>> {
+    pub fn compress_zlib(&mut self, data: &[u8]) -> Result<Vec<u8>> {
         let bound = self.zlib_compress_bound(data.len());
         self.compress_helper(data, bound, |c, data, out| c.compress_zlib(data, out))
     }
 
-    pub fn compress_zlib_into(&mut self, data: &[u8], output: &mut [u8]) -> io::Result<usize> {
-        self.compress_into_helper(data, output, "Compression failed", |c, data, out| {
+    pub fn compress_zlib_into(&mut self, data: &[u8], output: &mut [u8]) -> Result<usize> {
+        self.compress_into_helper(data, output, |c, data, out| {
             c.compress_zlib(data, out)
         })
     }
 
-    pub fn compress_gzip(&mut self, data: &[u8]) -> io::Result<Vec<u8>> {
+    pub fn compress_gzip(&mut self, data: &[u8]) -> Result<Vec<u8>> {
         let bound = self.gzip_compress_bound(data.len());
         self.compress_helper(data, bound, |c, data, out| c.compress_gzip(data, out))
     }
 
-    pub fn compress_gzip_into(&mut self, data: &[u8], output: &mut [u8]) -> io::Result<usize> {
-        self.compress_into_helper(data, output, "Compression failed", |c, data, out| {
+    pub fn compress_gzip_into(&mut self, data: &[u8], output: &mut [u8]) -> Result<usize> {
+        self.compress_into_helper(data, output, |c, data, out| {
             c.compress_gzip(data, out)
         })
     }
 
+    /// Sets a preset dictionary used by subsequent raw DEFLATE and zlib compression.
+    ///
+    /// zlib output carries the FDICT flag and the dictionary's Adler-32 (DICTID), so it
+    /// can be read by zlib's `inflateSetDictionary`. gzip output is not affected.
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) {
+        self.inner.set_dictionary(dictionary);
+    }
+
+    pub fn clear_dictionary(&mut self) {
+        self.inner.set_dictionary(&[]);
+    }
+
+    /// Controls whether each parallel chunk of a large input is primed with the
+    /// preceding 32 KiB (the default), or compressed with an empty window.
+    pub fn set_chunk_history(&mut self, enabled: bool) {
+        self.inner.set_chunk_history(enabled);
+    }
+
+    /// Controls whether large inputs are split across the rayon thread pool (the
+    /// default) or compressed on the calling thread, e.g. when the caller already runs
+    /// one compression per core. Has no effect without the `parallel` feature.
+    pub fn set_parallel(&mut self, enabled: bool) {
+        self.inner.set_parallel(enabled);
+    }
+
+    /// Sets the [`Strategy`] for subsequent compression, e.g. [`Strategy::Rle`] for
+    /// image data. Level 0 ignores it.
+    pub fn set_strategy(&mut self, strategy: Strategy) {
+        self.inner.set_strategy(strategy);
+    }
+
+    /// Sets the header written by subsequent gzip compression, e.g. to record the
+    /// original file name and modification time.
+    pub fn set_gzip_header(&mut self, header: GzipHeader) -> Result<()> {
+        header.validate()?;
+        self.inner.set_gzip_header(Some(header));
+        Ok(())
+    }
+
+    pub fn clear_gzip_header(&mut self) {
+        self.inner.set_gzip_header(None);
+    }
+
     pub fn deflate_compress_bound(&mut self, size: usize) -> usize {
         InternalCompressor::deflate_compress_bound(size)
     }
 
     pub fn zlib_compress_bound(&mut self, size: usize) -> usize {
-        InternalCompressor::zlib_compress_bound(size)
+        let bound = InternalCompressor::zlib_compress_bound(size);
+        if self.inner.dictionary_id().is_some() {
+            bound.saturating_add(crate::common::ZLIB_DICTID_SIZE)
+        } else {
+            bound
+        }
     }
 
+    /// Accounts for the optional fields of the header set with
+    /// [`set_gzip_header`](Self::set_gzip_header).
     pub fn gzip_compress_bound(&mut self, size: usize) -> usize {
-        InternalCompressor::gzip_compress_bound(size)
+        let bound = InternalCompressor::gzip_compress_bound(size);
+        match self.inner.gzip_header() {
+            Some(header) => bound.saturating_add(header.encoded_len() - GZIP_MIN_HEADER_SIZE),
+            None => bound,
+        }
     }
 
-    fn compress_helper<F>(&mut self, data: &[u8], bound: usize, f: F) -> io::Result<Vec<u8>>
+    fn compress_helper<F>(&mut self, data: &[u8], bound: usize, f: F) -> Result<Vec<u8>>
     where
         F: FnOnce(
             &mut InternalCompressor,
             &[u8],
-            &mut [std::mem::MaybeUninit<u8>],
+            &mut [core::mem::MaybeUninit<u8>],
         ) -> (CompressResult, usize),
     {
         let mut output = Vec::new();
-        output.try_reserve_exact(bound).map_err(io::Error::other)?;
+        output
+            .try_reserve_exact(bound)
+            .map_err(|_| Error::OutOfMemory)?;
 
         let out_uninit = &mut output.spare_capacity_mut()[..bound];
 
@@ -90,8 +161,8 @@ impl Compressor {
                 }
                 Ok(output)
             }
-            CompressResult::InsufficientSpace => Err(io::Error::other("Insufficient space")),
-            CompressResult::InternalError => Err(io::Error::other("Compression failed")),
+            CompressResult::InsufficientSpace => Err(Error::InsufficientSpace),
+            CompressResult::InternalError => Err(Error::CompressionFailed),
         }
     }
 
@@ -99,21 +170,17 @@ impl Compressor {
         &mut self,
         data: &[u8],
         output: &mut [u8],
-        error_msg: &str,
         f: F,
-    ) -> io::Result<usize>
+    ) -> Result<usize>
     where
         F: FnOnce(
             &mut InternalCompressor,
             &[u8],
-            &mut [std::mem::MaybeUninit<u8>],
+            &mut [core::mem::MaybeUninit<u8>],
         ) -> (CompressResult, usize),
     {
         if is_overlapping(data, output) {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Input and output buffers overlap",
-            ));
+            return Err(Error::OverlappingBuffers);
         }
         let out_uninit = crate::common::slice_as_uninit_mut(output);
         let (res, size) = f(&mut self.inner, data, out_uninit);
@@ -122,7 +189,8 @@ impl Compressor {
                 assert!(size <= output.len());
                 Ok(size)
             }
-            _ => Err(io::Error::other(error_msg)),
+            CompressResult::InsufficientSpace => Err(Error::InsufficientSpace),
+            CompressResult::InternalError => Err(Error::CompressionFailed),
         }
     }
 }
@@ -131,6 +199,8 @@ pub struct Decompressor {
     inner: InternalDecompressor,
     max_memory_limit: usize,
     limit_ratio: usize,
+    #[cfg(feature = "parallel")]
+    parallel_chunk_size: usize,
 }
 
 crate::impl_default_new!(Decompressor);
@@ -141,6 +211,8 @@ impl Decompressor {
             inner: InternalDecompressor::new(),
             max_memory_limit: usize::MAX,
             limit_ratio: 2000,
+            #[cfg(feature = "parallel")]
+            parallel_chunk_size: crate::decompress::PARALLEL_DECOMPRESS_CHUNK_SIZE,
         }
     }
 
@@ -152,40 +224,224 @@ impl Decompressor {
         self.limit_ratio = ratio;
     }
 
-    pub fn decompress_deflate(&mut self, data: &[u8], expected_size: usize) -> io::Result<Vec<u8>> {
+    /// Sets how many bytes of compressed input each thread of
+    /// [`decompress_gzip_parallel`](Self::decompress_gzip_parallel) and
+    /// [`decompress_deflate_parallel`](Self::decompress_deflate_parallel) decodes, 4 MiB
+    /// by default. Smaller chunks spread small inputs over more threads but spend a
+    /// larger share of the time searching for the block each chunk starts with.
+    #[cfg(feature = "parallel")]
+    pub fn set_parallel_chunk_size(&mut self, size: usize) {
+        self.parallel_chunk_size = size.max(1);
+    }
+
+    /// Sets a preset dictionary used by subsequent raw DEFLATE and zlib decompression.
+    ///
+    /// zlib streams with the FDICT flag are rejected unless their DICTID matches the
+    /// Adler-32 of `dictionary`, mirroring zlib's `inflateSetDictionary`.
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) {
+        self.inner.set_dictionary(dictionary);
+    }
+
+    pub fn clear_dictionary(&mut self) {
+        self.inner.set_dictionary(&[]);
+    }
+
+    /// Controls whether the optional gzip header CRC (FHCRC) is verified. When it is
+    /// off (the default) the CRC is skipped, as zlib does.
+    pub fn set_verify_gzip_header_crc(&mut self, verify: bool) {
+        self.inner.set_verify_gzip_header_crc(verify);
+    }
+
+    /// Controls whether the Adler-32 or CRC-32 of large zlib and gzip output is
+    /// verified on the rayon thread pool. Off by default, so one-shot decompression
+    /// stays on the calling thread; the `_parallel` methods always use the pool. Has
+    /// no effect without the `parallel` feature.
+    pub fn set_parallel(&mut self, enabled: bool) {
+        self.inner.set_parallel(enabled);
+    }
+
+    pub fn decompress_deflate(&mut self, data: &[u8], expected_size: usize) -> Result<Vec<u8>> {
         self.decompress_helper(data, expected_size, |d, data, out| unsafe {
             d.decompress_uninit(data, out)
         })
+        .map(|(output, _)| output)
     }
 
-    pub fn decompress_deflate_into(&mut self, data: &[u8], output: &mut [u8]) -> io::Result<usize> {
+    pub fn decompress_deflate_into(&mut self, data: &[u8], output: &mut [u8]) -> Result<usize> {
         self.decompress_into_helper(data, output, |d, data, out| unsafe {
             d.decompress_uninit(data, out)
         })
+        .map(|(size, _)| size)
     }
 
-    pub fn decompress_zlib(&mut self, data: &[u8], expected_size: usize) -> io::Result<Vec<u8>> {
+    pub fn decompress_zlib(&mut self, data: &[u8], expected_size: usize) -> Result<Vec<u8>> {
         self.decompress_helper(data, expected_size, |d, data, out| unsafe {
             d.decompress_zlib_uninit(data, out)
         })
+        .map(|(output, _)| output)
     }
 
-    pub fn decompress_zlib_into(&mut self, data: &[u8], output: &mut [u8]) -> io::Result<usize> {
+    pub fn decompress_zlib_into(&mut self, data: &[u8], output: &mut [u8]) -> Result<usize> {
         self.decompress_into_helper(data, output, |d, data, out| unsafe {
             d.decompress_zlib_uninit(data, out)
         })
+        .map(|(size, _)| size)
     }
 
-    pub fn decompress_gzip(&mut self, data: &[u8], expected_size: usize) -> io::Result<Vec<u8>> {
+    /// Decompresses a gzip stream. Concatenated members (as produced by `cat a.gz b.gz`,
+    /// pigz or bgzip) are decoded one after another into a single output. Zero bytes
+    /// after the last member, such as tape padding, are skipped as gzip(1) does; other
+    /// trailing data is an error.
+    pub fn decompress_gzip(&mut self, data: &[u8], expected_size: usize) -> Result<Vec<u8>> {
         self.decompress_helper(data, expected_size, |d, data, out| unsafe {
             d.decompress_gzip_uninit(data, out)
         })
+        .map(|(output, _)| output)
     }
 
-    pub fn decompress_gzip_into(&mut self, data: &[u8], output: &mut [u8]) -> io::Result<usize> {
+    pub fn decompress_gzip_into(&mut self, data: &[u8], output: &mut [u8]) -> Result<usize> {
         self.decompress_into_helper(data, output, |d, data, out| unsafe {
             d.decompress_gzip_uninit(data, out)
         })
+        .map(|(size, _)| size)
+    }
+
+    /// Like [`decompress_gzip`](Self::decompress_gzip), but also returns the header of
+    /// the first member: file name, comment, modification time and so on.
+    pub fn decompress_gzip_with_header(
+        &mut self,
+        data: &[u8],
+        expected_size: usize,
+    ) -> Result<(Vec<u8>, GzipHeader)> {
+        let mut header = GzipHeader::new();
+        let (output, _) = self.decompress_helper(data, expected_size, |d, data, out| unsafe {
+            d.decompress_gzip_with_header_uninit(data, out, Some(&mut header))
+        })?;
+        Ok((output, header))
+    }
+
+    pub fn decompress_gzip_into_with_header(
+        &mut self,
+        data: &[u8],
+        output: &mut [u8],
+    ) -> Result<(usize, GzipHeader)> {
+        let mut header = GzipHeader::new();
+        let (size, _) = self.decompress_into_helper(data, output, |d, data, out| unsafe {
+            d.decompress_gzip_with_header_uninit(data, out, Some(&mut header))
+        })?;
+        Ok((size, header))
+    }
+
+    /// Decompresses only the first member of a gzip stream, returning its data and
+    /// the offset in `data` at which the remaining bytes (if any) start.
+    pub fn decompress_gzip_member(
+        &mut self,
+        data: &[u8],
+        expected_size: usize,
+    ) -> Result<(Vec<u8>, usize)> {
+        self.decompress_helper(data, expected_size, |d, data, out| unsafe {
+            d.decompress_gzip_member_uninit(data, out, None)
+        })
+    }
+
+    /// Like [`decompress_gzip_member`](Self::decompress_gzip_member), but writes into
+    /// `output` and returns the decompressed size along with the offset.
+    pub fn decompress_gzip_member_into(
+        &mut self,
+        data: &[u8],
+        output: &mut [u8],
+    ) -> Result<(usize, usize)> {
+        self.decompress_into_helper(data, output, |d, data, out| unsafe {
+            d.decompress_gzip_member_uninit(data, out, None)
+        })
+    }
+
+    /// Decompresses a raw DEFLATE stream of unknown size, growing the output as needed.
+    ///
+    /// The output may grow up to the smaller of
+    /// [`set_max_memory_limit`](Self::set_max_memory_limit) and the input length times
+    /// [`set_limit_ratio`](Self::set_limit_ratio); data that decompresses to more fails
+    /// with [`Error::MemoryLimitExceeded`] or [`Error::ExpectedSizeTooLarge`]
+    /// respectively.
+    pub fn decompress_deflate_to_vec(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        self.decompress_to_vec_helper(data, data.len().saturating_mul(4), |d, data, out| unsafe {
+            d.decompress_uninit(data, out)
+        })
+    }
+
+    /// The zlib counterpart of [`decompress_deflate_to_vec`](Self::decompress_deflate_to_vec).
+    pub fn decompress_zlib_to_vec(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        self.decompress_to_vec_helper(data, data.len().saturating_mul(4), |d, data, out| unsafe {
+            d.decompress_zlib_uninit(data, out)
+        })
+    }
+
+    /// The gzip counterpart of [`decompress_deflate_to_vec`](Self::decompress_deflate_to_vec).
+    /// All members are decoded, and the ISIZE trailer of the last one is used as the
+    /// initial output size.
+    pub fn decompress_gzip_to_vec(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let size_hint = if data.len() >= GZIP_MIN_OVERHEAD {
+            let isize = &data[data.len() - 4..];
+            u32::from_le_bytes(isize.try_into().unwrap()) as usize
+        } else {
+            0
+        };
+        self.decompress_to_vec_helper(data, size_hint, |d, data, out| unsafe {
+            d.decompress_gzip_uninit(data, out)
+        })
+    }
+
+    /// Decompresses a gzip stream of unknown size like
+    /// [`decompress_gzip_to_vec`](Self::decompress_gzip_to_vec), on the rayon thread pool.
+    ///
+    /// The input is split into chunks of
+    /// [`set_parallel_chunk_size`](Self::set_parallel_chunk_size) bytes. Each thread
+    /// finds the first DEFLATE block starting in its chunk by trying every bit offset,
+    /// then decodes without the preceding 32 KiB, recording which bytes it could not
+    /// resolve. Those are filled in once the chunks before are done. This works for any
+    /// gzip file, including ones with a single member, but chunks whose blocks all use
+    /// static codes cannot be found and are decoded sequentially. Decoding needs about
+    /// three times the size of the output in memory, and inputs of at most one chunk are
+    /// decompressed on the calling thread.
+    #[cfg(feature = "parallel")]
+    pub fn decompress_gzip_parallel(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        if data.len() <= self.parallel_chunk_size || rayon::current_num_threads() == 1 {
+            return self.decompress_gzip_to_vec(data);
+        }
+        self.decompress_parallel_helper(data, true)
+    }
+
+    /// The raw DEFLATE counterpart of
+    /// [`decompress_gzip_parallel`](Self::decompress_gzip_parallel). Data after the
+    /// final block is ignored.
+    #[cfg(feature = "parallel")]
+    pub fn decompress_deflate_parallel(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        if data.len() <= self.parallel_chunk_size || rayon::current_num_threads() == 1 {
+            return self.decompress_deflate_to_vec(data);
+        }
+        self.decompress_parallel_helper(data, false)
+    }
+
+    #[cfg(feature = "parallel")]
+    fn decompress_parallel_helper(&mut self, data: &[u8], gzip: bool) -> Result<Vec<u8>> {
+        let ratio_limit = data
+            .len()
+            .saturating_mul(self.limit_ratio)
+            .saturating_add(4096);
+        let limit = ratio_limit.min(self.max_memory_limit);
+        let chunk_size = self.parallel_chunk_size;
+        match self
+            .inner
+            .decompress_parallel(data, gzip, chunk_size, limit)
+        {
+            Err(Error::MemoryLimitExceeded { .. }) if limit == ratio_limit => {
+                Err(Error::ExpectedSizeTooLarge {
+                    expected: limit.saturating_add(1),
+                    input_len: data.len(),
+                })
+            }
+            result => result,
+        }
     }
 
     fn decompress_helper<F>(
@@ -193,58 +449,109 @@ impl Decompressor {
         data: &[u8],
         expected_size: usize,
         f: F,
-    ) -> io::Result<Vec<u8>>
+    ) -> Result<(Vec<u8>, usize)>
     where
         F: FnOnce(
             &mut InternalDecompressor,
             &[u8],
-            &mut [std::mem::MaybeUninit<u8>],
-        ) -> (crate::decompress::DecompressResult, usize, usize),
+            &mut [core::mem::MaybeUninit<u8>],
+        ) -> (DecompressResult, usize, usize),
     {
         let limit = data
             .len()
             .saturating_mul(self.limit_ratio)
             .saturating_add(4096);
         if expected_size > limit {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                format!(
-                    "Expected size {} exceeds safety limit for input size {}",
-                    expected_size,
-                    data.len()
-                ),
-            ));
+            return Err(Error::ExpectedSizeTooLarge {
+                expected: expected_size,
+                input_len: data.len(),
+            });
         }
 
         if expected_size > self.max_memory_limit {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                format!(
-                    "Expected size {} exceeds maximum memory limit {}",
-                    expected_size, self.max_memory_limit
-                ),
-            ));
+            return Err(Error::MemoryLimitExceeded {
+                expected: expected_size,
+                limit: self.max_memory_limit,
+            });
         }
 
         let mut output = Vec::new();
         output
             .try_reserve_exact(expected_size)
-            .map_err(io::Error::other)?;
+            .map_err(|_| Error::OutOfMemory)?;
 
         let out_uninit = &mut output.spare_capacity_mut()[..expected_size];
 
-        let (res, _, size) = f(&mut self.inner, data, out_uninit);
-        if res == crate::decompress::DecompressResult::Success {
+        self.inner.failure = None;
+        self.inner.block_index = 0;
+        let (res, in_consumed, size) = f(&mut self.inner, data, out_uninit);
+        if res == DecompressResult::Success {
             assert!(size <= expected_size);
             unsafe {
                 output.set_len(size);
             }
-            Ok(output)
+            Ok((output, in_consumed))
         } else {
-            Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Decompression failed",
-            ))
+            Err(self.decompress_error(res, data, in_consumed))
+        }
+    }
+
+    /// Runs `f` with an output buffer of `size_hint` bytes, doubling it and starting
+    /// over each time the output does not fit, until the limits are reached.
+    fn decompress_to_vec_helper<F>(
+        &mut self,
+        data: &[u8],
+        size_hint: usize,
+        mut f: F,
+    ) -> Result<Vec<u8>>
+    where
+        F: FnMut(
+            &mut InternalDecompressor,
+            &[u8],
+            &mut [core::mem::MaybeUninit<u8>],
+        ) -> (DecompressResult, usize, usize),
+    {
+        let ratio_limit = data
+            .len()
+            .saturating_mul(self.limit_ratio)
+            .saturating_add(4096);
+        let limit = ratio_limit.min(self.max_memory_limit);
+        let mut capacity = size_hint.min(limit);
+        let mut output = Vec::new();
+        loop {
+            output
+                .try_reserve_exact(capacity)
+                .map_err(|_| Error::OutOfMemory)?;
+            let out_uninit = &mut output.spare_capacity_mut()[..capacity];
+
+            self.inner.failure = None;
+            self.inner.block_index = 0;
+            let (res, in_consumed, size) = f(&mut self.inner, data, out_uninit);
+            match res {
+                DecompressResult::Success => {
+                    assert!(size <= capacity);
+                    unsafe {
+                        output.set_len(size);
+                    }
+                    return Ok(output);
+                }
+                DecompressResult::InsufficientSpace if capacity < limit => {
+                    capacity = capacity.saturating_mul(2).max(4096).min(limit);
+                }
+                DecompressResult::InsufficientSpace if limit < ratio_limit => {
+                    return Err(Error::MemoryLimitExceeded {
+                        expected: limit.saturating_add(1),
+                        limit,
+                    });
+                }
+                DecompressResult::InsufficientSpace => {
+                    return Err(Error::ExpectedSizeTooLarge {
+                        expected: limit.saturating_add(1),
+                        input_len: data.len(),
+                    });
+                }
+                _ => return Err(self.decompress_error(res, data, in_consumed)),
+            }
         }
     }
 
@@ -253,31 +560,51 @@ impl Decompressor {
         data: &[u8],
         output: &mut [u8],
         f: F,
-    ) -> io::Result<usize>
+    ) -> Result<(usize, usize)>
     where
         F: FnOnce(
             &mut InternalDecompressor,
             &[u8],
-            &mut [std::mem::MaybeUninit<u8>],
-        ) -> (crate::decompress::DecompressResult, usize, usize),
+            &mut [core::mem::MaybeUninit<u8>],
+        ) -> (DecompressResult, usize, usize),
     {
         if is_overlapping(data, output) {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Input and output buffers overlap",
-            ));
+            return Err(Error::OverlappingBuffers);
         }
 
         let out_uninit = crate::common::slice_as_uninit_mut(output);
-        let (res, _, size) = f(&mut self.inner, data, out_uninit);
-        if res == crate::decompress::DecompressResult::Success {
+        self.inner.failure = None;
+        self.inner.block_index = 0;
+        let (res, in_consumed, size) = f(&mut self.inner, data, out_uninit);
+        if res == DecompressResult::Success {
             assert!(size <= output.len());
-            Ok(size)
+            Ok((size, in_consumed))
         } else {
-            Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Decompression failed",
-            ))
+            Err(self.decompress_error(res, data, in_consumed))
+        }
+    }
+
+    /// Builds the error for a failed call that consumed `in_consumed` bytes of `data`.
+    fn decompress_error(&mut self, res: DecompressResult, data: &[u8], in_consumed: usize) -> Error {
+        if let Some(failure) = self.inner.failure.take() {
+            return failure.into_error(0);
+        }
+        let block_index = self.inner.block_index;
+        match res {
+            DecompressResult::InsufficientSpace | DecompressResult::ShortOutput => {
+                Error::OutputTooSmall {
+                    at: Position {
+                        bit_offset: in_consumed as u64 * 8,
+                        block_index,
+                    },
+                }
+            }
+            _ => Error::TruncatedInput {
+                at: Position {
+                    bit_offset: data.len() as u64 * 8,
+                    block_index,
+                },
+            },
         }
     }
 }
@@ -291,6 +618,6 @@ fn is_overlapping(s1: &[u8], s2: &[u8]) -> bool {
     let end1 = p1.saturating_add(len1);
     let end2 = p2.saturating_add(len2);
 
-    use std::cmp::{max, min};
+    use core::cmp::{max, min};
     max(p1, p2) < min(end1, end2)
 }
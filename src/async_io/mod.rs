@@ -0,0 +1,230 @@
+//! Asynchronous counterparts of the [`stream`](crate::stream) encoders and decoders,
+//! for [`tokio`](self::tokio) (feature `tokio`) and [`futures-io`](self::futures)
+//! (feature `futures-io`).
+//!
+//! The adapters run the same code as the blocking types. Decoders feed them input as
+//! it arrives and resume the streaming decompressor where it stopped; encoders collect
+//! compressed output in memory and write it out as the underlying writer accepts it, so
+//! at most one buffer of output is held back. No call blocks the executor on I/O.
+
+#[cfg(feature = "futures-io")]
+pub mod futures;
+#[cfg(feature = "tokio")]
+pub mod tokio;
+
+use crate::stream;
+use std::io::{self, Read, Write};
+use std::task::{Context, Poll, ready};
+
+/// Size of the buffer filled from the asynchronous reader.
+const INPUT_BUFFER_SIZE: usize = 32 * 1024;
+
+/// The `Read` a blocking decoder is built on. It serves whatever the asynchronous
+/// reader last delivered and reports `WouldBlock` once that has been used up.
+pub(crate) struct Source {
+    buffer: Vec<u8>,
+    pos: usize,
+    cap: usize,
+    eof: bool,
+}
+
+impl Source {
+    pub(crate) fn new() -> Self {
+        Self {
+            buffer: vec![0; INPUT_BUFFER_SIZE],
+            pos: 0,
+            cap: 0,
+            eof: false,
+        }
+    }
+}
+
+impl Read for Source {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.cap {
+            if self.eof {
+                return Ok(0);
+            }
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let count = (self.cap - self.pos).min(buf.len());
+        buf[..count].copy_from_slice(&self.buffer[self.pos..self.pos + count]);
+        self.pos += count;
+        Ok(count)
+    }
+}
+
+/// A blocking decoder reading from a [`Source`].
+pub(crate) trait SourceDecoder: Read {
+    fn source(&mut self) -> &mut Source;
+}
+
+impl SourceDecoder for stream::DeflateDecoder<Source> {
+    fn source(&mut self) -> &mut Source {
+        self.get_mut()
+    }
+}
+
+impl SourceDecoder for stream::ZlibDecoder<Source> {
+    fn source(&mut self) -> &mut Source {
+        self.get_mut()
+    }
+}
+
+impl SourceDecoder for stream::GzipDecoder<Source> {
+    fn source(&mut self) -> &mut Source {
+        self.get_mut()
+    }
+}
+
+/// Decodes into `buf`, refilling the decoder's source with `fill` whenever it runs
+/// dry. `fill` reads from the asynchronous reader and returns 0 at EOF.
+pub(crate) fn poll_decode<D, F>(
+    decoder: &mut D,
+    cx: &mut Context<'_>,
+    buf: &mut [u8],
+    mut fill: F,
+) -> Poll<io::Result<usize>>
+where
+    D: SourceDecoder,
+    F: FnMut(&mut Context<'_>, &mut [u8]) -> Poll<io::Result<usize>>,
+{
+    loop {
+        match decoder.read(buf) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                let source = decoder.source();
+                let n = ready!(fill(cx, &mut source.buffer))?;
+                source.pos = 0;
+                source.cap = n;
+                source.eof = n == 0;
+            }
+            res => return Poll::Ready(res),
+        }
+    }
+}
+
+/// A blocking encoder writing to a `Vec<u8>`.
+pub(crate) trait VecEncoder: Write {
+    fn output(&mut self) -> &mut Vec<u8>;
+    fn finish(self) -> io::Result<Vec<u8>>;
+}
+
+impl VecEncoder for stream::DeflateEncoder<Vec<u8>> {
+    fn output(&mut self) -> &mut Vec<u8> {
+        self.get_mut()
+    }
+
+    fn finish(self) -> io::Result<Vec<u8>> {
+        stream::DeflateEncoder::finish(self)
+    }
+}
+
+impl VecEncoder for stream::ZlibEncoder<Vec<u8>> {
+    fn output(&mut self) -> &mut Vec<u8> {
+        self.get_mut()
+    }
+
+    fn finish(self) -> io::Result<Vec<u8>> {
+        stream::ZlibEncoder::finish(self)
+    }
+}
+
+impl VecEncoder for stream::GzipEncoder<Vec<u8>> {
+    fn output(&mut self) -> &mut Vec<u8> {
+        self.get_mut()
+    }
+
+    fn finish(self) -> io::Result<Vec<u8>> {
+        stream::GzipEncoder::finish(self)
+    }
+}
+
+/// The state shared by the asynchronous encoders: the blocking encoder until the
+/// stream is finished, then the rest of its output.
+pub(crate) struct EncoderState<E> {
+    encoder: Option<E>,
+    finished: Vec<u8>,
+    /// How much of the pending output has been written.
+    written: usize,
+}
+
+impl<E: VecEncoder> EncoderState<E> {
+    pub(crate) fn new(encoder: E) -> Self {
+        Self {
+            encoder: Some(encoder),
+            finished: Vec::new(),
+            written: 0,
+        }
+    }
+
+    /// Applies a builder method to the blocking encoder.
+    pub(crate) fn map(mut self, f: impl FnOnce(E) -> E) -> Self {
+        self.encoder = self.encoder.map(f);
+        self
+    }
+
+    /// Writes out all pending output with `write`.
+    fn poll_drain<F>(&mut self, cx: &mut Context<'_>, mut write: F) -> Poll<io::Result<()>>
+    where
+        F: FnMut(&mut Context<'_>, &[u8]) -> Poll<io::Result<usize>>,
+    {
+        let pending = match &mut self.encoder {
+            Some(encoder) => encoder.output(),
+            None => &mut self.finished,
+        };
+        while self.written < pending.len() {
+            match ready!(write(cx, &pending[self.written..])) {
+                Ok(0) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                Ok(n) => self.written += n,
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+        pending.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+
+    pub(crate) fn poll_write<F>(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        write: F,
+    ) -> Poll<io::Result<usize>>
+    where
+        F: FnMut(&mut Context<'_>, &[u8]) -> Poll<io::Result<usize>>,
+    {
+        ready!(self.poll_drain(cx, write))?;
+        match &mut self.encoder {
+            Some(encoder) => Poll::Ready(encoder.write(buf)),
+            None => Poll::Ready(Err(io::Error::other("write after shutdown"))),
+        }
+    }
+
+    /// Emits everything written so far, ending at a byte boundary, and writes it out.
+    pub(crate) fn poll_flush<F>(&mut self, cx: &mut Context<'_>, write: F) -> Poll<io::Result<()>>
+    where
+        F: FnMut(&mut Context<'_>, &[u8]) -> Poll<io::Result<usize>>,
+    {
+        if let Some(encoder) = &mut self.encoder {
+            // Does nothing when called again with no new input in between.
+            encoder.flush()?;
+        }
+        self.poll_drain(cx, write)
+    }
+
+    /// Finishes the stream and writes out the rest of it.
+    pub(crate) fn poll_finish<F>(&mut self, cx: &mut Context<'_>, write: F) -> Poll<io::Result<()>>
+    where
+        F: FnMut(&mut Context<'_>, &[u8]) -> Poll<io::Result<usize>>,
+    {
+        if let Some(mut encoder) = self.encoder.take() {
+            let output = std::mem::take(encoder.output());
+            let written = std::mem::replace(&mut self.written, 0);
+            let tail = encoder.finish()?;
+            self.finished = output;
+            self.finished.drain(..written);
+            self.finished.extend_from_slice(&tail);
+        }
+        self.poll_drain(cx, write)
+    }
+}
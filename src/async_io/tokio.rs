@@ -0,0 +1,215 @@
+//! [`AsyncRead`] decoders and [`AsyncWrite`] encoders for tokio.
+//!
+//! Encoders must be shut down (e.g. with `AsyncWriteExt::shutdown`) to write the end of
+//! the stream; dropping one discards whatever has not been written yet.
+
+use super::{EncoderState, Source, poll_decode};
+use crate::gzip::GzipHeader;
+use crate::stream;
+use ::tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+macro_rules! decoder {
+    ($(#[$doc:meta])* $name:ident, $sync:ident) => {
+        $(#[$doc])*
+        pub struct $name<R> {
+            inner: R,
+            decoder: stream::$sync<Source>,
+        }
+
+        impl<R: AsyncRead + Unpin> $name<R> {
+            pub fn new(inner: R) -> Self {
+                Self {
+                    inner,
+                    decoder: stream::$sync::new(Source::new()),
+                }
+            }
+
+            pub fn get_ref(&self) -> &R {
+                &self.inner
+            }
+
+            pub fn get_mut(&mut self) -> &mut R {
+                &mut self.inner
+            }
+
+            /// Returns the underlying reader. Input that was read from it but not yet
+            /// decoded is lost.
+            pub fn into_inner(self) -> R {
+                self.inner
+            }
+        }
+
+        impl<R: AsyncRead + Unpin> AsyncRead for $name<R> {
+            fn poll_read(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &mut ReadBuf<'_>,
+            ) -> Poll<io::Result<()>> {
+                if buf.remaining() == 0 {
+                    return Poll::Ready(Ok(()));
+                }
+                let this = self.get_mut();
+                let inner = &mut this.inner;
+                let n = ready!(poll_decode(
+                    &mut this.decoder,
+                    cx,
+                    buf.initialize_unfilled(),
+                    |cx, input| {
+                        let mut input = ReadBuf::new(input);
+                        ready!(Pin::new(&mut *inner).poll_read(cx, &mut input))?;
+                        Poll::Ready(Ok(input.filled().len()))
+                    },
+                ))?;
+                buf.advance(n);
+                Poll::Ready(Ok(()))
+            }
+        }
+    };
+}
+
+macro_rules! encoder {
+    ($(#[$doc:meta])* $name:ident, $sync:ident) => {
+        $(#[$doc])*
+        pub struct $name<W> {
+            inner: W,
+            state: EncoderState<stream::$sync<Vec<u8>>>,
+        }
+
+        impl<W: AsyncWrite + Unpin> $name<W> {
+            pub fn new(inner: W, level: usize) -> Self {
+                Self {
+                    inner,
+                    state: EncoderState::new(stream::$sync::new(Vec::new(), level)),
+                }
+            }
+
+            /// Sets how much input is collected before it is compressed, as for the
+            /// blocking encoder.
+            pub fn with_buffer_size(mut self, size: usize) -> Self {
+                self.state = self.state.map(|e| e.with_buffer_size(size));
+                self
+            }
+
+            pub fn with_chunk_history(mut self, enabled: bool) -> Self {
+                self.state = self.state.map(|e| e.with_chunk_history(enabled));
+                self
+            }
+
+            pub fn with_parallel(mut self, enabled: bool) -> Self {
+                self.state = self.state.map(|e| e.with_parallel(enabled));
+                self
+            }
+
+            pub fn get_ref(&self) -> &W {
+                &self.inner
+            }
+
+            pub fn get_mut(&mut self) -> &mut W {
+                &mut self.inner
+            }
+
+            /// Returns the underlying writer. Unless the encoder was shut down first,
+            /// the stream written to it is incomplete.
+            pub fn into_inner(self) -> W {
+                self.inner
+            }
+        }
+
+        impl<W: AsyncWrite + Unpin> AsyncWrite for $name<W> {
+            fn poll_write(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<io::Result<usize>> {
+                let this = self.get_mut();
+                let inner = &mut this.inner;
+                this.state
+                    .poll_write(cx, buf, |cx, out| Pin::new(&mut *inner).poll_write(cx, out))
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                let this = self.get_mut();
+                let inner = &mut this.inner;
+                ready!(
+                    this.state
+                        .poll_flush(cx, |cx, out| Pin::new(&mut *inner).poll_write(cx, out))
+                )?;
+                Pin::new(inner).poll_flush(cx)
+            }
+
+            fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                let this = self.get_mut();
+                let inner = &mut this.inner;
+                ready!(
+                    this.state
+                        .poll_finish(cx, |cx, out| Pin::new(&mut *inner).poll_write(cx, out))
+                )?;
+                Pin::new(inner).poll_shutdown(cx)
+            }
+        }
+    };
+}
+
+decoder!(
+    /// Decodes a raw DEFLATE stream read from an [`AsyncRead`].
+    DeflateDecoder,
+    DeflateDecoder
+);
+decoder!(
+    /// Decodes a zlib stream read from an [`AsyncRead`], checking its header and
+    /// Adler-32 trailer.
+    ZlibDecoder,
+    ZlibDecoder
+);
+decoder!(
+    /// Decodes a gzip stream read from an [`AsyncRead`]. Concatenated members are
+    /// decoded as one stream unless
+    /// [`with_multi_member(false)`](Self::with_multi_member) is set.
+    GzipDecoder,
+    GzipDecoder
+);
+encoder!(
+    /// Compresses data written to it into a raw DEFLATE stream on an [`AsyncWrite`].
+    DeflateEncoder,
+    DeflateEncoder
+);
+encoder!(
+    /// Compresses data written to it into a zlib stream on an [`AsyncWrite`].
+    ZlibEncoder,
+    ZlibEncoder
+);
+encoder!(
+    /// Compresses data written to it into a gzip stream on an [`AsyncWrite`].
+    GzipEncoder,
+    GzipEncoder
+);
+
+impl<R: AsyncRead + Unpin> GzipDecoder<R> {
+    /// See [`stream::GzipDecoder::with_multi_member`].
+    pub fn with_multi_member(mut self, enabled: bool) -> Self {
+        self.decoder = self.decoder.with_multi_member(enabled);
+        self
+    }
+
+    /// See [`stream::GzipDecoder::with_verify_header_crc`].
+    pub fn with_verify_header_crc(mut self, verify: bool) -> Self {
+        self.decoder = self.decoder.with_verify_header_crc(verify);
+        self
+    }
+
+    /// Returns the header of the member being decoded, or `None` before the first read.
+    pub fn header(&self) -> Option<&GzipHeader> {
+        self.decoder.header()
+    }
+}
+
+impl<W: AsyncWrite + Unpin> GzipEncoder<W> {
+    /// See [`stream::GzipEncoder::with_header`].
+    pub fn with_header(mut self, header: GzipHeader) -> Self {
+        self.state = self.state.map(|e| e.with_header(header));
+        self
+    }
+}
@@ -1,44 +1,107 @@
-use crate::compress::{CompressResult, Compressor};
+use crate::common::*;
+use crate::compress::{CompressResult, Compressor, FlushMode};
 use crate::decompress::{DecompressResult, Decompressor};
+use crate::gzip::GzipHeader;
+use core::mem::MaybeUninit;
+#[cfg(feature = "parallel")]
 use rayon::prelude::*;
 
+/// Compresses many independent inputs, in parallel with the `parallel` feature.
 pub struct BatchCompressor {
     level: usize,
+    gzip_header: Option<GzipHeader>,
 }
 
 impl BatchCompressor {
     pub fn new(level: usize) -> Self {
-        Self { level }
+        Self {
+            level,
+            gzip_header: None,
+        }
     }
 
+    /// Sets the header written at the start of every member by
+    /// [`compress_gzip_batch`](Self::compress_gzip_batch). It must pass
+    /// [`GzipHeader::validate`].
+    pub fn with_gzip_header(mut self, header: GzipHeader) -> Self {
+        self.gzip_header = Some(header);
+        self
+    }
+
+    /// Compresses each input to a raw DEFLATE stream. An empty output marks an input
+    /// that failed to compress.
     pub fn compress_batch(&self, inputs: &[&[u8]]) -> Vec<Vec<u8>> {
-        inputs
-            .par_iter()
-            .map_init(
-                || (Compressor::new(self.level), Vec::new()),
-                |(compressor, buffer), &input| {
-                    let bound = Compressor::deflate_compress_bound(input.len());
-                    buffer.clear();
-                    buffer.reserve(bound);
-                    let buf_slice = &mut buffer.spare_capacity_mut()[..bound];
+        self.run(
+            inputs,
+            Compressor::deflate_compress_bound,
+            |compressor, input, output| {
+                let (res, size, _) = compressor.compress(input, output, FlushMode::Finish);
+                (res, size)
+            },
+        )
+    }
+
+    /// Compresses each input to a complete single-member gzip stream. An empty output
+    /// marks an input that failed to compress.
+    pub fn compress_gzip_batch(&self, inputs: &[&[u8]]) -> Vec<Vec<u8>> {
+        let header_len = self
+            .gzip_header
+            .as_ref()
+            .map_or(GZIP_MIN_HEADER_SIZE, GzipHeader::encoded_len);
+        let bound = |size| {
+            Compressor::deflate_compress_bound(size).saturating_add(header_len + GZIP_FOOTER_SIZE)
+        };
+        self.run(inputs, bound, Compressor::compress_gzip)
+    }
+
+    fn run<B, F>(&self, inputs: &[&[u8]], bound: B, compress_one: F) -> Vec<Vec<u8>>
+    where
+        B: Fn(usize) -> usize + Sync,
+        F: Fn(&mut Compressor, &[u8], &mut [MaybeUninit<u8>]) -> (CompressResult, usize) + Sync,
+    {
+        let init = || {
+            let mut compressor = Compressor::new(self.level);
+            compressor.set_gzip_header(self.gzip_header.clone());
+            (compressor, Vec::new())
+        };
+        let compress = |(compressor, buffer): &mut (Compressor, Vec<u8>), input: &[u8]| {
+            let bound = bound(input.len());
+            buffer.clear();
+            buffer.reserve(bound);
+            let buf_slice = &mut buffer.spare_capacity_mut()[..bound];
 
-                    let (res, size, _) =
-                        compressor.compress(input, buf_slice, crate::compress::FlushMode::Finish);
-                    if res == CompressResult::Success {
-                        assert!(size <= bound);
-                        unsafe {
-                            buffer.set_len(size);
-                        }
-                        std::mem::take(buffer)
-                    } else {
-                        Vec::new()
-                    }
-                },
-            )
-            .collect()
+            let (res, size) = compress_one(compressor, input, buf_slice);
+            if res == CompressResult::Success {
+                assert!(size <= bound);
+                unsafe {
+                    buffer.set_len(size);
+                }
+                std::mem::take(buffer)
+            } else {
+                Vec::new()
+            }
+        };
+
+        #[cfg(feature = "parallel")]
+        {
+            inputs
+                .par_iter()
+                .map_init(init, |state, &input| compress(state, input))
+                .collect()
+        }
+        #[cfg(not(feature = "parallel"))]
+        {
+            let mut state = init();
+            inputs
+                .iter()
+                .map(|&input| compress(&mut state, input))
+                .collect()
+        }
     }
 }
 
+/// Decompresses many independent raw DEFLATE inputs, in parallel with the `parallel`
+/// feature.
 pub struct BatchDecompressor;
 
 crate::impl_default_new!(BatchDecompressor);
@@ -53,29 +116,44 @@ impl BatchDecompressor {
         inputs: &[&[u8]],
         max_out_sizes: &[usize],
     ) -> Vec<Option<Vec<u8>>> {
-        inputs
-            .par_iter()
-            .zip(max_out_sizes.par_iter())
-            .map_init(
-                || (Decompressor::new(), Vec::new()),
-                |(decompressor, buffer), (&input, &max_size)| {
-                    buffer.clear();
-                    buffer.reserve(max_size);
-                    let buf_slice = &mut buffer.spare_capacity_mut()[..max_size];
+        let init = || (Decompressor::new(), Vec::new());
+        let decompress = |(decompressor, buffer): &mut (Decompressor, Vec<u8>),
+                          input: &[u8],
+                          max_size: usize| {
+            buffer.clear();
+            buffer.reserve(max_size);
+            let buf_slice = &mut buffer.spare_capacity_mut()[..max_size];
+
+            let (res, _, size) = unsafe { decompressor.decompress_uninit(input, buf_slice) };
+            if res == DecompressResult::Success {
+                assert!(size <= max_size);
+                unsafe {
+                    buffer.set_len(size);
+                }
+                Some(std::mem::take(buffer))
+            } else {
+                None
+            }
+        };
 
-                    let (res, _, size) =
-                        unsafe { decompressor.decompress_uninit(input, buf_slice) };
-                    if res == DecompressResult::Success {
-                        assert!(size <= max_size);
-                        unsafe {
-                            buffer.set_len(size);
-                        }
-                        Some(std::mem::take(buffer))
-                    } else {
-                        None
-                    }
-                },
-            )
-            .collect()
+        #[cfg(feature = "parallel")]
+        {
+            inputs
+                .par_iter()
+                .zip(max_out_sizes.par_iter())
+                .map_init(init, |state, (&input, &max_size)| {
+                    decompress(state, input, max_size)
+                })
+                .collect()
+        }
+        #[cfg(not(feature = "parallel"))]
+        {
+            let mut state = init();
+            inputs
+                .iter()
+                .zip(max_out_sizes)
+                .map(|(&input, &max_size)| decompress(&mut state, input, max_size))
+                .collect()
+        }
     }
 }
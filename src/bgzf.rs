@@ -0,0 +1,365 @@
+//! Blocked gzip (BGZF), the container used by BAM, BCF, tabix and `bgzip`.
+//!
+//! A BGZF file is a series of gzip members, each holding at most 64 KiB of data and
+//! compressing to at most 64 KiB. Every member carries a `BC` FEXTRA subfield giving
+//! its total size minus one, so a reader can step from block to block without
+//! decompressing, and the file ends with an empty member, [`BGZF_EOF_MARKER`]. A
+//! position in the data is a virtual offset: the file offset of the block containing
+//! it shifted left by 16, ORed with the offset within the block's decompressed data.
+//!
+//! The output is an ordinary multi-member gzip stream that any gzip decoder reads.
+
+use crate::Decompressor;
+use crate::batch::BatchCompressor;
+use crate::common::*;
+use crate::gzip::GzipHeader;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// Largest compressed size of a BGZF block, header and trailer included.
+pub const BGZF_MAX_BLOCK_SIZE: usize = 64 * 1024;
+
+/// Amount of data [`BgzfWriter`] puts in each block: less than 64 KiB, so that
+/// incompressible data still fits in [`BGZF_MAX_BLOCK_SIZE`] once compressed.
+pub const BGZF_BLOCK_DATA_SIZE: usize = 0xff00;
+
+/// The empty block that ends a BGZF file.
+pub const BGZF_EOF_MARKER: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+const BGZF_SUBFIELD_ID: [u8; 2] = *b"BC";
+
+/// Header of a BGZF block up to and including XLEN.
+const BGZF_FIXED_HEADER_SIZE: usize = GZIP_MIN_HEADER_SIZE + 2;
+
+/// Offset of BSIZE in the headers written by [`BgzfWriter`].
+const BGZF_BSIZE_OFFSET: usize = 16;
+
+/// Default number of blocks compressed together by [`BgzfWriter`].
+const BGZF_DEFAULT_BATCH_BLOCKS: usize = 64;
+
+/// Builds a virtual offset from a block's file offset and an offset within its data.
+pub fn virtual_offset(coffset: u64, uoffset: u16) -> u64 {
+    (coffset << 16) | uoffset as u64
+}
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Writes a BGZF file, compressing batches of blocks in parallel through
+/// [`BatchCompressor`].
+///
+/// Data is buffered until a whole batch of blocks is available. [`flush`](Write::flush)
+/// writes out everything buffered, ending the current block early.
+///
+/// As with [`GzipEncoder`](crate::stream::GzipEncoder), call
+/// [`finish()`](Self::finish) to observe I/O errors; dropping the writer writes the
+/// remaining blocks and the EOF marker but ignores any errors.
+pub struct BgzfWriter<W: Write> {
+    writer: Option<W>,
+    compressor: BatchCompressor,
+    stored: BatchCompressor,
+    buffer: Vec<u8>,
+    batch_blocks: usize,
+    coffset: u64,
+}
+
+impl<W: Write> BgzfWriter<W> {
+    pub fn new(writer: W, level: usize) -> Self {
+        let header = GzipHeader::new()
+            .with_xfl(0)
+            .with_extra_field(BGZF_SUBFIELD_ID, &[0, 0]);
+        Self {
+            writer: Some(writer),
+            compressor: BatchCompressor::new(level).with_gzip_header(header.clone()),
+            stored: BatchCompressor::new(0).with_gzip_header(header),
+            buffer: Vec::new(),
+            batch_blocks: BGZF_DEFAULT_BATCH_BLOCKS,
+            coffset: 0,
+        }
+    }
+
+    /// Sets how many blocks are buffered and compressed together (64 by default).
+    /// Larger batches keep more threads busy at the cost of memory.
+    pub fn with_batch_blocks(mut self, blocks: usize) -> Self {
+        self.batch_blocks = blocks.max(1);
+        self
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.writer.as_ref().expect("writer already finished")
+    }
+
+    /// Writing to the underlying writer directly corrupts the BGZF file.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.writer.as_mut().expect("writer already finished")
+    }
+
+    /// Returns the virtual offset at which the next byte written will be found, as
+    /// needed to build a BAI or tabix index.
+    ///
+    /// The block address is only known once the blocks before it are compressed, so
+    /// this first writes out all complete blocks that are buffered. Calling it between
+    /// every record therefore limits how many blocks are compressed in parallel.
+    pub fn virtual_offset(&mut self) -> io::Result<u64> {
+        let complete = self.buffer.len() / BGZF_BLOCK_DATA_SIZE * BGZF_BLOCK_DATA_SIZE;
+        self.write_blocks(complete)?;
+        Ok(virtual_offset(self.coffset, self.buffer.len() as u16))
+    }
+
+    /// Compresses and writes the first `len` bytes of the buffer.
+    fn write_blocks(&mut self, len: usize) -> io::Result<()> {
+        if len == 0 {
+            return Ok(());
+        }
+        let blocks: Vec<&[u8]> = self.buffer[..len].chunks(BGZF_BLOCK_DATA_SIZE).collect();
+        let members = self.compressor.compress_gzip_batch(&blocks);
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| io::Error::other("writer already finished"))?;
+        for (mut member, block) in members.into_iter().zip(&blocks) {
+            // Blocks that do not compress into 64 KiB are stored instead, which always
+            // fits.
+            if member.is_empty() || member.len() > BGZF_MAX_BLOCK_SIZE {
+                member = self.stored.compress_gzip_batch(&[block]).remove(0);
+                if member.is_empty() {
+                    return Err(io::Error::other("compression failed"));
+                }
+            }
+            let bsize = (member.len() - 1) as u16;
+            member[BGZF_BSIZE_OFFSET..BGZF_BSIZE_OFFSET + 2].copy_from_slice(&bsize.to_le_bytes());
+            writer.write_all(&member)?;
+            self.coffset += member.len() as u64;
+        }
+        self.buffer.drain(..len);
+        Ok(())
+    }
+
+    fn finish_stream(&mut self) -> io::Result<()> {
+        self.write_blocks(self.buffer.len())?;
+        if let Some(writer) = &mut self.writer {
+            writer.write_all(&BGZF_EOF_MARKER)?;
+            self.coffset += BGZF_EOF_MARKER.len() as u64;
+        }
+        Ok(())
+    }
+
+    /// Writes the remaining blocks and the EOF marker, and returns the underlying
+    /// writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.finish_stream()?;
+        self.writer
+            .take()
+            .ok_or_else(|| io::Error::other("writer already finished"))
+    }
+}
+
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        let batch_size = self.batch_blocks * BGZF_BLOCK_DATA_SIZE;
+        if self.buffer.len() >= batch_size {
+            let complete = self.buffer.len() / batch_size * batch_size;
+            self.write_blocks(complete)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_blocks(self.buffer.len())?;
+        if let Some(writer) = &mut self.writer {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Drop for BgzfWriter<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.finish_stream();
+        }
+    }
+}
+
+/// Reads a BGZF file one block at a time.
+///
+/// The inner reader must be positioned at the start of the file, since block
+/// addresses are counted from there. With a seekable reader,
+/// [`seek_virtual`](Self::seek_virtual) jumps to a virtual offset taken from
+/// [`virtual_offset`](Self::virtual_offset), [`BgzfWriter::virtual_offset`] or an
+/// index.
+pub struct BgzfReader<R: Read> {
+    inner: R,
+    decompressor: Decompressor,
+    block: Vec<u8>,
+    data: Vec<u8>,
+    pos: usize,
+    block_coffset: u64,
+    next_coffset: u64,
+}
+
+impl<R: Read> BgzfReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            decompressor: Decompressor::new(),
+            block: Vec::with_capacity(BGZF_MAX_BLOCK_SIZE),
+            data: Vec::with_capacity(BGZF_MAX_BLOCK_SIZE),
+            pos: 0,
+            block_coffset: 0,
+            next_coffset: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Reading from the underlying reader directly desynchronizes the block
+    /// addresses.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Returns the virtual offset of the next byte to be read. At the end of a block
+    /// this is the start of the following one.
+    pub fn virtual_offset(&self) -> u64 {
+        if self.pos == self.data.len() {
+            virtual_offset(self.next_coffset, 0)
+        } else {
+            virtual_offset(self.block_coffset, self.pos as u16)
+        }
+    }
+
+    /// Reads and decompresses the next block, returning `false` at the end of the file.
+    fn read_block(&mut self) -> io::Result<bool> {
+        self.block.resize(BGZF_FIXED_HEADER_SIZE, 0);
+        let mut filled = 0;
+        while filled < BGZF_FIXED_HEADER_SIZE {
+            match self.inner.read(&mut self.block[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if filled == 0 {
+            return Ok(false);
+        }
+        if filled < BGZF_FIXED_HEADER_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "truncated BGZF block",
+            ));
+        }
+
+        let header = &self.block;
+        if header[0] != GZIP_ID1
+            || header[1] != GZIP_ID2
+            || header[2] != GZIP_CM_DEFLATE
+            || header[3] & GZIP_FEXTRA == 0
+        {
+            return Err(invalid_data("not a BGZF block"));
+        }
+        let xlen = u16::from_le_bytes([header[10], header[11]]) as usize;
+        self.block.resize(BGZF_FIXED_HEADER_SIZE + xlen, 0);
+        self.inner
+            .read_exact(&mut self.block[BGZF_FIXED_HEADER_SIZE..])?;
+
+        let (header, _) = GzipHeader::parse(&self.block, false)?;
+        let bsize = header
+            .extra_fields()
+            .find(|&(id, data)| id == BGZF_SUBFIELD_ID && data.len() == 2)
+            .map(|(_, data)| u16::from_le_bytes([data[0], data[1]]) as usize + 1)
+            .ok_or_else(|| invalid_data("gzip member has no BGZF block size"))?;
+        if bsize < self.block.len() + GZIP_FOOTER_SIZE {
+            return Err(invalid_data("invalid BGZF block size"));
+        }
+        let header_len = self.block.len();
+        self.block.resize(bsize, 0);
+        self.inner.read_exact(&mut self.block[header_len..])?;
+
+        let isize = u32::from_le_bytes(self.block[bsize - 4..].try_into().unwrap()) as usize;
+        if isize > BGZF_MAX_BLOCK_SIZE {
+            return Err(invalid_data("BGZF block data exceeds 64 KiB"));
+        }
+        self.data.resize(isize, 0);
+        let (size, member_len) = self
+            .decompressor
+            .decompress_gzip_member_into(&self.block, &mut self.data)?;
+        if size != isize || member_len != bsize {
+            return Err(invalid_data("invalid BGZF block size"));
+        }
+
+        self.block_coffset = self.next_coffset;
+        self.next_coffset += bsize as u64;
+        self.pos = 0;
+        Ok(true)
+    }
+}
+
+impl<R: Read + Seek> BgzfReader<R> {
+    /// Moves to the virtual offset `voffset`: the block at file offset `voffset >> 16`,
+    /// then `voffset & 0xffff` bytes into its data.
+    pub fn seek_virtual(&mut self, voffset: u64) -> io::Result<()> {
+        let coffset = voffset >> 16;
+        let uoffset = (voffset & 0xffff) as usize;
+        self.inner.seek(SeekFrom::Start(coffset))?;
+        self.next_coffset = coffset;
+        self.data.clear();
+        self.pos = 0;
+        if !self.read_block()? {
+            self.block_coffset = coffset;
+        }
+        if uoffset > self.data.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "virtual offset is past the end of its block",
+            ));
+        }
+        self.pos = uoffset;
+        Ok(())
+    }
+
+    /// Checks whether the file ends with [`BGZF_EOF_MARKER`]. A missing marker usually
+    /// means the file was truncated. The read position is left unchanged.
+    pub fn has_eof_marker(&mut self) -> io::Result<bool> {
+        let position = self.inner.stream_position()?;
+        let len = self.inner.seek(SeekFrom::End(0))?;
+        let mut found = false;
+        if len >= BGZF_EOF_MARKER.len() as u64 {
+            let mut tail = [0; BGZF_EOF_MARKER.len()];
+            self.inner
+                .seek(SeekFrom::End(-(BGZF_EOF_MARKER.len() as i64)))?;
+            self.inner.read_exact(&mut tail)?;
+            found = tail == BGZF_EOF_MARKER;
+        }
+        self.inner.seek(SeekFrom::Start(position))?;
+        Ok(found)
+    }
+}
+
+impl<R: Read> Read for BgzfReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.pos == self.data.len() {
+            if !self.read_block()? {
+                return Ok(0);
+            }
+        }
+        let n = buf.len().min(self.data.len() - self.pos);
+        buf[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
@@ -0,0 +1,546 @@
+//! C ABI compatible with libdeflate's `libdeflate.h`, exported when the crate is built
+//! as a `cdylib` or `staticlib`.
+//!
+//! The functions keep the C library's names, argument order and result codes, so a
+//! program built against libdeflate can link against this crate instead. The header
+//! in `include/libdeflate.h` is generated from this file with cbindgen.
+//!
+//! Only built with the `capi` feature: the exported symbols would clash with the C
+//! libdeflate if both ended up in one binary.
+//!
+//! Pointer arguments follow the contracts documented in `libdeflate.h`: handles must
+//! come from the matching `libdeflate_alloc_*` function, buffers must be valid for the
+//! given number of bytes, and a handle must not be used from two threads at once.
+//!
+//! Custom allocators set with [`libdeflate_set_memory_allocator`] or
+//! [`libdeflate_options`] are used for the compressor and decompressor objects
+//! themselves; working buffers are allocated by Rust's global allocator.
+#![allow(non_camel_case_types)]
+#![allow(clippy::missing_safety_doc)]
+
+use crate::compress::{CompressResult, Compressor, FlushMode};
+use crate::decompress::{DecompressResult, Decompressor};
+use std::ffi::{c_int, c_void};
+use std::mem::MaybeUninit;
+use std::sync::RwLock;
+
+pub const LIBDEFLATE_VERSION_MAJOR: u32 = 1;
+pub const LIBDEFLATE_VERSION_MINOR: u32 = 25;
+/// cbindgen skips string constants, so `cbindgen.toml` writes this one into the header.
+pub const LIBDEFLATE_VERSION_STRING: &str = "1.25";
+
+/// Result of a decompression call. The values match [`DecompressResult`], apart from
+/// `ShortInput`, which libdeflate reports as `LIBDEFLATE_BAD_DATA`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum libdeflate_result {
+    /// Decompression was successful.
+    LIBDEFLATE_SUCCESS = 0,
+    /// Decompression failed because the compressed data was invalid, corrupt, or
+    /// otherwise unsupported.
+    LIBDEFLATE_BAD_DATA = 1,
+    /// A NULL `actual_out_nbytes_ret` was provided, but the data would have
+    /// decompressed to fewer than `out_nbytes_avail` bytes.
+    LIBDEFLATE_SHORT_OUTPUT = 2,
+    /// The data would have decompressed to more than `out_nbytes_avail` bytes.
+    LIBDEFLATE_INSUFFICIENT_SPACE = 3,
+}
+
+const _: () = {
+    assert!(DecompressResult::Success as i32 == libdeflate_result::LIBDEFLATE_SUCCESS as i32);
+    assert!(DecompressResult::BadData as i32 == libdeflate_result::LIBDEFLATE_BAD_DATA as i32);
+    assert!(
+        DecompressResult::ShortOutput as i32 == libdeflate_result::LIBDEFLATE_SHORT_OUTPUT as i32
+    );
+    assert!(
+        DecompressResult::InsufficientSpace as i32
+            == libdeflate_result::LIBDEFLATE_INSUFFICIENT_SPACE as i32
+    );
+};
+
+impl From<DecompressResult> for libdeflate_result {
+    fn from(res: DecompressResult) -> Self {
+        match res {
+            DecompressResult::Success => libdeflate_result::LIBDEFLATE_SUCCESS,
+            DecompressResult::ShortOutput => libdeflate_result::LIBDEFLATE_SHORT_OUTPUT,
+            DecompressResult::InsufficientSpace => libdeflate_result::LIBDEFLATE_INSUFFICIENT_SPACE,
+            // libdeflate has no separate code for truncated input.
+            DecompressResult::BadData | DecompressResult::ShortInput => {
+                libdeflate_result::LIBDEFLATE_BAD_DATA
+            }
+        }
+    }
+}
+
+pub type libdeflate_malloc_func = Option<unsafe extern "C" fn(usize) -> *mut c_void>;
+pub type libdeflate_free_func = Option<unsafe extern "C" fn(*mut c_void)>;
+
+/// Advanced options for `libdeflate_alloc_compressor_ex` and
+/// `libdeflate_alloc_decompressor_ex`.
+#[repr(C)]
+pub struct libdeflate_options {
+    /// Must be set to `sizeof(struct libdeflate_options)`.
+    pub sizeof_options: usize,
+    /// Allocates the compressor or decompressor; overrides the global allocator.
+    pub malloc_func: libdeflate_malloc_func,
+    /// Frees memory allocated by `malloc_func`.
+    pub free_func: libdeflate_free_func,
+}
+
+pub struct libdeflate_compressor {
+    inner: Compressor,
+    free_func: libdeflate_free_func,
+}
+
+pub struct libdeflate_decompressor {
+    inner: Decompressor,
+    free_func: libdeflate_free_func,
+}
+
+static ALLOCATOR: RwLock<(libdeflate_malloc_func, libdeflate_free_func)> =
+    RwLock::new((None, None));
+
+/// Picks the allocator for a new object: the one in `options` if given, otherwise the
+/// global one. Returns `None` if `options` is malformed.
+unsafe fn allocator(
+    options: *const libdeflate_options,
+) -> Option<(libdeflate_malloc_func, libdeflate_free_func)> {
+    if let Some(options) = unsafe { options.as_ref() } {
+        if options.sizeof_options != size_of::<libdeflate_options>() {
+            return None;
+        }
+        if options.malloc_func.is_some() && options.free_func.is_some() {
+            return Some((options.malloc_func, options.free_func));
+        }
+    }
+    Some(*ALLOCATOR.read().unwrap_or_else(|e| e.into_inner()))
+}
+
+/// Moves `value` to the heap with `malloc_func`, or with `Box` if there is none.
+unsafe fn alloc_object<T>(value: T, malloc_func: libdeflate_malloc_func) -> *mut T {
+    match malloc_func {
+        None => Box::into_raw(Box::new(value)),
+        Some(malloc_func) => {
+            let ptr = unsafe { malloc_func(size_of::<T>()) } as *mut T;
+            if ptr.is_null() || !ptr.is_aligned() {
+                return std::ptr::null_mut();
+            }
+            unsafe { ptr.write(value) };
+            ptr
+        }
+    }
+}
+
+/// Drops and frees an object created by [`alloc_object`].
+unsafe fn free_object<T>(ptr: *mut T, free_func: libdeflate_free_func) {
+    match free_func {
+        None => drop(unsafe { Box::from_raw(ptr) }),
+        Some(free_func) => unsafe {
+            ptr.drop_in_place();
+            free_func(ptr as *mut c_void);
+        },
+    }
+}
+
+/// Builds a byte slice from a C buffer, which may be NULL when `len` is 0.
+unsafe fn input_slice<'a>(ptr: *const c_void, len: usize) -> &'a [u8] {
+    if len == 0 {
+        &[]
+    } else {
+        unsafe { std::slice::from_raw_parts(ptr as *const u8, len) }
+    }
+}
+
+unsafe fn output_slice<'a>(ptr: *mut c_void, len: usize) -> &'a mut [MaybeUninit<u8>] {
+    if len == 0 {
+        &mut []
+    } else {
+        unsafe { std::slice::from_raw_parts_mut(ptr as *mut MaybeUninit<u8>, len) }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn libdeflate_alloc_compressor(
+    compression_level: c_int,
+) -> *mut libdeflate_compressor {
+    unsafe { libdeflate_alloc_compressor_ex(compression_level, std::ptr::null()) }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn libdeflate_alloc_compressor_ex(
+    compression_level: c_int,
+    options: *const libdeflate_options,
+) -> *mut libdeflate_compressor {
+    if !(0..=12).contains(&compression_level) {
+        return std::ptr::null_mut();
+    }
+    let Some((malloc_func, free_func)) = (unsafe { allocator(options) }) else {
+        return std::ptr::null_mut();
+    };
+    let compressor = libdeflate_compressor {
+        inner: Compressor::new(compression_level as usize),
+        free_func,
+    };
+    unsafe { alloc_object(compressor, malloc_func) }
+}
+
+/// Runs `f` over the C buffers, returning the compressed size or 0 if it did not fit.
+unsafe fn compress_with<F>(
+    compressor: *mut libdeflate_compressor,
+    input: *const c_void,
+    in_nbytes: usize,
+    output: *mut c_void,
+    out_nbytes_avail: usize,
+    f: F,
+) -> usize
+where
+    F: FnOnce(&mut Compressor, &[u8], &mut [MaybeUninit<u8>]) -> (CompressResult, usize),
+{
+    let c = unsafe { &mut *compressor };
+    let input = unsafe { input_slice(input, in_nbytes) };
+    let output = unsafe { output_slice(output, out_nbytes_avail) };
+    match f(&mut c.inner, input, output) {
+        (CompressResult::Success, size) => size,
+        _ => 0,
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn libdeflate_deflate_compress(
+    compressor: *mut libdeflate_compressor,
+    input: *const c_void,
+    in_nbytes: usize,
+    output: *mut c_void,
+    out_nbytes_avail: usize,
+) -> usize {
+    unsafe {
+        compress_with(
+            compressor,
+            input,
+            in_nbytes,
+            output,
+            out_nbytes_avail,
+            |c, i, o| {
+                let (res, size, _) = c.compress(i, o, FlushMode::Finish);
+                (res, size)
+            },
+        )
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn libdeflate_deflate_compress_bound(
+    compressor: *mut libdeflate_compressor,
+    in_nbytes: usize,
+) -> usize {
+    // The bound does not depend on the compression level.
+    _ = compressor;
+    Compressor::deflate_compress_bound(in_nbytes)
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn libdeflate_zlib_compress(
+    compressor: *mut libdeflate_compressor,
+    input: *const c_void,
+    in_nbytes: usize,
+    output: *mut c_void,
+    out_nbytes_avail: usize,
+) -> usize {
+    unsafe {
+        compress_with(
+            compressor,
+            input,
+            in_nbytes,
+            output,
+            out_nbytes_avail,
+            |c, i, o| c.compress_zlib(i, o),
+        )
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn libdeflate_zlib_compress_bound(
+    compressor: *mut libdeflate_compressor,
+    in_nbytes: usize,
+) -> usize {
+    _ = compressor;
+    Compressor::zlib_compress_bound(in_nbytes)
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn libdeflate_gzip_compress(
+    compressor: *mut libdeflate_compressor,
+    input: *const c_void,
+    in_nbytes: usize,
+    output: *mut c_void,
+    out_nbytes_avail: usize,
+) -> usize {
+    unsafe {
+        compress_with(
+            compressor,
+            input,
+            in_nbytes,
+            output,
+            out_nbytes_avail,
+            |c, i, o| c.compress_gzip(i, o),
+        )
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn libdeflate_gzip_compress_bound(
+    compressor: *mut libdeflate_compressor,
+    in_nbytes: usize,
+) -> usize {
+    _ = compressor;
+    Compressor::gzip_compress_bound(in_nbytes)
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn libdeflate_free_compressor(compressor: *mut libdeflate_compressor) {
+    if compressor.is_null() {
+        return;
+    }
+    let free_func = unsafe { (*compressor).free_func };
+    unsafe { free_object(compressor, free_func) };
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn libdeflate_alloc_decompressor() -> *mut libdeflate_decompressor {
+    unsafe { libdeflate_alloc_decompressor_ex(std::ptr::null()) }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn libdeflate_alloc_decompressor_ex(
+    options: *const libdeflate_options,
+) -> *mut libdeflate_decompressor {
+    let Some((malloc_func, free_func)) = (unsafe { allocator(options) }) else {
+        return std::ptr::null_mut();
+    };
+    let decompressor = libdeflate_decompressor {
+        inner: Decompressor::new(),
+        free_func,
+    };
+    unsafe { alloc_object(decompressor, malloc_func) }
+}
+
+/// Runs `f` over the C buffers and applies libdeflate's rules for the optional
+/// out-parameters: without `actual_out_nbytes_ret`, the output must fill the buffer
+/// exactly.
+#[allow(clippy::too_many_arguments)]
+unsafe fn decompress_with<F>(
+    decompressor: *mut libdeflate_decompressor,
+    input: *const c_void,
+    in_nbytes: usize,
+    output: *mut c_void,
+    out_nbytes_avail: usize,
+    actual_in_nbytes_ret: *mut usize,
+    actual_out_nbytes_ret: *mut usize,
+    f: F,
+) -> libdeflate_result
+where
+    F: FnOnce(&mut Decompressor, &[u8], &mut [MaybeUninit<u8>]) -> (DecompressResult, usize, usize),
+{
+    let d = unsafe { &mut *decompressor };
+    let input = unsafe { input_slice(input, in_nbytes) };
+    let output = unsafe { output_slice(output, out_nbytes_avail) };
+    let (res, in_consumed, out_produced) = f(&mut d.inner, input, output);
+    if res != DecompressResult::Success {
+        return res.into();
+    }
+    if actual_out_nbytes_ret.is_null() {
+        if out_produced != out_nbytes_avail {
+            return libdeflate_result::LIBDEFLATE_SHORT_OUTPUT;
+        }
+    } else {
+        unsafe { *actual_out_nbytes_ret = out_produced };
+    }
+    if !actual_in_nbytes_ret.is_null() {
+        unsafe { *actual_in_nbytes_ret = in_consumed };
+    }
+    libdeflate_result::LIBDEFLATE_SUCCESS
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn libdeflate_deflate_decompress(
+    decompressor: *mut libdeflate_decompressor,
+    input: *const c_void,
+    in_nbytes: usize,
+    output: *mut c_void,
+    out_nbytes_avail: usize,
+    actual_out_nbytes_ret: *mut usize,
+) -> libdeflate_result {
+    unsafe {
+        libdeflate_deflate_decompress_ex(
+            decompressor,
+            input,
+            in_nbytes,
+            output,
+            out_nbytes_avail,
+            std::ptr::null_mut(),
+            actual_out_nbytes_ret,
+        )
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn libdeflate_deflate_decompress_ex(
+    decompressor: *mut libdeflate_decompressor,
+    input: *const c_void,
+    in_nbytes: usize,
+    output: *mut c_void,
+    out_nbytes_avail: usize,
+    actual_in_nbytes_ret: *mut usize,
+    actual_out_nbytes_ret: *mut usize,
+) -> libdeflate_result {
+    unsafe {
+        decompress_with(
+            decompressor,
+            input,
+            in_nbytes,
+            output,
+            out_nbytes_avail,
+            actual_in_nbytes_ret,
+            actual_out_nbytes_ret,
+            |d, i, o| d.decompress_uninit(i, o),
+        )
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn libdeflate_zlib_decompress(
+    decompressor: *mut libdeflate_decompressor,
+    input: *const c_void,
+    in_nbytes: usize,
+    output: *mut c_void,
+    out_nbytes_avail: usize,
+    actual_out_nbytes_ret: *mut usize,
+) -> libdeflate_result {
+    unsafe {
+        libdeflate_zlib_decompress_ex(
+            decompressor,
+            input,
+            in_nbytes,
+            output,
+            out_nbytes_avail,
+            std::ptr::null_mut(),
+            actual_out_nbytes_ret,
+        )
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn libdeflate_zlib_decompress_ex(
+    decompressor: *mut libdeflate_decompressor,
+    input: *const c_void,
+    in_nbytes: usize,
+    output: *mut c_void,
+    out_nbytes_avail: usize,
+    actual_in_nbytes_ret: *mut usize,
+    actual_out_nbytes_ret: *mut usize,
+) -> libdeflate_result {
+    unsafe {
+        decompress_with(
+            decompressor,
+            input,
+            in_nbytes,
+            output,
+            out_nbytes_avail,
+            actual_in_nbytes_ret,
+            actual_out_nbytes_ret,
+            |d, i, o| d.decompress_zlib_uninit(i, o),
+        )
+    }
+}
+
+/// Decompresses the first gzip member in `input`; like libdeflate, any data after it
+/// is left for the caller, whose offset `libdeflate_gzip_decompress_ex` reports.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn libdeflate_gzip_decompress(
+    decompressor: *mut libdeflate_decompressor,
+    input: *const c_void,
+    in_nbytes: usize,
+    output: *mut c_void,
+    out_nbytes_avail: usize,
+    actual_out_nbytes_ret: *mut usize,
+) -> libdeflate_result {
+    unsafe {
+        libdeflate_gzip_decompress_ex(
+            decompressor,
+            input,
+            in_nbytes,
+            output,
+            out_nbytes_avail,
+            std::ptr::null_mut(),
+            actual_out_nbytes_ret,
+        )
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn libdeflate_gzip_decompress_ex(
+    decompressor: *mut libdeflate_decompressor,
+    input: *const c_void,
+    in_nbytes: usize,
+    output: *mut c_void,
+    out_nbytes_avail: usize,
+    actual_in_nbytes_ret: *mut usize,
+    actual_out_nbytes_ret: *mut usize,
+) -> libdeflate_result {
+    unsafe {
+        decompress_with(
+            decompressor,
+            input,
+            in_nbytes,
+            output,
+            out_nbytes_avail,
+            actual_in_nbytes_ret,
+            actual_out_nbytes_ret,
+            |d, i, o| d.decompress_gzip_member_uninit(i, o, None),
+        )
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn libdeflate_free_decompressor(decompressor: *mut libdeflate_decompressor) {
+    if decompressor.is_null() {
+        return;
+    }
+    let free_func = unsafe { (*decompressor).free_func };
+    unsafe { free_object(decompressor, free_func) };
+}
+
+/// Updates a running Adler-32 checksum. Returns 1, the initial value, if `buffer` is
+/// NULL.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn libdeflate_adler32(adler: u32, buffer: *const c_void, len: usize) -> u32 {
+    if buffer.is_null() {
+        return 1;
+    }
+    crate::adler32::adler32(adler, unsafe { input_slice(buffer, len) })
+}
+
+/// Updates a running CRC-32 checksum. Returns 0, the initial value, if `buffer` is
+/// NULL.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn libdeflate_crc32(crc: u32, buffer: *const c_void, len: usize) -> u32 {
+    if buffer.is_null() {
+        return 0;
+    }
+    crate::crc32::crc32(crc, unsafe { input_slice(buffer, len) })
+}
+
+/// Sets the allocator used by compressors and decompressors allocated afterwards
+/// without their own in `libdeflate_options`. Passing NULL for both restores the
+/// default.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn libdeflate_set_memory_allocator(
+    malloc_func: libdeflate_malloc_func,
+    free_func: libdeflate_free_func,
+) {
+    let mut allocator = ALLOCATOR.write().unwrap_or_else(|e| e.into_inner());
+    *allocator = match (malloc_func, free_func) {
+        (Some(_), Some(_)) => (malloc_func, free_func),
+        _ => (None, None),
+    };
+}
@@ -37,6 +37,9 @@ pub const ZLIB_MIN_HEADER_SIZE: usize = 2;
 pub const ZLIB_FOOTER_SIZE: usize = 4;
 pub const ZLIB_MIN_OVERHEAD: usize = ZLIB_MIN_HEADER_SIZE + ZLIB_FOOTER_SIZE;
 
+pub const ZLIB_DICTID_SIZE: usize = 4;
+pub const ZLIB_FDICT: u16 = 0x20;
+
 pub const ZLIB_CM_DEFLATE: u8 = 8;
 pub const ZLIB_CINFO_32K_WINDOW: u8 = 7;
 
@@ -67,24 +70,109 @@ pub const GZIP_OS_UNKNOWN: u8 = 255;
 
 pub const MIN_BLOCK_LENGTH: usize = 5000;
 pub const SOFT_MAX_BLOCK_LENGTH: usize = 300000;
+pub const MAX_SOFT_MAX_BLOCK_LENGTH: usize = 1 << 24;
 pub const SEQ_STORE_LENGTH: usize = 50000;
 
 #[inline(always)]
-pub fn bsr32(v: u32) -> u32 {
+pub const fn bsr32(v: u32) -> u32 {
     31 - v.leading_zeros()
 }
 
 /// Safely converts a mutable byte slice to a mutable `MaybeUninit<u8>` slice.
 #[inline(always)]
-pub fn slice_as_uninit_mut(slice: &mut [u8]) -> &mut [std::mem::MaybeUninit<u8>] {
+pub fn slice_as_uninit_mut(slice: &mut [u8]) -> &mut [core::mem::MaybeUninit<u8>] {
     unsafe {
-        std::slice::from_raw_parts_mut(
-            slice.as_mut_ptr() as *mut std::mem::MaybeUninit<u8>,
+        core::slice::from_raw_parts_mut(
+            slice.as_mut_ptr() as *mut core::mem::MaybeUninit<u8>,
             slice.len(),
         )
     }
 }
 
+/// Whether the CPU supports an x86 target feature: detected at run time with `std`,
+/// otherwise only features enabled at compile time (`-C target-feature` or
+/// `-C target-cpu`) count.
+#[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
+macro_rules! x86_feature_detected {
+    ($feature:tt) => {
+        std::is_x86_feature_detected!($feature)
+    };
+}
+#[cfg(all(not(feature = "std"), any(target_arch = "x86", target_arch = "x86_64")))]
+macro_rules! x86_feature_detected {
+    ($feature:tt) => {
+        $crate::common::compile_time_feature($feature, cfg!(target_feature = $feature))
+    };
+}
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub(crate) use x86_feature_detected;
+
+/// Returns `enabled`, the `cfg!(target_feature)` value of `feature`. Passing the name
+/// along keeps checks for different features from expanding to identical `false &&
+/// false` expressions.
+#[cfg(not(feature = "std"))]
+#[inline(always)]
+pub(crate) const fn compile_time_feature(_feature: &str, enabled: bool) -> bool {
+    enabled
+}
+
+/// The aarch64 counterpart of [`x86_feature_detected`].
+#[cfg(all(feature = "std", target_arch = "aarch64"))]
+macro_rules! aarch64_feature_detected {
+    ($feature:tt) => {
+        std::arch::is_aarch64_feature_detected!($feature)
+    };
+}
+#[cfg(all(not(feature = "std"), target_arch = "aarch64"))]
+macro_rules! aarch64_feature_detected {
+    ($feature:tt) => {
+        $crate::common::compile_time_feature($feature, cfg!(target_feature = $feature))
+    };
+}
+#[cfg(target_arch = "aarch64")]
+pub(crate) use aarch64_feature_detected;
+
+/// The checksum in a zlib or gzip trailer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Checksum {
+    Adler32,
+    Crc32,
+}
+
+impl Checksum {
+    /// The checksum of `data`. With `parallel` and the `parallel` feature, data larger
+    /// than [`PARALLEL_CHUNK_SIZE`](crate::compress::PARALLEL_CHUNK_SIZE) is split into
+    /// chunks of that size that are checksummed on the rayon thread pool and combined.
+    pub(crate) fn compute(self, data: &[u8], parallel: bool) -> u32 {
+        #[cfg(feature = "parallel")]
+        if parallel && data.len() > crate::compress::PARALLEL_CHUNK_SIZE {
+            use rayon::prelude::*;
+            return data
+                .par_chunks(crate::compress::PARALLEL_CHUNK_SIZE)
+                .map(|chunk| (self.compute(chunk, false), chunk.len()))
+                .reduce(
+                    || (self.compute(&[], false), 0),
+                    |(sum1, len1), (sum2, len2)| (self.combine(sum1, sum2, len2), len1 + len2),
+                )
+                .0;
+        }
+        let _ = parallel;
+        match self {
+            Checksum::Adler32 => crate::adler32::adler32(1, data),
+            Checksum::Crc32 => crate::crc32::crc32(0, data),
+        }
+    }
+
+    /// The checksum of `A || B` from those of `A` and `B`, where `B` is `len2` bytes.
+    #[cfg(feature = "parallel")]
+    pub(crate) fn combine(self, sum1: u32, sum2: u32, len2: usize) -> u32 {
+        match self {
+            Checksum::Adler32 => crate::adler32::adler32_combine(sum1, sum2, len2 as u64),
+            Checksum::Crc32 => crate::crc32::crc32_combine(sum1, sum2, len2 as u64),
+        }
+    }
+}
+
 #[macro_export]
 macro_rules! impl_default_new {
     ($t:ty) => {
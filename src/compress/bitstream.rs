@@ -1,4 +1,4 @@
-use std::mem::MaybeUninit;
+use core::mem::MaybeUninit;
 
 pub struct Bitstream<'a> {
     pub output: &'a mut [MaybeUninit<u8>],
@@ -51,7 +51,7 @@ impl<'a> Bitstream<'a> {
 
         if new_bitcount >= 32 {
             let bitbuf = self.bitbuf | ((bits as u64) << bitcount);
-            std::ptr::write_unaligned(
+            core::ptr::write_unaligned(
                 self.output.as_mut_ptr().add(self.out_idx) as *mut u64,
                 bitbuf.to_le(),
             );
@@ -82,7 +82,7 @@ impl<'a> Bitstream<'a> {
             let bitbuf_low = self.bitbuf | (bits << bitcount);
             let bitbuf_high = bits >> (64 - bitcount);
 
-            std::ptr::write_unaligned(
+            core::ptr::write_unaligned(
                 self.output.as_mut_ptr().add(self.out_idx) as *mut u64,
                 bitbuf_low.to_le(),
             );
@@ -92,7 +92,7 @@ impl<'a> Bitstream<'a> {
         } else {
             let bitbuf = self.bitbuf | (bits << bitcount);
             if new_bitcount >= 32 {
-                std::ptr::write_unaligned(
+                core::ptr::write_unaligned(
                     self.output.as_mut_ptr().add(self.out_idx) as *mut u64,
                     bitbuf.to_le(),
                 );
@@ -123,7 +123,7 @@ impl<'a> Bitstream<'a> {
 
             if self.out_idx + 8 <= self.output.len() {
                 unsafe {
-                    std::ptr::write_unaligned(
+                    core::ptr::write_unaligned(
                         self.output.as_mut_ptr().add(self.out_idx) as *mut u64,
                         bitbuf.to_le(),
                     );
@@ -136,7 +136,7 @@ impl<'a> Bitstream<'a> {
 
             if self.out_idx + 4 <= self.output.len() {
                 unsafe {
-                    std::ptr::write_unaligned(
+                    core::ptr::write_unaligned(
                         self.output.as_mut_ptr().add(self.out_idx) as *mut u32,
                         (bitbuf as u32).to_le(),
                     );
@@ -1,5 +1,5 @@
 use crate::common::*;
-use std::cmp::min;
+use core::cmp::min;
 
 const NUM_SYMBOL_BITS: usize = 10;
 const SYMBOL_MASK: u32 = (1 << NUM_SYMBOL_BITS) - 1;
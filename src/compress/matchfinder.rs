@@ -1,10 +1,13 @@
+use super::Strategy;
 use crate::common::*;
-use std::cmp::min;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::min;
 
 #[cfg(target_arch = "aarch64")]
-use std::arch::aarch64::*;
+use core::arch::aarch64::*;
 #[cfg(target_arch = "x86_64")]
-use std::arch::x86_64::*;
+use core::arch::x86_64::*;
 
 pub const MATCHFINDER_HASH_ORDER: usize = 15;
 pub const MATCHFINDER_HASH_SIZE: usize = 1 << MATCHFINDER_HASH_ORDER;
@@ -241,6 +244,134 @@ impl_matchfinder_trait!(
     }
 );
 
+/// Shortest match kept by [`Strategy::Filtered`], as in zlib.
+const FILTERED_MIN_MATCH_LEN: usize = 6;
+
+/// Restricts the matches of `inner` to those a [`Strategy`] allows. The Huffman-only
+/// and RLE strategies never consult `inner`, so positions are not inserted into it.
+pub struct StrategyMatchFinder<'a, T> {
+    inner: &'a mut T,
+    strategy: Strategy,
+}
+
+impl<'a, T: MatchFinderTrait> StrategyMatchFinder<'a, T> {
+    pub fn new(inner: &'a mut T, strategy: Strategy) -> Self {
+        Self { inner, strategy }
+    }
+
+    fn uses_inner(&self) -> bool {
+        !matches!(self.strategy, Strategy::HuffmanOnly | Strategy::Rle)
+    }
+}
+
+/// The run of `data[pos - 1]` starting at `pos`, as a distance-1 match.
+fn rle_match(data: &[u8], pos: usize) -> (usize, usize) {
+    if pos == 0 {
+        return (0, 0);
+    }
+    let prev = data[pos - 1];
+    let max_len = min(DEFLATE_MAX_MATCH_LEN, data.len() - pos);
+    let len = data[pos..pos + max_len]
+        .iter()
+        .take_while(|&&b| b == prev)
+        .count();
+    if len >= DEFLATE_MIN_MATCH_LEN {
+        (len, 1)
+    } else {
+        (0, 0)
+    }
+}
+
+impl<T: MatchFinderTrait> MatchFinderTrait for StrategyMatchFinder<'_, T> {
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+
+    fn prepare(&mut self, len: usize) {
+        self.inner.prepare(len);
+    }
+
+    fn advance(&mut self, len: usize) {
+        self.inner.advance(len);
+    }
+
+    fn find_match(
+        &mut self,
+        data: &[u8],
+        pos: usize,
+        max_depth: usize,
+        nice_len: usize,
+    ) -> (usize, usize) {
+        match self.strategy {
+            Strategy::HuffmanOnly => (0, 0),
+            Strategy::Rle => rle_match(data, pos),
+            Strategy::Filtered => {
+                let (len, offset) = self.inner.find_match(data, pos, max_depth, nice_len);
+                if len >= FILTERED_MIN_MATCH_LEN {
+                    (len, offset)
+                } else {
+                    (0, 0)
+                }
+            }
+            Strategy::Default | Strategy::Fixed => {
+                self.inner.find_match(data, pos, max_depth, nice_len)
+            }
+        }
+    }
+
+    fn skip_match(&mut self, data: &[u8], pos: usize, max_depth: usize, nice_len: usize) {
+        if self.uses_inner() {
+            self.inner.skip_match(data, pos, max_depth, nice_len);
+        }
+    }
+
+    fn skip_positions(
+        &mut self,
+        data: &[u8],
+        pos: usize,
+        count: usize,
+        max_depth: usize,
+        nice_len: usize,
+    ) {
+        if self.uses_inner() {
+            self.inner
+                .skip_positions(data, pos, count, max_depth, nice_len);
+        }
+    }
+
+    fn find_matches(
+        &mut self,
+        data: &[u8],
+        pos: usize,
+        max_depth: usize,
+        nice_len: usize,
+        matches: &mut Vec<(u16, u16)>,
+    ) -> (usize, usize) {
+        if self.uses_inner() {
+            self.inner
+                .find_matches(data, pos, max_depth, nice_len, matches);
+        } else {
+            matches.clear();
+        }
+        match self.strategy {
+            Strategy::Rle => {
+                let (len, offset) = rle_match(data, pos);
+                if len != 0 {
+                    matches.push((len as u16, offset as u16));
+                }
+            }
+            Strategy::Filtered => {
+                matches.retain(|&(len, _)| len as usize >= FILTERED_MIN_MATCH_LEN);
+            }
+            _ => {}
+        }
+        matches
+            .iter()
+            .max_by_key(|&&(len, _)| len)
+            .map_or((0, 0), |&(len, offset)| (len as usize, offset as usize))
+    }
+}
+
 #[inline(always)]
 unsafe fn match_len_sw(a: *const u8, b: *const u8, max_len: usize) -> usize {
     let mut len = 0;
@@ -730,22 +861,22 @@ unsafe fn match_len_neon(a: *const u8, b: *const u8, max_len: usize) -> usize {
 fn get_match_len_strategy() -> MatchLenStrategy {
     #[cfg(target_arch = "x86_64")]
     {
-        if is_x86_feature_detected!("avx512vl") && is_x86_feature_detected!("avx512bw") {
+        if x86_feature_detected!("avx512vl") && x86_feature_detected!("avx512bw") {
             return MatchLenStrategy::Avx10;
         }
-        if is_x86_feature_detected!("avx512bw") {
+        if x86_feature_detected!("avx512bw") {
             return MatchLenStrategy::Avx512;
         }
-        if is_x86_feature_detected!("avx2") {
+        if x86_feature_detected!("avx2") {
             return MatchLenStrategy::Avx2;
         }
-        if is_x86_feature_detected!("sse2") {
+        if x86_feature_detected!("sse2") {
             return MatchLenStrategy::Sse2;
         }
     }
     #[cfg(target_arch = "aarch64")]
     {
-        if std::arch::is_aarch64_feature_detected!("neon") {
+        if aarch64_feature_detected!("neon") {
             return MatchLenStrategy::Neon;
         }
     }
@@ -2,16 +2,27 @@ pub mod bitstream;
 
 mod huffman_comp;
 mod matchfinder;
+mod options;
+mod streaming;
 
 use self::bitstream::Bitstream;
 use self::huffman_comp::make_huffman_code;
-use self::matchfinder::{BtMatchFinder, HtMatchFinder, MatchFinder, MatchFinderTrait};
+use self::matchfinder::{
+    BtMatchFinder, HtMatchFinder, MatchFinder, MatchFinderTrait, StrategyMatchFinder,
+};
 use crate::common::*;
+use crate::gzip::GzipHeader;
+use alloc::vec::Vec;
+use core::cmp::min;
+use core::mem::MaybeUninit;
+use core::ops::Range;
+#[cfg(feature = "parallel")]
 use rayon::prelude::*;
-use std::cmp::min;
+#[cfg(feature = "parallel")]
 use std::io;
-use std::mem::MaybeUninit;
-use std::sync::OnceLock;
+
+pub use self::options::{CompressionOptions, MatchFinderKind, Strategy};
+pub use self::streaming::StreamCompressor;
 
 const LENGTH_WRITE_TABLE: [u32; 260] = [
     3, 3, 3, 3, 16777220, 33554437, 50331654, 67108871, 83886088, 100663305, 117440522, 134283275,
@@ -104,25 +115,41 @@ pub const MAX_LITLEN_CODEWORD_LEN: usize = 14;
 pub const MAX_OFFSET_CODEWORD_LEN: usize = 15;
 pub const MAX_PRE_CODEWORD_LEN: usize = 7;
 
-fn gen_codewords_from_lens(lens: &[u8], codewords: &mut [u32], max_len: usize) {
+/// Inputs larger than this are split into chunks of this size that can be compressed
+/// independently, on several threads with the `parallel` feature.
+pub const PARALLEL_CHUNK_SIZE: usize = 256 * 1024;
+
+// Costs assumed for symbols that were not seen when the code was built, so the
+// optimal parser does not treat them as free.
+const LITERAL_NOSTAT_BITS: u32 = 13;
+const LENGTH_NOSTAT_BITS: u32 = 13;
+const OFFSET_NOSTAT_BITS: u32 = 10;
+
+const fn gen_codewords_from_lens(lens: &[u8], codewords: &mut [u32], max_len: usize) {
     let mut len_counts = [0u32; 16];
-    for &l in lens {
-        if l > 0 {
-            len_counts[l as usize] += 1;
+    let mut i = 0;
+    while i < lens.len() {
+        if lens[i] > 0 {
+            len_counts[lens[i] as usize] += 1;
         }
+        i += 1;
     }
     let mut next_code = [0u32; 16];
     let mut code = 0u32;
-    for len in 1..=max_len {
+    let mut len = 1;
+    while len <= max_len {
         code = (code + len_counts[len - 1]) << 1;
         next_code[len] = code;
+        len += 1;
     }
-    for i in 0..lens.len() {
+    let mut i = 0;
+    while i < lens.len() {
         if lens[i] > 0 {
             let c = next_code[lens[i] as usize];
             next_code[lens[i] as usize] += 1;
             codewords[i] = (c as u16).reverse_bits() as u32 >> (16 - lens[i]);
         }
+        i += 1;
     }
 }
 
@@ -134,7 +161,10 @@ struct StaticTables {
     match_len_table: [u64; DEFLATE_MAX_MATCH_LEN + 1],
 }
 
-fn compute_static_tables() -> StaticTables {
+/// The code tables for static Huffman blocks, built at compile time.
+static STATIC_TABLES: StaticTables = compute_static_tables();
+
+const fn compute_static_tables() -> StaticTables {
     let mut litlen_lens = [0u8; DEFLATE_NUM_LITLEN_SYMS];
     let mut offset_lens = [0u8; DEFLATE_NUM_OFFSET_SYMS];
     let mut litlen_codewords = [0u32; DEFLATE_NUM_LITLEN_SYMS];
@@ -160,32 +190,39 @@ fn compute_static_tables() -> StaticTables {
         litlen_lens[i] = 8;
         i += 1;
     }
-    for i in 0..32 {
+    let mut i = 0;
+    while i < 32 {
         offset_lens[i] = 5;
+        i += 1;
     }
 
     gen_codewords_from_lens(&litlen_lens, &mut litlen_codewords, 9);
     gen_codewords_from_lens(&offset_lens, &mut offset_codewords, 5);
 
-    for i in 0..DEFLATE_NUM_LITLEN_SYMS {
+    let mut i = 0;
+    while i < DEFLATE_NUM_LITLEN_SYMS {
         litlen_table[i] = (litlen_codewords[i] as u64) | ((litlen_lens[i] as u64) << 32);
+        i += 1;
     }
-    for i in 0..DEFLATE_NUM_OFFSET_SYMS {
+    let mut i = 0;
+    while i < DEFLATE_NUM_OFFSET_SYMS {
         let mut entry = (offset_codewords[i] as u64) | ((offset_lens[i] as u64) << 32);
         if i < 30 {
-            entry |= (unsafe { *OFFSET_EXTRA_BITS_TABLE.get_unchecked(i) } as u64) << 40;
-            entry |= (unsafe { *OFFSET_BASE_TABLE.get_unchecked(i) } as u64) << 48;
+            entry |= (OFFSET_EXTRA_BITS_TABLE[i] as u64) << 40;
+            entry |= (OFFSET_BASE_TABLE[i] as u64) << 48;
         }
         offset_table[i] = entry;
+        i += 1;
     }
 
-    for len in 3..=DEFLATE_MAX_MATCH_LEN {
-        let len_info = unsafe { *LENGTH_WRITE_TABLE.get_unchecked(len) };
+    let mut len = 3;
+    while len <= DEFLATE_MAX_MATCH_LEN {
+        let len_info = LENGTH_WRITE_TABLE[len];
         let slot = (len_info >> 24) as usize;
         let extra = (len_info >> 16) as u8;
         let base = len_info as u16;
 
-        let huff_entry = unsafe { *litlen_table.get_unchecked(257 + slot) };
+        let huff_entry = litlen_table[257 + slot];
         let code = huff_entry as u16;
         let huff_len = (huff_entry >> 32) as u8;
 
@@ -194,6 +231,7 @@ fn compute_static_tables() -> StaticTables {
         let val = (code as u32) | (diff << huff_len);
 
         match_len_table[len] = (val as u64) | ((total_len as u64) << 32);
+        len += 1;
     }
 
     StaticTables {
@@ -253,15 +291,19 @@ struct BlockSplitStats {
     observations: [u32; NUM_OBSERVATION_TYPES],
     num_new_observations: u32,
     num_observations: u32,
+    min_block_length: usize,
+    soft_max_block_length: usize,
 }
 
 impl BlockSplitStats {
-    fn new() -> Self {
+    fn new(min_block_length: usize, soft_max_block_length: usize) -> Self {
         Self {
             new_observations: [0; NUM_OBSERVATION_TYPES],
             observations: [0; NUM_OBSERVATION_TYPES],
             num_new_observations: 0,
             num_observations: 0,
+            min_block_length,
+            soft_max_block_length,
         }
     }
 
@@ -357,19 +399,19 @@ impl BlockSplitStats {
     #[inline(always)]
     fn should_end_block(&mut self, block_length: usize, input_remaining: usize) -> bool {
         if self.num_new_observations < NUM_OBSERVATIONS_PER_BLOCK_CHECK
-            && block_length < SOFT_MAX_BLOCK_LENGTH
+            && block_length < self.soft_max_block_length
         {
             return false;
         }
 
-        if input_remaining <= MIN_BLOCK_LENGTH {
+        if input_remaining <= self.min_block_length {
             return false;
         }
-        if block_length >= SOFT_MAX_BLOCK_LENGTH {
+        if block_length >= self.soft_max_block_length {
             return true;
         }
 
-        if block_length >= MIN_BLOCK_LENGTH {
+        if block_length >= self.min_block_length {
             if self.do_end_block_check(block_length) {
                 return true;
             }
@@ -385,13 +427,69 @@ enum MatchFinderEnum {
     Bt(BtMatchFinder),
 }
 
+/// Evaluates `$body` with `$mf` bound to the match finder in `$mf_enum`, wrapped in a
+/// [`StrategyMatchFinder`] when `$strategy` restricts the matches it may report.
+macro_rules! with_match_finder {
+    ($mf_enum:expr, $strategy:expr, |$mf:ident| $body:expr) => {
+        match $mf_enum {
+            MatchFinderEnum::Chain(inner) if $strategy.filters_matches() => {
+                let $mf = &mut StrategyMatchFinder::new(inner, $strategy);
+                $body
+            }
+            MatchFinderEnum::Table(inner) if $strategy.filters_matches() => {
+                let $mf = &mut StrategyMatchFinder::new(inner, $strategy);
+                $body
+            }
+            MatchFinderEnum::Bt(inner) if $strategy.filters_matches() => {
+                let $mf = &mut StrategyMatchFinder::new(inner, $strategy);
+                $body
+            }
+            MatchFinderEnum::Chain($mf) => $body,
+            MatchFinderEnum::Table($mf) => $body,
+            MatchFinderEnum::Bt($mf) => $body,
+        }
+    };
+}
+
+/// How a compression call ends its output. More modes may be added, so matches on it
+/// need a wildcard arm.
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[non_exhaustive]
 pub enum FlushMode {
     None,
     Sync,
+    /// Like [`Sync`](Self::Sync), and nothing compressed afterwards references the input
+    /// before it, so decoding can start right after the marker, as with zlib's
+    /// `Z_FULL_FLUSH`. [`Compressor::compress`] keeps no window between calls, so there
+    /// it writes the same output as `Sync`.
+    Full,
     Finish,
 }
 
+impl FlushMode {
+    /// Whether the output ends with an empty stored block, the `00 00 FF FF` marker.
+    fn writes_marker(self) -> bool {
+        matches!(self, FlushMode::Sync | FlushMode::Full)
+    }
+}
+
+/// The window a chunk starting at `pos` in `input` is compressed with: `dictionary` for
+/// the first chunk, otherwise the input just before it if `chunk_history` is set.
+fn chunk_dictionary<'a>(
+    dictionary: &'a [u8],
+    input: &'a [u8],
+    pos: usize,
+    chunk_history: bool,
+) -> &'a [u8] {
+    if pos == 0 {
+        dictionary
+    } else if chunk_history {
+        &input[pos - min(pos, DEFLATE_MAX_MATCH_OFFSET)..pos]
+    } else {
+        &[]
+    }
+}
+
 pub struct Compressor {
     pub compression_level: usize,
     pub max_search_depth: usize,
@@ -418,14 +516,30 @@ pub struct Compressor {
     split_stats: BlockSplitStats,
     matches: Vec<(u16, u16)>,
     path_nodes: Vec<(u16, u16)>,
+    dictionary: Vec<u8>,
+    dictionary_id: Option<u32>,
+    /// Scratch space that joins a dictionary and the input it precedes, reused across
+    /// calls.
+    window: Vec<u8>,
+    chunk_history: bool,
+    parallel: bool,
+    gzip_header: Option<GzipHeader>,
+    options: CompressionOptions,
 }
 
 impl Compressor {
     pub fn new(level: usize) -> Self {
-        let mut c = Self {
+        Self::with_options(CompressionOptions::for_level(level))
+    }
+
+    /// Creates a compressor with fine-grained parameters. They are not validated here;
+    /// see [`CompressionOptions::validate`].
+    pub fn with_options(options: CompressionOptions) -> Self {
+        let level = options.level();
+        Self {
             compression_level: level,
-            max_search_depth: 0,
-            nice_match_length: 0,
+            max_search_depth: options.max_search_depth(),
+            nice_match_length: options.nice_match_length(),
             litlen_freqs: [0; DEFLATE_NUM_LITLEN_SYMS],
             offset_freqs: [0; DEFLATE_NUM_OFFSET_SYMS],
             litlen_codewords: [0; DEFLATE_NUM_LITLEN_SYMS],
@@ -438,12 +552,10 @@ impl Compressor {
             literal_costs: [0; 256],
             length_costs: [0; DEFLATE_MAX_MATCH_LEN + 1],
             offset_slot_costs: [0; 32],
-            mf: Some(if level == 1 {
-                MatchFinderEnum::Table(HtMatchFinder::new())
-            } else if level >= 10 {
-                MatchFinderEnum::Bt(BtMatchFinder::new())
-            } else {
-                MatchFinderEnum::Chain(MatchFinder::new())
+            mf: Some(match options.match_finder() {
+                MatchFinderKind::HashTable => MatchFinderEnum::Table(HtMatchFinder::new()),
+                MatchFinderKind::HashChain => MatchFinderEnum::Chain(MatchFinder::new()),
+                MatchFinderKind::BinaryTree => MatchFinderEnum::Bt(BtMatchFinder::new()),
             }),
             sequences: if level == 0 {
                 Vec::new()
@@ -460,20 +572,39 @@ impl Compressor {
             } else {
                 Vec::new()
             },
-            split_stats: BlockSplitStats::new(),
+            split_stats: BlockSplitStats::new(
+                options.min_block_length(),
+                options.soft_max_block_length(),
+            ),
             matches: if level >= 10 {
                 Vec::with_capacity(32)
             } else {
                 Vec::new()
             },
             path_nodes: if level >= 10 {
-                Vec::with_capacity(SOFT_MAX_BLOCK_LENGTH / 3)
+                Vec::with_capacity(min(options.soft_max_block_length(), SOFT_MAX_BLOCK_LENGTH) / 3)
             } else {
                 Vec::new()
             },
-        };
-        c.init_params();
-        c
+            dictionary: Vec::new(),
+            dictionary_id: None,
+            window: Vec::new(),
+            chunk_history: true,
+            parallel: true,
+            gzip_header: None,
+            options,
+        }
+    }
+
+    pub fn options(&self) -> &CompressionOptions {
+        &self.options
+    }
+
+    /// Whether blocks are parsed greedily and coded with static codes without
+    /// building dynamic ones, as level 1 does unless the strategy needs otherwise.
+    fn uses_fast_path(&self) -> bool {
+        self.compression_level < 2
+            && matches!(self.options.strategy(), Strategy::Default | Strategy::Fixed)
     }
 
     fn update_huffman_tables(&mut self) {
@@ -509,137 +640,69 @@ impl Compressor {
         }
     }
 
-    fn init_params(&mut self) {
-        match self.compression_level {
-            0 => {
-                self.max_search_depth = 0;
-                self.nice_match_length = 0;
-            }
-            1 => {
-                self.max_search_depth = 2;
-                self.nice_match_length = 32;
-            }
-            2 => {
-                self.max_search_depth = 6;
-                self.nice_match_length = 10;
-            }
-            3 => {
-                self.max_search_depth = 12;
-                self.nice_match_length = 14;
-            }
-            4 => {
-                self.max_search_depth = 16;
-                self.nice_match_length = 30;
-            }
-            5 => {
-                self.max_search_depth = 16;
-                self.nice_match_length = 30;
-            }
-            6 => {
-                self.max_search_depth = 35;
-                self.nice_match_length = 65;
-            }
-            7 => {
-                self.max_search_depth = 100;
-                self.nice_match_length = 130;
-            }
-            8 => {
-                self.max_search_depth = 300;
-                self.nice_match_length = 258;
-            }
-            9 => {
-                self.max_search_depth = 600;
-                self.nice_match_length = 258;
-            }
-            10 => {
-                self.max_search_depth = 35;
-                self.nice_match_length = 75;
-            }
-            11 => {
-                self.max_search_depth = 100;
-                self.nice_match_length = 150;
-            }
-            12 => {
-                self.max_search_depth = 300;
-                self.nice_match_length = 258;
-            }
-            _ => {
-                self.max_search_depth = 300;
-                self.nice_match_length = 258;
-            }
-        }
-    }
-
+    /// Compresses `input[start..]`, treating `input[..start]` as history that
+    /// matches may reference but that is not itself emitted. The match finder must
+    /// already hold `input[..start]`.
     fn compress_loop<T: MatchFinderTrait>(
         &mut self,
         mf: &mut T,
         input: &[u8],
+        start: usize,
         bs: &mut Bitstream,
         flush_mode: FlushMode,
     ) -> (CompressResult, usize, u32) {
-        let mut in_idx = 0;
-        mf.prepare(input.len());
+        let mut in_idx = start;
 
         while in_idx < input.len() {
             let processed = if self.compression_level >= 10 {
+                let history = if in_idx == start { start } else { 0 };
                 self.compress_near_optimal_block(
                     mf,
                     input,
                     in_idx,
+                    history,
                     bs,
                     flush_mode == FlushMode::Finish,
                 )
             } else {
-                let lazy_depth = if self.compression_level >= 8 {
-                    2
-                } else if self.compression_level >= 5 {
-                    1
-                } else {
-                    0
-                };
                 self.compress_greedy_block(
                     mf,
                     input,
                     in_idx,
                     bs,
-                    lazy_depth,
+                    self.options.lazy_depth(),
                     flush_mode == FlushMode::Finish,
                 )
             };
 
             if processed == 0 {
-                mf.advance(input.len());
                 return (CompressResult::InsufficientSpace, 0, 0);
             }
             in_idx += processed;
         }
 
-        if in_idx == 0 && flush_mode == FlushMode::Finish {
+        if in_idx == start && flush_mode == FlushMode::Finish {
             let start_out = bs.out_idx;
             let start_bitcount = bs.bitcount;
             if self.compression_level >= 10 {
-                self.compress_near_optimal_block(mf, input, 0, bs, true);
+                self.compress_near_optimal_block(mf, input, start, 0, bs, true);
             } else {
-                self.compress_greedy_block(mf, input, 0, bs, 0, true);
+                self.compress_greedy_block(mf, input, start, bs, 0, true);
             }
             if bs.out_idx == start_out && bs.bitcount == start_bitcount {
-                mf.advance(input.len());
                 return (CompressResult::InsufficientSpace, 0, 0);
             }
         }
 
-        if flush_mode == FlushMode::Sync {
+        if flush_mode.writes_marker() {
             if !bs.write_bits(0, 3) {
-                mf.advance(input.len());
                 return (CompressResult::InsufficientSpace, 0, 0);
             }
             let (res, _) = bs.flush();
             if !res {
-                mf.advance(input.len());
                 return (CompressResult::InsufficientSpace, 0, 0);
             }
             if bs.out_idx + 4 > bs.output.len() {
-                mf.advance(input.len());
                 return (CompressResult::InsufficientSpace, 0, 0);
             }
             bs.output[bs.out_idx].write(0);
@@ -651,11 +714,9 @@ impl Compressor {
 
         let (res, valid_bits) = bs.flush();
         if !res {
-            mf.advance(input.len());
             return (CompressResult::InsufficientSpace, 0, 0);
         }
 
-        mf.advance(input.len());
         (CompressResult::Success, bs.out_idx, valid_bits)
     }
 
@@ -665,95 +726,337 @@ impl Compressor {
         output: &mut [MaybeUninit<u8>],
         flush_mode: FlushMode,
     ) -> (CompressResult, usize, u32) {
-        if input.len() > 256 * 1024 {
-            let chunk_size = 256 * 1024;
-            let chunks: Vec<&[u8]> = input.chunks(chunk_size).collect();
-
-            let compressed_chunks_res: Vec<io::Result<Vec<u8>>> = chunks
-                .par_iter()
-                .enumerate()
-                .map_init(
-                    || {
-                        (
-                            Compressor::new(self.compression_level),
-                            Vec::with_capacity(chunk_size + chunk_size / 2),
-                        )
-                    },
-                    |(compressor, buf), (i, chunk)| {
-                        let is_last = i == chunks.len() - 1;
-                        let mode = if is_last { flush_mode } else { FlushMode::Sync };
-
-                        let bound = Self::deflate_compress_bound(chunk.len());
-                        buf.clear();
-                        if buf.capacity() < bound {
-                            buf.reserve(bound);
-                        }
+        let dictionary = core::mem::take(&mut self.dictionary);
+        let res = self.compress_with_dictionary(&dictionary, input, output, flush_mode);
+        self.dictionary = dictionary;
+        res
+    }
 
-                        buf.resize(bound, 0);
-                        let buf_uninit = crate::common::slice_as_uninit_mut(&mut buf[..bound]);
+    /// Sets a preset dictionary for subsequent raw DEFLATE and zlib streams.
+    ///
+    /// Only the last 32 KiB of `dictionary` can be referenced by matches, but the
+    /// zlib DICTID is computed over all of it, as zlib's `deflateSetDictionary` does.
+    /// The dictionary stays in effect until it is replaced or cleared with an empty slice.
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) {
+        let keep = min(dictionary.len(), DEFLATE_MAX_MATCH_OFFSET);
+        self.dictionary.clear();
+        self.dictionary
+            .extend_from_slice(&dictionary[dictionary.len() - keep..]);
+        self.dictionary_id = if dictionary.is_empty() {
+            None
+        } else {
+            Some(crate::adler32::adler32(1, dictionary))
+        };
+    }
 
-                        let (res, size, _) = compressor.compress(chunk, buf_uninit, mode);
-                        if res == CompressResult::Success {
-                            assert!(size <= bound);
-                            buf.truncate(size);
-                            if size < buf.capacity() / 2 {
-                                Ok(buf.to_vec())
-                            } else {
-                                Ok(std::mem::replace(
+    /// Returns the Adler-32 of the current preset dictionary, if one is set.
+    pub fn dictionary_id(&self) -> Option<u32> {
+        self.dictionary_id
+    }
+
+    /// Controls whether inputs split into parallel chunks prime each chunk with the
+    /// 32 KiB that precede it, as pigz does. Enabled by default; disabling it makes
+    /// the chunks fully independent at some cost in ratio.
+    pub fn set_chunk_history(&mut self, enabled: bool) {
+        self.chunk_history = enabled;
+    }
+
+    /// Controls whether inputs larger than [`PARALLEL_CHUNK_SIZE`] are compressed on the
+    /// rayon thread pool (the default) or on the calling thread. The output is the same
+    /// either way. Without the `parallel` feature, compression is always sequential.
+    pub fn set_parallel(&mut self, enabled: bool) {
+        self.parallel = enabled;
+    }
+
+    /// Sets the [`Strategy`] for subsequent compression. Level 0 ignores it.
+    pub fn set_strategy(&mut self, strategy: Strategy) {
+        self.options = self.options.clone().with_strategy(strategy);
+    }
+
+    /// Sets the header written by [`compress_gzip`](Self::compress_gzip); `None`
+    /// restores the default. The header must have passed [`GzipHeader::validate`].
+    pub fn set_gzip_header(&mut self, header: Option<GzipHeader>) {
+        self.gzip_header = header;
+    }
+
+    pub fn gzip_header(&self) -> Option<&GzipHeader> {
+        self.gzip_header.as_ref()
+    }
+
+    /// Like [`compress_with_dictionary`](Self::compress_with_dictionary), but also returns
+    /// the `checksum` of `input`. Parallel compression checksums each chunk in the
+    /// worker that compresses it.
+    fn compress_checksummed(
+        &mut self,
+        dictionary: &[u8],
+        input: &[u8],
+        output: &mut [MaybeUninit<u8>],
+        flush_mode: FlushMode,
+        checksum: Checksum,
+    ) -> (CompressResult, usize, u32) {
+        #[cfg(feature = "parallel")]
+        if self.parallel && input.len() > PARALLEL_CHUNK_SIZE {
+            return self.compress_chunks_parallel(
+                dictionary,
+                input,
+                output,
+                flush_mode,
+                Some(checksum),
+            );
+        }
+        let (res, size, _) = self.compress_with_dictionary(dictionary, input, output, flush_mode);
+        if res != CompressResult::Success {
+            return (res, 0, 0);
+        }
+        (res, size, checksum.compute(input, false))
+    }
+
+    /// Compresses `input` as if `dictionary` had been compressed immediately before it,
+    /// so matches may reach back into it. Only its last 32 KiB are used.
+    pub fn compress_with_dictionary(
+        &mut self,
+        dictionary: &[u8],
+        input: &[u8],
+        output: &mut [MaybeUninit<u8>],
+        flush_mode: FlushMode,
+    ) -> (CompressResult, usize, u32) {
+        if input.len() > PARALLEL_CHUNK_SIZE {
+            #[cfg(feature = "parallel")]
+            if self.parallel {
+                return self.compress_chunks_parallel(dictionary, input, output, flush_mode, None);
+            }
+            return self.compress_chunks(dictionary, input, output, flush_mode);
+        }
+
+        if self.compression_level == 0 {
+            return self.compress_uncompressed(input, output, flush_mode);
+        }
+
+        if dictionary.is_empty() {
+            return self.compress_primed(input, 0, output, flush_mode);
+        }
+
+        let keep = min(dictionary.len(), DEFLATE_MAX_MATCH_OFFSET);
+        let mut window = core::mem::take(&mut self.window);
+        window.clear();
+        window.extend_from_slice(&dictionary[dictionary.len() - keep..]);
+        window.extend_from_slice(input);
+        let res = self.compress_primed(&window, keep, output, flush_mode);
+        self.window = window;
+        res
+    }
+
+    /// Compresses the chunk `input[range]` after the window [`chunk_dictionary`] gives
+    /// it. History taken from `input` is read in place rather than copied.
+    fn compress_chunk(
+        &mut self,
+        dictionary: &[u8],
+        input: &[u8],
+        range: Range<usize>,
+        chunk_history: bool,
+        output: &mut [MaybeUninit<u8>],
+        flush_mode: FlushMode,
+    ) -> (CompressResult, usize, u32) {
+        let pos = range.start;
+        if pos == 0 || !chunk_history || self.compression_level == 0 {
+            let history = chunk_dictionary(dictionary, input, pos, chunk_history);
+            return self.compress_with_dictionary(history, &input[range], output, flush_mode);
+        }
+        let start = min(pos, DEFLATE_MAX_MATCH_OFFSET);
+        self.compress_primed(&input[pos - start..range.end], start, output, flush_mode)
+    }
+
+    /// Compresses `input` in [`PARALLEL_CHUNK_SIZE`] chunks on the rayon thread pool,
+    /// each with its own compressor. With a `checksum`, each worker also checksums its
+    /// chunk, and the combined checksum of `input` is returned.
+    #[cfg(feature = "parallel")]
+    fn compress_chunks_parallel(
+        &mut self,
+        dictionary: &[u8],
+        input: &[u8],
+        output: &mut [MaybeUninit<u8>],
+        flush_mode: FlushMode,
+        checksum: Option<Checksum>,
+    ) -> (CompressResult, usize, u32) {
+        let chunk_size = PARALLEL_CHUNK_SIZE;
+        let chunks: Vec<&[u8]> = input.chunks(chunk_size).collect();
+        let chunk_history = self.chunk_history;
+
+        let compressed_chunks_res: Vec<io::Result<(Vec<u8>, u32)>> = chunks
+            .par_iter()
+            .enumerate()
+            .map_init(
+                || {
+                    (
+                        Compressor::with_options(self.options.clone()),
+                        Vec::with_capacity(chunk_size + chunk_size / 2),
+                    )
+                },
+                |(compressor, buf), (i, chunk)| {
+                    let is_last = i == chunks.len() - 1;
+                    let mode = if is_last { flush_mode } else { FlushMode::Sync };
+                    let pos = i * chunk_size;
+
+                    let bound = Self::deflate_compress_bound(chunk.len());
+                    buf.clear();
+                    if buf.capacity() < bound {
+                        buf.reserve(bound);
+                    }
+
+                    buf.resize(bound, 0);
+                    let buf_uninit = crate::common::slice_as_uninit_mut(&mut buf[..bound]);
+
+                    let (res, size, _) = compressor.compress_chunk(
+                        dictionary,
+                        input,
+                        pos..pos + chunk.len(),
+                        chunk_history,
+                        buf_uninit,
+                        mode,
+                    );
+                    if res == CompressResult::Success {
+                        assert!(size <= bound);
+                        buf.truncate(size);
+                        let sum = checksum.map_or(0, |c| c.compute(chunk, false));
+                        if size < buf.capacity() / 2 {
+                            Ok((buf.to_vec(), sum))
+                        } else {
+                            Ok((
+                                core::mem::replace(
                                     buf,
                                     Vec::with_capacity(chunk_size + chunk_size / 2),
-                                ))
-                            }
-                        } else {
-                            Err(io::Error::other("Compression failed"))
-                        }
-                    },
-                )
-                .collect();
-
-            let mut out_idx = 0;
-            for res in compressed_chunks_res {
-                match res {
-                    Ok(data) => {
-                        if out_idx + data.len() > output.len() {
-                            return (CompressResult::InsufficientSpace, 0, 0);
+                                ),
+                                sum,
+                            ))
                         }
-                        unsafe {
-                            std::ptr::copy_nonoverlapping(
-                                data.as_ptr(),
-                                output.as_mut_ptr().add(out_idx) as *mut u8,
-                                data.len(),
-                            );
-                        }
-                        out_idx += data.len();
+                    } else {
+                        Err(io::Error::other("Compression failed"))
+                    }
+                },
+            )
+            .collect();
+
+        let mut out_idx = 0;
+        let mut total_sum = checksum.map_or(0, |c| c.compute(&[], false));
+        for (res, chunk) in compressed_chunks_res.into_iter().zip(&chunks) {
+            match res {
+                Ok((data, sum)) => {
+                    if let Some(checksum) = checksum {
+                        total_sum = checksum.combine(total_sum, sum, chunk.len());
                     }
-                    Err(_) => return (CompressResult::InsufficientSpace, 0, 0),
+                    if out_idx + data.len() > output.len() {
+                        return (CompressResult::InsufficientSpace, 0, 0);
+                    }
+                    unsafe {
+                        core::ptr::copy_nonoverlapping(
+                            data.as_ptr(),
+                            output.as_mut_ptr().add(out_idx) as *mut u8,
+                            data.len(),
+                        );
+                    }
+                    out_idx += data.len();
                 }
+                Err(_) => return (CompressResult::InsufficientSpace, 0, 0),
             }
-            return (CompressResult::Success, out_idx, 0);
         }
+        (CompressResult::Success, out_idx, total_sum)
+    }
 
-        if self.compression_level == 0 {
-            return self.compress_uncompressed(input, output, flush_mode);
+    /// Compresses `input` in the same chunks as
+    /// [`compress_chunks_parallel`](Self::compress_chunks_parallel), one after another
+    /// on the calling thread.
+    fn compress_chunks(
+        &mut self,
+        dictionary: &[u8],
+        input: &[u8],
+        output: &mut [MaybeUninit<u8>],
+        flush_mode: FlushMode,
+    ) -> (CompressResult, usize, u32) {
+        let num_chunks = input.len().div_ceil(PARALLEL_CHUNK_SIZE);
+        let mut out_idx = 0;
+        for (i, chunk) in input.chunks(PARALLEL_CHUNK_SIZE).enumerate() {
+            let mode = if i == num_chunks - 1 {
+                flush_mode
+            } else {
+                FlushMode::Sync
+            };
+            let pos = i * PARALLEL_CHUNK_SIZE;
+            let (res, size, _) = self.compress_chunk(
+                dictionary,
+                input,
+                pos..pos + chunk.len(),
+                self.chunk_history,
+                &mut output[out_idx..],
+                mode,
+            );
+            if res != CompressResult::Success {
+                return (res, 0, 0);
+            }
+            out_idx += size;
         }
+        (CompressResult::Success, out_idx, 0)
+    }
 
+    fn compress_primed(
+        &mut self,
+        input: &[u8],
+        start: usize,
+        output: &mut [MaybeUninit<u8>],
+        flush_mode: FlushMode,
+    ) -> (CompressResult, usize, u32) {
         let mut bs = Bitstream::new(output);
+        self.compress_window(input, start, 0, 0, &mut bs, flush_mode)
+    }
 
+    /// Compresses `input[start..]` after the history `input[..start]`, of which the
+    /// match finder already holds the first `indexed` bytes. Afterwards it holds the
+    /// last `keep` bytes of `input` as the start of the next call's input.
+    fn compress_window(
+        &mut self,
+        input: &[u8],
+        start: usize,
+        indexed: usize,
+        keep: usize,
+        bs: &mut Bitstream,
+        flush_mode: FlushMode,
+    ) -> (CompressResult, usize, u32) {
         let mut mf_enum = match self.mf.take() {
             Some(mf) => mf,
             None => return (CompressResult::InternalError, 0, 0),
         };
 
-        let res = match &mut mf_enum {
-            MatchFinderEnum::Chain(mf) => self.compress_loop(mf, input, &mut bs, flush_mode),
-            MatchFinderEnum::Table(mf) => self.compress_loop(mf, input, &mut bs, flush_mode),
-            MatchFinderEnum::Bt(mf) => self.compress_loop(mf, input, &mut bs, flush_mode),
-        };
+        let strategy = self.options.strategy();
+        let res = with_match_finder!(&mut mf_enum, strategy, |mf| {
+            mf.prepare(input.len());
+            for pos in indexed..start {
+                MatchFinderTrait::skip_match(
+                    mf,
+                    input,
+                    pos,
+                    self.max_search_depth,
+                    self.nice_match_length,
+                );
+            }
+            let res = self.compress_loop(mf, input, start, bs, flush_mode);
+            mf.advance(input.len() - keep);
+            res
+        });
 
         self.mf = Some(mf_enum);
         res
     }
 
+    /// Makes the match finder forget all positions, e.g. before it is given a window
+    /// unrelated to the one it holds.
+    fn reset_match_finder(&mut self) {
+        match &mut self.mf {
+            Some(MatchFinderEnum::Chain(mf)) => mf.reset(),
+            Some(MatchFinderEnum::Table(mf)) => mf.reset(),
+            Some(MatchFinderEnum::Bt(mf)) => mf.reset(),
+            None => {}
+        }
+    }
+
     fn compress_to_size_loop<T: MatchFinderTrait>(
         &mut self,
         mf: &mut T,
@@ -765,7 +1068,7 @@ impl Compressor {
         mf.prepare(input.len());
 
         while in_idx < input.len() {
-            let (processed, bits) = if self.compression_level < 2 {
+            let (processed, bits) = if self.uses_fast_path() {
                 self.calculate_block_size_fast(mf, input, in_idx)
             } else if self.compression_level >= 10 {
                 self.calculate_block_size_near_optimal(mf, input, in_idx)
@@ -799,14 +1102,11 @@ impl Compressor {
         input: &[u8],
         in_idx: usize,
     ) -> (usize, usize) {
-        let lazy_depth = if self.compression_level >= 8 {
-            2
-        } else if self.compression_level >= 5 {
-            1
-        } else {
-            0
-        };
-        let processed = self.decide_greedy_sequences(mf, input, in_idx, lazy_depth);
+        let processed = self.decide_greedy_sequences(mf, input, in_idx, self.options.lazy_depth());
+        if self.options.strategy() == Strategy::Fixed {
+            self.load_static_huffman_codes();
+            return (processed, 3 + self.calculate_block_data_size());
+        }
 
         make_huffman_code(
             DEFLATE_NUM_LITLEN_SYMS,
@@ -915,114 +1215,16 @@ impl Compressor {
             &mut self.offset_codewords,
         );
 
-        self.update_costs();
-
-        self.dp_costs.clear();
-        self.dp_costs.resize(processed + 1, 0x3FFFFFFF);
-        self.dp_costs[0] = 0;
-
-        self.dp_path.clear();
-        self.dp_path.resize(processed + 1, 0);
-
-        mf.reset();
-        let mut pos = 0;
-        while pos < processed {
-            let cur_cost = self.dp_costs[pos];
-            if cur_cost >= 0x3FFFFFFF {
-                pos += 1;
-                continue;
-            }
-
-            let lit_cost = self.litlen_lens[block_input[pos] as usize] as u32;
-            if cur_cost + lit_cost < self.dp_costs[pos + 1] {
-                self.dp_costs[pos + 1] = cur_cost + lit_cost;
-                self.dp_path[pos + 1] = 1_u32;
-            }
-
-            mf.find_matches(
-                block_input,
-                pos,
-                self.max_search_depth,
-                self.nice_match_length,
-                &mut self.matches,
-            );
-            let mut best_len = 0;
-            for &(len, offset) in &self.matches {
-                let len = len as usize;
-                if len > best_len {
-                    best_len = len;
-                }
-                let cost = self.get_match_cost(len, offset as usize);
-                if cur_cost + cost < self.dp_costs[pos + len] {
-                    self.dp_costs[pos + len] = cur_cost + cost;
-                    self.dp_path[pos + len] = (len as u32) | ((offset as u32) << 16);
-                }
-            }
-
-            if best_len >= self.nice_match_length {
-                let skip = best_len;
-                mf.skip_positions(
-                    block_input,
-                    pos + 1,
-                    skip - 1,
-                    self.max_search_depth,
-                    self.nice_match_length,
-                );
-                pos += skip;
-            } else {
-                pos += 1;
-            }
+        if self.options.strategy() == Strategy::Fixed {
+            self.load_static_huffman_codes();
         }
-
-        self.sequences.clear();
-        self.litlen_freqs.fill(0);
-        self.offset_freqs.fill(0);
-        self.litlen_freqs[256] = 1;
-
-        let mut pos = processed;
-        self.path_nodes.clear();
-        while pos > 0 {
-            let packed = self.dp_path[pos];
-            let length = (packed & 0xFFFF) as u16;
-            let offset = (packed >> 16) as u16;
-            self.path_nodes.push((length, offset));
-            pos -= length as usize;
+        for _ in 0..self.options.near_optimal_passes() {
+            self.near_optimal_pass(mf, block_input, 0, processed);
         }
 
-        let mut litrunlen = 0;
-        let mut cur_pos = 0;
-        for &(length, offset) in self.path_nodes.iter().rev() {
-            if length == 1 {
-                self.litlen_freqs[block_input[cur_pos] as usize] += 1;
-                litrunlen += 1;
-                cur_pos += 1;
-            } else {
-                let off_slot = self.get_offset_slot(offset as usize);
-                self.sequences
-                    .push(Sequence::new(litrunlen, length, offset, off_slot as u8));
-                self.litlen_freqs[257 + self.get_length_slot(length as usize)] += 1;
-                self.offset_freqs[off_slot] += 1;
-                litrunlen = 0;
-                cur_pos += length as usize;
-            }
+        if self.options.strategy() == Strategy::Fixed {
+            return (processed, 3 + self.calculate_block_data_size());
         }
-        self.sequences.push(Sequence::new(litrunlen, 0, 0, 0));
-
-        make_huffman_code(
-            DEFLATE_NUM_LITLEN_SYMS,
-            MAX_LITLEN_CODEWORD_LEN,
-            &self.litlen_freqs,
-            &mut self.litlen_lens,
-            &mut self.litlen_codewords,
-        );
-        make_huffman_code(
-            DEFLATE_NUM_OFFSET_SYMS,
-            MAX_OFFSET_CODEWORD_LEN,
-            &self.offset_freqs,
-            &mut self.offset_lens,
-            &mut self.offset_codewords,
-        );
-
         let bits = 3 + self.calculate_dynamic_header_size() + self.calculate_block_data_size();
         (processed, bits)
     }
@@ -1043,11 +1245,10 @@ impl Compressor {
             None => return (CompressResult::InternalError, 0),
         };
 
-        let res = match &mut mf_enum {
-            MatchFinderEnum::Chain(mf) => self.compress_to_size_loop(mf, input, final_block),
-            MatchFinderEnum::Table(mf) => self.compress_to_size_loop(mf, input, final_block),
-            MatchFinderEnum::Bt(mf) => self.compress_to_size_loop(mf, input, final_block),
-        };
+        let strategy = self.options.strategy();
+        let res = with_match_finder!(&mut mf_enum, strategy, |mf| {
+            self.compress_to_size_loop(mf, input, final_block)
+        });
 
         self.mf = Some(mf_enum);
         (CompressResult::Success, res)
@@ -1381,12 +1582,12 @@ impl Compressor {
             let len = 0u16;
             let nlen = !len;
             unsafe {
-                std::ptr::copy_nonoverlapping(
+                core::ptr::copy_nonoverlapping(
                     len.to_le_bytes().as_ptr(),
                     bs.output.as_mut_ptr().add(bs.out_idx) as *mut u8,
                     2,
                 );
-                std::ptr::copy_nonoverlapping(
+                core::ptr::copy_nonoverlapping(
                     nlen.to_le_bytes().as_ptr(),
                     bs.output.as_mut_ptr().add(bs.out_idx + 2) as *mut u8,
                     2,
@@ -1416,12 +1617,12 @@ impl Compressor {
             let len = block_len as u16;
             let nlen = !len;
             unsafe {
-                std::ptr::copy_nonoverlapping(
+                core::ptr::copy_nonoverlapping(
                     len.to_le_bytes().as_ptr(),
                     bs.output.as_mut_ptr().add(bs.out_idx) as *mut u8,
                     2,
                 );
-                std::ptr::copy_nonoverlapping(
+                core::ptr::copy_nonoverlapping(
                     nlen.to_le_bytes().as_ptr(),
                     bs.output.as_mut_ptr().add(bs.out_idx + 2) as *mut u8,
                     2,
@@ -1429,7 +1630,7 @@ impl Compressor {
             }
             bs.out_idx += 4;
             unsafe {
-                std::ptr::copy_nonoverlapping(
+                core::ptr::copy_nonoverlapping(
                     input.as_ptr().add(curr_pos),
                     bs.output.as_mut_ptr().add(bs.out_idx) as *mut u8,
                     block_len,
@@ -1458,7 +1659,13 @@ impl Compressor {
             }
             in_idx += block_len;
         }
-        if flush_mode == FlushMode::Sync {
+        if input.is_empty()
+            && flush_mode == FlushMode::Finish
+            && !self.write_uncompressed_block_impl(input, 0, 0, &mut bs, true)
+        {
+            return (CompressResult::InsufficientSpace, 0, 0);
+        }
+        if flush_mode.writes_marker() {
             if bs.out_idx + 5 > bs.output.len() {
                 return (CompressResult::InsufficientSpace, 0, 0);
             }
@@ -1483,9 +1690,12 @@ impl Compressor {
         lazy_depth: u32,
         final_block: bool,
     ) -> usize {
-        if self.compression_level >= 2 {
+        if !self.uses_fast_path() {
             let processed = self.decide_greedy_sequences(mf, input, start_pos, lazy_depth);
             let is_final = (start_pos + processed >= input.len()) && final_block;
+            if self.options.strategy() == Strategy::Fixed {
+                return self.write_static_block(input, start_pos, processed, bs, is_final);
+            }
             make_huffman_code(
                 DEFLATE_NUM_LITLEN_SYMS,
                 MAX_LITLEN_CODEWORD_LEN,
@@ -1650,6 +1860,7 @@ impl Compressor {
         mf: &mut T,
         input: &[u8],
         start_pos: usize,
+        history: usize,
         bs: &mut Bitstream,
         final_block: bool,
     ) -> usize {
@@ -1689,7 +1900,8 @@ impl Compressor {
         }
 
         let processed = in_idx - start_pos;
-        let block_input = &input[start_pos..start_pos + processed];
+        let history = min(history, DEFLATE_MAX_MATCH_OFFSET);
+        let window = &input[start_pos - history..start_pos + processed];
         let is_final = (start_pos + processed >= input.len()) && final_block;
 
         self.sequences.clear();
@@ -1710,6 +1922,34 @@ impl Compressor {
             &mut self.offset_codewords,
         );
 
+        if self.options.strategy() == Strategy::Fixed {
+            self.load_static_huffman_codes();
+        }
+        for _ in 0..self.options.near_optimal_passes() {
+            self.near_optimal_pass(mf, window, history, processed);
+        }
+        if self.options.strategy() == Strategy::Fixed {
+            return self.write_static_block(input, start_pos, processed, bs, is_final);
+        }
+        self.update_huffman_tables();
+
+        if !self.write_dynamic_block_with_sequences(input, start_pos, bs, is_final) {
+            return 0;
+        }
+        processed
+    }
+
+    /// Prices matches with the current Huffman codes, finds the cheapest parse of
+    /// `window[history..history + processed]`, and rebuilds the sequences and codes
+    /// from it.
+    fn near_optimal_pass<T: MatchFinderTrait>(
+        &mut self,
+        mf: &mut T,
+        window: &[u8],
+        history: usize,
+        processed: usize,
+    ) {
+        let block_input = &window[history..];
         self.update_costs();
 
         self.dp_costs.clear();
@@ -1720,6 +1960,9 @@ impl Compressor {
         self.dp_path.resize(processed + 1, 0);
 
         mf.reset();
+        for pos in 0..history {
+            mf.skip_match(window, pos, self.max_search_depth, self.nice_match_length);
+        }
         let mut pos = 0;
         while pos < processed {
             let cur_cost = self.dp_costs[pos];
@@ -1728,15 +1971,15 @@ impl Compressor {
                 continue;
             }
 
-            let lit_cost = self.litlen_lens[block_input[pos] as usize] as u32;
+            let lit_cost = self.literal_costs[block_input[pos] as usize];
             if cur_cost + lit_cost < self.dp_costs[pos + 1] {
                 self.dp_costs[pos + 1] = cur_cost + lit_cost;
                 self.dp_path[pos + 1] = 1_u32;
             }
 
             mf.find_matches(
-                block_input,
-                pos,
+                window,
+                history + pos,
                 self.max_search_depth,
                 self.nice_match_length,
                 &mut self.matches,
@@ -1759,8 +2002,8 @@ impl Compressor {
             if best_len >= self.nice_match_length {
                 let skip = best_len;
                 mf.skip_positions(
-                    block_input,
-                    pos + 1,
+                    window,
+                    history + pos + 1,
                     skip - 1,
                     self.max_search_depth,
                     self.nice_match_length,
@@ -1805,6 +2048,9 @@ impl Compressor {
         }
         self.sequences.push(Sequence::new(litrunlen, 0, 0, 0));
 
+        if self.options.strategy() == Strategy::Fixed {
+            return;
+        }
         make_huffman_code(
             DEFLATE_NUM_LITLEN_SYMS,
             MAX_LITLEN_CODEWORD_LEN,
@@ -1819,9 +2065,32 @@ impl Compressor {
             &mut self.offset_lens,
             &mut self.offset_codewords,
         );
-        self.update_huffman_tables();
+    }
 
-        if !self.write_dynamic_block_with_sequences(input, start_pos, bs, is_final) {
+    /// Writes the sequences of a block as a static Huffman block, or as stored blocks
+    /// if those are smaller. Returns `processed`, or 0 if the output is full.
+    fn write_static_block(
+        &mut self,
+        input: &[u8],
+        start_pos: usize,
+        processed: usize,
+        bs: &mut Bitstream,
+        is_final: bool,
+    ) -> usize {
+        self.load_static_huffman_codes();
+        let static_cost = 3 + self.calculate_block_data_size();
+        let uncompressed_cost = (processed * 8) + (processed / 65535 + 1) * 40 + 7;
+        if static_cost > uncompressed_cost {
+            if !self.write_uncompressed_block_impl(input, start_pos, processed, bs, is_final) {
+                return 0;
+            }
+            return processed;
+        }
+        if !bs.write_bits(if is_final { 1 } else { 0 }, 1)
+            || !bs.write_bits(1, 2)
+            || !self.write_sequences_to_bitstream(bs, input, start_pos)
+            || !self.write_sym(bs, 256)
+        {
             return 0;
         }
         processed
@@ -1938,8 +2207,7 @@ impl Compressor {
     }
 
     fn load_static_huffman_codes(&mut self) {
-        static STATIC_TABLES: OnceLock<StaticTables> = OnceLock::new();
-        let tables = STATIC_TABLES.get_or_init(compute_static_tables);
+        let tables = &STATIC_TABLES;
 
         self.litlen_lens.copy_from_slice(&tables.litlen_lens);
         self.offset_lens.copy_from_slice(&tables.offset_lens);
@@ -2029,7 +2297,7 @@ impl Compressor {
                             if new_bitcount >= 64 {
                                 let low = bitbuf | (code << bitcount);
                                 let high = code >> (64 - bitcount);
-                                std::ptr::write_unaligned(
+                                core::ptr::write_unaligned(
                                     out_ptr.add(out_idx) as *mut u64,
                                     low.to_le(),
                                 );
@@ -2039,7 +2307,7 @@ impl Compressor {
                             } else {
                                 bitbuf |= code << bitcount;
                                 if new_bitcount >= 32 {
-                                    std::ptr::write_unaligned(
+                                    core::ptr::write_unaligned(
                                         out_ptr.add(out_idx) as *mut u32,
                                         (bitbuf as u32).to_le(),
                                     );
@@ -2063,7 +2331,7 @@ impl Compressor {
                             let new_bitcount = bitcount + len;
                             if new_bitcount >= 32 {
                                 let buf = bitbuf | (code << bitcount);
-                                std::ptr::write_unaligned(
+                                core::ptr::write_unaligned(
                                     out_ptr.add(out_idx) as *mut u32,
                                     (buf as u32).to_le(),
                                 );
@@ -2084,7 +2352,7 @@ impl Compressor {
                             let new_bitcount = bitcount + len;
                             if new_bitcount >= 32 {
                                 let buf = bitbuf | (code << bitcount);
-                                std::ptr::write_unaligned(
+                                core::ptr::write_unaligned(
                                     out_ptr.add(out_idx) as *mut u32,
                                     (buf as u32).to_le(),
                                 );
@@ -2139,7 +2407,7 @@ impl Compressor {
                             let new_bitcount = bitcount + combined_len;
                             if new_bitcount >= 32 {
                                 let buf = bitbuf | ((code as u64) << bitcount);
-                                std::ptr::write_unaligned(
+                                core::ptr::write_unaligned(
                                     out_ptr.add(out_idx) as *mut u32,
                                     (buf as u32).to_le(),
                                 );
@@ -2154,7 +2422,7 @@ impl Compressor {
                             let new_bitcount = bitcount + len_len;
                             if new_bitcount >= 32 {
                                 let buf = bitbuf | ((len_val as u64) << bitcount);
-                                std::ptr::write_unaligned(
+                                core::ptr::write_unaligned(
                                     out_ptr.add(out_idx) as *mut u32,
                                     (buf as u32).to_le(),
                                 );
@@ -2169,7 +2437,7 @@ impl Compressor {
                             let new_bitcount = bitcount + off_len_total;
                             if new_bitcount >= 32 {
                                 let buf = bitbuf | ((off_val as u64) << bitcount);
-                                std::ptr::write_unaligned(
+                                core::ptr::write_unaligned(
                                     out_ptr.add(out_idx) as *mut u32,
                                     (buf as u32).to_le(),
                                 );
@@ -2251,19 +2519,36 @@ impl Compressor {
     }
 
     fn update_costs(&mut self) {
+        for i in 0..256 {
+            let lit_len = self.litlen_lens[i] as u32;
+            self.literal_costs[i] = if lit_len != 0 {
+                lit_len
+            } else {
+                LITERAL_NOSTAT_BITS
+            };
+        }
         for len in 3..=DEFLATE_MAX_MATCH_LEN {
             let len_info = unsafe { *LENGTH_WRITE_TABLE.get_unchecked(len) };
             let len_slot = (len_info >> 24) as usize;
             let len_extra_bits = (len_info >> 16) & 0xFF;
 
-            let len_cost =
-                unsafe { *self.litlen_lens.get_unchecked(257 + len_slot) } as u32 + len_extra_bits;
-            unsafe { *self.length_costs.get_unchecked_mut(len) = len_cost };
+            let sym_len = unsafe { *self.litlen_lens.get_unchecked(257 + len_slot) } as u32;
+            let sym_cost = if sym_len != 0 {
+                sym_len
+            } else {
+                LENGTH_NOSTAT_BITS
+            };
+            unsafe { *self.length_costs.get_unchecked_mut(len) = sym_cost + len_extra_bits };
         }
         for slot in 0..30 {
             let extra_bits = unsafe { *OFFSET_EXTRA_BITS_TABLE.get_unchecked(slot) } as u32;
-            let off_cost = unsafe { *self.offset_lens.get_unchecked(slot) } as u32 + extra_bits;
-            unsafe { *self.offset_slot_costs.get_unchecked_mut(slot) = off_cost };
+            let sym_len = unsafe { *self.offset_lens.get_unchecked(slot) } as u32;
+            let sym_cost = if sym_len != 0 {
+                sym_len
+            } else {
+                OFFSET_NOSTAT_BITS
+            };
+            unsafe { *self.offset_slot_costs.get_unchecked_mut(slot) = sym_cost + extra_bits };
         }
     }
 
@@ -2296,7 +2581,12 @@ impl Compressor {
         input: &[u8],
         output: &mut [MaybeUninit<u8>],
     ) -> (CompressResult, usize) {
-        if output.len() < ZLIB_MIN_OVERHEAD {
+        let dictid_size = if self.dictionary_id.is_some() {
+            ZLIB_DICTID_SIZE
+        } else {
+            0
+        };
+        if output.len() < ZLIB_MIN_OVERHEAD + dictid_size {
             return (CompressResult::InsufficientSpace, 0);
         }
         let mut out_idx = 0;
@@ -2312,28 +2602,44 @@ impl Compressor {
             ZLIB_SLOWEST_COMPRESSION
         };
         hdr |= (level_hint as u16) << 6;
+        if self.dictionary_id.is_some() {
+            hdr |= ZLIB_FDICT;
+        }
         hdr |= 31 - (hdr % 31);
         unsafe {
-            std::ptr::copy_nonoverlapping(
+            core::ptr::copy_nonoverlapping(
                 hdr.to_be_bytes().as_ptr(),
                 output.as_mut_ptr().add(0) as *mut u8,
                 2,
             );
         }
         out_idx += 2;
+        if let Some(dictid) = self.dictionary_id {
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    dictid.to_be_bytes().as_ptr(),
+                    output.as_mut_ptr().add(out_idx) as *mut u8,
+                    4,
+                );
+            }
+            out_idx += ZLIB_DICTID_SIZE;
+        }
         let out_len = output.len();
-        let (res, deflate_size, _) = self.compress(
+        let dictionary = core::mem::take(&mut self.dictionary);
+        let (res, deflate_size, adler) = self.compress_checksummed(
+            &dictionary,
             input,
             &mut output[out_idx..out_len - ZLIB_FOOTER_SIZE],
             FlushMode::Finish,
+            Checksum::Adler32,
         );
+        self.dictionary = dictionary;
         if res != CompressResult::Success {
             return (res, 0);
         }
         out_idx += deflate_size;
-        let adler = crate::adler32::adler32(1, input);
         unsafe {
-            std::ptr::copy_nonoverlapping(
+            core::ptr::copy_nonoverlapping(
                 adler.to_be_bytes().as_ptr(),
                 output.as_mut_ptr().add(out_idx) as *mut u8,
                 4,
@@ -2348,43 +2654,35 @@ impl Compressor {
         input: &[u8],
         output: &mut [MaybeUninit<u8>],
     ) -> (CompressResult, usize) {
-        if output.len() < GZIP_MIN_OVERHEAD {
+        let header = match &self.gzip_header {
+            Some(header) => header.encode(self.compression_level),
+            None => GzipHeader::new().encode(self.compression_level),
+        };
+        if output.len() < header.len() + GZIP_FOOTER_SIZE {
             return (CompressResult::InsufficientSpace, 0);
         }
-        let mut out_idx = 0;
-        output[0].write(GZIP_ID1);
-        output[1].write(GZIP_ID2);
-        output[2].write(GZIP_CM_DEFLATE);
-        output[3].write(0);
         unsafe {
-            std::ptr::copy_nonoverlapping(
-                GZIP_MTIME_UNAVAILABLE.to_le_bytes().as_ptr(),
-                output.as_mut_ptr().add(4) as *mut u8,
-                4,
+            core::ptr::copy_nonoverlapping(
+                header.as_ptr(),
+                output.as_mut_ptr() as *mut u8,
+                header.len(),
             );
         }
-        let mut xfl = 0u8;
-        if self.compression_level < 2 {
-            xfl |= GZIP_XFL_FASTEST_COMPRESSION;
-        } else if self.compression_level >= 8 {
-            xfl |= GZIP_XFL_SLOWEST_COMPRESSION;
-        }
-        output[8].write(xfl);
-        output[9].write(GZIP_OS_UNKNOWN);
-        out_idx += 10;
+        let mut out_idx = header.len();
         let out_len = output.len();
-        let (res, deflate_size, _) = self.compress(
+        let (res, deflate_size, crc) = self.compress_checksummed(
+            &[],
             input,
             &mut output[out_idx..out_len - GZIP_FOOTER_SIZE],
             FlushMode::Finish,
+            Checksum::Crc32,
         );
         if res != CompressResult::Success {
             return (res, 0);
         }
         out_idx += deflate_size;
-        let crc = crate::crc32::crc32(0, input);
         unsafe {
-            std::ptr::copy_nonoverlapping(
+            core::ptr::copy_nonoverlapping(
                 crc.to_le_bytes().as_ptr(),
                 output.as_mut_ptr().add(out_idx) as *mut u8,
                 4,
@@ -2392,7 +2690,7 @@ impl Compressor {
         }
         out_idx += 4;
         unsafe {
-            std::ptr::copy_nonoverlapping(
+            core::ptr::copy_nonoverlapping(
                 (input.len() as u32).to_le_bytes().as_ptr(),
                 output.as_mut_ptr().add(out_idx) as *mut u8,
                 4,
@@ -0,0 +1,236 @@
+use crate::common::*;
+use crate::error::{Error, Result};
+
+/// The match finder used to search the window for earlier occurrences of the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatchFinderKind {
+    /// One candidate per hash bucket. The fastest; the default for level 1.
+    HashTable,
+    /// Hash chains searched up to the search depth. The default for levels 2 to 9.
+    HashChain,
+    /// Binary trees that find every match length at a position. The default for
+    /// levels 10 to 12.
+    BinaryTree,
+}
+
+/// How matches are chosen and blocks coded, after zlib's `strategy` parameter.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Strategy {
+    /// General-purpose LZ77 with the codes that the level selects.
+    #[default]
+    Default,
+    /// Drops matches shorter than 6 bytes, which rarely pay off on the small, noisy
+    /// values of filtered data such as PNG scanlines, so the data is mostly coded as
+    /// literals with only long matches in between. Like zlib's `Z_FILTERED`.
+    Filtered,
+    /// No matches: every byte is coded as a literal. Like zlib's `Z_HUFFMAN_ONLY`.
+    HuffmanOnly,
+    /// Only matches at distance 1, i.e. runs of the previous byte. Like zlib's `Z_RLE`.
+    Rle,
+    /// Static Huffman codes only, with no dynamic code headers. Blocks that would
+    /// expand are still stored. Like zlib's `Z_FIXED`.
+    Fixed,
+}
+
+impl Strategy {
+    /// Whether the strategy restricts the matches the match finder reports.
+    pub(crate) fn filters_matches(self) -> bool {
+        matches!(
+            self,
+            Strategy::Filtered | Strategy::HuffmanOnly | Strategy::Rle
+        )
+    }
+}
+
+/// Compression parameters beyond the level, for
+/// [`Compressor::with_options`](crate::Compressor::with_options).
+///
+/// [`new`](Self::new) starts from the parameters of a level, which the `with_*` methods
+/// then override. The level still selects the parser: 0 writes stored blocks, 1 greedy
+/// parsing with static codes, 2 to 9 greedy or lazy parsing, and 10 to 12 near-optimal
+/// parsing. Options that the parser does not use are ignored, and level 0 ignores the
+/// [`Strategy`] too.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompressionOptions {
+    level: usize,
+    strategy: Strategy,
+    match_finder: MatchFinderKind,
+    max_search_depth: usize,
+    nice_match_length: usize,
+    lazy_depth: u32,
+    min_block_length: usize,
+    soft_max_block_length: usize,
+    near_optimal_passes: usize,
+}
+
+impl CompressionOptions {
+    /// The parameters used by `level`, which must be in `0..=12`.
+    pub fn new(level: i32) -> Result<Self> {
+        if !(0..=12).contains(&level) {
+            return Err(Error::InvalidLevel(level));
+        }
+        Ok(Self::for_level(level as usize))
+    }
+
+    pub(crate) fn for_level(level: usize) -> Self {
+        let (max_search_depth, nice_match_length) = match level {
+            0 => (0, 0),
+            1 => (2, 32),
+            2 => (6, 10),
+            3 => (12, 14),
+            4 | 5 => (16, 30),
+            6 => (35, 65),
+            7 => (100, 130),
+            8 => (300, 258),
+            9 => (600, 258),
+            10 => (35, 75),
+            11 => (100, 150),
+            _ => (300, 258),
+        };
+        Self {
+            level,
+            strategy: Strategy::Default,
+            match_finder: if level == 1 {
+                MatchFinderKind::HashTable
+            } else if level >= 10 {
+                MatchFinderKind::BinaryTree
+            } else {
+                MatchFinderKind::HashChain
+            },
+            max_search_depth,
+            nice_match_length,
+            lazy_depth: if level >= 8 {
+                2
+            } else if level >= 5 {
+                1
+            } else {
+                0
+            },
+            min_block_length: MIN_BLOCK_LENGTH,
+            soft_max_block_length: SOFT_MAX_BLOCK_LENGTH,
+            near_optimal_passes: 1,
+        }
+    }
+
+    pub fn with_strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    pub fn with_match_finder(mut self, match_finder: MatchFinderKind) -> Self {
+        self.match_finder = match_finder;
+        self
+    }
+
+    /// Sets how many candidates the match finder examines per position. The hash table
+    /// match finder examines one regardless.
+    pub fn with_max_search_depth(mut self, depth: usize) -> Self {
+        self.max_search_depth = depth;
+        self
+    }
+
+    /// Sets the match length at which the search stops early and, for near-optimal
+    /// parsing, the match is taken without considering the positions it covers.
+    pub fn with_nice_match_length(mut self, length: usize) -> Self {
+        self.nice_match_length = length;
+        self
+    }
+
+    /// Sets how many following positions greedy parsing checks for a longer match
+    /// before taking one: 0 is greedy, 1 lazy and 2 doubly lazy. Used by levels 2 to 9.
+    pub fn with_lazy_depth(mut self, depth: u32) -> Self {
+        self.lazy_depth = depth;
+        self
+    }
+
+    /// Sets the length a block must reach before the compressor considers ending it
+    /// because the statistics of the data changed.
+    pub fn with_min_block_length(mut self, length: usize) -> Self {
+        self.min_block_length = length;
+        self
+    }
+
+    /// Sets the length at which a block is ended regardless of the data. At most
+    /// 16 MiB.
+    pub fn with_soft_max_block_length(mut self, length: usize) -> Self {
+        self.soft_max_block_length = length;
+        self
+    }
+
+    /// Sets how many times near-optimal parsing re-prices matches with the codes from
+    /// its previous pass. Used by levels 10 to 12.
+    pub fn with_near_optimal_passes(mut self, passes: usize) -> Self {
+        self.near_optimal_passes = passes;
+        self
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    pub fn match_finder(&self) -> MatchFinderKind {
+        self.match_finder
+    }
+
+    pub fn max_search_depth(&self) -> usize {
+        self.max_search_depth
+    }
+
+    pub fn nice_match_length(&self) -> usize {
+        self.nice_match_length
+    }
+
+    pub fn lazy_depth(&self) -> u32 {
+        self.lazy_depth
+    }
+
+    pub fn min_block_length(&self) -> usize {
+        self.min_block_length
+    }
+
+    pub fn soft_max_block_length(&self) -> usize {
+        self.soft_max_block_length
+    }
+
+    pub fn near_optimal_passes(&self) -> usize {
+        self.near_optimal_passes
+    }
+
+    /// Checks that the parameters are usable. Level 0 ignores all of them.
+    pub fn validate(&self) -> Result<()> {
+        if self.level == 0 {
+            return Ok(());
+        }
+        if self.max_search_depth == 0 {
+            return Err(Error::InvalidParameter("search depth must be at least 1"));
+        }
+        if !(DEFLATE_MIN_MATCH_LEN..=DEFLATE_MAX_MATCH_LEN).contains(&self.nice_match_length) {
+            return Err(Error::InvalidParameter(
+                "nice match length must be between 3 and 258",
+            ));
+        }
+        if self.lazy_depth > 2 {
+            return Err(Error::InvalidParameter("lazy depth must be at most 2"));
+        }
+        if self.min_block_length == 0 || self.min_block_length > self.soft_max_block_length {
+            return Err(Error::InvalidParameter(
+                "block lengths must satisfy 1 <= min <= soft max",
+            ));
+        }
+        if self.soft_max_block_length > MAX_SOFT_MAX_BLOCK_LENGTH {
+            return Err(Error::InvalidParameter(
+                "soft max block length must be at most 16 MiB",
+            ));
+        }
+        if self.near_optimal_passes == 0 {
+            return Err(Error::InvalidParameter(
+                "near-optimal passes must be at least 1",
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+use super::bitstream::Bitstream;
+use super::{CompressResult, CompressionOptions, Compressor, FlushMode, PARALLEL_CHUNK_SIZE};
+use crate::common::*;
+use alloc::vec::Vec;
+use core::cmp::min;
+
+/// Compresses one DEFLATE stream handed over in pieces, as zlib's `deflate` does.
+///
+/// The last 32 KiB of input and the match finder state over them carry over from one
+/// call to the next, so matches reach back into earlier calls and nothing is indexed
+/// twice. [`FlushMode::None`] may end the output in the middle of a byte; those bits
+/// are held back and completed by the next call. [`FlushMode::Sync`] ends the output
+/// with an empty stored block (`00 00 FF FF`) so that everything so far can be
+/// decoded, but keeps the window; [`FlushMode::Full`] also drops it. [`FlushMode::Finish`]
+/// ends the stream, and the next call starts a new one.
+pub struct StreamCompressor {
+    compressor: Compressor,
+    /// The history, followed during a call by the input being compressed.
+    window: Vec<u8>,
+    /// How many leading bytes of `window` the match finder holds.
+    indexed: usize,
+    /// The incomplete last byte held back after [`FlushMode::None`].
+    bitbuf: u64,
+    bitcount: u32,
+}
+
+impl StreamCompressor {
+    pub fn new(level: usize) -> Self {
+        Self::with_options(CompressionOptions::for_level(level))
+    }
+
+    /// See [`Compressor::with_options`].
+    pub fn with_options(options: CompressionOptions) -> Self {
+        Self {
+            compressor: Compressor::with_options(options),
+            window: Vec::with_capacity(DEFLATE_MAX_MATCH_OFFSET),
+            indexed: 0,
+            bitbuf: 0,
+            bitcount: 0,
+        }
+    }
+
+    pub fn options(&self) -> &CompressionOptions {
+        self.compressor.options()
+    }
+
+    /// The input the next call may reference: up to the last 32 KiB given so far in
+    /// the current stream, or what [`set_history`](Self::set_history) set.
+    pub fn history(&self) -> &[u8] {
+        &self.window
+    }
+
+    /// Replaces the history, e.g. with a preset dictionary before the first call, or
+    /// after data in between was compressed by other means. The decompressor must
+    /// have the same bytes as the last ones it produced.
+    pub fn set_history(&mut self, history: &[u8]) {
+        let keep = min(history.len(), DEFLATE_MAX_MATCH_OFFSET);
+        self.window.clear();
+        self.window
+            .extend_from_slice(&history[history.len() - keep..]);
+        self.indexed = 0;
+    }
+
+    /// Whether the output so far ends on a byte boundary, so that other DEFLATE data
+    /// may follow it. Only [`FlushMode::None`] leaves it unaligned.
+    pub fn is_byte_aligned(&self) -> bool {
+        self.bitcount == 0
+    }
+
+    /// Compresses `input` and appends the output to `output`. Inputs larger than
+    /// [`PARALLEL_CHUNK_SIZE`] are compressed in pieces of that size, with no marker
+    /// between them.
+    pub fn compress(
+        &mut self,
+        input: &[u8],
+        output: &mut Vec<u8>,
+        flush_mode: FlushMode,
+    ) -> CompressResult {
+        let mut chunks = input.chunks(PARALLEL_CHUNK_SIZE).peekable();
+        loop {
+            let chunk = chunks.next().unwrap_or(&[]);
+            let is_last = chunks.peek().is_none();
+            let mode = if is_last { flush_mode } else { FlushMode::None };
+            let res = self.compress_chunk(chunk, output, mode);
+            if res != CompressResult::Success || is_last {
+                return res;
+            }
+        }
+    }
+
+    fn compress_chunk(
+        &mut self,
+        input: &[u8],
+        output: &mut Vec<u8>,
+        flush_mode: FlushMode,
+    ) -> CompressResult {
+        // The near-optimal parser rebuilds the match finder for every block, so what
+        // it holds afterwards does not line up with the window.
+        if self.compressor.compression_level >= 10 {
+            self.indexed = 0;
+        }
+        if self.indexed == 0 {
+            self.compressor.reset_match_finder();
+        }
+
+        let start = self.window.len();
+        self.window.extend_from_slice(input);
+        let keep = min(self.window.len(), DEFLATE_MAX_MATCH_OFFSET);
+
+        // Room for the held-back byte and a sync marker besides the blocks. Blocks
+        // from the near-optimal parser are never stored, and on incompressible input
+        // they can exceed the bound, so those get a second try with more room.
+        let bound = Compressor::deflate_compress_bound(input.len()) + 6;
+        let mut space = bound;
+        let out_start = output.len();
+        let valid_bits = loop {
+            if output.try_reserve(space).is_err() {
+                self.window.truncate(start);
+                return CompressResult::InsufficientSpace;
+            }
+            let spare = &mut output.spare_capacity_mut()[..space];
+            let (res, size, valid_bits) = if self.compressor.compression_level == 0 {
+                // Stored blocks always end on a byte boundary, so there are no bits
+                // held back and the match finder is not used.
+                self.compressor
+                    .compress_uncompressed(input, spare, flush_mode)
+            } else {
+                let mut bs = Bitstream::new(spare);
+                bs.bitbuf = self.bitbuf;
+                bs.bitcount = self.bitcount;
+                self.compressor.compress_window(
+                    &self.window,
+                    start,
+                    self.indexed,
+                    keep,
+                    &mut bs,
+                    flush_mode,
+                )
+            };
+            if res == CompressResult::Success {
+                assert!(size <= space);
+                unsafe {
+                    output.set_len(out_start + size);
+                }
+                break valid_bits;
+            }
+            // The match finder has moved on, so it has to be rebuilt.
+            self.indexed = 0;
+            self.compressor.reset_match_finder();
+            if res != CompressResult::InsufficientSpace || space > bound {
+                self.window.truncate(start);
+                return res;
+            }
+            space = bound * 2;
+        };
+
+        self.bitbuf = 0;
+        self.bitcount = 0;
+        if flush_mode == FlushMode::None && valid_bits != 0 {
+            self.bitbuf = output.pop().unwrap() as u64;
+            self.bitcount = valid_bits;
+        }
+
+        if matches!(flush_mode, FlushMode::Full | FlushMode::Finish) {
+            self.window.clear();
+            self.indexed = 0;
+        } else {
+            self.window.drain(..self.window.len() - keep);
+            self.indexed = keep;
+        }
+        CompressResult::Success
+    }
+}
@@ -10,7 +10,7 @@ pub unsafe fn crc32_arm(mut crc: u32, p: &[u8]) -> u32 {
     if len > 0 {
         let align = (data.as_ptr() as usize) & 7;
         if align != 0 {
-            let n = std::cmp::min(len, 8 - align);
+            let n = core::cmp::min(len, 8 - align);
             for _ in 0..n {
                 crc = __crc32b(crc, data[0]);
                 data = &data[1..];
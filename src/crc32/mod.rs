@@ -1,5 +1,8 @@
+#[cfg(target_arch = "aarch64")]
+use crate::common::aarch64_feature_detected;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crate::common::x86_feature_detected;
 use crate::crc32_tables::{CRC32_SLICE1_TABLE, CRC32_SLICE8_TABLE};
-use std::sync::OnceLock;
 
 pub fn crc32_slice1(mut crc: u32, p: &[u8]) -> u32 {
     for &b in p {
@@ -14,7 +17,7 @@ pub fn crc32_slice8(mut crc: u32, p: &[u8]) -> u32 {
     let mut ptr = p.as_ptr();
 
     while len >= 8 {
-        let v = u64::from_le(unsafe { std::ptr::read_unaligned(ptr as *const u64) });
+        let v = u64::from_le(unsafe { core::ptr::read_unaligned(ptr as *const u64) });
         let v1 = v as u32;
         let v2 = (v >> 32) as u32;
 
@@ -44,7 +47,7 @@ pub fn crc32_slice8(mut crc: u32, p: &[u8]) -> u32 {
         len -= 8;
     }
     if len >= 4 {
-        let v = u32::from_le(unsafe { std::ptr::read_unaligned(ptr as *const u32) });
+        let v = u32::from_le(unsafe { core::ptr::read_unaligned(ptr as *const u32) });
         crc ^= v;
         crc = unsafe {
             *CRC32_SLICE8_TABLE.get_unchecked(0x300 + (crc as u8) as usize)
@@ -110,42 +113,105 @@ mod x86;
 
 type Crc32Fn = unsafe fn(u32, &[u8]) -> u32;
 
-#[inline]
-pub fn crc32(crc: u32, slice: &[u8]) -> u32 {
-    static IMPL: OnceLock<Crc32Fn> = OnceLock::new();
-    let func = IMPL.get_or_init(|| {
-        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+/// Picks the fastest implementation the CPU supports.
+fn select_impl() -> Crc32Fn {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        #[cfg(target_arch = "x86_64")]
+        if x86_feature_detected!("avx512f")
+            && x86_feature_detected!("avx512bw")
+            && x86_feature_detected!("avx512vl")
+            && x86_feature_detected!("vpclmulqdq")
         {
-            #[cfg(target_arch = "x86_64")]
-            if is_x86_feature_detected!("avx512f")
-                && is_x86_feature_detected!("avx512bw")
-                && is_x86_feature_detected!("avx512vl")
-                && is_x86_feature_detected!("vpclmulqdq")
-            {
-                return x86::crc32_x86_vpclmulqdq_avx512_vl512;
-            }
+            return x86::crc32_x86_vpclmulqdq_avx512_vl512;
+        }
 
-            #[cfg(target_arch = "x86_64")]
-            if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("vpclmulqdq") {
-                return x86::crc32_x86_vpclmulqdq_avx2;
-            }
+        #[cfg(target_arch = "x86_64")]
+        if x86_feature_detected!("avx2") && x86_feature_detected!("vpclmulqdq") {
+            return x86::crc32_x86_vpclmulqdq_avx2;
+        }
 
-            if is_x86_feature_detected!("pclmulqdq") && is_x86_feature_detected!("sse4.1") {
-                return x86::crc32_x86_pclmulqdq;
-            }
+        if x86_feature_detected!("pclmulqdq") && x86_feature_detected!("sse4.1") {
+            return x86::crc32_x86_pclmulqdq;
         }
-        #[cfg(target_arch = "aarch64")]
-        {
-            if std::arch::is_aarch64_feature_detected!("crc") {
-                return arm::crc32_arm;
-            }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        if aarch64_feature_detected!("crc") {
+            return arm::crc32_arm;
         }
-        crc32_slice8
-    });
+    }
+    crc32_slice8
+}
+
+#[inline]
+pub fn crc32(crc: u32, slice: &[u8]) -> u32 {
+    #[cfg(feature = "std")]
+    let func = {
+        static IMPL: std::sync::OnceLock<Crc32Fn> = std::sync::OnceLock::new();
+        *IMPL.get_or_init(select_impl)
+    };
+    #[cfg(not(feature = "std"))]
+    let func = select_impl();
 
     unsafe { !func(!crc, slice) }
 }
 
+/// Multiplies two polynomials modulo the CRC-32 generator, both bit-reflected.
+const fn multmodp(a: u32, mut b: u32) -> u32 {
+    let mut product = 0;
+    let mut m = 1u32 << 31;
+    while m != 0 {
+        if a & m != 0 {
+            product ^= b;
+        }
+        b = if b & 1 != 0 {
+            (b >> 1) ^ 0xedb88320
+        } else {
+            b >> 1
+        };
+        m >>= 1;
+    }
+    product
+}
+
+/// x^(2^k) mod G for k = 0..32, bit-reflected like the CRC itself (bit 31 holds the
+/// coefficient of x^0), computed by squaring x. The folding constants in
+/// `crc32_tables` are single powers of x in the form carry-less multiplication
+/// needs, so they do not give this sequence. x^(2^32) mod G is x again, so the
+/// table repeats with period 32.
+const X2N_MODG: [u32; 32] = {
+    let mut table = [0; 32];
+    let mut p = 1u32 << 30;
+    let mut k = 0;
+    while k < 32 {
+        table[k] = p;
+        p = multmodp(p, p);
+        k += 1;
+    }
+    table
+};
+
+/// Returns x^(n * 2^k) mod G by squaring.
+fn x2nmodp(mut n: u64, mut k: usize) -> u32 {
+    let mut p = 1u32 << 31;
+    while n != 0 {
+        if n & 1 != 0 {
+            p = multmodp(X2N_MODG[k & 31], p);
+        }
+        n >>= 1;
+        k += 1;
+    }
+    p
+}
+
+/// Returns the CRC-32 of the concatenation `A || B`, given `crc1 = crc32(0, A)`,
+/// `crc2 = crc32(0, B)` and the length of `B`. Takes O(log len2) time, without
+/// touching the data.
+pub fn crc32_combine(crc1: u32, crc2: u32, len2: u64) -> u32 {
+    multmodp(x2nmodp(len2, 3), crc1) ^ crc2
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -184,4 +250,35 @@ mod tests {
             assert_eq!(r1_init, r8_init, "Mismatch with initial CRC at size {}", i);
         }
     }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_crc32_x86_kernels_vs_slice8() {
+        let mut kernels: Vec<(&str, Crc32Fn)> = Vec::new();
+        if is_x86_feature_detected!("pclmulqdq") && is_x86_feature_detected!("sse4.1") {
+            kernels.push(("pclmulqdq", x86::crc32_x86_pclmulqdq));
+        }
+        if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("vpclmulqdq") {
+            kernels.push(("vpclmulqdq_avx2", x86::crc32_x86_vpclmulqdq_avx2));
+        }
+        if is_x86_feature_detected!("avx512f")
+            && is_x86_feature_detected!("avx512bw")
+            && is_x86_feature_detected!("avx512vl")
+            && is_x86_feature_detected!("vpclmulqdq")
+        {
+            kernels.push(("vpclmulqdq_avx512", x86::crc32_x86_vpclmulqdq_avx512_vl512));
+        }
+
+        let data: Vec<u8> = (0..3000u32).map(|i| (i.wrapping_mul(2654435761) >> 24) as u8).collect();
+        for (name, kernel) in kernels {
+            for len in 0..2100 {
+                for offset in [0, 1, 7] {
+                    let buf = &data[offset..offset + len];
+                    let expected = crc32_slice8(0x12345678, buf);
+                    let actual = unsafe { kernel(0x12345678, buf) };
+                    assert_eq!(actual, expected, "{} mismatch at size {} offset {}", name, len, offset);
+                }
+            }
+        }
+    }
 }
@@ -268,34 +268,32 @@ pub unsafe fn crc32_x86_vpclmulqdq_avx512_vl512(crc: u32, p: &[u8]) -> u32 {
                     data = &data[256..];
                     len -= 256;
 
+                    let mults_2v = _mm512_set_epi64(
+                        CRC32_X991_MODG as i64,
+                        CRC32_X1055_MODG as i64,
+                        CRC32_X991_MODG as i64,
+                        CRC32_X1055_MODG as i64,
+                        CRC32_X991_MODG as i64,
+                        CRC32_X1055_MODG as i64,
+                        CRC32_X991_MODG as i64,
+                        CRC32_X1055_MODG as i64,
+                    );
+                    v0 = fold_vec512(v0, v2, mults_2v);
+                    v1 = fold_vec512(v1, v3, mults_2v);
+
                     if len >= 128 {
-                        let mults_2v = _mm512_set_epi64(
-                            CRC32_X991_MODG as i64,
-                            CRC32_X1055_MODG as i64,
-                            CRC32_X991_MODG as i64,
-                            CRC32_X1055_MODG as i64,
-                            CRC32_X991_MODG as i64,
-                            CRC32_X1055_MODG as i64,
-                            CRC32_X991_MODG as i64,
-                            CRC32_X1055_MODG as i64,
+                        v0 = fold_vec512(
+                            v0,
+                            _mm512_loadu_si512(data.as_ptr() as *const _),
+                            mults_2v,
+                        );
+                        v1 = fold_vec512(
+                            v1,
+                            _mm512_loadu_si512(data.as_ptr().add(64) as *const _),
+                            mults_2v,
                         );
-                        v0 = fold_vec512(v0, v2, mults_2v);
-                        v1 = fold_vec512(v1, v3, mults_2v);
-
-                        if len >= 64 {
-                            v0 = fold_vec512(
-                                v0,
-                                _mm512_loadu_si512(data.as_ptr() as *const _),
-                                mults_2v,
-                            );
-                            v1 = fold_vec512(
-                                v1,
-                                _mm512_loadu_si512(data.as_ptr().add(64) as *const _),
-                                mults_2v,
-                            );
-                            data = &data[128..];
-                            len -= 128;
-                        }
+                        data = &data[128..];
+                        len -= 128;
                     }
 
                     v0 = fold_vec512(v0, v1, mults_1v);
@@ -3,11 +3,20 @@ mod tables;
 
 use self::tables::*;
 use crate::common::*;
-use std::cmp::min;
-
+use crate::error::{Failure, Reason};
+use crate::gzip::{GzipHeader, parse_header as parse_gzip_header};
+use alloc::vec::Vec;
+use core::cmp::min;
+
+#[cfg(feature = "parallel")]
+mod parallel;
+mod streaming;
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 mod x86;
 
+#[cfg(feature = "parallel")]
+pub use self::parallel::PARALLEL_DECOMPRESS_CHUNK_SIZE;
+pub use self::streaming::{Inflater, Status};
 pub use self::tables::{
     LITLEN_ENOUGH, LITLEN_TABLEBITS, OFFSET_ENOUGH, OFFSET_TABLEBITS, PRECODE_ENOUGH,
     PRECODE_TABLEBITS,
@@ -41,6 +50,7 @@ pub enum DecompressorState {
     DynamicHeader,
     BlockBody,
     BlockBodyOffset { length: usize, extra_bits: u32 },
+    BlockBodyMatch { length: usize, offset: usize },
     UncompressedHeader,
     UncompressedBody { len: usize },
     Done,
@@ -63,6 +73,20 @@ pub struct Decompressor {
     pub bitsleft: u32,
     pub state: DecompressorState,
     pub is_final_block: bool,
+    /// Index of the block being decoded within the current stream.
+    pub block_index: usize,
+    pub(crate) failure: Option<Failure>,
+    /// Makes streaming decompression return after every block that is not the last,
+    /// with the state back at `Start`, so callers can record block boundaries.
+    pub(crate) stop_after_block: bool,
+
+    dictionary: Vec<u8>,
+    dictionary_id: Option<u32>,
+    /// Set during one-shot decompression with a dictionary: matches that reach back
+    /// past the start of the output continue into `dictionary`.
+    dictionary_window: bool,
+    verify_gzip_header_crc: bool,
+    parallel: bool,
 }
 
 struct StaticHuffmanData {
@@ -72,16 +96,74 @@ struct StaticHuffmanData {
     litlen_tablebits: usize,
 }
 
-static STATIC_HUFFMAN_DATA: std::sync::OnceLock<StaticHuffmanData> = std::sync::OnceLock::new();
+impl StaticHuffmanData {
+    /// Builds the decode tables for the fixed code of static Huffman blocks.
+    const fn new() -> Self {
+        let mut data = Self {
+            offset_decode_table: [0; OFFSET_ENOUGH],
+            litlen_decode_table: [0; LITLEN_ENOUGH],
+            lens: [0; DEFLATE_NUM_LITLEN_SYMS + DEFLATE_NUM_OFFSET_SYMS + DEFLATE_MAX_LENS_OVERRUN],
+            litlen_tablebits: LITLEN_TABLEBITS,
+        };
+        let mut i = 0;
+        while i < 144 {
+            data.lens[i] = 8;
+            i += 1;
+        }
+        while i < 256 {
+            data.lens[i] = 9;
+            i += 1;
+        }
+        while i < 280 {
+            data.lens[i] = 7;
+            i += 1;
+        }
+        while i < 288 {
+            data.lens[i] = 8;
+            i += 1;
+        }
+        while i < 288 + 32 {
+            data.lens[i] = 5;
+            i += 1;
+        }
+
+        let mut sorted_syms = [0; DEFLATE_MAX_NUM_SYMS];
+        let (litlen_lens, rest) = data.lens.split_at(288);
+        let offset_lens = rest.split_at(32).0;
+        build_decode_table(
+            &mut data.offset_decode_table,
+            offset_lens,
+            32,
+            &OFFSET_DECODE_RESULTS,
+            OFFSET_TABLEBITS,
+            DEFLATE_MAX_OFFSET_CODEWORD_LEN,
+            &mut sorted_syms,
+            None,
+        );
+        build_decode_table(
+            &mut data.litlen_decode_table,
+            litlen_lens,
+            288,
+            &LITLEN_DECODE_RESULTS,
+            LITLEN_TABLEBITS,
+            DEFLATE_MAX_LITLEN_CODEWORD_LEN,
+            &mut sorted_syms,
+            Some(&mut data.litlen_tablebits),
+        );
+        data
+    }
+}
+
+static STATIC_HUFFMAN_DATA: StaticHuffmanData = StaticHuffmanData::new();
 
 #[derive(Debug, PartialEq, Eq)]
 #[must_use = "Decompression result must be checked for errors"]
 pub enum DecompressResult {
-    Success,
-    BadData,
-    ShortOutput,
-    InsufficientSpace,
-    ShortInput,
+    Success = 0,
+    BadData = 1,
+    ShortOutput = 2,
+    InsufficientSpace = 3,
+    ShortInput = 4,
 }
 
 crate::impl_default_new!(Decompressor);
@@ -101,9 +183,70 @@ impl Decompressor {
             bitsleft: 0,
             state: DecompressorState::Start,
             is_final_block: false,
+            dictionary: Vec::new(),
+            dictionary_id: None,
+            dictionary_window: false,
+            verify_gzip_header_crc: false,
+            parallel: false,
+            block_index: 0,
+            failure: None,
+            stop_after_block: false,
         }
     }
 
+    /// Records why decoding failed and returns `BadData`. `in_idx` is the number of
+    /// input bytes loaded so far, of which `pending_bits` bits are still unconsumed.
+    pub(crate) fn fail(
+        &mut self,
+        reason: Reason,
+        in_idx: usize,
+        pending_bits: u32,
+    ) -> DecompressResult {
+        self.failure = Some(Failure {
+            reason,
+            in_idx,
+            pending_bits,
+            block_index: self.block_index,
+        });
+        DecompressResult::BadData
+    }
+
+    /// Controls whether a gzip FHCRC is checked against the header it covers. It is
+    /// skipped by default, as zlib and libdeflate do.
+    pub fn set_verify_gzip_header_crc(&mut self, verify: bool) {
+        self.verify_gzip_header_crc = verify;
+    }
+
+    /// Controls whether the checksum of one-shot zlib and gzip output larger than
+    /// [`PARALLEL_CHUNK_SIZE`](crate::compress::PARALLEL_CHUNK_SIZE) is computed on the
+    /// rayon thread pool. Off by default. Without the `parallel` feature, checksums are
+    /// always computed on the calling thread.
+    pub fn set_parallel(&mut self, enabled: bool) {
+        self.parallel = enabled;
+    }
+
+    /// Sets a preset dictionary for subsequent raw DEFLATE and zlib streams.
+    ///
+    /// Only the last 32 KiB are kept, since back-references cannot reach further.
+    /// A zlib stream with FDICT set is accepted only if its DICTID matches the
+    /// Adler-32 of the full `dictionary`. Pass an empty slice to clear it.
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) {
+        let keep = min(dictionary.len(), DEFLATE_MAX_MATCH_OFFSET);
+        self.dictionary.clear();
+        self.dictionary
+            .extend_from_slice(&dictionary[dictionary.len() - keep..]);
+        self.dictionary_id = if dictionary.is_empty() {
+            None
+        } else {
+            Some(crate::adler32::adler32(1, dictionary))
+        };
+    }
+
+    /// Returns the Adler-32 of the current preset dictionary, if one is set.
+    pub fn dictionary_id(&self) -> Option<u32> {
+        self.dictionary_id
+    }
+
     fn build_precode_decode_table(&mut self) -> bool {
         build_decode_table(
             &mut self.precode_decode_table,
@@ -150,7 +293,7 @@ impl Decompressor {
     pub unsafe fn decompress_uninit(
         &mut self,
         input: &[u8],
-        output: &mut [std::mem::MaybeUninit<u8>],
+        output: &mut [core::mem::MaybeUninit<u8>],
     ) -> (DecompressResult, usize, usize) {
         let out_ptr = output.as_mut_ptr() as *mut u8;
         let out_len = output.len();
@@ -171,11 +314,69 @@ impl Decompressor {
         out_ptr: *mut u8,
         out_len: usize,
     ) -> (DecompressResult, usize, usize) {
+        // Back-references may reach into the dictionary, which the decoders then
+        // read as the window preceding the output.
+        self.dictionary_window = !self.dictionary.is_empty();
+        let res = self.decompress_ptr_without_dictionary(input, out_ptr, out_len);
+        self.dictionary_window = false;
+        res
+    }
+
+    /// How far matches may reach back before the start of the output.
+    #[inline(always)]
+    pub(crate) fn dictionary_reach(&self) -> usize {
+        if self.dictionary_window {
+            self.dictionary.len()
+        } else {
+            0
+        }
+    }
+
+    /// Copies a match of `length` bytes to `out_ptr[dest..]` whose source starts
+    /// `offset` bytes back, before the start of the output, in the dictionary.
+    ///
+    /// # Safety
+    ///
+    /// `offset` must be in `dest + 1..=dest + self.dictionary_reach()`, and
+    /// `out_ptr[..dest + length]` must be writable with `out_ptr[..dest]` initialized.
+    #[cold]
+    pub(crate) unsafe fn copy_match_from_dictionary(
+        &self,
+        out_ptr: *mut u8,
+        dest: usize,
+        offset: usize,
+        length: usize,
+    ) {
+        let back = offset - dest;
+        let from_dict = min(back, length);
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                self.dictionary.as_ptr().add(self.dictionary.len() - back),
+                out_ptr.add(dest),
+                from_dict,
+            );
+            // The rest of the match starts at the beginning of the output, and may
+            // overlap the bytes it produces.
+            for i in from_dict..length {
+                *out_ptr.add(dest + i) = *out_ptr.add(dest + i - offset);
+            }
+        }
+    }
+
+    /// Decodes into `out_ptr[..out_len]`. Matches reach into the dictionary only while
+    /// `dictionary_window` is set.
+    fn decompress_ptr_without_dictionary(
+        &mut self,
+        input: &[u8],
+        out_ptr: *mut u8,
+        out_len: usize,
+    ) -> (DecompressResult, usize, usize) {
+        self.block_index = 0;
         #[cfg(target_arch = "x86_64")]
         {
-            if is_x86_feature_detected!("bmi2")
-                && is_x86_feature_detected!("ssse3")
-                && is_x86_feature_detected!("sse4.1")
+            if x86_feature_detected!("bmi2")
+                && x86_feature_detected!("ssse3")
+                && x86_feature_detected!("sse4.1")
             {
                 let res = unsafe { x86::decompress_bmi2_ptr(self, input, out_ptr, out_len) };
                 self.state = DecompressorState::Start;
@@ -192,7 +393,10 @@ impl Decompressor {
         self.is_final_block = false;
 
         let mut out_idx = 0;
-        let res = unsafe { self.decompress_streaming_ptr(input, out_ptr, out_len, &mut out_idx) };
+        let mut res = unsafe { self.decompress_streaming_ptr(input, out_ptr, out_len, &mut out_idx) };
+        if res.0 == DecompressResult::Success {
+            res.1 -= (self.bitsleft / 8) as usize;
+        }
         self.state = DecompressorState::Start;
         self.is_final_block = false;
         self.bitbuf = 0;
@@ -246,18 +450,29 @@ impl Decompressor {
                         DEFLATE_BLOCKTYPE_DYNAMIC_HUFFMAN => {
                             self.state = DecompressorState::DynamicHeader
                         }
-                        _ => return (DecompressResult::BadData, in_idx, *out_idx - start_out_idx),
+                        _ => {
+                            let res = self.fail(Reason::BadBlockType, in_idx, self.bitsleft + 3);
+                            return (res, in_idx, *out_idx - start_out_idx);
+                        }
                     }
                 }
                 DecompressorState::DynamicHeader => {
+                    // The header is not resumable part-way through, so rewind to its
+                    // start and wait for more input if it is cut short.
+                    let saved = (self.bitbuf, self.bitsleft, in_idx);
                     let res = self.read_dynamic_huffman_header(input, &mut in_idx);
                     if res == DecompressResult::Success {
                         self.state = DecompressorState::BlockBody;
                     } else {
+                        if res == DecompressResult::ShortInput {
+                            (self.bitbuf, self.bitsleft, in_idx) = saved;
+                        }
                         return (res, in_idx, *out_idx - start_out_idx);
                     }
                 }
-                DecompressorState::BlockBody | DecompressorState::BlockBodyOffset { .. } => {
+                DecompressorState::BlockBody
+                | DecompressorState::BlockBodyOffset { .. }
+                | DecompressorState::BlockBodyMatch { .. } => {
                     let res = unsafe {
                         self.decompress_huffman_block_ptr(
                             input,
@@ -273,42 +488,67 @@ impl Decompressor {
                             return (DecompressResult::Success, in_idx, *out_idx - start_out_idx);
                         } else {
                             self.state = DecompressorState::Start;
+                            self.block_index += 1;
+                            if self.stop_after_block {
+                                return (
+                                    DecompressResult::Success,
+                                    in_idx,
+                                    *out_idx - start_out_idx,
+                                );
+                            }
                         }
                     } else {
                         return (res, in_idx, *out_idx - start_out_idx);
                     }
                 }
                 DecompressorState::UncompressedHeader => {
+                    // Whole bytes may already sit in the bit buffer, possibly loaded
+                    // from an earlier call's input, so LEN/NLEN come from there and
+                    // the body drains it before reading `input` directly.
                     let skip = self.bitsleft & 7;
                     self.bitbuf >>= skip;
                     self.bitsleft -= skip;
-                    let unused_bytes = self.bitsleft / 8;
-                    in_idx = in_idx.saturating_sub(unused_bytes as usize);
-                    self.bitbuf = 0;
-                    self.bitsleft = 0;
-                    if in_idx + 4 > input.len() {
+                    refill_bits!(input, in_idx, self.bitbuf, self.bitsleft);
+                    if self.bitsleft < 32 {
                         return (
                             DecompressResult::ShortInput,
                             in_idx,
                             *out_idx - start_out_idx,
                         );
                     }
-                    let len = u16::from_le_bytes([input[in_idx], input[in_idx + 1]]) as usize;
-                    let nlen = u16::from_le_bytes([input[in_idx + 2], input[in_idx + 3]]) as usize;
-                    in_idx += 4;
+                    let len = (self.bitbuf & 0xFFFF) as usize;
+                    let nlen = ((self.bitbuf >> 16) & 0xFFFF) as usize;
+                    self.bitbuf >>= 32;
+                    self.bitsleft -= 32;
+                    self.bitbuf &= (1u64 << self.bitsleft) - 1;
                     if len != (!nlen & 0xFFFF) {
-                        return (DecompressResult::BadData, in_idx, *out_idx - start_out_idx);
+                        let res = self.fail(Reason::InvalidStoredBlock, in_idx, self.bitsleft + 32);
+                        return (res, in_idx, *out_idx - start_out_idx);
                     }
                     self.state = DecompressorState::UncompressedBody { len };
                 }
                 DecompressorState::UncompressedBody { len } => {
-                    let remaining = len;
-                    let available_in = input.len() - in_idx;
+                    let mut remaining = len;
+                    while remaining > 0 && self.bitsleft >= 8 && *out_idx < out_len {
+                        unsafe {
+                            *out_ptr.add(*out_idx) = self.bitbuf as u8;
+                        }
+                        self.bitbuf >>= 8;
+                        self.bitsleft -= 8;
+                        *out_idx += 1;
+                        remaining -= 1;
+                    }
+
+                    let available_in = if self.bitsleft == 0 {
+                        input.len() - in_idx
+                    } else {
+                        0
+                    };
                     let available_out = out_len - *out_idx;
                     let copy_len = min(remaining, min(available_in, available_out));
 
                     unsafe {
-                        std::ptr::copy_nonoverlapping(
+                        core::ptr::copy_nonoverlapping(
                             input.as_ptr().add(in_idx),
                             out_ptr.add(*out_idx),
                             copy_len,
@@ -324,23 +564,29 @@ impl Decompressor {
                             return (DecompressResult::Success, in_idx, *out_idx - start_out_idx);
                         } else {
                             self.state = DecompressorState::Start;
+                            self.block_index += 1;
+                            if self.stop_after_block {
+                                return (
+                                    DecompressResult::Success,
+                                    in_idx,
+                                    *out_idx - start_out_idx,
+                                );
+                            }
                         }
                     } else {
                         self.state = DecompressorState::UncompressedBody { len: new_len };
-                        if available_out == 0 {
+                        if *out_idx == out_len {
                             return (
                                 DecompressResult::InsufficientSpace,
                                 in_idx,
                                 *out_idx - start_out_idx,
                             );
                         }
-                        if available_in == 0 {
-                            return (
-                                DecompressResult::ShortInput,
-                                in_idx,
-                                *out_idx - start_out_idx,
-                            );
-                        }
+                        return (
+                            DecompressResult::ShortInput,
+                            in_idx,
+                            *out_idx - start_out_idx,
+                        );
                     }
                 }
                 DecompressorState::Done => {
@@ -356,40 +602,7 @@ impl Decompressor {
             return;
         }
 
-        let data = STATIC_HUFFMAN_DATA.get_or_init(|| {
-            let mut d = Decompressor::new();
-            let mut i = 0;
-            while i < 144 {
-                d.lens[i] = 8;
-                i += 1;
-            }
-            while i < 256 {
-                d.lens[i] = 9;
-                i += 1;
-            }
-            while i < 280 {
-                d.lens[i] = 7;
-                i += 1;
-            }
-            while i < 288 {
-                d.lens[i] = 8;
-                i += 1;
-            }
-            while i < 288 + 32 {
-                d.lens[i] = 5;
-                i += 1;
-            }
-            d.build_offset_decode_table(288, 32);
-            d.build_litlen_decode_table(288);
-
-            StaticHuffmanData {
-                offset_decode_table: d.offset_decode_table,
-                litlen_decode_table: d.litlen_decode_table,
-                lens: d.lens,
-                litlen_tablebits: d.litlen_tablebits,
-            }
-        });
-
+        let data = &STATIC_HUFFMAN_DATA;
         self.offset_decode_table
             .copy_from_slice(&data.offset_decode_table);
         self.litlen_decode_table
@@ -421,7 +634,7 @@ impl Decompressor {
             self.precode_lens[permutation[i]] = 0;
         }
         if !self.build_precode_decode_table() {
-            return DecompressResult::BadData;
+            return self.fail(Reason::InvalidHuffmanCode, *in_idx, self.bitsleft);
         }
         DecompressResult::Success
     }
@@ -449,7 +662,7 @@ impl Decompressor {
                 i += 1;
             } else if presym == 16 {
                 if i == 0 {
-                    return DecompressResult::BadData;
+                    return self.fail(Reason::InvalidHuffmanCode, *in_idx, self.bitsleft);
                 }
                 let rep_val = self.lens[i - 1];
                 if self.bitsleft < 2 {
@@ -484,7 +697,7 @@ impl Decompressor {
             }
         }
         if i != total_syms {
-            return DecompressResult::BadData;
+            return self.fail(Reason::InvalidHuffmanCode, *in_idx, self.bitsleft);
         }
         DecompressResult::Success
     }
@@ -515,11 +728,10 @@ impl Decompressor {
             return res_huffman;
         }
 
-        if !self.build_offset_decode_table(num_litlen_syms, num_offset_syms) {
-            return DecompressResult::BadData;
-        }
-        if !self.build_litlen_decode_table(num_litlen_syms) {
-            return DecompressResult::BadData;
+        if !self.build_offset_decode_table(num_litlen_syms, num_offset_syms)
+            || !self.build_litlen_decode_table(num_litlen_syms)
+        {
+            return self.fail(Reason::InvalidHuffmanCode, *in_idx, self.bitsleft);
         }
         self.static_codes_loaded = false;
         DecompressResult::Success
@@ -535,12 +747,32 @@ impl Decompressor {
     ) -> DecompressResult {
         let litlen_tablemask = (1 << self.litlen_tablebits) - 1;
 
+        if let DecompressorState::BlockBodyMatch { length, offset } = self.state {
+            // A match that did not fit in the output on the previous call.
+            if *out_idx + length > out_len {
+                return DecompressResult::InsufficientSpace;
+            }
+            let dest = *out_idx;
+            unsafe {
+                if offset > dest {
+                    self.copy_match_from_dictionary(out_ptr_start, dest, offset, length);
+                } else {
+                    for i in 0..length {
+                        *out_ptr_start.add(dest + i) = *out_ptr_start.add(dest - offset + i);
+                    }
+                }
+            }
+            *out_idx += length;
+            self.state = DecompressorState::BlockBody;
+        }
+
         if let DecompressorState::BlockBodyOffset {
             length,
             extra_bits: _,
         } = self.state
         {
             refill_bits!(input, *in_idx, self.bitbuf, self.bitsleft);
+            let (pre_bitbuf, pre_bitsleft) = (self.bitbuf, self.bitsleft);
             let mut entry =
                 self.offset_decode_table[(self.bitbuf as usize) & ((1 << OFFSET_TABLEBITS) - 1)];
             if entry & HUFFDEC_SUBTABLE_POINTER != 0 {
@@ -555,8 +787,11 @@ impl Decompressor {
                 entry = self.offset_decode_table
                     [subtable_idx + ((self.bitbuf as usize) & ((1 << subtable_bits) - 1))];
             }
+            let saved_bitbuf = self.bitbuf;
             let total_bits = entry & 0xFF;
             if self.bitsleft < total_bits {
+                self.bitbuf = pre_bitbuf;
+                self.bitsleft = pre_bitsleft;
                 return DecompressResult::ShortInput;
             }
             self.bitbuf >>= total_bits;
@@ -565,70 +800,88 @@ impl Decompressor {
             let len = (entry >> 8) & 0xFF;
             let extra_bits = total_bits - len;
             if extra_bits > 0 {
-                offset += (self.bitbuf as usize) & ((1 << extra_bits) - 1);
-                self.bitbuf >>= extra_bits;
-                self.bitsleft -= extra_bits;
+                offset += ((saved_bitbuf >> len) as usize) & ((1 << extra_bits) - 1);
             }
 
-            if offset > *out_idx {
-                return DecompressResult::BadData;
+            if offset > *out_idx + self.dictionary_reach() {
+                let reason = Reason::DistanceTooFarBack {
+                    distance: offset,
+                    available: *out_idx,
+                };
+                return self.fail(reason, *in_idx, self.bitsleft);
             }
             let dest = *out_idx;
-            let src = dest - offset;
             if dest + length > out_len {
+                self.state = DecompressorState::BlockBodyMatch { length, offset };
                 return DecompressResult::InsufficientSpace;
             }
-            unsafe {
-                let out_ptr = out_ptr_start;
-                if offset >= length {
-                    std::ptr::copy_nonoverlapping(out_ptr.add(src), out_ptr.add(dest), length);
-                } else if offset == 1 {
-                    let b = *out_ptr.add(src);
-                    std::ptr::write_bytes(out_ptr.add(dest), b, length);
-                } else if offset < 8 {
-                    let src_ptr = out_ptr.add(src);
-                    let dest_ptr = out_ptr.add(dest);
-                    if offset == 1 || offset == 2 || offset == 4 {
-                        let pattern = prepare_pattern(offset, src_ptr);
-                        let mut i = 0;
-                        while i + 32 <= length {
-                            std::ptr::write_unaligned(dest_ptr.add(i) as *mut u64, pattern);
-                            std::ptr::write_unaligned(dest_ptr.add(i + 8) as *mut u64, pattern);
-                            std::ptr::write_unaligned(dest_ptr.add(i + 16) as *mut u64, pattern);
-                            std::ptr::write_unaligned(dest_ptr.add(i + 24) as *mut u64, pattern);
-                            i += 32;
-                        }
-                        while i + 8 <= length {
-                            std::ptr::write_unaligned(dest_ptr.add(i) as *mut u64, pattern);
-                            i += 8;
-                        }
-                        while i < length {
-                            *dest_ptr.add(i) = (pattern >> ((i & 7) * 8)) as u8;
-                            i += 1;
+            if offset > dest {
+                unsafe {
+                    self.copy_match_from_dictionary(out_ptr_start, dest, offset, length);
+                }
+            } else {
+                let src = dest - offset;
+                unsafe {
+                    let out_ptr = out_ptr_start;
+                    if offset >= length {
+                        core::ptr::copy_nonoverlapping(out_ptr.add(src), out_ptr.add(dest), length);
+                    } else if offset == 1 {
+                        let b = *out_ptr.add(src);
+                        core::ptr::write_bytes(out_ptr.add(dest), b, length);
+                    } else if offset < 8 {
+                        let src_ptr = out_ptr.add(src);
+                        let dest_ptr = out_ptr.add(dest);
+                        if offset == 1 || offset == 2 || offset == 4 {
+                            let pattern = prepare_pattern(offset, src_ptr);
+                            let mut i = 0;
+                            while i + 32 <= length {
+                                core::ptr::write_unaligned(dest_ptr.add(i) as *mut u64, pattern);
+                                core::ptr::write_unaligned(
+                                    dest_ptr.add(i + 8) as *mut u64,
+                                    pattern,
+                                );
+                                core::ptr::write_unaligned(
+                                    dest_ptr.add(i + 16) as *mut u64,
+                                    pattern,
+                                );
+                                core::ptr::write_unaligned(
+                                    dest_ptr.add(i + 24) as *mut u64,
+                                    pattern,
+                                );
+                                i += 32;
+                            }
+                            while i + 8 <= length {
+                                core::ptr::write_unaligned(dest_ptr.add(i) as *mut u64, pattern);
+                                i += 8;
+                            }
+                            while i < length {
+                                *dest_ptr.add(i) = (pattern >> ((i & 7) * 8)) as u8;
+                                i += 1;
+                            }
+                        } else {
+                            let pattern = prepare_pattern(offset, src_ptr);
+                            let mut i = 0;
+                            while i + 8 <= length {
+                                core::ptr::write_unaligned(dest_ptr.add(i) as *mut u64, pattern);
+                                i += offset;
+                            }
+                            while i < length {
+                                *dest_ptr.add(i) = *src_ptr.add(i);
+                                i += 1;
+                            }
                         }
                     } else {
-                        let pattern = prepare_pattern(offset, src_ptr);
-                        let mut i = 0;
-                        while i + 8 <= length {
-                            std::ptr::write_unaligned(dest_ptr.add(i) as *mut u64, pattern);
-                            i += offset;
-                        }
-                        while i < length {
-                            *dest_ptr.add(i) = *src_ptr.add(i);
-                            i += 1;
+                        let mut copied = 0;
+                        while copied < length {
+                            let copy_len = min(offset, length - copied);
+                            core::ptr::copy_nonoverlapping(
+                                out_ptr.add(src + copied),
+                                out_ptr.add(dest + copied),
+                                copy_len,
+                            );
+                            copied += copy_len;
                         }
                     }
-                } else {
-                    let mut copied = 0;
-                    while copied < length {
-                        let copy_len = min(offset, length - copied);
-                        std::ptr::copy_nonoverlapping(
-                            out_ptr.add(src + copied),
-                            out_ptr.add(dest + copied),
-                            copy_len,
-                        );
-                        copied += copy_len;
-                    }
                 }
             }
             *out_idx += length;
@@ -727,7 +980,17 @@ impl Decompressor {
 
                     let current_out_idx = out_next.offset_from(out_ptr_start) as usize;
                     if offset > current_out_idx {
-                        break;
+                        if offset > current_out_idx + self.dictionary_reach() {
+                            break;
+                        }
+                        self.copy_match_from_dictionary(
+                            out_ptr_start,
+                            current_out_idx,
+                            offset,
+                            length,
+                        );
+                        out_next = out_next.add(length);
+                        continue;
                     }
 
                     let src = out_next.sub(offset);
@@ -763,7 +1026,7 @@ impl Decompressor {
                             }
                         }
                     } else if offset >= length {
-                        std::ptr::copy_nonoverlapping(src, out_next, length);
+                        core::ptr::copy_nonoverlapping(src, out_next, length);
                     } else {
                         let src_ptr = src;
                         let dest_ptr = out_next;
@@ -874,8 +1137,22 @@ impl Decompressor {
                             ((saved_bitbuf_off >> len_off) as usize) & ((1 << extra_bits_off) - 1);
                     }
 
-                    if offset > *out_idx {
-                        return DecompressResult::BadData;
+                    if offset > *out_idx + self.dictionary_reach() {
+                        let reason = Reason::DistanceTooFarBack {
+                            distance: offset,
+                            available: *out_idx,
+                        };
+                        return self.fail(reason, *in_idx, self.bitsleft);
+                    } else if offset > *out_idx {
+                        unsafe {
+                            self.copy_match_from_dictionary(
+                                out_ptr_start,
+                                *out_idx,
+                                offset,
+                                length,
+                            );
+                        }
+                        *out_idx += length;
                     } else {
                         let src = *out_idx - offset;
                         let dest = *out_idx;
@@ -889,26 +1166,26 @@ impl Decompressor {
                                     let pattern = prepare_pattern(offset, src_ptr);
                                     let mut i = 0;
                                     while i + 32 <= length {
-                                        std::ptr::write_unaligned(
+                                        core::ptr::write_unaligned(
                                             dest_ptr.add(i) as *mut u64,
                                             pattern,
                                         );
-                                        std::ptr::write_unaligned(
+                                        core::ptr::write_unaligned(
                                             dest_ptr.add(i + 8) as *mut u64,
                                             pattern,
                                         );
-                                        std::ptr::write_unaligned(
+                                        core::ptr::write_unaligned(
                                             dest_ptr.add(i + 16) as *mut u64,
                                             pattern,
                                         );
-                                        std::ptr::write_unaligned(
+                                        core::ptr::write_unaligned(
                                             dest_ptr.add(i + 24) as *mut u64,
                                             pattern,
                                         );
                                         i += 32;
                                     }
                                     while i + 8 <= length {
-                                        std::ptr::write_unaligned(
+                                        core::ptr::write_unaligned(
                                             dest_ptr.add(i) as *mut u64,
                                             pattern,
                                         );
@@ -922,7 +1199,7 @@ impl Decompressor {
                                     let pattern = prepare_pattern(offset, src_ptr);
                                     let mut i = 0;
                                     while i + 8 <= length {
-                                        std::ptr::write_unaligned(
+                                        core::ptr::write_unaligned(
                                             dest_ptr.add(i) as *mut u64,
                                             pattern,
                                         );
@@ -937,7 +1214,7 @@ impl Decompressor {
                                 let mut copied = 0;
                                 while copied < length {
                                     let copy_len = min(offset, length - copied);
-                                    std::ptr::copy_nonoverlapping(
+                                    core::ptr::copy_nonoverlapping(
                                         out_ptr.add(src + copied),
                                         out_ptr.add(dest + copied),
                                         copy_len,
@@ -952,9 +1229,13 @@ impl Decompressor {
             }
 
             refill_bits!(input, *in_idx, self.bitbuf, self.bitsleft);
+            let (pre_bitbuf, pre_bitsleft) = (self.bitbuf, self.bitsleft);
             let mut entry = self.litlen_decode_table[(self.bitbuf as usize) & litlen_tablemask];
             if entry & HUFFDEC_EXCEPTIONAL != 0 {
                 if entry & HUFFDEC_END_OF_BLOCK != 0 {
+                    if self.bitsleft < entry & 0xFF {
+                        return DecompressResult::ShortInput;
+                    }
                     self.bitbuf >>= entry as u8;
                     self.bitsleft -= entry & 0xFF;
                     return DecompressResult::Success;
@@ -970,17 +1251,31 @@ impl Decompressor {
                     let subtable_bits = (entry >> 8) & 0x3F;
                     entry = self.litlen_decode_table
                         [subtable_idx + ((self.bitbuf as usize) & ((1 << subtable_bits) - 1))];
+                    if entry & HUFFDEC_END_OF_BLOCK != 0 {
+                        if self.bitsleft < entry & 0xFF {
+                            self.bitbuf = pre_bitbuf;
+                            self.bitsleft = pre_bitsleft;
+                            return DecompressResult::ShortInput;
+                        }
+                        self.bitbuf >>= entry as u8;
+                        self.bitsleft -= entry & 0xFF;
+                        return DecompressResult::Success;
+                    }
                 }
             }
             let saved_bitbuf = self.bitbuf;
             let total_bits = entry & 0xFF;
             if self.bitsleft < total_bits {
+                self.bitbuf = pre_bitbuf;
+                self.bitsleft = pre_bitsleft;
                 return DecompressResult::ShortInput;
             }
             self.bitbuf >>= total_bits;
             self.bitsleft -= total_bits;
             if entry & HUFFDEC_LITERAL != 0 {
                 if *out_idx >= out_len {
+                    self.bitbuf = pre_bitbuf;
+                    self.bitsleft = pre_bitsleft;
                     return DecompressResult::InsufficientSpace;
                 }
                 unsafe {
@@ -995,6 +1290,7 @@ impl Decompressor {
                     length += ((saved_bitbuf >> len) as usize) & ((1 << extra_bits) - 1);
                 }
                 refill_bits!(input, *in_idx, self.bitbuf, self.bitsleft);
+                let (pre_bitbuf, pre_bitsleft) = (self.bitbuf, self.bitsleft);
                 let mut entry = self.offset_decode_table
                     [(self.bitbuf as usize) & ((1 << OFFSET_TABLEBITS) - 1)];
                 if entry & HUFFDEC_SUBTABLE_POINTER != 0 {
@@ -1016,6 +1312,8 @@ impl Decompressor {
                 let saved_bitbuf = self.bitbuf;
                 let total_bits = entry & 0xFF;
                 if self.bitsleft < total_bits {
+                    self.bitbuf = pre_bitbuf;
+                    self.bitsleft = pre_bitsleft;
                     self.state = DecompressorState::BlockBodyOffset {
                         length,
                         extra_bits: 0,
@@ -1030,54 +1328,72 @@ impl Decompressor {
                 if extra_bits > 0 {
                     offset += ((saved_bitbuf >> len) as usize) & ((1 << extra_bits) - 1);
                 }
-                if offset > *out_idx {
-                    return DecompressResult::BadData;
+                if offset > *out_idx + self.dictionary_reach() {
+                    let reason = Reason::DistanceTooFarBack {
+                        distance: offset,
+                        available: *out_idx,
+                    };
+                    return self.fail(reason, *in_idx, self.bitsleft);
                 }
                 let dest = *out_idx;
-                let src = dest - offset;
                 if dest + length > out_len {
+                    self.state = DecompressorState::BlockBodyMatch { length, offset };
                     return DecompressResult::InsufficientSpace;
                 }
 
-                unsafe {
-                    let out_ptr = out_ptr_start;
-                    if offset >= length {
-                        std::ptr::copy_nonoverlapping(out_ptr.add(src), out_ptr.add(dest), length);
-                    } else if offset == 1 {
-                        let b = *out_ptr.add(src);
-                        std::ptr::write_bytes(out_ptr.add(dest), b, length);
-                    } else if offset < 8 {
-                        let src_ptr = out_ptr.add(src);
-                        let dest_ptr = out_ptr.add(dest);
-                        if offset == 1 || offset == 2 || offset == 4 {
-                            let pattern = prepare_pattern(offset, src_ptr);
-                            let mut i = 0;
-                            while i + 8 <= length {
-                                std::ptr::write_unaligned(dest_ptr.add(i) as *mut u64, pattern);
-                                i += 8;
-                            }
-                            while i < length {
-                                *dest_ptr.add(i) = (pattern >> ((i & 7) * 8)) as u8;
-                                i += 1;
+                if offset > dest {
+                    unsafe {
+                        self.copy_match_from_dictionary(out_ptr_start, dest, offset, length);
+                    }
+                } else {
+                    let src = dest - offset;
+                    unsafe {
+                        let out_ptr = out_ptr_start;
+                        if offset >= length {
+                            core::ptr::copy_nonoverlapping(
+                                out_ptr.add(src),
+                                out_ptr.add(dest),
+                                length,
+                            );
+                        } else if offset == 1 {
+                            let b = *out_ptr.add(src);
+                            core::ptr::write_bytes(out_ptr.add(dest), b, length);
+                        } else if offset < 8 {
+                            let src_ptr = out_ptr.add(src);
+                            let dest_ptr = out_ptr.add(dest);
+                            if offset == 1 || offset == 2 || offset == 4 {
+                                let pattern = prepare_pattern(offset, src_ptr);
+                                let mut i = 0;
+                                while i + 8 <= length {
+                                    core::ptr::write_unaligned(
+                                        dest_ptr.add(i) as *mut u64,
+                                        pattern,
+                                    );
+                                    i += 8;
+                                }
+                                while i < length {
+                                    *dest_ptr.add(i) = (pattern >> ((i & 7) * 8)) as u8;
+                                    i += 1;
+                                }
+                            } else {
+                                let mut copied = 0;
+                                while copied < length {
+                                    *dest_ptr.add(copied) = *src_ptr.add(copied);
+                                    copied += 1;
+                                }
                             }
                         } else {
                             let mut copied = 0;
                             while copied < length {
-                                *dest_ptr.add(copied) = *src_ptr.add(copied);
-                                copied += 1;
+                                let copy_len = min(offset, length - copied);
+                                core::ptr::copy_nonoverlapping(
+                                    out_ptr.add(src + copied),
+                                    out_ptr.add(dest + copied),
+                                    copy_len,
+                                );
+                                copied += copy_len;
                             }
                         }
-                    } else {
-                        let mut copied = 0;
-                        while copied < length {
-                            let copy_len = min(offset, length - copied);
-                            std::ptr::copy_nonoverlapping(
-                                out_ptr.add(src + copied),
-                                out_ptr.add(dest + copied),
-                                copy_len,
-                            );
-                            copied += copy_len;
-                        }
                     }
                 }
                 *out_idx += length;
@@ -1088,54 +1404,86 @@ impl Decompressor {
     pub unsafe fn decompress_zlib_uninit(
         &mut self,
         input: &[u8],
-        output: &mut [std::mem::MaybeUninit<u8>],
+        output: &mut [core::mem::MaybeUninit<u8>],
     ) -> (DecompressResult, usize, usize) {
         if input.len() < ZLIB_MIN_OVERHEAD {
             return (DecompressResult::ShortInput, 0, 0);
         }
 
         let hdr = u16::from_be_bytes([input[0], input[1]]);
-        if !hdr.is_multiple_of(31) {
-            return (DecompressResult::BadData, 0, 0);
-        }
-        if ((hdr >> 8) & 0xF) as u8 != ZLIB_CM_DEFLATE {
-            return (DecompressResult::BadData, 0, 0);
-        }
-        if ((hdr >> 12) & 0xF) as u8 > ZLIB_CINFO_32K_WINDOW {
-            return (DecompressResult::BadData, 0, 0);
-        }
-        if (hdr >> 5) & 1 != 0 {
-            return (DecompressResult::BadData, 0, 0);
+        if !hdr.is_multiple_of(31)
+            || ((hdr >> 8) & 0xF) as u8 != ZLIB_CM_DEFLATE
+            || ((hdr >> 12) & 0xF) as u8 > ZLIB_CINFO_32K_WINDOW
+        {
+            return (self.fail(Reason::InvalidHeader, 0, 0), 0, 0);
         }
 
-        let (res, in_consumed, out_produced) =
-            unsafe { self.decompress_uninit(&input[2..input.len() - ZLIB_FOOTER_SIZE], output) };
+        let deflate_end = input.len() - ZLIB_FOOTER_SIZE;
+        let (hdr_size, (res, in_consumed, out_produced)) = if hdr & ZLIB_FDICT != 0 {
+            let hdr_size = ZLIB_MIN_HEADER_SIZE + ZLIB_DICTID_SIZE;
+            if input.len() < hdr_size + ZLIB_FOOTER_SIZE {
+                return (DecompressResult::ShortInput, 0, 0);
+            }
+            let dictid = u32::from_be_bytes([input[2], input[3], input[4], input[5]]);
+            if self.dictionary_id != Some(dictid) {
+                let reason = Reason::DictionaryMismatch {
+                    expected: dictid,
+                    actual: self.dictionary_id,
+                };
+                return (self.fail(reason, 2, 0), 0, 0);
+            }
+            (
+                hdr_size,
+                self.decompress_ptr(
+                    &input[hdr_size..deflate_end],
+                    output.as_mut_ptr() as *mut u8,
+                    output.len(),
+                ),
+            )
+        } else {
+            (
+                ZLIB_MIN_HEADER_SIZE,
+                self.decompress_ptr_without_dictionary(
+                    &input[ZLIB_MIN_HEADER_SIZE..deflate_end],
+                    output.as_mut_ptr() as *mut u8,
+                    output.len(),
+                ),
+            )
+        };
 
         if res != DecompressResult::Success {
-            return (res, in_consumed + 2, out_produced);
+            if let Some(failure) = &mut self.failure {
+                failure.in_idx += hdr_size;
+            }
+            return (res, in_consumed + hdr_size, out_produced);
         }
 
         let out_slice =
-            unsafe { std::slice::from_raw_parts(output.as_ptr() as *const u8, out_produced) };
-        let actual_adler = crate::adler32::adler32(1, out_slice);
+            unsafe { core::slice::from_raw_parts(output.as_ptr() as *const u8, out_produced) };
+        let actual_adler = Checksum::Adler32.compute(out_slice, self.parallel);
+        let footer = hdr_size + in_consumed;
         let expected_adler = u32::from_be_bytes([
-            input[2 + in_consumed],
-            input[2 + in_consumed + 1],
-            input[2 + in_consumed + 2],
-            input[2 + in_consumed + 3],
+            input[footer],
+            input[footer + 1],
+            input[footer + 2],
+            input[footer + 3],
         ]);
 
         if actual_adler != expected_adler {
+            let reason = Reason::ChecksumMismatch {
+                expected: expected_adler,
+                actual: actual_adler,
+            };
             return (
-                DecompressResult::BadData,
-                in_consumed + 2 + ZLIB_FOOTER_SIZE,
+                self.fail(reason, footer, 0),
+                footer + ZLIB_FOOTER_SIZE,
                 out_produced,
             );
         }
 
         (
             DecompressResult::Success,
-            in_consumed + 2 + ZLIB_FOOTER_SIZE,
+            footer + ZLIB_FOOTER_SIZE,
             out_produced,
         )
     }
@@ -1149,67 +1497,108 @@ impl Decompressor {
         unsafe { self.decompress_zlib_uninit(input, output_uninit) }
     }
 
+    /// Decodes every member of a (possibly concatenated) gzip stream into `output`.
+    /// Zero bytes after the last member are skipped; any other trailing data is an
+    /// error.
+    ///
+    /// # Safety
+    ///
+    /// Only the first `out_produced` bytes of `output` are initialized on return.
     pub unsafe fn decompress_gzip_uninit(
         &mut self,
         input: &[u8],
-        output: &mut [std::mem::MaybeUninit<u8>],
+        output: &mut [core::mem::MaybeUninit<u8>],
     ) -> (DecompressResult, usize, usize) {
-        if input.len() < GZIP_MIN_OVERHEAD {
-            return (DecompressResult::ShortInput, 0, 0);
-        }
-
-        if input[0] != GZIP_ID1 || input[1] != GZIP_ID2 || input[2] != GZIP_CM_DEFLATE {
-            return (DecompressResult::BadData, 0, 0);
-        }
+        unsafe { self.decompress_gzip_with_header_uninit(input, output, None) }
+    }
 
-        let flg = input[3];
-        if flg & GZIP_FRESERVED != 0 {
-            return (DecompressResult::BadData, 0, 0);
+    /// Like [`decompress_gzip_uninit`](Self::decompress_gzip_uninit), additionally
+    /// storing the first member's header in `header`.
+    ///
+    /// # Safety
+    ///
+    /// Only the first `out_produced` bytes of `output` are initialized on return.
+    pub unsafe fn decompress_gzip_with_header_uninit(
+        &mut self,
+        input: &[u8],
+        output: &mut [core::mem::MaybeUninit<u8>],
+        header: Option<&mut GzipHeader>,
+    ) -> (DecompressResult, usize, usize) {
+        let (res, mut in_idx, mut out_idx) =
+            unsafe { self.decompress_gzip_member_uninit(input, output, header) };
+        if res != DecompressResult::Success {
+            return (res, in_idx, out_idx);
         }
-
-        let mut in_idx = 10;
-
-        if flg & GZIP_FEXTRA != 0 {
-            if in_idx + 2 > input.len() {
-                return (DecompressResult::ShortInput, 0, 0);
+        while in_idx < input.len() {
+            // Zero padding after the last member is skipped, as gzip(1) does.
+            if input[in_idx..].iter().all(|&b| b == 0) {
+                in_idx = input.len();
+                break;
             }
-            let xlen = u16::from_le_bytes([input[in_idx], input[in_idx + 1]]) as usize;
-            in_idx += 2 + xlen;
-        }
-
-        if flg & GZIP_FNAME != 0 {
-            while in_idx < input.len() && input[in_idx] != 0 {
-                in_idx += 1;
+            let (res, in_consumed, out_produced) = unsafe {
+                self.decompress_gzip_member_uninit(&input[in_idx..], &mut output[out_idx..], None)
+            };
+            if res != DecompressResult::Success {
+                if let Some(failure) = &mut self.failure {
+                    failure.in_idx += in_idx;
+                }
+                return (res, in_idx + in_consumed, out_idx + out_produced);
             }
-            in_idx += 1;
+            in_idx += in_consumed;
+            out_idx += out_produced;
         }
+        (DecompressResult::Success, in_idx, out_idx)
+    }
 
-        if flg & GZIP_FCOMMENT != 0 {
-            while in_idx < input.len() && input[in_idx] != 0 {
-                in_idx += 1;
-            }
-            in_idx += 1;
+    /// Decodes the first member of a gzip stream, storing its header in `header` if
+    /// given. The returned input count is the offset at which any following members or
+    /// trailing data begin.
+    ///
+    /// # Safety
+    ///
+    /// Only the first `out_produced` bytes of `output` are initialized on return.
+    pub unsafe fn decompress_gzip_member_uninit(
+        &mut self,
+        input: &[u8],
+        output: &mut [core::mem::MaybeUninit<u8>],
+        header: Option<&mut GzipHeader>,
+    ) -> (DecompressResult, usize, usize) {
+        if input.len() < GZIP_MIN_OVERHEAD {
+            return (DecompressResult::ShortInput, 0, 0);
         }
 
-        if flg & GZIP_FHCRC != 0 {
-            in_idx += 2;
-        }
+        self.block_index = 0;
+        let in_idx = match parse_gzip_header(input, self.verify_gzip_header_crc, header) {
+            Ok(len) => len,
+            Err(DecompressResult::BadData) => {
+                return (self.fail(Reason::InvalidHeader, 0, 0), 0, 0);
+            }
+            Err(res) => return (res, 0, 0),
+        };
 
         if in_idx + GZIP_FOOTER_SIZE > input.len() {
             return (DecompressResult::ShortInput, 0, 0);
         }
 
-        let (res, in_consumed, out_produced) = unsafe {
-            self.decompress_uninit(&input[in_idx..input.len() - GZIP_FOOTER_SIZE], output)
-        };
+        let (res, in_consumed, out_produced) = self.decompress_ptr_without_dictionary(
+            &input[in_idx..],
+            output.as_mut_ptr() as *mut u8,
+            output.len(),
+        );
 
         if res != DecompressResult::Success {
+            if let Some(failure) = &mut self.failure {
+                failure.in_idx += in_idx;
+            }
             return (res, in_idx + in_consumed, out_produced);
         }
+        if in_idx + in_consumed + GZIP_FOOTER_SIZE > input.len() {
+            return (DecompressResult::ShortInput, in_idx + in_consumed, out_produced);
+        }
 
         let out_slice =
-            unsafe { std::slice::from_raw_parts(output.as_ptr() as *const u8, out_produced) };
-        let actual_crc = crate::crc32::crc32(0, out_slice);
+            unsafe { core::slice::from_raw_parts(output.as_ptr() as *const u8, out_produced) };
+        let actual_crc = Checksum::Crc32.compute(out_slice, self.parallel);
         let expected_crc = u32::from_le_bytes([
             input[in_idx + in_consumed],
             input[in_idx + in_consumed + 1],
@@ -1218,8 +1607,12 @@ impl Decompressor {
         ]);
 
         if actual_crc != expected_crc {
+            let reason = Reason::ChecksumMismatch {
+                expected: expected_crc,
+                actual: actual_crc,
+            };
             return (
-                DecompressResult::BadData,
+                self.fail(reason, in_idx + in_consumed, 0),
                 in_idx + in_consumed + GZIP_FOOTER_SIZE,
                 out_produced,
             );
@@ -1233,8 +1626,12 @@ impl Decompressor {
         ]);
 
         if (out_produced as u32) != expected_isize {
+            let reason = Reason::SizeMismatch {
+                expected: expected_isize,
+                actual: out_produced as u32,
+            };
             return (
-                DecompressResult::BadData,
+                self.fail(reason, in_idx + in_consumed + 4, 0),
                 in_idx + in_consumed + GZIP_FOOTER_SIZE,
                 out_produced,
             );
@@ -1266,7 +1663,7 @@ pub(crate) unsafe fn prepare_pattern(offset: usize, src_ptr: *const u8) -> u64 {
                 b.wrapping_mul(0x0101010101010101)
             }
             2 => {
-                let w = std::ptr::read_unaligned(src_ptr as *const u16) as u64;
+                let w = core::ptr::read_unaligned(src_ptr as *const u16) as u64;
                 w | (w << 16) | (w << 32) | (w << 48)
             }
             3 => {
@@ -1277,7 +1674,7 @@ pub(crate) unsafe fn prepare_pattern(offset: usize, src_ptr: *const u8) -> u64 {
                 u64::from_le(p_le)
             }
             4 => {
-                let w = std::ptr::read_unaligned(src_ptr as *const u32) as u64;
+                let w = core::ptr::read_unaligned(src_ptr as *const u32) as u64;
                 w | (w << 32)
             }
             5 => {
@@ -1317,11 +1714,21 @@ pub(crate) unsafe fn prepare_pattern(offset: usize, src_ptr: *const u8) -> u64 {
 }
 
 #[inline(always)]
-fn make_decode_table_entry(decode_results: &[u32], sym: usize, len: u32) -> u32 {
+const fn make_decode_table_entry(decode_results: &[u32], sym: usize, len: u32) -> u32 {
     decode_results[sym] + (len << 8) + len
 }
 
-fn build_decode_table(
+/// Copies the first `size` entries of `decode_table` to the `size` entries after them.
+#[inline(always)]
+const fn double_table(decode_table: &mut [u32], size: usize) {
+    let (head, tail) = decode_table.split_at_mut(size);
+    tail.split_at_mut(size).0.copy_from_slice(head);
+}
+
+/// Builds a decode table for the code with the given codeword lengths. This is a
+/// `const fn` so the static Huffman tables can be built at compile time, hence the
+/// `while` loops.
+const fn build_decode_table(
     decode_table: &mut [u32],
     lens: &[u8],
     num_syms: usize,
@@ -1333,34 +1740,41 @@ fn build_decode_table(
 ) -> bool {
     let mut len_counts = [0u32; DEFLATE_MAX_CODEWORD_LEN + 1];
     let mut offsets = [0u32; DEFLATE_MAX_CODEWORD_LEN + 1];
-    for &len in lens {
+    let mut i = 0;
+    while i < lens.len() {
+        let len = lens[i];
         if len as usize > DEFLATE_MAX_CODEWORD_LEN {
             return false;
         }
         len_counts[len as usize] += 1;
+        i += 1;
     }
     let mut actual_max_len = max_codeword_len;
     while actual_max_len > 1 && len_counts[actual_max_len] == 0 {
         actual_max_len -= 1;
     }
     if let Some(tb_ret) = table_bits_ret {
-        table_bits = min(table_bits, actual_max_len);
+        if actual_max_len < table_bits {
+            table_bits = actual_max_len;
+        }
         *tb_ret = table_bits;
     }
     offsets[0] = 0;
     offsets[1] = len_counts[0];
     let mut codespace_used = 0u32;
-    for len in 1..actual_max_len {
+    let mut len = 1;
+    while len < actual_max_len {
         offsets[len + 1] = offsets[len] + len_counts[len];
         codespace_used = (codespace_used << 1) + len_counts[len];
+        len += 1;
     }
     codespace_used = (codespace_used << 1) + len_counts[actual_max_len];
-    for (sym, &len) in lens.iter().enumerate() {
-        if sym >= num_syms {
-            break;
-        }
-        sorted_syms[offsets[len as usize] as usize] = sym as u16;
-        offsets[len as usize] += 1;
+    let mut sym = 0;
+    while sym < lens.len() && sym < num_syms {
+        let len = lens[sym] as usize;
+        sorted_syms[offsets[len] as usize] = sym as u16;
+        offsets[len] += 1;
+        sym += 1;
     }
     if codespace_used > (1 << actual_max_len) {
         return false;
@@ -1376,8 +1790,10 @@ fn build_decode_table(
             sym = sorted_syms[offsets[0] as usize] as usize;
         }
         let entry = make_decode_table_entry(decode_results, sym, 1);
-        for i in 0..(1 << table_bits) {
+        let mut i = 0;
+        while i < (1 << table_bits) {
             decode_table[i] = entry;
+            i += 1;
         }
         return true;
     }
@@ -1408,12 +1824,11 @@ fn build_decode_table(
                 make_decode_table_entry(decode_results, sorted_syms[sym_ptr] as usize, len as u32);
             sym_ptr += 1;
             if codeword == (cur_table_end as u32) - 1 {
-                for _ in len..table_bits {
-                    let size = cur_table_end;
-                    for i in 0..size {
-                        decode_table[size + i] = decode_table[i];
-                    }
+                let mut fill_len = len;
+                while fill_len < table_bits {
+                    double_table(decode_table, cur_table_end);
                     cur_table_end <<= 1;
+                    fill_len += 1;
                 }
                 return true;
             }
@@ -1430,13 +1845,10 @@ fn build_decode_table(
         loop {
             len += 1;
             if len <= table_bits {
-                let size = cur_table_end;
-                for i in 0..size {
-                    decode_table[size + i] = decode_table[i];
-                }
+                double_table(decode_table, cur_table_end);
                 cur_table_end <<= 1;
             }
-            if len > table_bits || len_counts[len] != 0 {
+            if len > DEFLATE_MAX_CODEWORD_LEN || len_counts[len] != 0 {
                 break;
             }
         }
@@ -0,0 +1,739 @@
+//! Decompression of a single large DEFLATE or gzip stream on the rayon thread pool.
+//!
+//! The compressed input is cut into chunks. Every chunk but the first searches its
+//! range for the first bit offset at which a dynamic or stored block plausibly starts
+//! and decodes from there without knowing the 32 KiB of output that precede it:
+//! bytes copied from before the chunk are written as markers naming a position in
+//! that window. Walking the chunks in order then checks that each one starts where
+//! its predecessor ended, decodes again any chunk that does not, and computes the
+//! window every chunk needs, after which the markers are replaced in parallel. A
+//! block boundary found by mistake therefore costs time but never changes the output.
+
+use super::tables::*;
+use super::{DecompressResult, Decompressor, STATIC_HUFFMAN_DATA};
+use crate::common::*;
+use crate::error::{Error, Position, Result};
+use crate::gzip::parse_header as parse_gzip_header;
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use rayon::prelude::*;
+
+/// The default amount of compressed input per parallel chunk.
+pub const PARALLEL_DECOMPRESS_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+const WINDOW_SIZE: usize = DEFLATE_MAX_MATCH_OFFSET;
+
+/// Decoded symbols below this are bytes; `MARKER_BASE + i` stands for byte `i` of the
+/// window preceding the chunk.
+const MARKER_BASE: u16 = 256;
+
+/// Reads bits LSB-first at an arbitrary bit offset, as zeros past the end of the data.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: u64,
+}
+
+impl BitReader<'_> {
+    /// At least the next 57 bits.
+    #[inline(always)]
+    fn peek(&self) -> u64 {
+        let byte = (self.pos >> 3) as usize;
+        let word = match self.data.get(byte..byte + 8) {
+            Some(word) => u64::from_le_bytes(word.try_into().unwrap()),
+            None => {
+                let mut word = [0u8; 8];
+                if byte < self.data.len() {
+                    let tail = &self.data[byte..];
+                    word[..tail.len()].copy_from_slice(tail);
+                }
+                u64::from_le_bytes(word)
+            }
+        };
+        word >> (self.pos & 7)
+    }
+
+    #[inline(always)]
+    fn consume(&mut self, bits: u32) {
+        self.pos += bits as u64;
+    }
+
+    fn read(&mut self, bits: u32) -> u32 {
+        let value = (self.peek() & ((1 << bits) - 1)) as u32;
+        self.consume(bits);
+        value
+    }
+
+    fn align_to_byte(&mut self) {
+        self.pos = (self.pos + 7) & !7;
+    }
+
+    fn overrun(&self) -> bool {
+        self.pos > self.data.len() as u64 * 8
+    }
+}
+
+/// The reference in a chunk that reaches furthest back before the chunk's start.
+struct WindowRef {
+    /// How many bytes before the chunk's start it reaches.
+    depth: usize,
+    distance: usize,
+    /// The output decoded in the chunk before the reference.
+    available: usize,
+    at: Position,
+}
+
+/// The end of a gzip member.
+struct MemberEnd {
+    /// Output offset, within the chunk until the chunks are joined, of the member's end.
+    out_end: usize,
+    crc: u32,
+    isize: u32,
+    /// Byte offset of the trailer in the input.
+    trailer: usize,
+    /// Index of the member's final block.
+    block_index: usize,
+    /// Whether the member started before the chunk did.
+    first_in_chunk: bool,
+}
+
+/// The output of one chunk, decoded from the block starting at bit `start` up to the
+/// first block boundary at or after the chunk's end, or the end of the stream.
+struct Chunk {
+    start: u64,
+    end: u64,
+    symbols: Vec<u16>,
+    deepest: Option<WindowRef>,
+    members: Vec<MemberEnd>,
+    /// Index, within its member, of the block at `start`, as assumed when decoding.
+    first_block_index: usize,
+    /// Index of the block at `end`.
+    end_block_index: usize,
+    /// Output offset at which the last gzip member starting inside the chunk begins.
+    /// References may not reach back past it.
+    member_start: Option<usize>,
+    /// Whether the first block is a non-final stored block whose header is followed by
+    /// zero padding.
+    starts_with_stored_block: bool,
+    reached_end: bool,
+}
+
+impl Chunk {
+    /// Whether decoding from bit `pos` gives the same output as from `start`.
+    fn starts_at(&self, pos: u64) -> bool {
+        // A stored block's header is followed by zero bits up to the next byte, so a
+        // header read from any of those bits is the same non-final stored block, as
+        // long as its 3 bits still end before the byte boundary.
+        self.start == pos
+            || (self.starts_with_stored_block
+                && self.start < pos
+                && pos + 3 <= (self.start + 3 + 7) & !7)
+    }
+}
+
+struct ChunkDecoder<'a> {
+    data: &'a [u8],
+    gzip: bool,
+    verify_gzip_header_crc: bool,
+    limit: usize,
+    tables: Box<Decompressor>,
+}
+
+impl<'a> ChunkDecoder<'a> {
+    fn new(data: &'a [u8], gzip: bool, verify_gzip_header_crc: bool, limit: usize) -> Self {
+        Self {
+            data,
+            gzip,
+            verify_gzip_header_crc,
+            limit,
+            tables: Box::new(Decompressor::new()),
+        }
+    }
+
+    fn truncated(&self, block_index: usize) -> Error {
+        Error::TruncatedInput {
+            at: Position {
+                bit_offset: self.data.len() as u64 * 8,
+                block_index,
+            },
+        }
+    }
+
+    fn limit_exceeded(&self) -> Error {
+        Error::MemoryLimitExceeded {
+            expected: self.limit.saturating_add(1),
+            limit: self.limit,
+        }
+    }
+
+    /// Reads a dynamic block's code lengths at bit `pos` into the decode tables,
+    /// returning the offset of the block's first symbol.
+    fn read_dynamic_header(&mut self, pos: u64) -> core::result::Result<u64, DecompressResult> {
+        let mut in_idx = (pos >> 3) as usize;
+        if in_idx >= self.data.len() {
+            return Err(DecompressResult::ShortInput);
+        }
+        let tables = &mut *self.tables;
+        tables.bitbuf = (self.data[in_idx] >> (pos & 7)) as u64;
+        tables.bitsleft = 8 - (pos & 7) as u32;
+        in_idx += 1;
+        let res = tables.read_dynamic_huffman_header(self.data, &mut in_idx);
+        let end = in_idx as u64 * 8 - tables.bitsleft as u64;
+        tables.bitbuf = 0;
+        tables.bitsleft = 0;
+        tables.failure = None;
+        match res {
+            DecompressResult::Success => Ok(end),
+            res => Err(res),
+        }
+    }
+
+    /// Whether a non-final dynamic or stored block plausibly starts at bit `pos`. Static
+    /// blocks have no header to check and are not looked for, and neither are final
+    /// blocks, which are rare and would end a chunk that started in the wrong place
+    /// after a single block.
+    fn is_block_start(&mut self, pos: u64) -> bool {
+        let mut bits = BitReader {
+            data: self.data,
+            pos,
+        };
+        let header = bits.read(3);
+        if header & 1 != 0 {
+            return false;
+        }
+        match (header >> 1) as u8 {
+            DEFLATE_BLOCKTYPE_DYNAMIC_HUFFMAN => {
+                let counts = bits.peek();
+                // At most 286 literal/length and 30 offset codes are defined.
+                if counts & 0x1F > 29 || (counts >> 5) & 0x1F > 29 {
+                    return false;
+                }
+                self.read_dynamic_header(bits.pos).is_ok() && self.tables.lens[256] != 0
+            }
+            DEFLATE_BLOCKTYPE_UNCOMPRESSED => {
+                let padding = (8 - (bits.pos & 7) as u32) & 7;
+                if bits.read(padding) != 0 {
+                    return false;
+                }
+                let byte = (bits.pos >> 3) as usize;
+                match self.data.get(byte..byte + 4) {
+                    Some(lens) => lens[0] == !lens[2] && lens[1] == !lens[3],
+                    None => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    /// Decodes the chunk starting at the first plausible block in `from..to`, if any
+    /// decodes without error.
+    fn decode_speculatively(&mut self, from: u64, to: u64) -> Option<Chunk> {
+        for pos in from..to {
+            if self.is_block_start(pos)
+                && let Ok(chunk) = self.decode(pos, to, 0)
+            {
+                return Some(chunk);
+            }
+        }
+        None
+    }
+
+    /// Decodes blocks from bit `start` until one ends at or after bit `stop`, or the
+    /// stream ends. `block_index` is the index of the first block within its member.
+    fn decode(&mut self, start: u64, stop: u64, mut block_index: usize) -> Result<Chunk> {
+        let data = self.data;
+        let mut bits = BitReader { data, pos: start };
+        let mut chunk = Chunk {
+            start,
+            end: start,
+            // Room for a typical compression ratio, to avoid most reallocations.
+            symbols: Vec::with_capacity(
+                (stop.saturating_sub(start) / 2).min(self.limit as u64) as usize
+            ),
+            deepest: None,
+            members: Vec::new(),
+            first_block_index: block_index,
+            end_block_index: block_index,
+            member_start: None,
+            starts_with_stored_block: false,
+            reached_end: false,
+        };
+        loop {
+            let header_pos = bits.pos;
+            let header = bits.read(3);
+            if bits.overrun() {
+                return Err(self.truncated(block_index));
+            }
+            match (header >> 1) as u8 {
+                DEFLATE_BLOCKTYPE_UNCOMPRESSED => {
+                    if header_pos == start && header & 1 == 0 {
+                        let padding = (8 - (bits.pos & 7) as u32) & 7;
+                        chunk.starts_with_stored_block = bits.peek() & ((1 << padding) - 1) == 0;
+                    }
+                    self.decode_stored_block(&mut bits, &mut chunk, block_index)?
+                }
+                DEFLATE_BLOCKTYPE_STATIC_HUFFMAN => {
+                    let codes = &STATIC_HUFFMAN_DATA;
+                    decode_huffman_block(
+                        &mut bits,
+                        &mut chunk,
+                        &codes.litlen_decode_table,
+                        codes.litlen_tablebits,
+                        &codes.offset_decode_table,
+                        block_index,
+                        self.limit,
+                    )?
+                }
+                DEFLATE_BLOCKTYPE_DYNAMIC_HUFFMAN => {
+                    bits.pos = match self.read_dynamic_header(bits.pos) {
+                        Ok(pos) => pos,
+                        Err(DecompressResult::ShortInput) => {
+                            return Err(self.truncated(block_index));
+                        }
+                        Err(_) => {
+                            return Err(Error::InvalidHuffmanCode {
+                                at: Position {
+                                    bit_offset: bits.pos,
+                                    block_index,
+                                },
+                            });
+                        }
+                    };
+                    decode_huffman_block(
+                        &mut bits,
+                        &mut chunk,
+                        &self.tables.litlen_decode_table,
+                        self.tables.litlen_tablebits,
+                        &self.tables.offset_decode_table,
+                        block_index,
+                        self.limit,
+                    )?
+                }
+                _ => {
+                    return Err(Error::BadBlockType {
+                        at: Position {
+                            bit_offset: header_pos,
+                            block_index,
+                        },
+                    });
+                }
+            }
+            if chunk.symbols.len() > self.limit {
+                return Err(self.limit_exceeded());
+            }
+
+            if header & 1 == 0 {
+                block_index += 1;
+            } else if !self.gzip {
+                chunk.reached_end = true;
+                break;
+            } else {
+                bits.align_to_byte();
+                let trailer = (bits.pos >> 3) as usize;
+                let Some(footer) = data.get(trailer..trailer + GZIP_FOOTER_SIZE) else {
+                    return Err(self.truncated(block_index));
+                };
+                chunk.members.push(MemberEnd {
+                    out_end: chunk.symbols.len(),
+                    crc: u32::from_le_bytes(footer[..4].try_into().unwrap()),
+                    isize: u32::from_le_bytes(footer[4..].try_into().unwrap()),
+                    trailer,
+                    block_index,
+                    first_in_chunk: chunk.member_start.is_none(),
+                });
+
+                let next = trailer + GZIP_FOOTER_SIZE;
+                // Zero padding after the last member is skipped, as gzip(1) does.
+                if data[next..].iter().all(|&b| b == 0) {
+                    bits.pos = data.len() as u64 * 8;
+                    chunk.reached_end = true;
+                    break;
+                }
+                block_index = 0;
+                if data.len() - next < GZIP_MIN_OVERHEAD {
+                    return Err(self.truncated(block_index));
+                }
+                match parse_gzip_header(&data[next..], self.verify_gzip_header_crc, None) {
+                    Ok(header_len) => bits.pos = (next + header_len) as u64 * 8,
+                    Err(DecompressResult::BadData) => {
+                        return Err(Error::InvalidHeader {
+                            at: Position {
+                                bit_offset: next as u64 * 8,
+                                block_index,
+                            },
+                        });
+                    }
+                    Err(_) => return Err(self.truncated(block_index)),
+                }
+                chunk.member_start = Some(chunk.symbols.len());
+            }
+            if bits.pos >= stop {
+                break;
+            }
+        }
+        chunk.end = bits.pos;
+        chunk.end_block_index = block_index;
+        Ok(chunk)
+    }
+
+    fn decode_stored_block(
+        &self,
+        bits: &mut BitReader,
+        chunk: &mut Chunk,
+        block_index: usize,
+    ) -> Result<()> {
+        bits.align_to_byte();
+        let byte = (bits.pos >> 3) as usize;
+        let Some(lens) = self.data.get(byte..byte + 4) else {
+            return Err(self.truncated(block_index));
+        };
+        let len = u16::from_le_bytes([lens[0], lens[1]]);
+        let nlen = u16::from_le_bytes([lens[2], lens[3]]);
+        if len != !nlen {
+            return Err(Error::InvalidStoredBlock {
+                at: Position {
+                    bit_offset: bits.pos,
+                    block_index,
+                },
+            });
+        }
+        let body = byte + 4;
+        let Some(stored) = self.data.get(body..body + len as usize) else {
+            return Err(self.truncated(block_index));
+        };
+        chunk.symbols.extend(stored.iter().map(|&b| b as u16));
+        bits.pos = (body + len as usize) as u64 * 8;
+        Ok(())
+    }
+}
+
+/// Decodes the body of a Huffman block, writing a marker for every byte that lies
+/// before the start of the chunk.
+fn decode_huffman_block(
+    bits: &mut BitReader,
+    chunk: &mut Chunk,
+    litlen_decode_table: &[u32],
+    litlen_tablebits: usize,
+    offset_decode_table: &[u32],
+    block_index: usize,
+    limit: usize,
+) -> Result<()> {
+    let litlen_tablemask = (1u64 << litlen_tablebits) - 1;
+    let offset_tablemask = (1u64 << OFFSET_TABLEBITS) - 1;
+    let symbols = &mut chunk.symbols;
+    loop {
+        if bits.overrun() {
+            return Err(Error::TruncatedInput {
+                at: Position {
+                    bit_offset: bits.data.len() as u64 * 8,
+                    block_index,
+                },
+            });
+        }
+        let mut word = bits.peek();
+        let mut entry = litlen_decode_table[(word & litlen_tablemask) as usize];
+        if entry & HUFFDEC_SUBTABLE_POINTER != 0 {
+            let main_bits = entry & 0xFF;
+            bits.consume(main_bits);
+            word >>= main_bits;
+            let subtable_bits = (entry >> 8) & 0x3F;
+            entry = litlen_decode_table
+                [(entry >> 16) as usize + (word & ((1 << subtable_bits) - 1)) as usize];
+        }
+        let total_bits = entry & 0xFF;
+        if entry & HUFFDEC_LITERAL != 0 {
+            symbols.push(((entry >> 16) & 0xFF) as u16);
+            bits.consume(total_bits);
+            continue;
+        }
+        if entry & HUFFDEC_END_OF_BLOCK != 0 {
+            bits.consume(total_bits);
+            return Ok(());
+        }
+        let len = (entry >> 8) & 0xFF;
+        let length =
+            (entry >> 16) as usize + ((word >> len) & ((1 << (total_bits - len)) - 1)) as usize;
+        bits.consume(total_bits);
+
+        let mut word = bits.peek();
+        let mut entry = offset_decode_table[(word & offset_tablemask) as usize];
+        if entry & HUFFDEC_SUBTABLE_POINTER != 0 {
+            let main_bits = entry & 0xFF;
+            bits.consume(main_bits);
+            word >>= main_bits;
+            let subtable_bits = (entry >> 8) & 0x3F;
+            entry = offset_decode_table
+                [(entry >> 16) as usize + (word & ((1 << subtable_bits) - 1)) as usize];
+        }
+        let total_bits = entry & 0xFF;
+        let len = (entry >> 8) & 0xFF;
+        let distance =
+            (entry >> 16) as usize + ((word >> len) & ((1 << (total_bits - len)) - 1)) as usize;
+        bits.consume(total_bits);
+
+        let out_len = symbols.len();
+        if out_len + length > limit {
+            return Err(Error::MemoryLimitExceeded {
+                expected: limit.saturating_add(1),
+                limit,
+            });
+        }
+        let available = out_len - chunk.member_start.unwrap_or(0);
+        if distance <= available {
+            let src = out_len - distance;
+            if distance >= length {
+                symbols.extend_from_within(src..src + length);
+            } else {
+                for i in 0..length {
+                    symbols.push(symbols[src + i]);
+                }
+            }
+        } else if chunk.member_start.is_some() {
+            return Err(Error::DistanceTooFarBack {
+                distance,
+                available,
+                at: Position {
+                    bit_offset: bits.pos,
+                    block_index,
+                },
+            });
+        } else {
+            let depth = distance - out_len;
+            if chunk.deepest.as_ref().is_none_or(|r| depth > r.depth) {
+                chunk.deepest = Some(WindowRef {
+                    depth,
+                    distance,
+                    available: out_len,
+                    at: Position {
+                        bit_offset: bits.pos,
+                        block_index,
+                    },
+                });
+            }
+            for i in 0..length {
+                let symbol = if i < depth {
+                    MARKER_BASE + (WINDOW_SIZE - depth + i) as u16
+                } else {
+                    symbols[out_len + i - distance]
+                };
+                symbols.push(symbol);
+            }
+        }
+    }
+}
+
+/// A window preceding a chunk, laid out so that indexing it with a decoded symbol gives
+/// the byte the symbol stands for: the 256 byte values followed by the window itself.
+fn new_window() -> Box<[u8]> {
+    let mut window = vec![0u8; MARKER_BASE as usize + WINDOW_SIZE].into_boxed_slice();
+    for (i, byte) in window[..MARKER_BASE as usize].iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    window
+}
+
+/// The window preceding the output that follows `symbols`, given the window that
+/// precedes them.
+fn next_window(window: &[u8], symbols: &[u16]) -> Box<[u8]> {
+    let mut next = new_window();
+    let tail = &symbols[symbols.len().saturating_sub(WINDOW_SIZE)..];
+    let keep = WINDOW_SIZE - tail.len();
+    let (kept, resolved) = next[MARKER_BASE as usize..].split_at_mut(keep);
+    kept.copy_from_slice(&window[window.len() - keep..]);
+    for (byte, &symbol) in resolved.iter_mut().zip(tail) {
+        *byte = window[symbol as usize];
+    }
+    next
+}
+
+impl Decompressor {
+    /// Decompresses a whole raw DEFLATE stream, or with `gzip` every member of a gzip
+    /// stream, splitting the input into chunks of `chunk_size` bytes that are decoded
+    /// on the rayon thread pool. A raw stream may reference the preset dictionary. Fails
+    /// with [`Error::MemoryLimitExceeded`] once the output would exceed `limit` bytes.
+    pub(crate) fn decompress_parallel(
+        &self,
+        input: &[u8],
+        gzip: bool,
+        chunk_size: usize,
+        limit: usize,
+    ) -> Result<Vec<u8>> {
+        let verify_header_crc = self.verify_gzip_header_crc;
+        let start = if gzip {
+            let truncated = Error::TruncatedInput {
+                at: Position {
+                    bit_offset: input.len() as u64 * 8,
+                    block_index: 0,
+                },
+            };
+            if input.len() < GZIP_MIN_OVERHEAD {
+                return Err(truncated);
+            }
+            match parse_gzip_header(input, verify_header_crc, None) {
+                Ok(header_len) => header_len,
+                Err(DecompressResult::BadData) => {
+                    return Err(Error::InvalidHeader {
+                        at: Position::default(),
+                    });
+                }
+                Err(_) => return Err(truncated),
+            }
+        } else {
+            0
+        };
+
+        // Chunk `k` covers the input bits `boundaries[k]..boundaries[k + 1]`.
+        let mut boundaries: Vec<u64> = (start..input.len().max(start + 1))
+            .step_by(chunk_size.max(1))
+            .map(|byte| byte as u64 * 8)
+            .collect();
+        boundaries.push(input.len() as u64 * 8);
+        let num_chunks = boundaries.len() - 1;
+
+        let mut speculative: Vec<Option<Chunk>> = (0..num_chunks)
+            .into_par_iter()
+            .map_init(
+                || ChunkDecoder::new(input, gzip, verify_header_crc, limit),
+                |decoder, k| {
+                    if k == 0 {
+                        decoder.decode(boundaries[0], boundaries[1], 0).ok()
+                    } else {
+                        decoder.decode_speculatively(boundaries[k], boundaries[k + 1])
+                    }
+                },
+            )
+            .collect();
+
+        // Follow the chain of chunks from the start of the stream, decoding again any
+        // chunk that does not start where the previous one ended, and find the window
+        // preceding each.
+        let mut decoder = ChunkDecoder::new(input, gzip, verify_header_crc, limit);
+        let mut window = new_window();
+        let mut window_len = 0;
+        if !gzip {
+            let dictionary = &self.dictionary;
+            let start = window.len() - dictionary.len();
+            window[start..].copy_from_slice(dictionary);
+            window_len = dictionary.len();
+        }
+        let mut chunks = Vec::new();
+        let mut members = Vec::new();
+        let mut total_len = 0usize;
+        let mut block_index = 0;
+        let mut pos = boundaries[0];
+        let mut k = 0;
+        loop {
+            while k + 1 < num_chunks && boundaries[k + 1] <= pos {
+                k += 1;
+            }
+            let mut chunk = match speculative[k].take() {
+                Some(chunk) if chunk.starts_at(pos) => chunk,
+                _ => decoder.decode(pos, boundaries[k + 1], block_index)?,
+            };
+
+            let delta = block_index - chunk.first_block_index;
+            if let Some(deepest) = &chunk.deepest
+                && deepest.depth > window_len
+            {
+                return Err(Error::DistanceTooFarBack {
+                    distance: deepest.distance,
+                    available: deepest.available + window_len,
+                    at: Position {
+                        block_index: deepest.at.block_index + delta,
+                        ..deepest.at
+                    },
+                });
+            }
+            for member in &mut chunk.members {
+                member.out_end += total_len;
+                if member.first_in_chunk {
+                    member.block_index += delta;
+                }
+            }
+            members.append(&mut chunk.members);
+            block_index = match chunk.member_start {
+                Some(_) => chunk.end_block_index,
+                None => chunk.end_block_index + delta,
+            };
+            total_len += chunk.symbols.len();
+            if total_len > limit {
+                return Err(decoder.limit_exceeded());
+            }
+
+            let next = next_window(&window, &chunk.symbols);
+            window_len = match chunk.member_start {
+                Some(member_start) => chunk.symbols.len() - member_start,
+                None => window_len + chunk.symbols.len(),
+            }
+            .min(WINDOW_SIZE);
+            pos = chunk.end;
+            let reached_end = chunk.reached_end;
+            chunks.push((chunk.symbols, window));
+            window = next;
+            if reached_end {
+                break;
+            }
+        }
+        drop(speculative);
+
+        let mut output = Vec::new();
+        output
+            .try_reserve_exact(total_len)
+            .map_err(|_| Error::OutOfMemory)?;
+        let mut outputs = Vec::with_capacity(chunks.len());
+        let mut rest = &mut output.spare_capacity_mut()[..total_len];
+        for (symbols, _) in &chunks {
+            let (head, tail) = rest.split_at_mut(symbols.len());
+            outputs.push(head);
+            rest = tail;
+        }
+        chunks
+            .into_par_iter()
+            .zip(outputs)
+            .for_each(|((symbols, window), out)| {
+                for (byte, &symbol) in out.iter_mut().zip(&symbols) {
+                    byte.write(window[symbol as usize]);
+                }
+            });
+        unsafe {
+            output.set_len(total_len);
+        }
+
+        let mut member_start = 0;
+        let spans: Vec<_> = members
+            .iter()
+            .map(|member| {
+                let span = member_start..member.out_end;
+                member_start = member.out_end;
+                span
+            })
+            .collect();
+        // Parallel decompression is itself the opt-in to the thread pool, so the CRCs
+        // are computed there regardless of `set_parallel`.
+        let crcs: Vec<u32> = spans
+            .par_iter()
+            .map(|span| Checksum::Crc32.compute(&output[span.clone()], true))
+            .collect();
+        for ((member, span), actual) in members.iter().zip(spans).zip(crcs) {
+            let at = |offset: usize| Position {
+                bit_offset: (member.trailer + offset) as u64 * 8,
+                block_index: member.block_index,
+            };
+            if actual != member.crc {
+                return Err(Error::ChecksumMismatch {
+                    expected: member.crc,
+                    actual,
+                    at: at(0),
+                });
+            }
+            if span.len() as u32 != member.isize {
+                return Err(Error::SizeMismatch {
+                    expected: member.isize,
+                    actual: span.len() as u32,
+                    at: at(4),
+                });
+            }
+        }
+        Ok(output)
+    }
+}
@@ -0,0 +1,213 @@
+use super::{DecompressResult, Decompressor, DecompressorState};
+use crate::common::*;
+use crate::error::{Error, Position, Result};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::min;
+
+const WINDOW_SIZE: usize = 2 * DEFLATE_MAX_MATCH_OFFSET;
+
+/// Input added at a time to bytes held back from an earlier call; more than any
+/// dynamic block header needs.
+const CARRY_CHUNK: usize = 1024;
+
+/// Why [`Inflater::inflate`] returned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// All input was consumed and the stream is not finished yet.
+    NeedsInput,
+    /// The output is full and there is more to produce.
+    NeedsOutput,
+    /// The stream ended. Input past its end is left unconsumed.
+    StreamEnd,
+}
+
+/// Decompresses one raw DEFLATE stream handed over in pieces, as zlib's `inflate`
+/// does, into output buffers of any size.
+///
+/// The last 32 KiB of output are kept internally for back-references, so neither the
+/// input nor the output has to be held on to between calls. Input that was consumed
+/// but cannot be decoded yet, such as a block header cut short, is kept as well.
+pub struct Inflater {
+    decompressor: Decompressor,
+    /// The history, followed by output not yet handed out.
+    window: Vec<u8>,
+    read_pos: usize,
+    write_pos: usize,
+    /// Consumed input the decompressor has not loaded yet.
+    carry: Vec<u8>,
+    /// Stream offset of the first input byte the decompressor has not loaded.
+    loaded: u64,
+    total_in: u64,
+    total_out: u64,
+    done: bool,
+}
+
+crate::impl_default_new!(Inflater);
+
+impl Inflater {
+    pub fn new() -> Self {
+        Self {
+            decompressor: Decompressor::new(),
+            window: vec![0; WINDOW_SIZE],
+            read_pos: 0,
+            write_pos: 0,
+            carry: Vec::new(),
+            loaded: 0,
+            total_in: 0,
+            total_out: 0,
+            done: false,
+        }
+    }
+
+    /// Prepares to decompress a new stream, keeping the allocations.
+    pub fn reset(&mut self) {
+        self.decompressor.state = DecompressorState::Start;
+        self.decompressor.is_final_block = false;
+        self.decompressor.block_index = 0;
+        self.decompressor.bitbuf = 0;
+        self.decompressor.bitsleft = 0;
+        self.decompressor.failure = None;
+        self.read_pos = 0;
+        self.write_pos = 0;
+        self.carry.clear();
+        self.loaded = 0;
+        self.total_in = 0;
+        self.total_out = 0;
+        self.done = false;
+    }
+
+    /// Input bytes consumed since the stream started.
+    pub fn total_in(&self) -> u64 {
+        self.total_in
+    }
+
+    /// Output bytes produced since the stream started.
+    pub fn total_out(&self) -> u64 {
+        self.total_out
+    }
+
+    /// Whether the end of the stream has been decoded, though output may still be
+    /// waiting to be handed out.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Decompresses as much of `input` into `output` as possible and returns the
+    /// number of bytes consumed and produced.
+    ///
+    /// Unconsumed input must be passed again, followed by what comes next. Once
+    /// [`Status::StreamEnd`] is returned, the unconsumed input is whatever follows
+    /// the stream, such as a trailer, and further calls produce nothing.
+    pub fn inflate(&mut self, input: &[u8], output: &mut [u8]) -> Result<(usize, usize, Status)> {
+        let mut consumed = 0;
+        let mut produced = 0;
+        let mut starved = false;
+        let status = loop {
+            let count = min(output.len() - produced, self.write_pos - self.read_pos);
+            output[produced..produced + count]
+                .copy_from_slice(&self.window[self.read_pos..self.read_pos + count]);
+            self.read_pos += count;
+            produced += count;
+
+            if self.read_pos < self.write_pos {
+                self.unread_lookahead(&mut consumed);
+                break Status::NeedsOutput;
+            }
+            if self.done {
+                break Status::StreamEnd;
+            }
+            if starved {
+                break Status::NeedsInput;
+            }
+
+            // Compact before the window can no longer hold a maximum-length match;
+            // the decompressor stops rather than splitting one across calls.
+            if self.write_pos + DEFLATE_MAX_MATCH_LEN > self.window.len() {
+                let shift = self.write_pos - DEFLATE_MAX_MATCH_OFFSET;
+                self.window.copy_within(shift..self.write_pos, 0);
+                self.write_pos -= shift;
+                self.read_pos -= shift;
+            }
+
+            let res = if self.carry.is_empty() {
+                let (res, n) = self.decode_input(&input[consumed..])?;
+                consumed += n;
+                if res == DecompressResult::ShortInput {
+                    // Whatever was not loaded is the start of something that needs
+                    // more input, such as a dynamic block header.
+                    self.carry.extend_from_slice(&input[consumed..]);
+                    consumed = input.len();
+                }
+                res
+            } else {
+                let take = min(input.len() - consumed, CARRY_CHUNK);
+                self.carry
+                    .extend_from_slice(&input[consumed..consumed + take]);
+                consumed += take;
+                let carry = core::mem::take(&mut self.carry);
+                let result = self.decode_input(&carry);
+                self.carry = carry;
+                let (res, n) = result?;
+                self.carry.drain(..n);
+                res
+            };
+
+            match res {
+                DecompressResult::ShortInput => starved = consumed == input.len(),
+                _ if self.decompressor.state == DecompressorState::Done => {
+                    self.done = true;
+                    self.unread_lookahead(&mut consumed);
+                    // Bytes held back from earlier calls always belong to the stream,
+                    // so the bytes past its end all came from this call.
+                    self.carry.clear();
+                    self.decompressor.bitbuf = 0;
+                    self.decompressor.bitsleft = 0;
+                }
+                _ => {}
+            }
+        };
+
+        self.total_in += consumed as u64;
+        self.total_out += produced as u64;
+        Ok((consumed, produced, status))
+    }
+
+    /// Runs the decompressor on `input` and returns the number of bytes it loaded.
+    fn decode_input(&mut self, input: &[u8]) -> Result<(DecompressResult, usize)> {
+        let (res, in_consumed, _) =
+            self.decompressor
+                .decompress_streaming(input, &mut self.window, &mut self.write_pos);
+        if res == DecompressResult::BadData {
+            let at = Position {
+                bit_offset: self.loaded * 8,
+                block_index: self.decompressor.block_index,
+            };
+            return Err(match self.decompressor.failure.take() {
+                Some(failure) => failure.into_error(self.loaded),
+                None => Error::BadBlockType { at },
+            });
+        }
+        self.loaded += in_consumed as u64;
+        Ok((res, in_consumed))
+    }
+
+    /// Moves the whole bytes the decompressor read ahead back in front of the held
+    /// back input, then gives back as much of that as came from this call's input:
+    /// they may lie past the end of the stream, which only later input reveals.
+    fn unread_lookahead(&mut self, consumed: &mut usize) {
+        let partial = self.decompressor.bitsleft & 7;
+        let n = (self.decompressor.bitsleft / 8) as usize;
+        if n > 0 {
+            let bitbuf = self.decompressor.bitbuf >> partial;
+            self.carry
+                .splice(0..0, (0..n).map(|i| (bitbuf >> (i * 8)) as u8));
+            self.decompressor.bitbuf &= (1 << partial) - 1;
+            self.decompressor.bitsleft = partial;
+            self.loaded -= n as u64;
+        }
+        let back = min(self.carry.len(), *consumed);
+        self.carry.truncate(self.carry.len() - back);
+        *consumed -= back;
+    }
+}
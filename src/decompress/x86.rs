@@ -7,9 +7,10 @@ use crate::decompress::{
     DEFLATE_BLOCKTYPE_DYNAMIC_HUFFMAN, DEFLATE_BLOCKTYPE_STATIC_HUFFMAN,
     DEFLATE_BLOCKTYPE_UNCOMPRESSED, DecompressResult, Decompressor,
 };
+use crate::error::Reason;
 
 #[cfg(target_arch = "x86_64")]
-use std::arch::x86_64::*;
+use core::arch::x86_64::*;
 
 macro_rules! refill_bits {
     ($input:expr, $in_idx:expr, $bitbuf:expr, $bitsleft:expr) => {
@@ -85,7 +86,7 @@ macro_rules! decompress_offset_rotated {
             if $copied < $length {
                 let mut tmp = [0u8; 16];
                 _mm_storeu_si128(tmp.as_mut_ptr() as *mut __m128i, $v_pat);
-                std::ptr::copy_nonoverlapping(tmp.as_ptr(), $out_next.add($copied), $length - $copied);
+                core::ptr::copy_nonoverlapping(tmp.as_ptr(), $out_next.add($copied), $length - $copied);
             }
         }
     }
@@ -124,7 +125,7 @@ macro_rules! decompress_offset_simple {
             if $copied < $length {
                 let mut tmp = [0u8; 16];
                 _mm_storeu_si128(tmp.as_mut_ptr() as *mut __m128i, $v_pat);
-                std::ptr::copy_nonoverlapping(tmp.as_ptr(), $out_next.add($copied), $length - $copied);
+                core::ptr::copy_nonoverlapping(tmp.as_ptr(), $out_next.add($copied), $length - $copied);
             }
         }
     }
@@ -198,7 +199,7 @@ unsafe fn decompress_offset_18(out_next: *mut u8, src: *const u8, v: __m128i, le
     }
 
     if copied < length {
-        std::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
+        core::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
     }
 }
 
@@ -218,7 +219,7 @@ unsafe fn decompress_fill_pattern_16(out_next: *mut u8, v: __m128i, src: *const
         i += 16;
     }
     if i < length {
-        std::ptr::copy_nonoverlapping(src.add(i), out_next.add(i), length - i);
+        core::ptr::copy_nonoverlapping(src.add(i), out_next.add(i), length - i);
     }
 }
 
@@ -244,13 +245,13 @@ unsafe fn decompress_fill_pattern(out_next: *mut u8, v_pattern: __m128i, length:
     }
     let pattern = _mm_cvtsi128_si64(v_pattern) as u64;
     while i + 8 <= length {
-        std::ptr::write_unaligned(out_next.add(i) as *mut u64, pattern);
+        core::ptr::write_unaligned(out_next.add(i) as *mut u64, pattern);
         i += 8;
     }
 
     if i < length {
         if length <= 250 {
-            std::ptr::write_unaligned(out_next.add(i) as *mut u64, pattern);
+            core::ptr::write_unaligned(out_next.add(i) as *mut u64, pattern);
         } else {
             while i < length {
                 *out_next.add(i) = (pattern >> ((i & 7) * 8)) as u8;
@@ -454,7 +455,7 @@ unsafe fn decompress_offset_alignr_cycle<const SHIFT: i32>(
     }
 
     if copied < length {
-        std::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
+        core::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
     }
 }
 
@@ -522,7 +523,7 @@ unsafe fn decompress_offset_cycle3<const SHIFT: i32>(
     }
 
     if copied < length {
-        std::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
+        core::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
     }
 }
 
@@ -577,7 +578,7 @@ unsafe fn decompress_offset_cycle4<const SHIFT: i32>(
     }
 
     if copied < length {
-        std::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
+        core::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
     }
 }
 
@@ -979,7 +980,7 @@ unsafe fn decompress_offset_17(out_next: *mut u8, src: *const u8, v: __m128i, le
     }
 
     if copied < length {
-        std::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
+        core::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
     }
 }
 
@@ -1026,7 +1027,7 @@ unsafe fn decompress_offset_20(out_next: *mut u8, src: *const u8, v: __m128i, le
     }
 
     if copied < length {
-        std::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
+        core::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
     }
 }
 
@@ -1071,7 +1072,7 @@ unsafe fn decompress_offset_24(out_next: *mut u8, src: *const u8, v: __m128i, le
     }
 
     if copied < length {
-        std::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
+        core::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
     }
 }
 
@@ -1180,7 +1181,7 @@ unsafe fn decompress_offset_30(out_next: *mut u8, src: *const u8, v: __m128i, le
     }
 
     if copied < length {
-        std::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
+        core::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
     }
 }
 
@@ -1209,7 +1210,7 @@ unsafe fn decompress_offset_32(out_next: *mut u8, src: *const u8, v: __m128i, le
     }
 
     if copied < length {
-        std::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
+        core::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
     }
 }
 
@@ -1269,7 +1270,7 @@ unsafe fn decompress_offset_28(out_next: *mut u8, src: *const u8, v: __m128i, le
     }
 
     if copied < length {
-        std::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
+        core::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
     }
 }
 
@@ -1341,7 +1342,7 @@ unsafe fn decompress_offset_36(out_next: *mut u8, src: *const u8, v: __m128i, le
     }
 
     if copied < length {
-        std::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
+        core::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
     }
 }
 
@@ -1387,7 +1388,7 @@ unsafe fn decompress_offset_40(out_next: *mut u8, src: *const u8, v: __m128i, le
     }
 
     if copied < length {
-        std::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
+        core::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
     }
 }
 
@@ -1449,7 +1450,7 @@ unsafe fn decompress_offset_42(out_next: *mut u8, src: *const u8, v: __m128i, le
     }
 
     if copied < length {
-        std::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
+        core::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
     }
 }
 
@@ -1536,7 +1537,7 @@ unsafe fn decompress_offset_44(out_next: *mut u8, src: *const u8, v: __m128i, le
     }
 
     if copied < length {
-        std::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
+        core::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
     }
 }
 
@@ -1579,7 +1580,7 @@ unsafe fn decompress_offset_48(out_next: *mut u8, src: *const u8, v: __m128i, le
     }
 
     if copied < length {
-        std::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
+        core::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
     }
 }
 
@@ -1675,7 +1676,7 @@ unsafe fn decompress_offset_52(out_next: *mut u8, src: *const u8, v: __m128i, le
     }
 
     if copied < length {
-        std::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
+        core::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
     }
 }
 
@@ -1735,7 +1736,7 @@ unsafe fn decompress_offset_56(out_next: *mut u8, src: *const u8, v: __m128i, le
     }
 
     if copied < length {
-        std::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
+        core::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
     }
 }
 
@@ -1844,7 +1845,7 @@ unsafe fn decompress_offset_60(out_next: *mut u8, src: *const u8, v: __m128i, le
     }
 
     if copied < length {
-        std::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
+        core::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
     }
 }
 
@@ -1895,7 +1896,7 @@ unsafe fn decompress_offset_64(out_next: *mut u8, src: *const u8, v: __m128i, le
     }
 
     if copied < length {
-        std::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
+        core::ptr::copy_nonoverlapping(src.add(copied), out_next.add(copied), length - copied);
     }
 }
 
@@ -1907,7 +1908,7 @@ unsafe fn copy_match_bmi2(out_next: *mut u8, src: *const u8, offset: usize, leng
         _mm_storeu_si128(out_next as *mut __m128i, v);
         if length > 16 {
             if offset >= length {
-                std::ptr::copy_nonoverlapping(src.add(16), out_next.add(16), length - 16);
+                core::ptr::copy_nonoverlapping(src.add(16), out_next.add(16), length - 16);
             } else {
                 match offset {
                     34 => decompress_offset_cycle3::<14>(out_next, src, v, length),
@@ -1980,13 +1981,13 @@ unsafe fn copy_match_bmi2(out_next: *mut u8, src: *const u8, offset: usize, leng
                     32 => decompress_offset_32(out_next, src, v, length),
                     40 => decompress_offset_40(out_next, src, v, length),
                     _ => {
-                        let init = std::cmp::min(offset, length);
-                        std::ptr::copy_nonoverlapping(src, out_next, init);
+                        let init = core::cmp::min(offset, length);
+                        core::ptr::copy_nonoverlapping(src, out_next, init);
 
                         let mut copied = init;
                         while copied < length {
-                            let to_copy = std::cmp::min(length - copied, copied);
-                            std::ptr::copy_nonoverlapping(out_next, out_next.add(copied), to_copy);
+                            let to_copy = core::cmp::min(length - copied, copied);
+                            core::ptr::copy_nonoverlapping(out_next, out_next.add(copied), to_copy);
                             copied += to_copy;
                         }
                     }
@@ -1994,17 +1995,17 @@ unsafe fn copy_match_bmi2(out_next: *mut u8, src: *const u8, offset: usize, leng
             }
         }
     } else if offset >= length {
-        std::ptr::copy_nonoverlapping(src, out_next, length);
+        core::ptr::copy_nonoverlapping(src, out_next, length);
     } else {
         match offset {
             1 => {
                 let b = *src;
-                std::ptr::write_bytes(out_next, b, length);
+                core::ptr::write_bytes(out_next, b, length);
             }
             2 | 4 => {
                 let v_pattern = match offset {
-                    2 => _mm_set1_epi16(std::ptr::read_unaligned(src as *const u16) as i16),
-                    4 => _mm_set1_epi32(std::ptr::read_unaligned(src as *const u32) as i32),
+                    2 => _mm_set1_epi16(core::ptr::read_unaligned(src as *const u16) as i16),
+                    4 => _mm_set1_epi32(core::ptr::read_unaligned(src as *const u32) as i32),
                     _ => unreachable!(),
                 };
                 decompress_fill_pattern(out_next, v_pattern, length);
@@ -2022,7 +2023,7 @@ unsafe fn copy_match_bmi2(out_next: *mut u8, src: *const u8, offset: usize, leng
                 decompress_offset_7(out_next, src, length);
             }
             8 => {
-                let val = std::ptr::read_unaligned(src as *const u64);
+                let val = core::ptr::read_unaligned(src as *const u64);
                 let v_pattern = _mm_set1_epi64x(val as i64);
                 decompress_fill_pattern(out_next, v_pattern, length);
             }
@@ -2050,8 +2051,8 @@ unsafe fn copy_match_bmi2(out_next: *mut u8, src: *const u8, offset: usize, leng
             _ => {
                 let mut copied = 0;
                 while copied + 8 <= length {
-                    let val = std::ptr::read_unaligned(src.add(copied) as *const u64);
-                    std::ptr::write_unaligned(out_next.add(copied) as *mut u64, val);
+                    let val = core::ptr::read_unaligned(src.add(copied) as *const u64);
+                    core::ptr::write_unaligned(out_next.add(copied) as *mut u64, val);
                     copied += 8;
                 }
                 while copied < length {
@@ -2097,21 +2098,21 @@ pub unsafe fn decompress_bmi2_ptr(
                 bitbuf = 0;
                 bitsleft = 0;
                 if in_idx + 4 > in_len {
-                    return (DecompressResult::BadData, 0, 0);
+                    return (DecompressResult::ShortInput, in_len, 0);
                 }
                 let len = u16::from_le_bytes([input[in_idx], input[in_idx + 1]]) as usize;
                 let nlen = u16::from_le_bytes([input[in_idx + 2], input[in_idx + 3]]) as usize;
-                in_idx += 4;
                 if len != (!nlen & 0xFFFF) {
-                    return (DecompressResult::BadData, 0, 0);
+                    return (d.fail(Reason::InvalidStoredBlock, in_idx, 0), in_idx, 0);
                 }
+                in_idx += 4;
                 if out_idx + len > out_len {
-                    return (DecompressResult::InsufficientSpace, 0, 0);
+                    return (DecompressResult::InsufficientSpace, in_idx, 0);
                 }
                 if in_idx + len > in_len {
-                    return (DecompressResult::BadData, 0, 0);
+                    return (DecompressResult::ShortInput, in_len, 0);
                 }
-                std::ptr::copy_nonoverlapping(
+                core::ptr::copy_nonoverlapping(
                     input.as_ptr().add(in_idx),
                     out_ptr.add(out_idx),
                     len,
@@ -2127,7 +2128,7 @@ pub unsafe fn decompress_bmi2_ptr(
                     bitbuf = d.bitbuf;
                     bitsleft = d.bitsleft;
                     if res != DecompressResult::Success {
-                        return (res, 0, 0);
+                        return (res, in_idx, 0);
                     }
                 } else {
                     d.load_static_huffman_codes();
@@ -2136,6 +2137,7 @@ pub unsafe fn decompress_bmi2_ptr(
                 #[allow(clippy::never_loop)]
                 loop {
                     let mut eob_found = false;
+                    let mut too_far = None;
                     unsafe {
                         let in_ptr_start = input.as_ptr();
                         let in_ptr_end = in_ptr_start.add(in_len);
@@ -2180,6 +2182,7 @@ pub unsafe fn decompress_bmi2_ptr(
                                         if entry & HUFFDEC_END_OF_BLOCK != 0 {
                                             bitbuf >>= entry as u8;
                                             bitsleft -= entry & 0xFF;
+                                            eob_found = true;
                                             break;
                                         }
                                         bitbuf = saved_bitbuf;
@@ -2253,11 +2256,20 @@ pub unsafe fn decompress_bmi2_ptr(
 
                                 let current_out_idx = out_next.offset_from(out_ptr_start) as usize;
                                 if offset > current_out_idx {
-                                    break;
+                                    if offset > current_out_idx + d.dictionary_reach() {
+                                        too_far = Some((offset, current_out_idx));
+                                        break;
+                                    }
+                                    d.copy_match_from_dictionary(
+                                        out_ptr_start,
+                                        current_out_idx,
+                                        offset,
+                                        length,
+                                    );
+                                } else {
+                                    let src = out_next.sub(offset);
+                                    copy_match_bmi2(out_next, src, offset, length);
                                 }
-
-                                let src = out_next.sub(offset);
-                                copy_match_bmi2(out_next, src, offset, length);
                                 out_next = out_next.add(length);
                             }
                         }
@@ -2268,6 +2280,13 @@ pub unsafe fn decompress_bmi2_ptr(
                     if eob_found {
                         break;
                     }
+                    if let Some((distance, available)) = too_far {
+                        let reason = Reason::DistanceTooFarBack {
+                            distance,
+                            available,
+                        };
+                        return (d.fail(reason, in_idx, bitsleft), in_idx, out_idx);
+                    }
 
                     d.bitbuf = bitbuf;
                     d.bitsleft = bitsleft;
@@ -2292,8 +2311,15 @@ pub unsafe fn decompress_bmi2_ptr(
                     break;
                 }
             }
-            _ => return (DecompressResult::BadData, 0, 0),
+            _ => {
+                let res = d.fail(Reason::BadBlockType, in_idx, bitsleft + 3);
+                return (res, in_idx, 0);
+            }
         }
+        d.block_index += 1;
     }
+    // Whole bytes still in the bit buffer were read ahead and belong to whatever
+    // follows the stream.
+    in_idx -= (bitsleft / 8) as usize;
     (DecompressResult::Success, in_idx, out_idx)
 }
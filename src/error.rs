@@ -0,0 +1,294 @@
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io;
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Where in the compressed input a decoding error was detected.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Position {
+    /// Offset in bits from the start of the input.
+    pub bit_offset: u64,
+    /// Zero-based index of the DEFLATE block being decoded. Errors in a zlib or gzip
+    /// header or trailer report the last block reached, or 0 if none was.
+    pub block_index: usize,
+}
+
+impl Position {
+    /// Offset of the byte containing [`bit_offset`](Self::bit_offset).
+    pub fn byte_offset(&self) -> u64 {
+        self.bit_offset / 8
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "byte {} (bit {}), block {}",
+            self.byte_offset(),
+            self.bit_offset % 8,
+            self.block_index
+        )
+    }
+}
+
+/// Errors returned by [`Compressor`](crate::Compressor) and
+/// [`Decompressor`](crate::Decompressor).
+///
+/// With the `std` feature, converts into `io::Error`, keeping the variant as the inner
+/// error, so it can be propagated with `?` from functions returning `io::Result`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// A block header has the reserved block type 3.
+    BadBlockType {
+        at: Position,
+    },
+    /// A dynamic block describes an invalid Huffman code.
+    InvalidHuffmanCode {
+        at: Position,
+    },
+    /// A stored block's LEN and NLEN fields are not complements of each other.
+    InvalidStoredBlock {
+        at: Position,
+    },
+    /// A match refers back further than the data decoded so far.
+    DistanceTooFarBack {
+        distance: usize,
+        available: usize,
+        at: Position,
+    },
+    /// The zlib or gzip header is malformed.
+    InvalidHeader {
+        at: Position,
+    },
+    /// A zlib stream needs a preset dictionary other than the one set, if any.
+    DictionaryMismatch {
+        expected: u32,
+        actual: Option<u32>,
+        at: Position,
+    },
+    /// The Adler-32 or CRC32 in the trailer does not match the decompressed data.
+    ChecksumMismatch {
+        expected: u32,
+        actual: u32,
+        at: Position,
+    },
+    /// The gzip ISIZE trailer does not match the decompressed size modulo 2^32.
+    SizeMismatch {
+        expected: u32,
+        actual: u32,
+        at: Position,
+    },
+    /// The input ended before the stream did.
+    TruncatedInput {
+        at: Position,
+    },
+    /// The decompressed data does not fit in the output buffer or expected size.
+    OutputTooSmall {
+        at: Position,
+    },
+    /// The compressed data does not fit in the output buffer.
+    InsufficientSpace,
+    CompressionFailed,
+    InvalidLevel(i32),
+    /// Input and output buffers passed to an `_into` method overlap.
+    OverlappingBuffers,
+    /// The expected size is larger than the input can plausibly expand to, as limited
+    /// by [`set_limit_ratio`](crate::Decompressor::set_limit_ratio). For the `_to_vec`
+    /// methods, `expected` is the smallest size that would have been needed.
+    ExpectedSizeTooLarge {
+        expected: usize,
+        input_len: usize,
+    },
+    /// The expected size is above
+    /// [`set_max_memory_limit`](crate::Decompressor::set_max_memory_limit). For the
+    /// `_to_vec` methods, `expected` is the smallest size that would have been needed.
+    MemoryLimitExceeded {
+        expected: usize,
+        limit: usize,
+    },
+    OutOfMemory,
+    /// A parameter such as a [`GzipHeader`](crate::GzipHeader) field cannot be encoded.
+    InvalidParameter(&'static str),
+}
+
+impl Error {
+    /// The [`io::ErrorKind`] this error converts to: `InvalidData` for corrupt input,
+    /// `InvalidInput` for bad arguments and `Other` otherwise.
+    #[cfg(feature = "std")]
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            Error::InsufficientSpace | Error::CompressionFailed | Error::OutOfMemory => {
+                io::ErrorKind::Other
+            }
+            Error::InvalidLevel(_)
+            | Error::OverlappingBuffers
+            | Error::ExpectedSizeTooLarge { .. }
+            | Error::MemoryLimitExceeded { .. }
+            | Error::InvalidParameter(_) => io::ErrorKind::InvalidInput,
+            _ => io::ErrorKind::InvalidData,
+        }
+    }
+
+    /// Where decoding failed, for errors caused by the compressed input.
+    pub fn position(&self) -> Option<Position> {
+        match *self {
+            Error::BadBlockType { at }
+            | Error::InvalidHuffmanCode { at }
+            | Error::InvalidStoredBlock { at }
+            | Error::DistanceTooFarBack { at, .. }
+            | Error::InvalidHeader { at }
+            | Error::DictionaryMismatch { at, .. }
+            | Error::ChecksumMismatch { at, .. }
+            | Error::SizeMismatch { at, .. }
+            | Error::TruncatedInput { at }
+            | Error::OutputTooSmall { at } => Some(at),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::BadBlockType { at } => write!(f, "invalid block type at {at}"),
+            Error::InvalidHuffmanCode { at } => write!(f, "invalid Huffman code at {at}"),
+            Error::InvalidStoredBlock { at } => {
+                write!(f, "stored block length check failed at {at}")
+            }
+            Error::DistanceTooFarBack {
+                distance,
+                available,
+                at,
+            } => write!(
+                f,
+                "match distance {distance} exceeds the {available} bytes available at {at}"
+            ),
+            Error::InvalidHeader { at } => write!(f, "invalid header at {at}"),
+            Error::DictionaryMismatch {
+                expected,
+                actual: Some(actual),
+                at,
+            } => write!(
+                f,
+                "stream needs dictionary {expected:08x}, but {actual:08x} is set, at {at}"
+            ),
+            Error::DictionaryMismatch {
+                expected,
+                actual: None,
+                at,
+            } => write!(f, "stream needs dictionary {expected:08x} at {at}"),
+            Error::ChecksumMismatch {
+                expected,
+                actual,
+                at,
+            } => write!(
+                f,
+                "checksum mismatch at {at}: expected {expected:08x}, got {actual:08x}"
+            ),
+            Error::SizeMismatch {
+                expected,
+                actual,
+                at,
+            } => write!(
+                f,
+                "size mismatch at {at}: expected {expected}, got {actual}"
+            ),
+            Error::TruncatedInput { at } => write!(f, "truncated input at {at}"),
+            Error::OutputTooSmall { at } => write!(f, "output buffer too small at {at}"),
+            Error::InsufficientSpace => f.write_str("Insufficient space"),
+            Error::CompressionFailed => f.write_str("Compression failed"),
+            Error::InvalidLevel(_) => f.write_str("Compression level must be between 0 and 12"),
+            Error::OverlappingBuffers => f.write_str("Input and output buffers overlap"),
+            Error::ExpectedSizeTooLarge {
+                expected,
+                input_len,
+            } => write!(
+                f,
+                "Expected size {expected} exceeds safety limit for input size {input_len}"
+            ),
+            Error::MemoryLimitExceeded { expected, limit } => write!(
+                f,
+                "Expected size {expected} exceeds maximum memory limit {limit}"
+            ),
+            Error::OutOfMemory => f.write_str("memory allocation failed"),
+            Error::InvalidParameter(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
+
+#[cfg(feature = "std")]
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        io::Error::new(err.kind(), err)
+    }
+}
+
+/// What the decompressor found wrong with its input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Reason {
+    BadBlockType,
+    InvalidHuffmanCode,
+    InvalidStoredBlock,
+    DistanceTooFarBack { distance: usize, available: usize },
+    InvalidHeader,
+    DictionaryMismatch { expected: u32, actual: Option<u32> },
+    ChecksumMismatch { expected: u32, actual: u32 },
+    SizeMismatch { expected: u32, actual: u32 },
+}
+
+/// A decoding error as recorded by the internal decompressor, located relative to the
+/// input of the call that detected it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Failure {
+    pub reason: Reason,
+    /// Input bytes loaded by the failing call when the error was detected.
+    pub in_idx: usize,
+    /// How many of the loaded bits had not been consumed.
+    pub pending_bits: u32,
+    pub block_index: usize,
+}
+
+impl Failure {
+    /// Converts to a public error, given the offset of the failing call's input in
+    /// the whole stream.
+    pub(crate) fn into_error(self, base: u64) -> Error {
+        let at = Position {
+            bit_offset: ((base + self.in_idx as u64) * 8).saturating_sub(self.pending_bits as u64),
+            block_index: self.block_index,
+        };
+        match self.reason {
+            Reason::BadBlockType => Error::BadBlockType { at },
+            Reason::InvalidHuffmanCode => Error::InvalidHuffmanCode { at },
+            Reason::InvalidStoredBlock => Error::InvalidStoredBlock { at },
+            Reason::DistanceTooFarBack {
+                distance,
+                available,
+            } => Error::DistanceTooFarBack {
+                distance,
+                available,
+                at,
+            },
+            Reason::InvalidHeader => Error::InvalidHeader { at },
+            Reason::DictionaryMismatch { expected, actual } => Error::DictionaryMismatch {
+                expected,
+                actual,
+                at,
+            },
+            Reason::ChecksumMismatch { expected, actual } => Error::ChecksumMismatch {
+                expected,
+                actual,
+                at,
+            },
+            Reason::SizeMismatch { expected, actual } => Error::SizeMismatch {
+                expected,
+                actual,
+                at,
+            },
+        }
+    }
+}
@@ -0,0 +1,358 @@
+use crate::common::*;
+use crate::decompress::DecompressResult;
+use crate::error::{Error, Position, Result};
+use alloc::vec::Vec;
+
+/// The fields of a gzip member header (RFC 1952).
+///
+/// Built with the `with_*` methods and passed to
+/// [`Compressor::set_gzip_header`](crate::Compressor::set_gzip_header) or
+/// [`GzipEncoder::with_header`](crate::stream::GzipEncoder::with_header). The default
+/// header is the one written when none is set: no optional fields, MTIME 0 and
+/// OS 255 (unknown).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GzipHeader {
+    text: bool,
+    mtime: u32,
+    xfl: Option<u8>,
+    os: u8,
+    extra: Option<Vec<u8>>,
+    filename: Option<Vec<u8>>,
+    comment: Option<Vec<u8>>,
+    header_crc: bool,
+}
+
+impl Default for GzipHeader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GzipHeader {
+    pub fn new() -> Self {
+        Self {
+            text: false,
+            mtime: GZIP_MTIME_UNAVAILABLE,
+            xfl: None,
+            os: GZIP_OS_UNKNOWN,
+            extra: None,
+            filename: None,
+            comment: None,
+            header_crc: false,
+        }
+    }
+
+    /// Sets FTEXT, hinting that the data is probably text.
+    pub fn with_text(mut self, text: bool) -> Self {
+        self.text = text;
+        self
+    }
+
+    /// Sets MTIME, the modification time in seconds since the Unix epoch.
+    pub fn with_mtime(mut self, mtime: u32) -> Self {
+        self.mtime = mtime;
+        self
+    }
+
+    /// Overrides XFL, which is otherwise derived from the compression level.
+    pub fn with_xfl(mut self, xfl: u8) -> Self {
+        self.xfl = Some(xfl);
+        self
+    }
+
+    pub fn with_os(mut self, os: u8) -> Self {
+        self.os = os;
+        self
+    }
+
+    /// Sets the raw FEXTRA payload, replacing any subfields added so far.
+    pub fn with_extra(mut self, extra: &[u8]) -> Self {
+        self.extra = Some(extra.to_vec());
+        self
+    }
+
+    /// Appends an FEXTRA subfield with the two-byte identifier `id`.
+    pub fn with_extra_field(mut self, id: [u8; 2], data: &[u8]) -> Self {
+        let extra = self.extra.get_or_insert_with(Vec::new);
+        extra.extend_from_slice(&id);
+        extra.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        extra.extend_from_slice(data);
+        self
+    }
+
+    /// Sets FNAME, the original file name. It must not contain a zero byte.
+    pub fn with_filename(mut self, filename: &[u8]) -> Self {
+        self.filename = Some(filename.to_vec());
+        self
+    }
+
+    /// Sets FCOMMENT. It must not contain a zero byte.
+    pub fn with_comment(mut self, comment: &[u8]) -> Self {
+        self.comment = Some(comment.to_vec());
+        self
+    }
+
+    /// Controls whether the header ends with FHCRC, the low 16 bits of its CRC32.
+    pub fn with_header_crc(mut self, header_crc: bool) -> Self {
+        self.header_crc = header_crc;
+        self
+    }
+
+    pub fn text(&self) -> bool {
+        self.text
+    }
+
+    pub fn mtime(&self) -> u32 {
+        self.mtime
+    }
+
+    pub fn xfl(&self) -> Option<u8> {
+        self.xfl
+    }
+
+    pub fn os(&self) -> u8 {
+        self.os
+    }
+
+    pub fn extra(&self) -> Option<&[u8]> {
+        self.extra.as_deref()
+    }
+
+    /// Iterates over the FEXTRA subfields as `(id, data)` pairs. Iteration stops at
+    /// the first subfield whose length runs past the end of the extra field.
+    pub fn extra_fields(&self) -> GzipExtraFields<'_> {
+        GzipExtraFields {
+            data: self.extra.as_deref().unwrap_or(&[]),
+        }
+    }
+
+    pub fn filename(&self) -> Option<&[u8]> {
+        self.filename.as_deref()
+    }
+
+    pub fn comment(&self) -> Option<&[u8]> {
+        self.comment.as_deref()
+    }
+
+    pub fn header_crc(&self) -> bool {
+        self.header_crc
+    }
+
+    /// Parses the header at the start of `data`, returning it along with its length in
+    /// bytes. When `verify_crc` is set, an FHCRC that does not match the header is
+    /// rejected; otherwise it is skipped like zlib and libdeflate do.
+    pub fn parse(data: &[u8], verify_crc: bool) -> Result<(Self, usize)> {
+        let mut header = Self::new();
+        match parse_header(data, verify_crc, Some(&mut header)) {
+            Ok(len) => Ok((header, len)),
+            Err(DecompressResult::ShortInput) => Err(Error::TruncatedInput {
+                at: Position {
+                    bit_offset: data.len() as u64 * 8,
+                    block_index: 0,
+                },
+            }),
+            Err(_) => Err(Error::InvalidHeader {
+                at: Position::default(),
+            }),
+        }
+    }
+
+    /// Checks that the header can be encoded: FEXTRA fits in its 16-bit length and
+    /// FNAME and FCOMMENT have no embedded zero bytes.
+    pub fn validate(&self) -> Result<()> {
+        if self.extra.as_ref().is_some_and(|e| e.len() > u16::MAX as usize) {
+            return Err(Error::InvalidParameter(
+                "gzip extra field exceeds 65535 bytes",
+            ));
+        }
+        for field in [&self.filename, &self.comment].into_iter().flatten() {
+            if field.contains(&0) {
+                return Err(Error::InvalidParameter(
+                    "gzip file name and comment must not contain zero bytes",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Size of the encoded header in bytes.
+    pub fn encoded_len(&self) -> usize {
+        let mut len = GZIP_MIN_HEADER_SIZE;
+        if let Some(extra) = &self.extra {
+            len += 2 + extra.len();
+        }
+        if let Some(filename) = &self.filename {
+            len += filename.len() + 1;
+        }
+        if let Some(comment) = &self.comment {
+            len += comment.len() + 1;
+        }
+        if self.header_crc {
+            len += 2;
+        }
+        len
+    }
+
+    /// Encodes the header, using `level` to derive XFL unless it was set explicitly.
+    /// The header must have passed [`validate`](Self::validate).
+    pub(crate) fn encode(&self, level: usize) -> Vec<u8> {
+        let mut flg = 0;
+        if self.text {
+            flg |= GZIP_FTEXT;
+        }
+        if self.header_crc {
+            flg |= GZIP_FHCRC;
+        }
+        if self.extra.is_some() {
+            flg |= GZIP_FEXTRA;
+        }
+        if self.filename.is_some() {
+            flg |= GZIP_FNAME;
+        }
+        if self.comment.is_some() {
+            flg |= GZIP_FCOMMENT;
+        }
+
+        let mut out = Vec::with_capacity(self.encoded_len());
+        out.extend_from_slice(&[GZIP_ID1, GZIP_ID2, GZIP_CM_DEFLATE, flg]);
+        out.extend_from_slice(&self.mtime.to_le_bytes());
+        out.push(self.xfl.unwrap_or_else(|| gzip_xfl(level)));
+        out.push(self.os);
+        if let Some(extra) = &self.extra {
+            out.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+            out.extend_from_slice(extra);
+        }
+        if let Some(filename) = &self.filename {
+            out.extend_from_slice(filename);
+            out.push(0);
+        }
+        if let Some(comment) = &self.comment {
+            out.extend_from_slice(comment);
+            out.push(0);
+        }
+        if self.header_crc {
+            let crc16 = crate::crc32::crc32(0, &out) as u16;
+            out.extend_from_slice(&crc16.to_le_bytes());
+        }
+        out
+    }
+}
+
+/// Iterator over the subfields of a gzip FEXTRA field, returned by
+/// [`GzipHeader::extra_fields`].
+#[derive(Clone, Debug)]
+pub struct GzipExtraFields<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for GzipExtraFields<'a> {
+    type Item = ([u8; 2], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.len() < 4 {
+            return None;
+        }
+        let len = u16::from_le_bytes([self.data[2], self.data[3]]) as usize;
+        if 4 + len > self.data.len() {
+            self.data = &[];
+            return None;
+        }
+        let item = ([self.data[0], self.data[1]], &self.data[4..4 + len]);
+        self.data = &self.data[4 + len..];
+        Some(item)
+    }
+}
+
+/// Returns the length of the gzip header at the start of `input`, filling in `header`
+/// if given. Fails with `ShortInput` if the header runs past the end of `input` and
+/// with `BadData` if it is malformed or, with `verify_crc`, its FHCRC is wrong.
+pub(crate) fn parse_header(
+    input: &[u8],
+    verify_crc: bool,
+    mut header: Option<&mut GzipHeader>,
+) -> core::result::Result<usize, DecompressResult> {
+    if input.len() < GZIP_MIN_HEADER_SIZE {
+        return Err(DecompressResult::ShortInput);
+    }
+    if input[0] != GZIP_ID1 || input[1] != GZIP_ID2 || input[2] != GZIP_CM_DEFLATE {
+        return Err(DecompressResult::BadData);
+    }
+    let flg = input[3];
+    if flg & GZIP_FRESERVED != 0 {
+        return Err(DecompressResult::BadData);
+    }
+    if let Some(h) = header.as_deref_mut() {
+        *h = GzipHeader {
+            text: flg & GZIP_FTEXT != 0,
+            mtime: u32::from_le_bytes([input[4], input[5], input[6], input[7]]),
+            xfl: Some(input[8]),
+            os: input[9],
+            extra: None,
+            filename: None,
+            comment: None,
+            header_crc: flg & GZIP_FHCRC != 0,
+        };
+    }
+
+    let mut pos = GZIP_MIN_HEADER_SIZE;
+    if flg & GZIP_FEXTRA != 0 {
+        if pos + 2 > input.len() {
+            return Err(DecompressResult::ShortInput);
+        }
+        let xlen = u16::from_le_bytes([input[pos], input[pos + 1]]) as usize;
+        pos += 2;
+        if pos + xlen > input.len() {
+            return Err(DecompressResult::ShortInput);
+        }
+        if let Some(h) = header.as_deref_mut() {
+            h.extra = Some(input[pos..pos + xlen].to_vec());
+        }
+        pos += xlen;
+    }
+    if flg & GZIP_FNAME != 0 {
+        let len = string_len(&input[pos..])?;
+        if let Some(h) = header.as_deref_mut() {
+            h.filename = Some(input[pos..pos + len].to_vec());
+        }
+        pos += len + 1;
+    }
+    if flg & GZIP_FCOMMENT != 0 {
+        let len = string_len(&input[pos..])?;
+        if let Some(h) = header {
+            h.comment = Some(input[pos..pos + len].to_vec());
+        }
+        pos += len + 1;
+    }
+    if flg & GZIP_FHCRC != 0 {
+        if pos + 2 > input.len() {
+            return Err(DecompressResult::ShortInput);
+        }
+        if verify_crc {
+            let expected = u16::from_le_bytes([input[pos], input[pos + 1]]);
+            if crate::crc32::crc32(0, &input[..pos]) as u16 != expected {
+                return Err(DecompressResult::BadData);
+            }
+        }
+        pos += 2;
+    }
+    Ok(pos)
+}
+
+/// Length of the zero-terminated string at the start of `input`, excluding the NUL.
+fn string_len(input: &[u8]) -> core::result::Result<usize, DecompressResult> {
+    input
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or(DecompressResult::ShortInput)
+}
+
+/// The XFL value libdeflate writes for a compression level.
+pub(crate) fn gzip_xfl(level: usize) -> u8 {
+    if level < 2 {
+        GZIP_XFL_FASTEST_COMPRESSION
+    } else if level >= 8 {
+        GZIP_XFL_SLOWEST_COMPRESSION
+    } else {
+        0
+    }
+}
@@ -0,0 +1,671 @@
+//! Random access into gzip and raw DEFLATE streams, in the manner of zlib's `zran.c`.
+//!
+//! [`IndexBuilder`] decompresses a stream once and records a [`Checkpoint`] at a block
+//! boundary about every [`span`](IndexBuilder::with_span) bytes of output: where the
+//! block starts in the compressed stream, to the bit, where it starts in the
+//! uncompressed data, and the 32 KiB of output before it that its back-references may
+//! reach. [`SeekableGzipReader`] resumes decoding at the checkpoint nearest a seek
+//! target, so reading a range costs at most one span of decompression beyond the range
+//! itself.
+
+use crate::common::*;
+use crate::decompress::{DecompressResult, Decompressor, DecompressorState};
+use crate::error::Position;
+use crate::gzip::parse_header as parse_gzip_header;
+use std::cmp::min;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// Default distance in uncompressed bytes between checkpoints.
+pub const DEFAULT_INDEX_SPAN: u64 = 1024 * 1024;
+
+const INDEX_MAGIC: [u8; 4] = *b"LDZI";
+const INDEX_VERSION: u8 = 1;
+
+/// Output decoded per step on top of the window kept for back-references.
+const OUTPUT_CHUNK_SIZE: usize = 128 * 1024;
+
+fn invalid_index() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "invalid gzip index")
+}
+
+fn truncated(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, msg)
+}
+
+/// Container of the indexed stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexFormat {
+    /// One or more concatenated gzip members.
+    Gzip,
+    /// A raw DEFLATE stream; anything after its final block is ignored.
+    Deflate,
+}
+
+/// A point at which decoding can resume: the start of a DEFLATE block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Checkpoint {
+    bit_offset: u64,
+    uncompressed_offset: u64,
+    window: Vec<u8>,
+}
+
+impl Checkpoint {
+    /// Offset of the block in the compressed stream, in bits.
+    pub fn bit_offset(&self) -> u64 {
+        self.bit_offset
+    }
+
+    /// Offset in the uncompressed data of the first byte the block decodes to.
+    pub fn uncompressed_offset(&self) -> u64 {
+        self.uncompressed_offset
+    }
+
+    /// Up to 32 KiB of uncompressed data just before the block.
+    pub fn window(&self) -> &[u8] {
+        &self.window
+    }
+}
+
+/// Checkpoints into a compressed stream, built by [`IndexBuilder`] and used by
+/// [`SeekableGzipReader`].
+///
+/// [`write_to`](Self::write_to) and [`read_from`](Self::read_from) save and load the
+/// index, so it only has to be built once per file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GzipIndex {
+    format: IndexFormat,
+    compressed_size: u64,
+    uncompressed_size: u64,
+    checkpoints: Vec<Checkpoint>,
+}
+
+impl GzipIndex {
+    pub fn format(&self) -> IndexFormat {
+        self.format
+    }
+
+    /// Length of the indexed stream, up to the end of its last gzip member or final
+    /// DEFLATE block.
+    pub fn compressed_size(&self) -> u64 {
+        self.compressed_size
+    }
+
+    pub fn uncompressed_size(&self) -> u64 {
+        self.uncompressed_size
+    }
+
+    /// The checkpoints in increasing order of offset.
+    pub fn checkpoints(&self) -> &[Checkpoint] {
+        &self.checkpoints
+    }
+
+    /// Returns the last checkpoint at or before the uncompressed `offset`.
+    fn checkpoint_before(&self, offset: u64) -> Option<&Checkpoint> {
+        let i = self
+            .checkpoints
+            .partition_point(|c| c.uncompressed_offset <= offset);
+        i.checked_sub(1).map(|i| &self.checkpoints[i])
+    }
+
+    /// Serializes the index. The windows are stored uncompressed, so an index takes
+    /// about 32 KiB per checkpoint.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let format = match self.format {
+            IndexFormat::Gzip => 0,
+            IndexFormat::Deflate => 1,
+        };
+        writer.write_all(&INDEX_MAGIC)?;
+        writer.write_all(&[INDEX_VERSION, format])?;
+        writer.write_all(&self.compressed_size.to_le_bytes())?;
+        writer.write_all(&self.uncompressed_size.to_le_bytes())?;
+        writer.write_all(&(self.checkpoints.len() as u64).to_le_bytes())?;
+        for checkpoint in &self.checkpoints {
+            writer.write_all(&checkpoint.bit_offset.to_le_bytes())?;
+            writer.write_all(&checkpoint.uncompressed_offset.to_le_bytes())?;
+            writer.write_all(&(checkpoint.window.len() as u32).to_le_bytes())?;
+            writer.write_all(&checkpoint.window)?;
+        }
+        Ok(())
+    }
+
+    /// Loads an index written by [`write_to`](Self::write_to). Malformed input fails
+    /// with [`io::ErrorKind::InvalidData`].
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<Self> {
+        fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+            let mut bytes = [0; 8];
+            reader.read_exact(&mut bytes)?;
+            Ok(u64::from_le_bytes(bytes))
+        }
+
+        let mut header = [0; 6];
+        reader.read_exact(&mut header)?;
+        if header[..4] != INDEX_MAGIC || header[4] != INDEX_VERSION {
+            return Err(invalid_index());
+        }
+        let format = match header[5] {
+            0 => IndexFormat::Gzip,
+            1 => IndexFormat::Deflate,
+            _ => return Err(invalid_index()),
+        };
+        let compressed_size = read_u64(&mut reader)?;
+        let uncompressed_size = read_u64(&mut reader)?;
+        let count = read_u64(&mut reader)?;
+
+        let mut checkpoints: Vec<Checkpoint> = Vec::with_capacity(min(count, 1024) as usize);
+        for _ in 0..count {
+            let bit_offset = read_u64(&mut reader)?;
+            let uncompressed_offset = read_u64(&mut reader)?;
+            let mut len = [0; 4];
+            reader.read_exact(&mut len)?;
+            let len = u32::from_le_bytes(len) as usize;
+            if len > DEFLATE_MAX_MATCH_OFFSET
+                || bit_offset > compressed_size.saturating_mul(8)
+                || uncompressed_offset > uncompressed_size
+                || checkpoints
+                    .last()
+                    .is_some_and(|c| c.uncompressed_offset > uncompressed_offset)
+            {
+                return Err(invalid_index());
+            }
+            let mut window = vec![0; len];
+            reader.read_exact(&mut window)?;
+            checkpoints.push(Checkpoint {
+                bit_offset,
+                uncompressed_offset,
+                window,
+            });
+        }
+        Ok(Self {
+            format,
+            compressed_size,
+            uncompressed_size,
+            checkpoints,
+        })
+    }
+}
+
+/// Builds a [`GzipIndex`] by decompressing a whole stream once. Gzip trailers are
+/// verified along the way.
+pub struct IndexBuilder {
+    format: IndexFormat,
+    span: u64,
+}
+
+impl IndexBuilder {
+    pub fn new(format: IndexFormat) -> Self {
+        Self {
+            format,
+            span: DEFAULT_INDEX_SPAN,
+        }
+    }
+
+    /// Sets the minimum distance in uncompressed bytes between checkpoints
+    /// ([`DEFAULT_INDEX_SPAN`] by default). Smaller spans make seeking cheaper and the
+    /// index larger.
+    pub fn with_span(mut self, span: u64) -> Self {
+        self.span = span.max(1);
+        self
+    }
+
+    /// Reads `reader` to the end of the stream and returns its index.
+    pub fn build<R: Read>(&self, reader: R) -> io::Result<GzipIndex> {
+        let mut decoder = BlockDecoder::new(reader, self.format, true);
+        let mut checkpoints = Vec::new();
+        let mut last_offset = 0;
+        while decoder.step()? {
+            decoder.read_pos = decoder.write_pos;
+            if decoder.at_block_boundary() && decoder.out_offset >= last_offset + self.span {
+                last_offset = decoder.out_offset;
+                checkpoints.push(decoder.checkpoint());
+            }
+        }
+        Ok(GzipIndex {
+            format: self.format,
+            compressed_size: decoder.input_offset(),
+            uncompressed_size: decoder.out_offset,
+            checkpoints,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Phase {
+    Header,
+    Body,
+    Trailer,
+    End,
+}
+
+/// Decodes a gzip or raw DEFLATE stream, stopping after every DEFLATE block so that
+/// checkpoints can be taken and restored.
+struct BlockDecoder<R: Read> {
+    inner: R,
+    format: IndexFormat,
+    verify: bool,
+    decompressor: Decompressor,
+    input: Vec<u8>,
+    input_pos: usize,
+    input_cap: usize,
+    total_in: u64,
+    window: Vec<u8>,
+    read_pos: usize,
+    write_pos: usize,
+    /// Uncompressed offset of `window[write_pos]`.
+    out_offset: u64,
+    phase: Phase,
+    crc: u32,
+    size: u32,
+}
+
+impl<R: Read> BlockDecoder<R> {
+    fn new(inner: R, format: IndexFormat, verify: bool) -> Self {
+        let mut decompressor = Decompressor::new();
+        decompressor.stop_after_block = true;
+        Self {
+            inner,
+            format,
+            verify,
+            decompressor,
+            input: vec![0; 64 * 1024],
+            input_pos: 0,
+            input_cap: 0,
+            total_in: 0,
+            window: vec![0; DEFLATE_MAX_MATCH_OFFSET + OUTPUT_CHUNK_SIZE],
+            read_pos: 0,
+            write_pos: 0,
+            out_offset: 0,
+            phase: Self::start_phase(format),
+            crc: 0,
+            size: 0,
+        }
+    }
+
+    fn start_phase(format: IndexFormat) -> Phase {
+        match format {
+            IndexFormat::Gzip => Phase::Header,
+            IndexFormat::Deflate => Phase::Body,
+        }
+    }
+
+    /// Number of input bytes consumed so far, counting those still in the bit buffer.
+    fn input_offset(&self) -> u64 {
+        self.total_in - (self.input_cap - self.input_pos) as u64
+    }
+
+    /// Offset in bits of the next unconsumed bit of input.
+    fn bit_position(&self) -> u64 {
+        self.input_offset() * 8 - self.decompressor.bitsleft as u64
+    }
+
+    /// Uncompressed offset of the next byte to be read.
+    fn position(&self) -> u64 {
+        self.out_offset - (self.write_pos - self.read_pos) as u64
+    }
+
+    fn at_block_boundary(&self) -> bool {
+        self.phase == Phase::Body && self.decompressor.state == DecompressorState::Start
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        let keep = min(self.write_pos, DEFLATE_MAX_MATCH_OFFSET);
+        Checkpoint {
+            bit_offset: self.bit_position(),
+            uncompressed_offset: self.out_offset,
+            window: self.window[self.write_pos - keep..self.write_pos].to_vec(),
+        }
+    }
+
+    fn reset_member(&mut self) {
+        self.decompressor.state = DecompressorState::Start;
+        self.decompressor.is_final_block = false;
+        self.decompressor.block_index = 0;
+        self.decompressor.bitbuf = 0;
+        self.decompressor.bitsleft = 0;
+        self.crc = 0;
+        self.size = 0;
+    }
+
+    /// Reads from `inner` until at least `needed` unconsumed input bytes are buffered.
+    /// Returns `false` if the reader hits EOF first.
+    fn fill_input(&mut self, needed: usize) -> io::Result<bool> {
+        while self.input_cap - self.input_pos < needed {
+            if self.input_pos > 0 {
+                self.input.copy_within(self.input_pos..self.input_cap, 0);
+                self.input_cap -= self.input_pos;
+                self.input_pos = 0;
+            }
+            if self.input.len() < needed {
+                self.input.resize(needed, 0);
+            }
+            let n = self.inner.read(&mut self.input[self.input_cap..])?;
+            if n == 0 {
+                return Ok(false);
+            }
+            self.input_cap += n;
+            self.total_in += n as u64;
+        }
+        Ok(true)
+    }
+
+    /// Does one unit of work: a gzip header or trailer, or one call into the
+    /// decompressor. Returns `false` once the stream has ended.
+    fn step(&mut self) -> io::Result<bool> {
+        match self.phase {
+            Phase::Header => self.read_header()?,
+            Phase::Body => self.inflate()?,
+            Phase::Trailer => self.read_trailer()?,
+            Phase::End => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn read_header(&mut self) -> io::Result<()> {
+        loop {
+            match parse_gzip_header(&self.input[self.input_pos..self.input_cap], false, None) {
+                Ok(len) => {
+                    self.input_pos += len;
+                    break;
+                }
+                Err(DecompressResult::ShortInput) => {
+                    if !self.fill_input(self.input_cap - self.input_pos + 1)? {
+                        return Err(truncated("truncated gzip header"));
+                    }
+                }
+                Err(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "invalid gzip header",
+                    ));
+                }
+            }
+        }
+        self.reset_member();
+        self.phase = Phase::Body;
+        Ok(())
+    }
+
+    fn inflate(&mut self) -> io::Result<()> {
+        // Keep only the window once the output area is nearly used up. Callers read
+        // all output before stepping again.
+        if self.window.len() - self.write_pos < DEFLATE_MAX_MATCH_LEN {
+            let shift = self.write_pos - DEFLATE_MAX_MATCH_OFFSET;
+            self.window.copy_within(shift..self.write_pos, 0);
+            self.write_pos -= shift;
+            self.read_pos -= shift;
+        }
+        if self.input_pos == self.input_cap {
+            self.fill_input(1)?;
+        }
+
+        let base = self.input_offset();
+        let start = self.write_pos;
+        let (res, consumed, _) = self.decompressor.decompress_streaming(
+            &self.input[self.input_pos..self.input_cap],
+            &mut self.window,
+            &mut self.write_pos,
+        );
+        self.input_pos += consumed;
+        let produced = &self.window[start..self.write_pos];
+        if self.verify {
+            self.crc = crate::crc32::crc32(self.crc, produced);
+            self.size = self.size.wrapping_add(produced.len() as u32);
+        }
+        self.out_offset += produced.len() as u64;
+
+        if self.decompressor.state == DecompressorState::Done {
+            self.unread_lookahead();
+            self.phase = match self.format {
+                IndexFormat::Gzip => Phase::Trailer,
+                IndexFormat::Deflate => Phase::End,
+            };
+            return Ok(());
+        }
+        match res {
+            DecompressResult::BadData => Err(match self.decompressor.failure.take() {
+                Some(failure) => failure.into_error(base).into(),
+                None => io::Error::new(io::ErrorKind::InvalidData, "deflate decompression failed"),
+            }),
+            DecompressResult::ShortInput => {
+                let available = self.input_cap - self.input_pos;
+                if !self.fill_input(available + 1)? && start == self.write_pos && consumed == 0 {
+                    return Err(truncated("truncated deflate stream"));
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the whole bytes the decompressor read ahead of the end of the stream to
+    /// the input buffer, so that the trailer or next member can be read from there.
+    fn unread_lookahead(&mut self) {
+        let bitsleft = self.decompressor.bitsleft & !7;
+        let bitbuf = self.decompressor.bitbuf >> (self.decompressor.bitsleft & 7);
+        let n = (bitsleft / 8) as usize;
+        self.decompressor.bitbuf = 0;
+        self.decompressor.bitsleft = 0;
+        if n == 0 {
+            return;
+        }
+        if self.input_pos < n {
+            let len = self.input_cap - self.input_pos;
+            if self.input.len() < len + n {
+                self.input.resize(len + n, 0);
+            }
+            self.input.copy_within(self.input_pos..self.input_cap, n);
+            self.input_pos = n;
+            self.input_cap = n + len;
+        }
+        self.input_pos -= n;
+        for i in 0..n {
+            self.input[self.input_pos + i] = (bitbuf >> (i * 8)) as u8;
+        }
+    }
+
+    fn read_trailer(&mut self) -> io::Result<()> {
+        if !self.fill_input(GZIP_FOOTER_SIZE)? {
+            return Err(truncated("truncated gzip stream"));
+        }
+        let mut at = Position {
+            bit_offset: self.input_offset() * 8,
+            block_index: self.decompressor.block_index,
+        };
+        let trailer = &self.input[self.input_pos..self.input_pos + GZIP_FOOTER_SIZE];
+        let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+        self.input_pos += GZIP_FOOTER_SIZE;
+        if self.verify {
+            if crc != self.crc {
+                return Err(crate::Error::ChecksumMismatch {
+                    expected: crc,
+                    actual: self.crc,
+                    at,
+                }
+                .into());
+            }
+            if size != self.size {
+                at.bit_offset += 32;
+                return Err(crate::Error::SizeMismatch {
+                    expected: size,
+                    actual: self.size,
+                    at,
+                }
+                .into());
+            }
+        }
+        self.phase = if self.fill_input(1)? {
+            Phase::Header
+        } else {
+            Phase::End
+        };
+        Ok(())
+    }
+
+    /// Copies decoded data to `buf`, decoding more as needed. Returns 0 at the end of
+    /// the stream.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.read_pos < self.write_pos {
+                let n = min(buf.len(), self.write_pos - self.read_pos);
+                buf[..n].copy_from_slice(&self.window[self.read_pos..self.read_pos + n]);
+                self.read_pos += n;
+                return Ok(n);
+            }
+            if !self.step()? {
+                return Ok(0);
+            }
+        }
+    }
+
+    /// Decodes and discards up to `len` bytes, stopping early at the end of the stream.
+    fn skip(&mut self, mut len: u64) -> io::Result<()> {
+        while len > 0 {
+            if self.read_pos < self.write_pos {
+                let n = min(len, (self.write_pos - self.read_pos) as u64);
+                self.read_pos += n as usize;
+                len -= n;
+            } else if !self.step()? {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> BlockDecoder<R> {
+    /// Moves back to the start of the stream.
+    fn rewind(&mut self) -> io::Result<()> {
+        self.inner.seek(SeekFrom::Start(0))?;
+        self.input_pos = 0;
+        self.input_cap = 0;
+        self.total_in = 0;
+        self.reset_member();
+        self.read_pos = 0;
+        self.write_pos = 0;
+        self.out_offset = 0;
+        self.phase = Self::start_phase(self.format);
+        Ok(())
+    }
+
+    /// Resumes decoding at `checkpoint`, reloading the bits of its first byte that
+    /// belong to the block.
+    fn restore(&mut self, checkpoint: &Checkpoint) -> io::Result<()> {
+        let byte = checkpoint.bit_offset / 8;
+        let bits = (checkpoint.bit_offset % 8) as u32;
+        self.inner.seek(SeekFrom::Start(byte))?;
+        self.input_pos = 0;
+        self.input_cap = 0;
+        self.total_in = byte;
+        self.reset_member();
+        if bits != 0 {
+            if !self.fill_input(1)? {
+                return Err(truncated("truncated deflate stream"));
+            }
+            self.decompressor.bitbuf = (self.input[0] >> bits) as u64;
+            self.decompressor.bitsleft = 8 - bits;
+            self.input_pos = 1;
+        }
+        let window = &checkpoint.window;
+        self.window[..window.len()].copy_from_slice(window);
+        self.read_pos = window.len();
+        self.write_pos = window.len();
+        self.out_offset = checkpoint.uncompressed_offset;
+        self.phase = Phase::Body;
+        Ok(())
+    }
+}
+
+/// Reads a gzip or raw DEFLATE stream with random access, using a [`GzipIndex`] built
+/// for it.
+///
+/// A seek only records the target; the next read resumes decoding at the nearest
+/// checkpoint before it, or continues from the current position if that is closer.
+/// Checksums are verified by [`IndexBuilder`], not here, since decoding usually
+/// starts part-way through a member.
+pub struct SeekableGzipReader<R: Read + Seek> {
+    decoder: BlockDecoder<R>,
+    index: GzipIndex,
+    pos: u64,
+}
+
+impl<R: Read + Seek> SeekableGzipReader<R> {
+    /// Fails with [`io::ErrorKind::InvalidInput`] if `inner` is shorter than the
+    /// stream the index was built for.
+    pub fn new(mut inner: R, index: GzipIndex) -> io::Result<Self> {
+        if inner.seek(SeekFrom::End(0))? < index.compressed_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "index does not match the stream",
+            ));
+        }
+        let mut decoder = BlockDecoder::new(inner, index.format, false);
+        decoder.rewind()?;
+        Ok(Self {
+            decoder,
+            index,
+            pos: 0,
+        })
+    }
+
+    pub fn index(&self) -> &GzipIndex {
+        &self.index
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.decoder.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.decoder.inner
+    }
+
+    /// Positions the decoder at `self.pos`, restoring a checkpoint if that is faster
+    /// than decoding forward from where it is.
+    fn sync_position(&mut self) -> io::Result<()> {
+        let current = self.decoder.position();
+        let checkpoint = self.index.checkpoint_before(self.pos);
+        let checkpoint_offset = checkpoint.map_or(0, |c| c.uncompressed_offset);
+        if self.pos < current || checkpoint_offset > current {
+            match checkpoint {
+                Some(checkpoint) => self.decoder.restore(checkpoint)?,
+                None => self.decoder.rewind()?,
+            }
+        }
+        self.decoder.skip(self.pos - self.decoder.position())
+    }
+}
+
+impl<R: Read + Seek> Read for SeekableGzipReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.decoder.position() != self.pos {
+            self.sync_position()?;
+            if self.decoder.position() != self.pos {
+                // The position is past the end of the data.
+                return Ok(0);
+            }
+        }
+        let n = self.decoder.read(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for SeekableGzipReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.index.uncompressed_size.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        };
+        self.pos = target.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
@@ -1,15 +1,36 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 #![cfg_attr(target_arch = "aarch64", feature(stdarch_neon_dotprod))]
 #![allow(unsafe_op_in_unsafe_fn)]
+
+extern crate alloc;
+
 pub mod adler32;
 pub mod api;
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+pub mod async_io;
+#[cfg(feature = "std")]
 pub mod batch;
+#[cfg(feature = "std")]
+pub mod bgzf;
+#[cfg(feature = "capi")]
+pub mod capi;
 pub mod common;
 pub mod compress;
 pub mod crc32;
 pub mod crc32_tables;
 pub mod decompress;
+pub mod error;
+pub mod gzip;
+#[cfg(feature = "std")]
+pub mod index;
+#[cfg(feature = "std")]
 pub mod stream;
+#[cfg(feature = "std")]
+pub mod zip;
 
 pub use adler32::adler32;
 pub use api::{Compressor, Decompressor};
+pub use compress::{CompressionOptions, MatchFinderKind, Strategy};
 pub use crc32::crc32;
+pub use error::{Error, Position};
+pub use gzip::GzipHeader;
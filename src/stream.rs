@@ -1,5 +1,11 @@
-use crate::compress::{CompressResult, Compressor};
+use crate::common::*;
+use crate::compress::{
+    CompressResult, Compressor, FlushMode, PARALLEL_CHUNK_SIZE, StreamCompressor,
+};
 use crate::decompress::{DecompressResult, Decompressor, DecompressorState};
+use crate::error::{Error, Position};
+use crate::gzip::{GzipHeader, parse_header as parse_gzip_header};
+#[cfg(feature = "parallel")]
 use rayon::prelude::*;
 use std::cmp::min;
 use std::io::{self, Read, Write};
@@ -16,28 +22,83 @@ pub struct DeflateEncoder<W: Write + Send> {
     buffer: Vec<u8>,
     buffer_size: usize,
     level: usize,
+    stream: StreamCompressor,
     compressors: Vec<Compressor>,
     output_buffers: Vec<Vec<u8>>,
+    chunk_history: bool,
+    parallel: bool,
+    total_out: u64,
 }
 
 impl<W: Write + Send> DeflateEncoder<W> {
     pub fn new(writer: W, level: usize) -> Self {
         Self {
             writer: Some(writer),
-            buffer: Vec::with_capacity(1024 * 1024),
-            buffer_size: 1024 * 1024,
+            buffer: Vec::with_capacity(PARALLEL_CHUNK_SIZE),
+            buffer_size: PARALLEL_CHUNK_SIZE,
             level,
+            stream: StreamCompressor::new(level),
             compressors: Vec::new(),
             output_buffers: Vec::new(),
+            chunk_history: true,
+            parallel: true,
+            total_out: 0,
         }
     }
 
+    /// Sets how much input is collected before it is compressed, [`PARALLEL_CHUNK_SIZE`]
+    /// (256 KiB) by default.
+    ///
+    /// Buffers of up to 256 KiB continue the stream with the window and the match
+    /// finder state of the previous ones, so by default the output is the same as
+    /// compressing the whole input as one stream. Larger buffers are split into chunks
+    /// of that size, compressed on several threads with the `parallel` feature; each
+    /// chunk ends with a sync marker, and the match finder starts over after the
+    /// buffer. A larger size thus trades some ratio for throughput. A single write of
+    /// at least the buffer size is compressed as one buffer.
     pub fn with_buffer_size(mut self, size: usize) -> Self {
         self.buffer_size = size;
         self.buffer.reserve(size);
         self
     }
 
+    /// Controls whether each compressed chunk is primed with the 32 KiB of input that
+    /// precede it, including across buffer flushes. Enabled by default; disabling it
+    /// compresses every chunk with an empty window.
+    pub fn with_chunk_history(mut self, enabled: bool) -> Self {
+        self.chunk_history = enabled;
+        self
+    }
+
+    /// Controls whether buffers larger than one chunk are compressed on the rayon
+    /// thread pool (the default) or on the writing thread. See
+    /// [`Compressor::set_parallel`].
+    pub fn with_parallel(mut self, enabled: bool) -> Self {
+        self.parallel = enabled;
+        self
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.writer.as_ref().expect("encoder already finished")
+    }
+
+    /// Writing to the underlying writer directly corrupts the compressed stream.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.writer.as_mut().expect("encoder already finished")
+    }
+
+    /// Resets the history after a buffer was compressed by other means than `stream`.
+    fn update_history(&mut self) {
+        let keep = if self.chunk_history {
+            min(self.buffer.len(), DEFLATE_MAX_MATCH_OFFSET)
+        } else {
+            0
+        };
+        self.stream
+            .set_history(&self.buffer[self.buffer.len() - keep..]);
+    }
+
+    #[cfg(feature = "parallel")]
     fn flush_buffer_parallel(&mut self, final_block: bool, chunk_size: usize, buffer_len: usize) -> io::Result<()> {
         let num_chunks = (buffer_len + chunk_size - 1) / chunk_size;
 
@@ -58,6 +119,16 @@ impl<W: Write + Send> DeflateEncoder<W> {
             }
         }
 
+        let histories: Vec<&[u8]> = (0..num_chunks)
+            .map(|i| {
+                if self.chunk_history {
+                    chunk_history(self.stream.history(), &self.buffer, i * chunk_size)
+                } else {
+                    &[]
+                }
+            })
+            .collect();
+
         self.buffer
             .par_chunks(chunk_size)
             .zip(self.compressors.par_iter_mut())
@@ -74,13 +145,14 @@ impl<W: Write + Send> DeflateEncoder<W> {
                 }
 
                 let mode = if final_block && i == num_chunks - 1 {
-                    crate::compress::FlushMode::Finish
+                    FlushMode::Finish
                 } else {
-                    crate::compress::FlushMode::Sync
+                    FlushMode::Sync
                 };
                 unsafe { output.set_len(bound); }
                 let out_uninit = crate::common::slice_as_uninit_mut(&mut output[..bound]);
-                let (res, size, _) = compressor.compress(chunk, out_uninit, mode);
+                let (res, size, _) =
+                    compressor.compress_with_dictionary(histories[i], chunk, out_uninit, mode);
                 if res == CompressResult::Success {
                     assert!(size <= bound);
                     output.truncate(size);
@@ -93,20 +165,48 @@ impl<W: Write + Send> DeflateEncoder<W> {
         if let Some(writer) = &mut self.writer {
             for i in 0..num_chunks {
                 writer.write_all(&self.output_buffers[i])?;
+                self.total_out += self.output_buffers[i].len() as u64;
             }
         }
         Ok(())
     }
 
+    /// Continues the stream with the buffered input, keeping the window and the match
+    /// finder state.
+    fn flush_buffer_stream(&mut self, flush_mode: FlushMode) -> io::Result<()> {
+        if self.output_buffers.is_empty() {
+            self.output_buffers.push(Vec::new());
+        }
+        let output = &mut self.output_buffers[0];
+        output.clear();
+        if self.stream.compress(&self.buffer, output, flush_mode) != CompressResult::Success {
+            return Err(io::Error::other("Compression failed"));
+        }
+        if let Some(writer) = &mut self.writer {
+            writer.write_all(output)?;
+            self.total_out += output.len() as u64;
+        }
+        if !self.chunk_history {
+            self.stream.set_history(&[]);
+        }
+        Ok(())
+    }
+
     fn flush_buffer_sequential(&mut self, final_block: bool) -> io::Result<()> {
         if self.compressors.is_empty() {
             self.compressors.push(Compressor::new(self.level));
         }
+        self.compressors[0].set_parallel(self.parallel);
         if self.output_buffers.is_empty() {
             let bound = Compressor::deflate_compress_bound(self.buffer.len()) + 5;
             self.output_buffers.push(Vec::with_capacity(bound));
         }
 
+        let history: &[u8] = if self.chunk_history {
+            self.stream.history()
+        } else {
+            &[]
+        };
         let compressor = &mut self.compressors[0];
         let output = &mut self.output_buffers[0];
         let mut bound = Compressor::deflate_compress_bound(self.buffer.len());
@@ -119,18 +219,20 @@ impl<W: Write + Send> DeflateEncoder<W> {
         }
 
         let mode = if final_block {
-            crate::compress::FlushMode::Finish
+            FlushMode::Finish
         } else {
-            crate::compress::FlushMode::Sync
+            FlushMode::Sync
         };
         unsafe { output.set_len(bound); }
         let out_uninit = crate::common::slice_as_uninit_mut(&mut output[..bound]);
-        let (res, size, _) = compressor.compress(&self.buffer, out_uninit, mode);
+        let (res, size, _) =
+            compressor.compress_with_dictionary(history, &self.buffer, out_uninit, mode);
         if res == CompressResult::Success {
             assert!(size <= bound);
             output.truncate(size);
             if let Some(writer) = &mut self.writer {
                 writer.write_all(&output[..size])?;
+                self.total_out += size as u64;
             }
         } else {
             return Err(io::Error::other("Compression failed"));
@@ -138,31 +240,84 @@ impl<W: Write + Send> DeflateEncoder<W> {
         Ok(())
     }
 
-    fn flush_buffer(&mut self, final_block: bool) -> io::Result<()> {
-        if self.buffer.is_empty() && !final_block {
+    /// Compresses the buffered input. [`FlushMode::None`] is used when the buffer is
+    /// full, [`FlushMode::Sync`] by [`flush`](Write::flush), [`FlushMode::Full`] by
+    /// [`full_flush`](Self::full_flush) and [`FlushMode::Finish`] at the end; all but
+    /// the first make all input so far decodable.
+    ///
+    /// Buffers of up to one chunk continue the stream. Larger ones, which only a
+    /// buffer size above one chunk or a single large write produce, are split into
+    /// chunks as by [`Compressor`], the same way whether or not they are compressed
+    /// in parallel, each ending with a sync marker.
+    fn flush_buffer(&mut self, flush_mode: FlushMode) -> io::Result<()> {
+        if self.buffer.is_empty()
+            && matches!(flush_mode, FlushMode::None | FlushMode::Sync)
+            && self.stream.is_byte_aligned()
+        {
             return Ok(());
         }
 
-        let chunk_size = 256 * 1024;
-        let buffer_len = self.buffer.len();
+        if self.buffer.len() <= PARALLEL_CHUNK_SIZE {
+            self.flush_buffer_stream(flush_mode)?;
+            self.buffer.clear();
+            return Ok(());
+        }
 
-        if buffer_len > chunk_size {
-            self.flush_buffer_parallel(final_block, chunk_size, buffer_len)?;
+        // The chunks are compressed independently of `stream`, so its output has to
+        // end on a byte boundary first.
+        if !self.stream.is_byte_aligned() {
+            let mut marker = Vec::new();
+            if self.stream.compress(&[], &mut marker, FlushMode::Sync) != CompressResult::Success {
+                return Err(io::Error::other("Compression failed"));
+            }
+            if let Some(writer) = &mut self.writer {
+                writer.write_all(&marker)?;
+                self.total_out += marker.len() as u64;
+            }
+        }
+        let final_block = flush_mode == FlushMode::Finish;
+
+        #[cfg(feature = "parallel")]
+        if self.parallel {
+            self.flush_buffer_parallel(final_block, PARALLEL_CHUNK_SIZE, self.buffer.len())?;
         } else {
             self.flush_buffer_sequential(final_block)?;
         }
+        #[cfg(not(feature = "parallel"))]
+        self.flush_buffer_sequential(final_block)?;
 
+        self.update_history();
+        if flush_mode == FlushMode::Full {
+            self.stream.set_history(&[]);
+        }
         self.buffer.clear();
         Ok(())
     }
 
+    /// Compresses everything written so far and ends it with a full flush point: the
+    /// `00 00 FF FF` marker, after which nothing references earlier input. Returns the
+    /// offset just past the marker in the output written so far, where a reader can
+    /// start decoding, e.g. to resynchronize after corruption or to seek. Unlike
+    /// [`flush`](Write::flush), it does not flush the underlying writer.
+    ///
+    /// Frequent full flushes cost ratio, since each one starts with an empty window.
+    pub fn full_flush(&mut self) -> io::Result<u64> {
+        self.flush_buffer(FlushMode::Full)?;
+        Ok(self.total_out)
+    }
+
+    /// The number of compressed bytes written to the underlying writer so far.
+    pub fn total_out(&self) -> u64 {
+        self.total_out
+    }
+
     /// Flushes the internal buffer, finishes the compression stream, and returns the underlying writer.
     ///
     /// This method must be called to complete the compression process and handle any potential I/O errors.
     /// If this method is not called, the `Drop` implementation will attempt to finish the stream,
     /// but will silently ignore any errors.
     pub fn finish(mut self) -> io::Result<W> {
-        self.flush_buffer(true)?;
+        self.flush_buffer(FlushMode::Finish)?;
         self.writer
             .take()
             .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "encoder already finished"))
@@ -171,15 +326,22 @@ impl<W: Write + Send> DeflateEncoder<W> {
 
 impl<W: Write + Send> Write for DeflateEncoder<W> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.buffer.extend_from_slice(buf);
+        // Smaller writes fill the buffer up to exactly its size, so that it does not
+        // outgrow a chunk when the buffer size does not.
+        let len = if buf.len() < self.buffer_size && self.buffer.len() < self.buffer_size {
+            min(buf.len(), self.buffer_size - self.buffer.len())
+        } else {
+            buf.len()
+        };
+        self.buffer.extend_from_slice(&buf[..len]);
         if self.buffer.len() >= self.buffer_size {
-            self.flush_buffer(false)?;
+            self.flush_buffer(FlushMode::None)?;
         }
-        Ok(buf.len())
+        Ok(len)
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.flush_buffer(false)?;
+        self.flush_buffer(FlushMode::Sync)?;
         if let Some(writer) = &mut self.writer {
             writer.flush()?;
         }
@@ -190,11 +352,22 @@ impl<W: Write + Send> Write for DeflateEncoder<W> {
 impl<W: Write + Send> Drop for DeflateEncoder<W> {
     fn drop(&mut self) {
         if self.writer.is_some() {
-            let _ = self.flush_buffer(true);
+            let _ = self.flush_buffer(FlushMode::Finish);
         }
     }
 }
 
+/// Returns the window a chunk starting at `buffer_pos` is primed with: the tail of the
+/// previous flush for the first chunk, otherwise the bytes of `buffer` just before it.
+#[cfg(feature = "parallel")]
+fn chunk_history<'a>(history: &'a [u8], buffer: &'a [u8], buffer_pos: usize) -> &'a [u8] {
+    if buffer_pos == 0 {
+        history
+    } else {
+        &buffer[buffer_pos - min(buffer_pos, DEFLATE_MAX_MATCH_OFFSET)..buffer_pos]
+    }
+}
+
 pub struct DeflateDecoder<R: Read> {
     inner: R,
     decompressor: Decompressor,
@@ -205,6 +378,7 @@ pub struct DeflateDecoder<R: Read> {
     read_pos: usize,
     write_pos: usize,
     done: bool,
+    total_in: u64,
 }
 
 impl<R: Read> DeflateDecoder<R> {
@@ -219,6 +393,104 @@ impl<R: Read> DeflateDecoder<R> {
             read_pos: 0,
             write_pos: 0,
             done: false,
+            total_in: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Reading from the underlying reader directly corrupts the decoded stream.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Returns the underlying reader together with the bytes read from it but not
+    /// consumed, for formats that embed a DEFLATE stream and continue after it.
+    ///
+    /// Once `read` has returned 0, those bytes are exactly what follows the stream,
+    /// so reading them and then the reader continues the input where the stream
+    /// ended. Before that, part of the last byte may already have been decoded.
+    pub fn into_inner_with_remaining(self) -> (R, Vec<u8>) {
+        let remaining = self.input_buffer[self.input_pos..self.input_cap].to_vec();
+        (self.inner, remaining)
+    }
+
+    /// Number of input bytes consumed so far, excluding any that have been read from
+    /// the underlying reader but not yet decoded.
+    fn input_offset(&self) -> u64 {
+        self.total_in - (self.input_cap - self.input_pos) as u64
+    }
+
+    /// Position of the next unconsumed input byte, for errors found outside the
+    /// DEFLATE data such as a bad trailer.
+    fn position(&self) -> Position {
+        Position {
+            bit_offset: self.input_offset() * 8,
+            block_index: self.decompressor.block_index,
+        }
+    }
+
+    /// Prepares to decode a new DEFLATE stream from the remaining input.
+    fn reset_stream(&mut self) {
+        self.decompressor.state = DecompressorState::Start;
+        self.decompressor.is_final_block = false;
+        self.decompressor.block_index = 0;
+        self.decompressor.bitbuf = 0;
+        self.decompressor.bitsleft = 0;
+        self.read_pos = 0;
+        self.write_pos = 0;
+        self.done = false;
+    }
+
+    /// Reads from `inner` until at least `needed` unconsumed input bytes are buffered.
+    /// Returns `false` if the reader hits EOF first.
+    fn fill_input(&mut self, needed: usize) -> io::Result<bool> {
+        while self.input_cap - self.input_pos < needed {
+            if self.input_pos > 0 {
+                self.input_buffer
+                    .copy_within(self.input_pos..self.input_cap, 0);
+                self.input_cap -= self.input_pos;
+                self.input_pos = 0;
+            }
+            if self.input_buffer.len() < needed {
+                self.input_buffer.resize(needed, 0);
+            }
+            let n = self.inner.read(&mut self.input_buffer[self.input_cap..])?;
+            if n == 0 {
+                return Ok(false);
+            }
+            self.input_cap += n;
+            self.total_in += n as u64;
+        }
+        Ok(true)
+    }
+
+    /// Returns the whole bytes the decompressor read ahead of the end of the stream to
+    /// the input buffer, so that whatever follows it (e.g. a trailer) can be read.
+    fn unread_lookahead(&mut self) {
+        let bitsleft = self.decompressor.bitsleft & !7;
+        let bitbuf = self.decompressor.bitbuf >> (self.decompressor.bitsleft & 7);
+        let n = (bitsleft / 8) as usize;
+        self.decompressor.bitbuf = 0;
+        self.decompressor.bitsleft = 0;
+        if n == 0 {
+            return;
+        }
+        if self.input_pos < n {
+            let len = self.input_cap - self.input_pos;
+            if self.input_buffer.len() < len + n {
+                self.input_buffer.resize(len + n, 0);
+            }
+            self.input_buffer
+                .copy_within(self.input_pos..self.input_cap, n);
+            self.input_pos = n;
+            self.input_cap = n + len;
+        }
+        self.input_pos -= n;
+        for i in 0..n {
+            self.input_buffer[self.input_pos + i] = (bitbuf >> (i * 8)) as u8;
         }
     }
 }
@@ -237,12 +509,11 @@ impl<R: Read> Read for DeflateDecoder<R> {
         }
 
         loop {
-            if self.write_pos >= 64 * 1024 && self.read_pos >= 32 * 1024 {
-                self.window.copy_within(
-                    self.read_pos - 32 * 1024..self.write_pos,
-                    32 * 1024 - (self.read_pos - 32 * 1024),
-                );
-
+            // Compact before the window can no longer hold a maximum-length match;
+            // the decompressor stops rather than splitting one across calls.
+            if self.write_pos + DEFLATE_MAX_MATCH_LEN > self.window.len()
+                && self.read_pos >= 32 * 1024
+            {
                 let amount_to_keep = 32 * 1024;
                 let shift = self.write_pos - amount_to_keep;
                 self.window.copy_within(shift..self.write_pos, 0);
@@ -252,6 +523,7 @@ impl<R: Read> Read for DeflateDecoder<R> {
 
             let mut output_full = false;
             if self.input_pos < self.input_cap {
+                let base = self.input_offset();
                 let input = &self.input_buffer[self.input_pos..self.input_cap];
                 let (res, in_consumed) = {
                     let (res, inc, _outc) = self.decompressor.decompress_streaming(
@@ -266,6 +538,7 @@ impl<R: Read> Read for DeflateDecoder<R> {
 
                 if let DecompressorState::Done = self.decompressor.state {
                     self.done = true;
+                    self.unread_lookahead();
                     if self.read_pos < self.write_pos {
                         let count = min(buf.len(), self.write_pos - self.read_pos);
                         buf[..count]
@@ -290,10 +563,13 @@ impl<R: Read> Read for DeflateDecoder<R> {
                         output_full = true;
                     }
                     DecompressResult::BadData => {
-                        return Err(io::Error::new(
-                            io::ErrorKind::InvalidData,
-                            "deflate decompression failed",
-                        ));
+                        return Err(match self.decompressor.failure.take() {
+                            Some(failure) => failure.into_error(base).into(),
+                            None => io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                "deflate decompression failed",
+                            ),
+                        });
                     }
                     _ => {}
                 }
@@ -334,7 +610,586 @@ impl<R: Read> Read for DeflateDecoder<R> {
                     return Ok(0);
                 }
                 self.input_cap += n;
+                self.total_in += n as u64;
+            }
+        }
+    }
+}
+
+/// A streaming encoder that writes a gzip stream: a header, DEFLATE data produced
+/// as by [`DeflateEncoder`], and a CRC32/ISIZE trailer.
+///
+/// As with [`DeflateEncoder`], call [`finish()`](Self::finish) to observe I/O errors;
+/// dropping the encoder writes the trailer but ignores any errors.
+pub struct GzipEncoder<W: Write + Send> {
+    inner: DeflateEncoder<W>,
+    header: GzipHeader,
+    header_written: bool,
+    crc: u32,
+    size: u32,
+}
+
+impl<W: Write + Send> GzipEncoder<W> {
+    pub fn new(writer: W, level: usize) -> Self {
+        Self {
+            inner: DeflateEncoder::new(writer, level),
+            header: GzipHeader::new(),
+            header_written: false,
+            crc: 0,
+            size: 0,
+        }
+    }
+
+    /// See [`DeflateEncoder::with_buffer_size`].
+    pub fn with_buffer_size(mut self, size: usize) -> Self {
+        self.inner.buffer_size = size;
+        self.inner.buffer.reserve(size);
+        self
+    }
+
+    pub fn with_chunk_history(mut self, enabled: bool) -> Self {
+        self.inner.chunk_history = enabled;
+        self
+    }
+
+    pub fn with_parallel(mut self, enabled: bool) -> Self {
+        self.inner.parallel = enabled;
+        self
+    }
+
+    /// Sets the header fields to write; an invalid header is reported by the first
+    /// write, flush or [`finish()`](Self::finish).
+    pub fn with_header(mut self, header: GzipHeader) -> Self {
+        self.header = header;
+        self
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.inner.get_ref()
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.get_mut()
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if self.header_written {
+            return Ok(());
+        }
+        self.header.validate()?;
+        let header = self.header.encode(self.inner.level);
+        if let Some(writer) = &mut self.inner.writer {
+            writer.write_all(&header)?;
+        }
+        self.header_written = true;
+        Ok(())
+    }
+
+    fn finish_stream(&mut self) -> io::Result<()> {
+        self.write_header()?;
+        self.inner.flush_buffer(FlushMode::Finish)?;
+        if let Some(writer) = &mut self.inner.writer {
+            writer.write_all(&self.crc.to_le_bytes())?;
+            writer.write_all(&self.size.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Finishes the DEFLATE stream, writes the gzip trailer, and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.finish_stream()?;
+        self.inner
+            .writer
+            .take()
+            .ok_or_else(|| io::Error::other("encoder already finished"))
+    }
+}
+
+impl<W: Write + Send> Write for GzipEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_header()?;
+        let n = self.inner.write(buf)?;
+        self.crc = crate::crc32::crc32(self.crc, &buf[..n]);
+        self.size = self.size.wrapping_add(n as u32);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_header()?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write + Send> Drop for GzipEncoder<W> {
+    fn drop(&mut self) {
+        if self.inner.writer.is_some() {
+            let _ = self.finish_stream();
+            self.inner.writer = None;
+        }
+    }
+}
+
+/// A streaming decoder for a gzip stream. The header is parsed before the first byte
+/// of output, and the CRC32 and ISIZE trailer are checked once the DEFLATE data ends.
+///
+/// Concatenated members are decoded as one continuous stream unless
+/// [`with_multi_member(false)`](Self::with_multi_member) is set. Zero bytes after the
+/// last member are skipped, as gzip(1) does.
+pub struct GzipDecoder<R: Read> {
+    inner: DeflateDecoder<R>,
+    header: Option<GzipHeader>,
+    verify_header_crc: bool,
+    header_read: bool,
+    trailer_read: bool,
+    crc: u32,
+    size: u32,
+    multi_member: bool,
+}
+
+impl<R: Read> GzipDecoder<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner: DeflateDecoder::new(inner),
+            header: None,
+            verify_header_crc: false,
+            header_read: false,
+            trailer_read: false,
+            crc: 0,
+            size: 0,
+            multi_member: true,
+        }
+    }
+
+    /// Controls whether members following the first are decoded (the default). When
+    /// disabled, reading stops at the end of the first member and
+    /// [`input_offset`](Self::input_offset) reports where the remaining bytes start.
+    pub fn with_multi_member(mut self, enabled: bool) -> Self {
+        self.multi_member = enabled;
+        self
+    }
+
+    /// Controls whether the header CRC (FHCRC) is checked when a member has one.
+    pub fn with_verify_header_crc(mut self, verify: bool) -> Self {
+        self.verify_header_crc = verify;
+        self
+    }
+
+    /// Returns the header of the member being decoded, or `None` before the first read.
+    pub fn header(&self) -> Option<&GzipHeader> {
+        self.header.as_ref()
+    }
+
+    pub fn get_ref(&self) -> &R {
+        self.inner.get_ref()
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        self.inner.get_mut()
+    }
+
+    /// Returns the underlying reader together with the bytes read from it but not
+    /// consumed. With multi-member decoding disabled, once `read` has returned 0 they
+    /// are what follows the first member's trailer.
+    pub fn into_inner_with_remaining(self) -> (R, Vec<u8>) {
+        self.inner.into_inner_with_remaining()
+    }
+
+    /// Returns the number of bytes of the underlying reader consumed by decoding so far.
+    /// Once the first member is finished with multi-member decoding disabled, this is
+    /// the offset of the first byte after it; bytes the decoder has read beyond that
+    /// point are buffered internally.
+    pub fn input_offset(&self) -> u64 {
+        self.inner.input_offset()
+    }
+
+    /// Starts on the next member if one follows the current one.
+    fn next_member(&mut self) -> io::Result<bool> {
+        if !self.multi_member || !self.inner.fill_input(1)? {
+            return Ok(false);
+        }
+        if self.inner.input_buffer[self.inner.input_pos] == 0 {
+            self.skip_zero_padding()?;
+            return Ok(false);
+        }
+        self.inner.reset_stream();
+        self.header_read = false;
+        self.trailer_read = false;
+        self.crc = 0;
+        self.size = 0;
+        Ok(true)
+    }
+
+    /// Consumes the zero bytes some tools pad gzip files with, which gzip(1) skips.
+    /// Anything else after them is an error.
+    fn skip_zero_padding(&mut self) -> io::Result<()> {
+        let d = &mut self.inner;
+        while d.fill_input(1)? {
+            if d.input_buffer[d.input_pos..d.input_cap]
+                .iter()
+                .any(|&b| b != 0)
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "trailing data after gzip padding",
+                ));
             }
+            d.input_pos = d.input_cap;
+        }
+        Ok(())
+    }
+
+    fn read_header(&mut self) -> io::Result<()> {
+        let d = &mut self.inner;
+        if !d.fill_input(GZIP_MIN_HEADER_SIZE)? {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "truncated gzip header",
+            ));
+        }
+        let header = &d.input_buffer[d.input_pos..d.input_cap];
+        if header[0] != GZIP_ID1 || header[1] != GZIP_ID2 || header[2] != GZIP_CM_DEFLATE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid gzip header",
+            ));
+        }
+        let flg = header[3];
+        if flg & GZIP_FRESERVED != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid gzip header",
+            ));
+        }
+
+        let mut pos = GZIP_MIN_HEADER_SIZE;
+        if flg & GZIP_FEXTRA != 0 {
+            gzip_header_need(d, pos + 2)?;
+            let xlen = u16::from_le_bytes([
+                d.input_buffer[d.input_pos + pos],
+                d.input_buffer[d.input_pos + pos + 1],
+            ]) as usize;
+            pos += 2 + xlen;
+            gzip_header_need(d, pos)?;
+        }
+        if flg & GZIP_FNAME != 0 {
+            pos = gzip_header_skip_string(d, pos)?;
+        }
+        if flg & GZIP_FCOMMENT != 0 {
+            pos = gzip_header_skip_string(d, pos)?;
+        }
+        if flg & GZIP_FHCRC != 0 {
+            pos += 2;
+            gzip_header_need(d, pos)?;
+        }
+        let mut header = GzipHeader::new();
+        let bytes = &d.input_buffer[d.input_pos..d.input_pos + pos];
+        if parse_gzip_header(bytes, self.verify_header_crc, Some(&mut header)).is_err() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "gzip header CRC mismatch",
+            ));
+        }
+        d.input_pos += pos;
+        self.header = Some(header);
+        self.header_read = true;
+        Ok(())
+    }
+
+    fn read_trailer(&mut self) -> io::Result<()> {
+        let d = &mut self.inner;
+        if !d.done || !d.fill_input(GZIP_FOOTER_SIZE)? {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "truncated gzip stream",
+            ));
+        }
+        let mut at = d.position();
+        let trailer = &d.input_buffer[d.input_pos..d.input_pos + GZIP_FOOTER_SIZE];
+        let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+        d.input_pos += GZIP_FOOTER_SIZE;
+        if crc != self.crc {
+            return Err(Error::ChecksumMismatch {
+                expected: crc,
+                actual: self.crc,
+                at,
+            }
+            .into());
+        }
+        if size != self.size {
+            at.bit_offset += 32;
+            return Err(Error::SizeMismatch {
+                expected: size,
+                actual: self.size,
+                at,
+            }
+            .into());
+        }
+        self.trailer_read = true;
+        Ok(())
+    }
+}
+
+fn gzip_header_need<R: Read>(d: &mut DeflateDecoder<R>, needed: usize) -> io::Result<()> {
+    if d.fill_input(needed)? {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated gzip header",
+        ))
+    }
+}
+
+/// Returns the header offset just past the zero-terminated string starting at `pos`.
+fn gzip_header_skip_string<R: Read>(d: &mut DeflateDecoder<R>, mut pos: usize) -> io::Result<usize> {
+    loop {
+        gzip_header_need(d, pos + 1)?;
+        let avail = &d.input_buffer[d.input_pos + pos..d.input_cap];
+        if let Some(i) = avail.iter().position(|&b| b == 0) {
+            return Ok(pos + i + 1);
+        }
+        pos += avail.len();
+    }
+}
+
+impl<R: Read> Read for GzipDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if self.trailer_read && !self.next_member()? {
+                return Ok(0);
+            }
+            if !self.header_read {
+                self.read_header()?;
+            }
+            let n = self.inner.read(buf)?;
+            if n > 0 {
+                self.crc = crate::crc32::crc32(self.crc, &buf[..n]);
+                self.size = self.size.wrapping_add(n as u32);
+                return Ok(n);
+            }
+            self.read_trailer()?;
+        }
+    }
+}
+
+/// A streaming encoder that writes a zlib stream: the CMF/FLG header, DEFLATE data
+/// produced as by [`DeflateEncoder`], and a big-endian Adler-32 trailer.
+///
+/// As with [`DeflateEncoder`], call [`finish()`](Self::finish) to observe I/O errors;
+/// dropping the encoder writes the trailer but ignores any errors.
+pub struct ZlibEncoder<W: Write + Send> {
+    inner: DeflateEncoder<W>,
+    header_written: bool,
+    adler: u32,
+}
+
+impl<W: Write + Send> ZlibEncoder<W> {
+    pub fn new(writer: W, level: usize) -> Self {
+        Self {
+            inner: DeflateEncoder::new(writer, level),
+            header_written: false,
+            adler: 1,
+        }
+    }
+
+    /// See [`DeflateEncoder::with_buffer_size`].
+    pub fn with_buffer_size(mut self, size: usize) -> Self {
+        self.inner.buffer_size = size;
+        self.inner.buffer.reserve(size);
+        self
+    }
+
+    pub fn with_chunk_history(mut self, enabled: bool) -> Self {
+        self.inner.chunk_history = enabled;
+        self
+    }
+
+    pub fn with_parallel(mut self, enabled: bool) -> Self {
+        self.inner.parallel = enabled;
+        self
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.inner.get_ref()
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.get_mut()
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if self.header_written {
+            return Ok(());
+        }
+        let mut hdr = (ZLIB_CM_DEFLATE as u16) << 8;
+        hdr |= (ZLIB_CINFO_32K_WINDOW as u16) << 12;
+        let level_hint = if self.inner.level < 2 {
+            ZLIB_FASTEST_COMPRESSION
+        } else if self.inner.level < 6 {
+            ZLIB_FAST_COMPRESSION
+        } else if self.inner.level < 8 {
+            ZLIB_DEFAULT_COMPRESSION
+        } else {
+            ZLIB_SLOWEST_COMPRESSION
+        };
+        hdr |= (level_hint as u16) << 6;
+        hdr |= 31 - (hdr % 31);
+        if let Some(writer) = &mut self.inner.writer {
+            writer.write_all(&hdr.to_be_bytes())?;
+        }
+        self.header_written = true;
+        Ok(())
+    }
+
+    fn finish_stream(&mut self) -> io::Result<()> {
+        self.write_header()?;
+        self.inner.flush_buffer(FlushMode::Finish)?;
+        if let Some(writer) = &mut self.inner.writer {
+            writer.write_all(&self.adler.to_be_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Finishes the DEFLATE stream, writes the Adler-32 trailer, and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.finish_stream()?;
+        self.inner
+            .writer
+            .take()
+            .ok_or_else(|| io::Error::other("encoder already finished"))
+    }
+}
+
+impl<W: Write + Send> Write for ZlibEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_header()?;
+        let n = self.inner.write(buf)?;
+        self.adler = crate::adler32::adler32(self.adler, &buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_header()?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write + Send> Drop for ZlibEncoder<W> {
+    fn drop(&mut self) {
+        if self.inner.writer.is_some() {
+            let _ = self.finish_stream();
+            self.inner.writer = None;
+        }
+    }
+}
+
+/// A streaming decoder for a zlib stream. The header is checked before the first byte
+/// of output, and the Adler-32 trailer once the DEFLATE data ends. Streams that need a
+/// preset dictionary are rejected.
+pub struct ZlibDecoder<R: Read> {
+    inner: DeflateDecoder<R>,
+    header_read: bool,
+    trailer_read: bool,
+    adler: u32,
+}
+
+impl<R: Read> ZlibDecoder<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner: DeflateDecoder::new(inner),
+            header_read: false,
+            trailer_read: false,
+            adler: 1,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        self.inner.get_ref()
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        self.inner.get_mut()
+    }
+
+    /// Returns the underlying reader together with the bytes read from it but not
+    /// consumed. Once `read` has returned 0, they are what follows the Adler-32
+    /// trailer.
+    pub fn into_inner_with_remaining(self) -> (R, Vec<u8>) {
+        self.inner.into_inner_with_remaining()
+    }
+
+    fn read_header(&mut self) -> io::Result<()> {
+        let d = &mut self.inner;
+        if !d.fill_input(ZLIB_MIN_HEADER_SIZE)? {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "truncated zlib header",
+            ));
+        }
+        let hdr = u16::from_be_bytes([d.input_buffer[d.input_pos], d.input_buffer[d.input_pos + 1]]);
+        if !hdr.is_multiple_of(31)
+            || ((hdr >> 8) & 0xF) as u8 != ZLIB_CM_DEFLATE
+            || ((hdr >> 12) & 0xF) as u8 > ZLIB_CINFO_32K_WINDOW
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid zlib header",
+            ));
+        }
+        if hdr & ZLIB_FDICT != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "zlib stream requires a preset dictionary",
+            ));
+        }
+        d.input_pos += ZLIB_MIN_HEADER_SIZE;
+        self.header_read = true;
+        Ok(())
+    }
+
+    fn read_trailer(&mut self) -> io::Result<()> {
+        let d = &mut self.inner;
+        if !d.done || !d.fill_input(ZLIB_FOOTER_SIZE)? {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "truncated zlib stream",
+            ));
+        }
+        let at = d.position();
+        let trailer = &d.input_buffer[d.input_pos..d.input_pos + ZLIB_FOOTER_SIZE];
+        let adler = u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        d.input_pos += ZLIB_FOOTER_SIZE;
+        if adler != self.adler {
+            return Err(Error::ChecksumMismatch {
+                expected: adler,
+                actual: self.adler,
+                at,
+            }
+            .into());
+        }
+        self.trailer_read = true;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for ZlibDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.trailer_read || buf.is_empty() {
+            return Ok(0);
+        }
+        if !self.header_read {
+            self.read_header()?;
+        }
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.adler = crate::adler32::adler32(self.adler, &buf[..n]);
+            return Ok(n);
         }
+        self.read_trailer()?;
+        Ok(0)
     }
 }
@@ -0,0 +1,855 @@
+//! ZIP archives with stored and deflate entries.
+//!
+//! An archive is a series of entries, each a local header followed by the entry's
+//! data and, if bit 3 of its flags is set, a data descriptor holding the CRC32 and
+//! sizes that the local header leaves as zero. The central directory at the end
+//! repeats every header along with the offset of its local header, so a reader finds
+//! any entry from the end of the file without scanning the rest. Zip64 extra fields
+//! and end records take over when a size, an offset or the number of entries does
+//! not fit the original 16- and 32-bit fields.
+//!
+//! Encrypted entries, split archives and compression methods other than stored and
+//! deflate are not supported.
+
+use crate::Decompressor;
+use crate::batch::BatchCompressor;
+use crate::crc32::crc32;
+use crate::error::{Error, Position};
+use crate::stream::DeflateDecoder;
+use std::collections::HashMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x0807_4b50;
+const EOCD_SIGNATURE: u32 = 0x0605_4b50;
+const ZIP64_EOCD_SIGNATURE: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+
+const LOCAL_HEADER_SIZE: usize = 30;
+const CENTRAL_HEADER_SIZE: usize = 46;
+const EOCD_SIZE: usize = 22;
+const ZIP64_EOCD_SIZE: usize = 56;
+const ZIP64_LOCATOR_SIZE: usize = 20;
+const MAX_COMMENT_SIZE: usize = 0xffff;
+
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+
+const FLAG_ENCRYPTED: u16 = 1 << 0;
+const FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+const FLAG_UTF8: u16 = 1 << 11;
+
+/// Version 4.5 of the specification, the first with Zip64. The upper byte of "version
+/// made by" is 0, MS-DOS, whose attributes mark directories.
+const VERSION_ZIP64: u16 = 45;
+const VERSION_DEFLATE: u16 = 20;
+const VERSION_STORED: u16 = 10;
+
+const DOS_DIRECTORY_ATTRIBUTE: u32 = 0x10;
+
+/// 1980-01-01, the earliest DOS date.
+const DOS_EPOCH_DATE: u16 = (1 << 5) | 1;
+
+/// Default amount of entry data buffered by [`ZipWriter`] before compressing it.
+const DEFAULT_BATCH_SIZE: usize = 16 * 1024 * 1024;
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn unsupported(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, msg)
+}
+
+fn u16_at(bytes: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([bytes[pos], bytes[pos + 1]])
+}
+
+fn u32_at(bytes: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], pos: usize) -> u64 {
+    u64::from_le_bytes(bytes[pos..pos + 8].try_into().unwrap())
+}
+
+/// How an entry's data is stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressionMethod {
+    Stored,
+    Deflated,
+    /// Any other method number; such entries are listed but cannot be read.
+    Other(u16),
+}
+
+impl CompressionMethod {
+    fn from_u16(method: u16) -> Self {
+        match method {
+            0 => CompressionMethod::Stored,
+            8 => CompressionMethod::Deflated,
+            other => CompressionMethod::Other(other),
+        }
+    }
+
+    fn to_u16(self) -> u16 {
+        match self {
+            CompressionMethod::Stored => 0,
+            CompressionMethod::Deflated => 8,
+            CompressionMethod::Other(other) => other,
+        }
+    }
+}
+
+/// An entry as described by the central directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ZipEntry {
+    name: String,
+    method: CompressionMethod,
+    flags: u16,
+    crc32: u32,
+    compressed_size: u64,
+    size: u64,
+    header_offset: u64,
+    external_attributes: u32,
+}
+
+impl ZipEntry {
+    /// The entry's path, with `/` as separator. Names not flagged as UTF-8 should be
+    /// CP437, but are decoded as UTF-8 with invalid sequences replaced, as most tools
+    /// write UTF-8 either way.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn method(&self) -> CompressionMethod {
+        self.method
+    }
+
+    pub fn crc32(&self) -> u32 {
+        self.crc32
+    }
+
+    pub fn compressed_size(&self) -> u64 {
+        self.compressed_size
+    }
+
+    /// Size of the uncompressed data.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Offset of the entry's local header in the archive.
+    pub fn header_offset(&self) -> u64 {
+        self.header_offset
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.name.ends_with('/')
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.flags & FLAG_ENCRYPTED != 0
+    }
+
+    /// Whether the CRC32 and sizes follow the data instead of being in the local
+    /// header.
+    pub fn has_data_descriptor(&self) -> bool {
+        self.flags & FLAG_DATA_DESCRIPTOR != 0
+    }
+
+    fn needs_zip64(&self) -> bool {
+        self.size >= 0xffff_ffff || self.compressed_size >= 0xffff_ffff
+    }
+
+    fn version_needed(&self, zip64: bool) -> u16 {
+        if zip64 {
+            VERSION_ZIP64
+        } else if self.method == CompressionMethod::Deflated || self.is_dir() {
+            VERSION_DEFLATE
+        } else {
+            VERSION_STORED
+        }
+    }
+}
+
+/// Writes a ZIP archive, compressing the buffered entries in parallel through
+/// [`BatchCompressor`].
+///
+/// Entries are passed whole and buffered until about
+/// [`with_batch_size`](Self::with_batch_size) bytes are waiting, then compressed
+/// together and written in the order they were added. An entry that deflate does not
+/// make smaller is stored instead. Entries are dated 1980-01-01, so the same entries
+/// always give the same archive.
+///
+/// As with [`BgzfWriter`](crate::bgzf::BgzfWriter), call [`finish()`](Self::finish) to
+/// observe I/O errors; dropping the writer writes the remaining entries and the
+/// central directory but ignores any errors.
+pub struct ZipWriter<W: Write> {
+    writer: Option<W>,
+    compressor: BatchCompressor,
+    level: usize,
+    pending: Vec<(ZipEntry, Vec<u8>)>,
+    pending_size: usize,
+    batch_size: usize,
+    entries: Vec<ZipEntry>,
+    offset: u64,
+    data_descriptors: bool,
+    force_zip64: bool,
+}
+
+impl<W: Write> ZipWriter<W> {
+    /// Creates a writer compressing deflate entries at `level`. At level 0 every
+    /// entry is stored.
+    pub fn new(writer: W, level: usize) -> Self {
+        Self {
+            writer: Some(writer),
+            compressor: BatchCompressor::new(level),
+            level,
+            pending: Vec::new(),
+            pending_size: 0,
+            batch_size: DEFAULT_BATCH_SIZE,
+            entries: Vec::new(),
+            offset: 0,
+            data_descriptors: false,
+            force_zip64: false,
+        }
+    }
+
+    /// Sets how much entry data is buffered and compressed together (16 MiB by
+    /// default). Each entry is compressed by one thread, so a batch should hold
+    /// several entries to keep threads busy.
+    pub fn with_batch_size(mut self, bytes: usize) -> Self {
+        self.batch_size = bytes;
+        self
+    }
+
+    /// Controls whether the CRC32 and sizes of each entry are written in a data
+    /// descriptor after its data rather than in its local header, as a streaming
+    /// writer has to. Readers going by the central directory see no difference.
+    pub fn with_data_descriptors(mut self, enabled: bool) -> Self {
+        self.data_descriptors = enabled;
+        self
+    }
+
+    /// Controls whether Zip64 fields and end records are written even where the
+    /// original fields would do. They are always written where those overflow.
+    pub fn with_zip64(mut self, enabled: bool) -> Self {
+        self.force_zip64 = enabled;
+        self
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.writer.as_ref().expect("writer already finished")
+    }
+
+    /// Writing to the underlying writer directly corrupts the archive.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.writer.as_mut().expect("writer already finished")
+    }
+
+    /// Adds a file named `name` holding `data`. [`CompressionMethod::Other`] fails
+    /// with [`io::ErrorKind::InvalidInput`].
+    pub fn add_file(
+        &mut self,
+        name: &str,
+        data: &[u8],
+        method: CompressionMethod,
+    ) -> io::Result<()> {
+        if let CompressionMethod::Other(_) = method {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only stored and deflate entries can be written",
+            ));
+        }
+        self.add_entry(name.to_string(), data.to_vec(), method, 0)
+    }
+
+    /// Adds a directory entry. A trailing `/` is appended to `name` if missing.
+    pub fn add_directory(&mut self, name: &str) -> io::Result<()> {
+        let mut name = name.to_string();
+        if !name.ends_with('/') {
+            name.push('/');
+        }
+        self.add_entry(
+            name,
+            Vec::new(),
+            CompressionMethod::Stored,
+            DOS_DIRECTORY_ATTRIBUTE,
+        )
+    }
+
+    fn add_entry(
+        &mut self,
+        name: String,
+        data: Vec<u8>,
+        method: CompressionMethod,
+        external_attributes: u32,
+    ) -> io::Result<()> {
+        if name.len() > 0xffff {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "entry name is longer than 65535 bytes",
+            ));
+        }
+        let mut flags = 0;
+        if !name.is_ascii() {
+            flags |= FLAG_UTF8;
+        }
+        if self.data_descriptors {
+            flags |= FLAG_DATA_DESCRIPTOR;
+        }
+        let entry = ZipEntry {
+            name,
+            method,
+            flags,
+            crc32: 0,
+            compressed_size: 0,
+            size: data.len() as u64,
+            header_offset: 0,
+            external_attributes,
+        };
+        self.pending_size += data.len();
+        self.pending.push((entry, data));
+        if self.pending_size >= self.batch_size {
+            self.write_pending()?;
+        }
+        Ok(())
+    }
+
+    /// Compresses and writes the buffered entries.
+    fn write_pending(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        for (entry, data) in &mut self.pending {
+            if self.level == 0 || data.is_empty() {
+                entry.method = CompressionMethod::Stored;
+            }
+        }
+        let inputs: Vec<&[u8]> = self
+            .pending
+            .iter()
+            .filter(|(entry, _)| entry.method == CompressionMethod::Deflated)
+            .map(|(_, data)| data.as_slice())
+            .collect();
+        let mut compressed = self.compressor.compress_batch(&inputs).into_iter();
+
+        let pending = std::mem::take(&mut self.pending);
+        self.pending_size = 0;
+        for (mut entry, data) in pending {
+            let mut body = data.as_slice();
+            let deflated;
+            if entry.method == CompressionMethod::Deflated {
+                deflated = compressed.next().unwrap();
+                if deflated.is_empty() {
+                    return Err(io::Error::other("compression failed"));
+                }
+                if deflated.len() < data.len() {
+                    body = &deflated;
+                } else {
+                    entry.method = CompressionMethod::Stored;
+                }
+            }
+            entry.crc32 = crc32(0, &data);
+            entry.compressed_size = body.len() as u64;
+            self.write_local(entry, body)?;
+        }
+        Ok(())
+    }
+
+    fn write_local(&mut self, mut entry: ZipEntry, body: &[u8]) -> io::Result<()> {
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| io::Error::other("writer already finished"))?;
+        let zip64 = self.force_zip64 || entry.needs_zip64();
+        let descriptor = entry.has_data_descriptor();
+        // With a descriptor the header only says whether its sizes take 8 bytes.
+        let (crc, size, compressed_size) = if descriptor {
+            (0, 0, 0)
+        } else {
+            (entry.crc32, entry.size, entry.compressed_size)
+        };
+
+        let mut header = Vec::with_capacity(LOCAL_HEADER_SIZE + entry.name.len() + 20);
+        header.extend_from_slice(&LOCAL_HEADER_SIGNATURE.to_le_bytes());
+        header.extend_from_slice(&entry.version_needed(zip64).to_le_bytes());
+        header.extend_from_slice(&entry.flags.to_le_bytes());
+        header.extend_from_slice(&entry.method.to_u16().to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&DOS_EPOCH_DATE.to_le_bytes());
+        header.extend_from_slice(&crc.to_le_bytes());
+        if zip64 {
+            header.extend_from_slice(&[0xff; 8]);
+        } else {
+            header.extend_from_slice(&(compressed_size as u32).to_le_bytes());
+            header.extend_from_slice(&(size as u32).to_le_bytes());
+        }
+        header.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&(if zip64 { 20u16 } else { 0 }).to_le_bytes());
+        header.extend_from_slice(entry.name.as_bytes());
+        if zip64 {
+            header.extend_from_slice(&ZIP64_EXTRA_ID.to_le_bytes());
+            header.extend_from_slice(&16u16.to_le_bytes());
+            header.extend_from_slice(&size.to_le_bytes());
+            header.extend_from_slice(&compressed_size.to_le_bytes());
+        }
+        writer.write_all(&header)?;
+        writer.write_all(body)?;
+        let mut len = (header.len() + body.len()) as u64;
+
+        if descriptor {
+            let mut trailer = Vec::with_capacity(24);
+            trailer.extend_from_slice(&DATA_DESCRIPTOR_SIGNATURE.to_le_bytes());
+            trailer.extend_from_slice(&entry.crc32.to_le_bytes());
+            if zip64 {
+                trailer.extend_from_slice(&entry.compressed_size.to_le_bytes());
+                trailer.extend_from_slice(&entry.size.to_le_bytes());
+            } else {
+                trailer.extend_from_slice(&(entry.compressed_size as u32).to_le_bytes());
+                trailer.extend_from_slice(&(entry.size as u32).to_le_bytes());
+            }
+            writer.write_all(&trailer)?;
+            len += trailer.len() as u64;
+        }
+
+        entry.header_offset = self.offset;
+        self.offset += len;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    fn finish_archive(&mut self) -> io::Result<()> {
+        self.write_pending()?;
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+
+        let cd_offset = self.offset;
+        let mut cd = Vec::new();
+        for entry in &self.entries {
+            // Only the fields that overflow move to the Zip64 extra field, in this
+            // order.
+            let mut zip64 = Vec::new();
+            let mut field = |value: u64| {
+                if self.force_zip64 || value >= 0xffff_ffff {
+                    zip64.extend_from_slice(&value.to_le_bytes());
+                    0xffff_ffff
+                } else {
+                    value as u32
+                }
+            };
+            let size = field(entry.size);
+            let compressed_size = field(entry.compressed_size);
+            let header_offset = field(entry.header_offset);
+            let version = entry.version_needed(!zip64.is_empty());
+
+            cd.extend_from_slice(&CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+            cd.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+            cd.extend_from_slice(&version.to_le_bytes());
+            cd.extend_from_slice(&entry.flags.to_le_bytes());
+            cd.extend_from_slice(&entry.method.to_u16().to_le_bytes());
+            cd.extend_from_slice(&0u16.to_le_bytes());
+            cd.extend_from_slice(&DOS_EPOCH_DATE.to_le_bytes());
+            cd.extend_from_slice(&entry.crc32.to_le_bytes());
+            cd.extend_from_slice(&compressed_size.to_le_bytes());
+            cd.extend_from_slice(&size.to_le_bytes());
+            cd.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            let extra_len = if zip64.is_empty() { 0 } else { 4 + zip64.len() };
+            cd.extend_from_slice(&(extra_len as u16).to_le_bytes());
+            // Comment length, disk number start and internal attributes.
+            cd.extend_from_slice(&[0; 6]);
+            cd.extend_from_slice(&entry.external_attributes.to_le_bytes());
+            cd.extend_from_slice(&header_offset.to_le_bytes());
+            cd.extend_from_slice(entry.name.as_bytes());
+            if !zip64.is_empty() {
+                cd.extend_from_slice(&ZIP64_EXTRA_ID.to_le_bytes());
+                cd.extend_from_slice(&(zip64.len() as u16).to_le_bytes());
+                cd.extend_from_slice(&zip64);
+            }
+        }
+        let cd_size = cd.len() as u64;
+        let count = self.entries.len() as u64;
+
+        let mut end = Vec::with_capacity(ZIP64_EOCD_SIZE + ZIP64_LOCATOR_SIZE + EOCD_SIZE);
+        let zip64 = self.force_zip64
+            || count >= 0xffff
+            || cd_size >= 0xffff_ffff
+            || cd_offset >= 0xffff_ffff;
+        if zip64 {
+            let zip64_eocd_offset = cd_offset + cd_size;
+            end.extend_from_slice(&ZIP64_EOCD_SIGNATURE.to_le_bytes());
+            end.extend_from_slice(&((ZIP64_EOCD_SIZE - 12) as u64).to_le_bytes());
+            end.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+            end.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+            // This disk and the disk where the central directory starts.
+            end.extend_from_slice(&[0; 8]);
+            end.extend_from_slice(&count.to_le_bytes());
+            end.extend_from_slice(&count.to_le_bytes());
+            end.extend_from_slice(&cd_size.to_le_bytes());
+            end.extend_from_slice(&cd_offset.to_le_bytes());
+
+            end.extend_from_slice(&ZIP64_LOCATOR_SIGNATURE.to_le_bytes());
+            end.extend_from_slice(&0u32.to_le_bytes());
+            end.extend_from_slice(&zip64_eocd_offset.to_le_bytes());
+            end.extend_from_slice(&1u32.to_le_bytes());
+        }
+        let count16 = if zip64 { 0xffff } else { count as u16 };
+        end.extend_from_slice(&EOCD_SIGNATURE.to_le_bytes());
+        end.extend_from_slice(&[0; 4]);
+        end.extend_from_slice(&count16.to_le_bytes());
+        end.extend_from_slice(&count16.to_le_bytes());
+        if zip64 {
+            end.extend_from_slice(&[0xff; 8]);
+        } else {
+            end.extend_from_slice(&(cd_size as u32).to_le_bytes());
+            end.extend_from_slice(&(cd_offset as u32).to_le_bytes());
+        }
+        // No archive comment.
+        end.extend_from_slice(&0u16.to_le_bytes());
+
+        writer.write_all(&cd)?;
+        writer.write_all(&end)?;
+        self.offset += cd_size + end.len() as u64;
+        self.entries.clear();
+        Ok(())
+    }
+
+    /// Writes the remaining entries and the central directory, and returns the
+    /// underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.finish_archive()?;
+        self.writer
+            .take()
+            .ok_or_else(|| io::Error::other("writer already finished"))
+    }
+}
+
+impl<W: Write> Drop for ZipWriter<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.finish_archive();
+        }
+    }
+}
+
+/// Reads entries of a ZIP archive in any order.
+///
+/// [`new`](Self::new) reads the central directory, after which any entry can be
+/// read by index or by name. Entry data is checked against its CRC32 and size.
+pub struct ZipArchive<R: Read + Seek> {
+    inner: R,
+    decompressor: Decompressor,
+    entries: Vec<ZipEntry>,
+    names: HashMap<String, usize>,
+}
+
+impl<R: Read + Seek> ZipArchive<R> {
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let (count, cd_size, cd_offset) = read_end_records(&mut inner)?;
+        inner.seek(SeekFrom::Start(cd_offset))?;
+        let mut cd = Vec::new();
+        (&mut inner).take(cd_size).read_to_end(&mut cd)?;
+        if cd.len() as u64 != cd_size {
+            return Err(invalid_data(
+                "central directory extends past the end of the file",
+            ));
+        }
+
+        let mut entries =
+            Vec::with_capacity(count.min(cd_size / CENTRAL_HEADER_SIZE as u64) as usize);
+        let mut pos = 0;
+        for _ in 0..count {
+            let (entry, len) = parse_central_header(&cd[pos..])?;
+            entries.push(entry);
+            pos += len;
+        }
+        let mut names = HashMap::with_capacity(entries.len());
+        for (i, entry) in entries.iter().enumerate() {
+            names.entry(entry.name.clone()).or_insert(i);
+        }
+        Ok(Self {
+            inner,
+            decompressor: Decompressor::new(),
+            entries,
+            names,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The entries in central directory order.
+    pub fn entries(&self) -> &[ZipEntry] {
+        &self.entries
+    }
+
+    pub fn entry(&self, index: usize) -> Option<&ZipEntry> {
+        self.entries.get(index)
+    }
+
+    /// Returns the index of the entry named `name`, or of the first one if there
+    /// are several.
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads and decompresses the entry at `index`.
+    pub fn read_entry(&mut self, index: usize) -> io::Result<Vec<u8>> {
+        let (entry, data_offset) = self.seek_to_data(index)?;
+        let mut data = Vec::new();
+        (&mut self.inner)
+            .take(entry.compressed_size)
+            .read_to_end(&mut data)?;
+        if data.len() as u64 != entry.compressed_size {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "truncated ZIP entry",
+            ));
+        }
+        if entry.method == CompressionMethod::Deflated {
+            let size = usize::try_from(entry.size)
+                .map_err(|_| unsupported("ZIP entry does not fit in memory"))?;
+            data = self.decompressor.decompress_deflate(&data, size)?;
+        }
+        check_entry(&entry, crc32(0, &data), data.len() as u64, data_offset)?;
+        Ok(data)
+    }
+
+    /// Reads and decompresses the entry named `name`.
+    pub fn read_entry_by_name(&mut self, name: &str) -> io::Result<Vec<u8>> {
+        let index = self
+            .index_of(name)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such ZIP entry"))?;
+        self.read_entry(index)
+    }
+
+    /// Returns a reader that decompresses the entry at `index` as it is read, for
+    /// entries too large to hold in memory. The CRC32 and size are checked when it
+    /// reaches the end.
+    pub fn entry_reader(&mut self, index: usize) -> io::Result<ZipEntryReader<'_, R>> {
+        let (entry, data_offset) = self.seek_to_data(index)?;
+        let data = (&mut self.inner).take(entry.compressed_size);
+        let data = match entry.method {
+            CompressionMethod::Deflated => EntryData::Deflated(Box::new(DeflateDecoder::new(data))),
+            _ => EntryData::Stored(data),
+        };
+        Ok(ZipEntryReader {
+            data,
+            entry,
+            data_offset,
+            crc: 0,
+            size: 0,
+        })
+    }
+
+    /// Checks that the entry at `index` can be read and positions the reader at its
+    /// data, whose offset is returned along with the entry.
+    fn seek_to_data(&mut self, index: usize) -> io::Result<(ZipEntry, u64)> {
+        let entry = self
+            .entries
+            .get(index)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such ZIP entry"))?
+            .clone();
+        if entry.is_encrypted() {
+            return Err(unsupported("encrypted ZIP entries are not supported"));
+        }
+        if let CompressionMethod::Other(_) = entry.method {
+            return Err(unsupported("unsupported ZIP compression method"));
+        }
+
+        self.inner.seek(SeekFrom::Start(entry.header_offset))?;
+        let mut header = [0; LOCAL_HEADER_SIZE];
+        self.inner.read_exact(&mut header)?;
+        if u32_at(&header, 0) != LOCAL_HEADER_SIGNATURE {
+            return Err(invalid_data("invalid ZIP local header"));
+        }
+        let skip = u16_at(&header, 26) as u64 + u16_at(&header, 28) as u64;
+        let data_offset = entry.header_offset + LOCAL_HEADER_SIZE as u64 + skip;
+        self.inner.seek(SeekFrom::Start(data_offset))?;
+        Ok((entry, data_offset))
+    }
+}
+
+/// Fails if the data read for `entry` does not match its CRC32 and size.
+fn check_entry(entry: &ZipEntry, crc: u32, size: u64, data_offset: u64) -> io::Result<()> {
+    if size != entry.size {
+        return Err(invalid_data("ZIP entry size mismatch"));
+    }
+    if crc != entry.crc32 {
+        return Err(Error::ChecksumMismatch {
+            expected: entry.crc32,
+            actual: crc,
+            at: Position {
+                bit_offset: (data_offset + entry.compressed_size) * 8,
+                block_index: 0,
+            },
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Finds the end of central directory record, and the Zip64 one if present, and
+/// returns the number of entries and the size and offset of the central directory.
+fn read_end_records<R: Read + Seek>(inner: &mut R) -> io::Result<(u64, u64, u64)> {
+    let len = inner.seek(SeekFrom::End(0))?;
+    let tail_len = len.min((ZIP64_LOCATOR_SIZE + EOCD_SIZE + MAX_COMMENT_SIZE) as u64);
+    let tail_start = len - tail_len;
+    inner.seek(SeekFrom::Start(tail_start))?;
+    let mut tail = vec![0; tail_len as usize];
+    inner.read_exact(&mut tail)?;
+    if tail.len() < EOCD_SIZE {
+        return Err(invalid_data("ZIP end of central directory not found"));
+    }
+
+    // The record is followed only by the archive comment, which may contain the
+    // signature itself, so take the last candidate whose comment ends the file.
+    let eocd = (0..=tail.len() - EOCD_SIZE)
+        .rev()
+        .find(|&pos| {
+            u32_at(&tail, pos) == EOCD_SIGNATURE
+                && pos + EOCD_SIZE + u16_at(&tail, pos + 20) as usize == tail.len()
+        })
+        .ok_or_else(|| invalid_data("ZIP end of central directory not found"))?;
+    let record = &tail[eocd..eocd + EOCD_SIZE];
+    if u16_at(record, 4) != 0 || u16_at(record, 6) != 0 {
+        return Err(unsupported("split ZIP archives are not supported"));
+    }
+    let count = u16_at(record, 10) as u64;
+    let cd_size = u32_at(record, 12) as u64;
+    let cd_offset = u32_at(record, 16) as u64;
+
+    if eocd < ZIP64_LOCATOR_SIZE
+        || u32_at(&tail, eocd - ZIP64_LOCATOR_SIZE) != ZIP64_LOCATOR_SIGNATURE
+    {
+        return Ok((count, cd_size, cd_offset));
+    }
+    let locator = &tail[eocd - ZIP64_LOCATOR_SIZE..eocd];
+    inner.seek(SeekFrom::Start(u64_at(locator, 8)))?;
+    let mut record = [0; ZIP64_EOCD_SIZE];
+    inner.read_exact(&mut record)?;
+    if u32_at(&record, 0) != ZIP64_EOCD_SIGNATURE {
+        return Err(invalid_data("invalid Zip64 end of central directory"));
+    }
+    if u32_at(&record, 16) != 0 || u32_at(&record, 20) != 0 {
+        return Err(unsupported("split ZIP archives are not supported"));
+    }
+    Ok((
+        u64_at(&record, 32),
+        u64_at(&record, 40),
+        u64_at(&record, 48),
+    ))
+}
+
+/// Parses the central directory header at the start of `bytes` and returns the
+/// entry and the header's length.
+fn parse_central_header(bytes: &[u8]) -> io::Result<(ZipEntry, usize)> {
+    let truncated = || invalid_data("truncated ZIP central directory");
+    if bytes.len() < CENTRAL_HEADER_SIZE {
+        return Err(truncated());
+    }
+    if u32_at(bytes, 0) != CENTRAL_HEADER_SIGNATURE {
+        return Err(invalid_data("invalid ZIP central directory header"));
+    }
+    let name_len = u16_at(bytes, 28) as usize;
+    let extra_len = u16_at(bytes, 30) as usize;
+    let comment_len = u16_at(bytes, 32) as usize;
+    let len = CENTRAL_HEADER_SIZE + name_len + extra_len + comment_len;
+    if bytes.len() < len {
+        return Err(truncated());
+    }
+    let name = &bytes[CENTRAL_HEADER_SIZE..CENTRAL_HEADER_SIZE + name_len];
+    let mut entry = ZipEntry {
+        name: String::from_utf8_lossy(name).into_owned(),
+        method: CompressionMethod::from_u16(u16_at(bytes, 10)),
+        flags: u16_at(bytes, 8),
+        crc32: u32_at(bytes, 16),
+        compressed_size: u32_at(bytes, 20) as u64,
+        size: u32_at(bytes, 24) as u64,
+        header_offset: u32_at(bytes, 42) as u64,
+        external_attributes: u32_at(bytes, 38),
+    };
+
+    // The Zip64 extra field holds the fields set to all ones, in this order.
+    let mut extra =
+        &bytes[CENTRAL_HEADER_SIZE + name_len..CENTRAL_HEADER_SIZE + name_len + extra_len];
+    while extra.len() >= 4 {
+        let id = u16_at(extra, 0);
+        let size = (u16_at(extra, 2) as usize).min(extra.len() - 4);
+        if id == ZIP64_EXTRA_ID {
+            let mut values = extra[4..4 + size].chunks_exact(8).map(|v| u64_at(v, 0));
+            for field in [
+                &mut entry.size,
+                &mut entry.compressed_size,
+                &mut entry.header_offset,
+            ] {
+                if *field == 0xffff_ffff {
+                    *field = values
+                        .next()
+                        .ok_or_else(|| invalid_data("invalid Zip64 extra field"))?;
+                }
+            }
+        }
+        extra = &extra[4 + size..];
+    }
+    Ok((entry, len))
+}
+
+enum EntryData<'a, R: Read> {
+    Stored(io::Take<&'a mut R>),
+    Deflated(Box<DeflateDecoder<io::Take<&'a mut R>>>),
+}
+
+/// Reads the data of one entry, as returned by [`ZipArchive::entry_reader`].
+pub struct ZipEntryReader<'a, R: Read> {
+    data: EntryData<'a, R>,
+    entry: ZipEntry,
+    data_offset: u64,
+    crc: u32,
+    size: u64,
+}
+
+impl<R: Read> ZipEntryReader<'_, R> {
+    pub fn entry(&self) -> &ZipEntry {
+        &self.entry
+    }
+}
+
+impl<R: Read> Read for ZipEntryReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let n = match &mut self.data {
+            EntryData::Stored(data) => data.read(buf)?,
+            EntryData::Deflated(decoder) => decoder.read(buf)?,
+        };
+        self.crc = crc32(self.crc, &buf[..n]);
+        self.size += n as u64;
+        if n == 0 || self.size > self.entry.size {
+            // A deflate stream cut short fails in the decoder; stored data just ends.
+            if n == 0 && self.size < self.entry.size && matches!(self.data, EntryData::Stored(_)) {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "truncated ZIP entry",
+                ));
+            }
+            check_entry(&self.entry, self.crc, self.size, self.data_offset)?;
+        }
+        Ok(n)
+    }
+}
@@ -25,3 +25,16 @@ fn test_adler32_large_chunks() {
         "Failed at large size"
     );
 }
+
+#[test]
+fn test_adler32_matches_generic_all_tail_lengths() {
+    let data: Vec<u8> = (0..4096u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+    for len in 0..=300 {
+        assert_eq!(
+            libdeflate::adler32(1, &data[..len]),
+            adler32_generic(1, &data[..len]),
+            "Mismatch at length {}",
+            len
+        );
+    }
+}
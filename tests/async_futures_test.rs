@@ -0,0 +1,145 @@
+use futures::executor::block_on;
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libdeflate::async_io::futures::{
+    DeflateDecoder, DeflateEncoder, GzipDecoder, GzipEncoder, ZlibDecoder, ZlibEncoder,
+};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+fn test_data(len: usize) -> Vec<u8> {
+    (0..len as u32)
+        .map(|i| (i.wrapping_mul(2654435761) >> 28) as u8 + b'a')
+        .collect()
+}
+
+/// An in-memory reader and writer that moves at most `step` bytes per call and returns
+/// `Pending` on every other call, waking itself so the executor polls again.
+struct Trickle {
+    data: Vec<u8>,
+    pos: usize,
+    step: usize,
+    ready: bool,
+}
+
+impl Trickle {
+    fn new(data: Vec<u8>, step: usize) -> Self {
+        Self {
+            data,
+            pos: 0,
+            step,
+            ready: false,
+        }
+    }
+
+    fn tick(&mut self, cx: &mut Context<'_>) -> bool {
+        self.ready = !self.ready;
+        if !self.ready {
+            cx.waker().wake_by_ref();
+        }
+        self.ready
+    }
+}
+
+impl AsyncRead for Trickle {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if !this.tick(cx) {
+            return Poll::Pending;
+        }
+        let n = buf.len().min(this.step).min(this.data.len() - this.pos);
+        buf[..n].copy_from_slice(&this.data[this.pos..this.pos + n]);
+        this.pos += n;
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl AsyncWrite for Trickle {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if !this.tick(cx) {
+            return Poll::Pending;
+        }
+        let n = buf.len().min(this.step);
+        this.data.extend_from_slice(&buf[..n]);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+macro_rules! round_trip {
+    ($encoder:ident, $decoder:ident, $data:expr) => {{
+        let data: Vec<u8> = $data;
+        let compressed = block_on(async {
+            let mut encoder =
+                $encoder::new(Trickle::new(Vec::new(), 1000), 6).with_buffer_size(64 * 1024);
+            for chunk in data.chunks(5000) {
+                encoder.write_all(chunk).await.unwrap();
+            }
+            encoder.close().await.unwrap();
+            encoder.into_inner().data
+        });
+        let output = block_on(async {
+            let mut output = Vec::new();
+            $decoder::new(Trickle::new(compressed, 777))
+                .read_to_end(&mut output)
+                .await
+                .unwrap();
+            output
+        });
+        assert_eq!(output, data);
+    }};
+}
+
+#[test]
+fn test_futures_round_trip() {
+    for len in [0, 1, 1000, 300_000] {
+        round_trip!(DeflateEncoder, DeflateDecoder, test_data(len));
+        round_trip!(ZlibEncoder, ZlibDecoder, test_data(len));
+        round_trip!(GzipEncoder, GzipDecoder, test_data(len));
+    }
+}
+
+#[test]
+fn test_futures_gzip_single_member() {
+    let first = test_data(1000);
+    let mut compressor = libdeflate::Compressor::new(6).unwrap();
+    let mut gzip = compressor.compress_gzip(&first).unwrap();
+    gzip.extend(compressor.compress_gzip(b"second").unwrap());
+
+    let output = block_on(async {
+        let mut output = Vec::new();
+        GzipDecoder::new(Trickle::new(gzip.clone(), 100))
+            .read_to_end(&mut output)
+            .await
+            .unwrap();
+        output
+    });
+    assert_eq!(output, [&first[..], b"second"].concat());
+
+    let output = block_on(async {
+        let mut output = Vec::new();
+        GzipDecoder::new(Trickle::new(gzip, 100))
+            .with_multi_member(false)
+            .read_to_end(&mut output)
+            .await
+            .unwrap();
+        output
+    });
+    assert_eq!(output, first);
+}
@@ -0,0 +1,119 @@
+use libdeflate::GzipHeader;
+use libdeflate::async_io::tokio::{
+    DeflateDecoder, DeflateEncoder, GzipDecoder, GzipEncoder, ZlibDecoder, ZlibEncoder,
+};
+use libdeflate::{Compressor, Decompressor, Error};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, duplex};
+
+fn test_data(len: usize) -> Vec<u8> {
+    (0..len as u32)
+        .map(|i| (i.wrapping_mul(2654435761) >> 28) as u8 + b'a')
+        .collect()
+}
+
+/// Writes `data` to the encoder in uneven pieces from one task while another reads the
+/// compressed stream back through a small pipe.
+macro_rules! round_trip {
+    ($encoder:ident, $decoder:ident, $data:expr) => {{
+        let data: Vec<u8> = $data;
+        let (writer, reader) = duplex(1024);
+        let expected = data.clone();
+        let write = tokio::spawn(async move {
+            let mut encoder = $encoder::new(writer, 6).with_buffer_size(64 * 1024);
+            for chunk in data.chunks(7777) {
+                encoder.write_all(chunk).await.unwrap();
+            }
+            encoder.shutdown().await.unwrap();
+        });
+        let mut decoder = $decoder::new(reader);
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).await.unwrap();
+        write.await.unwrap();
+        assert_eq!(output, expected);
+    }};
+}
+
+#[tokio::test]
+async fn test_tokio_round_trip() {
+    for len in [0, 1, 1000, 300_000] {
+        round_trip!(DeflateEncoder, DeflateDecoder, test_data(len));
+        round_trip!(ZlibEncoder, ZlibDecoder, test_data(len));
+        round_trip!(GzipEncoder, GzipDecoder, test_data(len));
+    }
+}
+
+#[tokio::test]
+async fn test_tokio_decode_one_shot_output() {
+    let data = test_data(200_000);
+    let compressed = Compressor::new(9).unwrap().compress_gzip(&data).unwrap();
+    let (mut writer, reader) = duplex(100);
+    let write = tokio::spawn(async move {
+        for chunk in compressed.chunks(333) {
+            writer.write_all(chunk).await.unwrap();
+        }
+    });
+    let mut output = Vec::new();
+    GzipDecoder::new(reader)
+        .read_to_end(&mut output)
+        .await
+        .unwrap();
+    write.await.unwrap();
+    assert_eq!(output, data);
+}
+
+#[tokio::test]
+async fn test_tokio_flush() {
+    let (writer, mut reader) = duplex(64 * 1024);
+    let mut encoder = ZlibEncoder::new(writer, 6);
+    encoder.write_all(b"hello, ").await.unwrap();
+    encoder.flush().await.unwrap();
+
+    // The flushed data is readable before the stream ends.
+    let mut flushed = vec![0; 1024];
+    let n = reader.read(&mut flushed).await.unwrap();
+    let mut partial = ZlibDecoder::new(&flushed[..n]);
+    let mut buf = [0; 7];
+    partial.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"hello, ");
+
+    encoder.write_all(b"world").await.unwrap();
+    encoder.shutdown().await.unwrap();
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).await.unwrap();
+    let compressed = [&flushed[..n], &rest[..]].concat();
+    let output = Decompressor::new()
+        .decompress_zlib_to_vec(&compressed)
+        .unwrap();
+    assert_eq!(output, b"hello, world");
+}
+
+#[tokio::test]
+async fn test_tokio_gzip_header_and_errors() {
+    let header = GzipHeader::new().with_filename(b"data.txt").with_mtime(42);
+    let mut encoder = GzipEncoder::new(Vec::new(), 6).with_header(header.clone());
+    encoder.write_all(b"payload").await.unwrap();
+    encoder.shutdown().await.unwrap();
+    let mut gzip = encoder.into_inner();
+
+    let mut decoder = GzipDecoder::new(&gzip[..]);
+    let mut output = Vec::new();
+    decoder.read_to_end(&mut output).await.unwrap();
+    assert_eq!(output, b"payload");
+    assert_eq!(decoder.header().unwrap().filename(), Some(&b"data.txt"[..]));
+    assert_eq!(decoder.header().unwrap().mtime(), 42);
+
+    let len = gzip.len();
+    gzip[len - 8] ^= 1;
+    let err = GzipDecoder::new(&gzip[..])
+        .read_to_end(&mut Vec::new())
+        .await
+        .unwrap_err();
+    let inner = err.get_ref().unwrap().downcast_ref::<Error>().unwrap();
+    assert!(matches!(inner, Error::ChecksumMismatch { .. }), "{inner:?}");
+
+    let err = GzipDecoder::new(&gzip[..len / 2])
+        .read_to_end(&mut Vec::new())
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+}
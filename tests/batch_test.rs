@@ -1,4 +1,5 @@
 use libdeflate::batch::{BatchCompressor, BatchDecompressor};
+use libdeflate::{Decompressor, GzipHeader};
 
 #[test]
 fn test_batch_compress_decompress_roundtrip() {
@@ -98,3 +99,23 @@ fn test_batch_decompress_insufficient_buffer() {
     assert_eq!(decompressed.len(), 1);
     assert_eq!(decompressed[0], None);
 }
+
+#[test]
+fn test_batch_compress_gzip() {
+    let inputs: Vec<&[u8]> = vec![b"first member", b"", &[7u8; 5000]];
+    let header = GzipHeader::new()
+        .with_filename(b"batch.txt")
+        .with_extra_field(*b"XY", b"data");
+    let compressor = BatchCompressor::new(6).with_gzip_header(header.clone());
+    let members = compressor.compress_gzip_batch(&inputs);
+
+    assert_eq!(members.len(), inputs.len());
+    for (member, input) in members.iter().zip(&inputs) {
+        let (decompressed, parsed) = Decompressor::new()
+            .decompress_gzip_with_header(member, input.len())
+            .unwrap();
+        assert_eq!(decompressed.as_slice(), *input);
+        assert_eq!(parsed.filename(), header.filename());
+        assert_eq!(parsed.extra(), header.extra());
+    }
+}
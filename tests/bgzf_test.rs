@@ -0,0 +1,193 @@
+mod common;
+
+use common::noise;
+use libdeflate::bgzf::{
+    BGZF_BLOCK_DATA_SIZE, BGZF_EOF_MARKER, BGZF_MAX_BLOCK_SIZE, BgzfReader, BgzfWriter,
+};
+use libdeflate::{Compressor, Decompressor, GzipHeader};
+use std::io::{Cursor, ErrorKind, Read, Write};
+
+fn records(count: usize) -> Vec<Vec<u8>> {
+    (0..count)
+        .map(|i| {
+            format!(
+                "chr1\t{}\t.\tA\tG\t{}\tPASS\tDP={}\n",
+                i * 37,
+                i % 60,
+                i % 1000
+            )
+            .into_bytes()
+        })
+        .collect()
+}
+
+fn bgzf(data: &[u8], level: usize) -> Vec<u8> {
+    let mut writer = BgzfWriter::new(Vec::new(), level).with_batch_blocks(4);
+    writer.write_all(data).unwrap();
+    writer.finish().unwrap()
+}
+
+/// Splits a BGZF file into its blocks using the BSIZE fields.
+fn blocks(file: &[u8]) -> Vec<&[u8]> {
+    let mut blocks = Vec::new();
+    let mut pos = 0;
+    while pos < file.len() {
+        let (header, _) = GzipHeader::parse(&file[pos..], true).unwrap();
+        let (_, bsize) = header.extra_fields().find(|&(id, _)| id == *b"BC").unwrap();
+        let size = u16::from_le_bytes([bsize[0], bsize[1]]) as usize + 1;
+        blocks.push(&file[pos..pos + size]);
+        pos += size;
+    }
+    assert_eq!(pos, file.len());
+    blocks
+}
+
+#[test]
+fn test_bgzf_roundtrip() {
+    let data: Vec<u8> = records(50_000).concat();
+    for level in [0, 1, 6, 12] {
+        let file = bgzf(&data, level);
+
+        let blocks = blocks(&file);
+        assert_eq!(blocks.len(), data.len().div_ceil(BGZF_BLOCK_DATA_SIZE) + 1);
+        assert!(blocks.iter().all(|b| b.len() <= BGZF_MAX_BLOCK_SIZE));
+        assert_eq!(*blocks.last().unwrap(), BGZF_EOF_MARKER);
+
+        // Any gzip decoder reads a BGZF file as a multi-member stream.
+        let decompressed = Decompressor::new().decompress_gzip_to_vec(&file).unwrap();
+        assert!(decompressed == data, "level {level}");
+
+        let mut output = Vec::new();
+        BgzfReader::new(Cursor::new(&file))
+            .read_to_end(&mut output)
+            .unwrap();
+        assert!(output == data, "level {level}");
+    }
+}
+
+#[test]
+fn test_bgzf_incompressible_blocks_fit() {
+    let data = noise(5 * BGZF_BLOCK_DATA_SIZE + 1000, 0x2545_f491);
+    for level in [0, 1, 12] {
+        let file = bgzf(&data, level);
+        assert!(blocks(&file).iter().all(|b| b.len() <= BGZF_MAX_BLOCK_SIZE));
+        let mut output = Vec::new();
+        BgzfReader::new(Cursor::new(&file))
+            .read_to_end(&mut output)
+            .unwrap();
+        assert!(output == data, "level {level}");
+    }
+}
+
+#[test]
+fn test_bgzf_empty() {
+    let file = BgzfWriter::new(Vec::new(), 6).finish().unwrap();
+    assert_eq!(file, BGZF_EOF_MARKER);
+    let mut output = Vec::new();
+    let mut reader = BgzfReader::new(Cursor::new(&file));
+    reader.read_to_end(&mut output).unwrap();
+    assert!(output.is_empty());
+    assert!(reader.has_eof_marker().unwrap());
+}
+
+#[test]
+fn test_bgzf_virtual_offset_seek() {
+    let records = records(20_000);
+    let mut writer = BgzfWriter::new(Vec::new(), 6).with_batch_blocks(3);
+    let mut offsets = Vec::new();
+    for (i, record) in records.iter().enumerate() {
+        // Only ask for some offsets so that the rest are written in batches.
+        if i % 97 == 0 {
+            offsets.push((i, writer.virtual_offset().unwrap()));
+        }
+        writer.write_all(record).unwrap();
+    }
+    let file = writer.finish().unwrap();
+    assert!(offsets.iter().any(|&(_, v)| v >> 16 > 0));
+
+    let mut reader = BgzfReader::new(Cursor::new(&file));
+    for &(i, voffset) in offsets.iter().rev() {
+        reader.seek_virtual(voffset).unwrap();
+        assert_eq!(reader.virtual_offset(), voffset);
+        let mut record = vec![0; records[i].len()];
+        reader.read_exact(&mut record).unwrap();
+        assert!(record == records[i], "record {i}");
+    }
+
+    // Offsets reported while reading lead back to the same data.
+    let mut reader = BgzfReader::new(Cursor::new(&file));
+    let mut skipped = vec![0; 200_000];
+    reader.read_exact(&mut skipped).unwrap();
+    let voffset = reader.virtual_offset();
+    let mut expected = vec![0; 1000];
+    reader.read_exact(&mut expected).unwrap();
+    reader.seek_virtual(voffset).unwrap();
+    let mut actual = vec![0; 1000];
+    reader.read_exact(&mut actual).unwrap();
+    assert_eq!(actual, expected);
+
+    let first_block = blocks(&file)[0].len() as u64;
+    assert_eq!(
+        reader
+            .seek_virtual(first_block << 16 | 0xffff)
+            .unwrap_err()
+            .kind(),
+        ErrorKind::InvalidInput
+    );
+}
+
+#[test]
+fn test_bgzf_eof_marker() {
+    let data: Vec<u8> = records(1000).concat();
+    let file = bgzf(&data, 6);
+    let mut reader = BgzfReader::new(Cursor::new(&file));
+    let mut head = [0; 100];
+    reader.read_exact(&mut head).unwrap();
+    assert!(reader.has_eof_marker().unwrap());
+    // The check does not disturb reading.
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).unwrap();
+    assert!([&head[..], &rest].concat() == data);
+
+    // Without the marker the blocks are still readable, but the file looks truncated.
+    let unterminated = &file[..file.len() - BGZF_EOF_MARKER.len()];
+    let mut reader = BgzfReader::new(Cursor::new(unterminated));
+    assert!(!reader.has_eof_marker().unwrap());
+    let mut output = Vec::new();
+    reader.read_to_end(&mut output).unwrap();
+    assert!(output == data);
+
+    assert!(
+        !BgzfReader::new(Cursor::new(&[0u8; 4]))
+            .has_eof_marker()
+            .unwrap()
+    );
+}
+
+#[test]
+fn test_bgzf_invalid_input() {
+    let data: Vec<u8> = records(5000).concat();
+    let file = bgzf(&data, 6);
+    let read = |file: &[u8]| {
+        let mut output = Vec::new();
+        BgzfReader::new(Cursor::new(file)).read_to_end(&mut output)
+    };
+
+    // A plain gzip member has no BC subfield.
+    let gzip = Compressor::new(6).unwrap().compress_gzip(&data).unwrap();
+    assert_eq!(read(&gzip).unwrap_err().kind(), ErrorKind::InvalidData);
+
+    let first_block = blocks(&file)[0].len();
+    assert_eq!(
+        read(&file[..first_block + 100]).unwrap_err().kind(),
+        ErrorKind::UnexpectedEof
+    );
+
+    let mut bad_crc = file.clone();
+    bad_crc[first_block - 8] ^= 1;
+    assert_eq!(read(&bad_crc).unwrap_err().kind(), ErrorKind::InvalidData);
+
+    let mut bad_bsize = file.clone();
+    bad_bsize[16] = bad_bsize[16].wrapping_sub(1);
+    assert!(read(&bad_bsize).is_err());
+}
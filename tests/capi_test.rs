@@ -0,0 +1,282 @@
+use libdeflate::capi::*;
+use std::ffi::c_void;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+fn test_data(len: usize) -> Vec<u8> {
+    (0..len as u32)
+        .map(|i| (i.wrapping_mul(2654435761) >> 28) as u8 + b'a')
+        .collect()
+}
+
+type CompressFn = unsafe extern "C" fn(
+    *mut libdeflate_compressor,
+    *const c_void,
+    usize,
+    *mut c_void,
+    usize,
+) -> usize;
+type BoundFn = unsafe extern "C" fn(*mut libdeflate_compressor, usize) -> usize;
+type DecompressExFn = unsafe extern "C" fn(
+    *mut libdeflate_decompressor,
+    *const c_void,
+    usize,
+    *mut c_void,
+    usize,
+    *mut usize,
+    *mut usize,
+) -> libdeflate_result;
+
+unsafe fn compress(
+    c: *mut libdeflate_compressor,
+    data: &[u8],
+    compress: CompressFn,
+    bound: BoundFn,
+) -> Vec<u8> {
+    let mut out = vec![0u8; unsafe { bound(c, data.len()) }];
+    let size = unsafe {
+        compress(
+            c,
+            data.as_ptr().cast(),
+            data.len(),
+            out.as_mut_ptr().cast(),
+            out.len(),
+        )
+    };
+    assert!(size > 0);
+    out.truncate(size);
+    out
+}
+
+#[test]
+fn test_capi_round_trip() {
+    let data = test_data(100_000);
+    let formats: [(CompressFn, BoundFn, DecompressExFn); 3] = [
+        (
+            libdeflate_deflate_compress,
+            libdeflate_deflate_compress_bound,
+            libdeflate_deflate_decompress_ex,
+        ),
+        (
+            libdeflate_zlib_compress,
+            libdeflate_zlib_compress_bound,
+            libdeflate_zlib_decompress_ex,
+        ),
+        (
+            libdeflate_gzip_compress,
+            libdeflate_gzip_compress_bound,
+            libdeflate_gzip_decompress_ex,
+        ),
+    ];
+    unsafe {
+        let c = libdeflate_alloc_compressor(6);
+        let d = libdeflate_alloc_decompressor();
+        assert!(!c.is_null() && !d.is_null());
+        for (compress_fn, bound_fn, decompress_fn) in formats {
+            let mut compressed = compress(c, &data, compress_fn, bound_fn);
+            // Trailing bytes are not consumed.
+            compressed.extend_from_slice(b"tail");
+
+            let mut out = vec![0u8; data.len() + 10];
+            let (mut in_used, mut out_used) = (0, 0);
+            let res = decompress_fn(
+                d,
+                compressed.as_ptr().cast(),
+                compressed.len(),
+                out.as_mut_ptr().cast(),
+                out.len(),
+                &mut in_used,
+                &mut out_used,
+            );
+            assert_eq!(res, libdeflate_result::LIBDEFLATE_SUCCESS);
+            assert_eq!(in_used, compressed.len() - 4);
+            assert_eq!(&out[..out_used], &data[..]);
+        }
+        libdeflate_free_compressor(c);
+        libdeflate_free_decompressor(d);
+    }
+}
+
+#[test]
+fn test_capi_result_codes() {
+    let data = test_data(10_000);
+    unsafe {
+        let c = libdeflate_alloc_compressor(1);
+        let d = libdeflate_alloc_decompressor();
+        let compressed = compress(
+            c,
+            &data,
+            libdeflate_gzip_compress,
+            libdeflate_gzip_compress_bound,
+        );
+
+        // Without actual_out_nbytes_ret the output must be filled exactly.
+        let mut out = vec![0u8; data.len()];
+        let res = libdeflate_gzip_decompress(
+            d,
+            compressed.as_ptr().cast(),
+            compressed.len(),
+            out.as_mut_ptr().cast(),
+            out.len(),
+            ptr::null_mut(),
+        );
+        assert_eq!(res, libdeflate_result::LIBDEFLATE_SUCCESS);
+        assert_eq!(out, data);
+
+        let mut big = vec![0u8; data.len() + 1];
+        let res = libdeflate_gzip_decompress(
+            d,
+            compressed.as_ptr().cast(),
+            compressed.len(),
+            big.as_mut_ptr().cast(),
+            big.len(),
+            ptr::null_mut(),
+        );
+        assert_eq!(res, libdeflate_result::LIBDEFLATE_SHORT_OUTPUT);
+
+        let res = libdeflate_gzip_decompress(
+            d,
+            compressed.as_ptr().cast(),
+            compressed.len(),
+            out.as_mut_ptr().cast(),
+            out.len() - 1,
+            ptr::null_mut(),
+        );
+        assert_eq!(res, libdeflate_result::LIBDEFLATE_INSUFFICIENT_SPACE);
+
+        // Truncated input is reported as bad data, as in libdeflate.
+        let res = libdeflate_gzip_decompress(
+            d,
+            compressed.as_ptr().cast(),
+            compressed.len() / 2,
+            out.as_mut_ptr().cast(),
+            out.len(),
+            ptr::null_mut(),
+        );
+        assert_eq!(res, libdeflate_result::LIBDEFLATE_BAD_DATA);
+
+        let mut actual = 0;
+        let res = libdeflate_zlib_decompress(
+            d,
+            compressed.as_ptr().cast(),
+            compressed.len(),
+            out.as_mut_ptr().cast(),
+            out.len(),
+            &mut actual,
+        );
+        assert_eq!(res, libdeflate_result::LIBDEFLATE_BAD_DATA);
+
+        // Compression fails with 0 when the output buffer is too small.
+        let size = libdeflate_deflate_compress(
+            c,
+            data.as_ptr().cast(),
+            data.len(),
+            out.as_mut_ptr().cast(),
+            10,
+        );
+        assert_eq!(size, 0);
+
+        libdeflate_free_compressor(c);
+        libdeflate_free_decompressor(d);
+    }
+}
+
+#[test]
+fn test_capi_levels_and_null() {
+    unsafe {
+        assert!(libdeflate_alloc_compressor(-1).is_null());
+        assert!(libdeflate_alloc_compressor(13).is_null());
+        for level in 0..=12 {
+            let c = libdeflate_alloc_compressor(level);
+            assert!(!c.is_null());
+            libdeflate_free_compressor(c);
+        }
+        libdeflate_free_compressor(ptr::null_mut());
+        libdeflate_free_decompressor(ptr::null_mut());
+
+        let options = libdeflate_options {
+            sizeof_options: 1,
+            malloc_func: None,
+            free_func: None,
+        };
+        assert!(libdeflate_alloc_decompressor_ex(&options).is_null());
+    }
+}
+
+#[test]
+fn test_capi_checksums() {
+    let data = test_data(1000);
+    unsafe {
+        assert_eq!(
+            libdeflate_crc32(0, data.as_ptr().cast(), data.len()),
+            libdeflate::crc32(0, &data)
+        );
+        assert_eq!(
+            libdeflate_adler32(1, data.as_ptr().cast(), data.len()),
+            libdeflate::adler32(1, &data)
+        );
+        assert_eq!(libdeflate_crc32(1234, ptr::null(), 0), 0);
+        assert_eq!(libdeflate_adler32(1234, ptr::null(), 0), 1);
+    }
+}
+
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+static FREES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe extern "C" fn counting_malloc(size: usize) -> *mut c_void {
+    ALLOCS.fetch_add(1, Ordering::SeqCst);
+    let layout = std::alloc::Layout::from_size_align(size + 64, 64).unwrap();
+    let base = unsafe { std::alloc::alloc(layout) };
+    // Remember the size in front of the returned block so free can rebuild the layout.
+    unsafe {
+        base.cast::<usize>().write(size);
+        base.add(64).cast()
+    }
+}
+
+unsafe extern "C" fn counting_free(ptr: *mut c_void) {
+    FREES.fetch_add(1, Ordering::SeqCst);
+    unsafe {
+        let base = ptr.cast::<u8>().sub(64);
+        let size = base.cast::<usize>().read();
+        let layout = std::alloc::Layout::from_size_align(size + 64, 64).unwrap();
+        std::alloc::dealloc(base, layout);
+    }
+}
+
+#[test]
+fn test_capi_custom_allocator() {
+    let options = libdeflate_options {
+        sizeof_options: size_of::<libdeflate_options>(),
+        malloc_func: Some(counting_malloc),
+        free_func: Some(counting_free),
+    };
+    let data = test_data(5000);
+    unsafe {
+        let c = libdeflate_alloc_compressor_ex(9, &options);
+        let d = libdeflate_alloc_decompressor_ex(&options);
+        assert_eq!(ALLOCS.load(Ordering::SeqCst), 2);
+
+        let compressed = compress(
+            c,
+            &data,
+            libdeflate_deflate_compress,
+            libdeflate_deflate_compress_bound,
+        );
+        let mut out = vec![0u8; data.len()];
+        let res = libdeflate_deflate_decompress(
+            d,
+            compressed.as_ptr().cast(),
+            compressed.len(),
+            out.as_mut_ptr().cast(),
+            out.len(),
+            ptr::null_mut(),
+        );
+        assert_eq!(res, libdeflate_result::LIBDEFLATE_SUCCESS);
+        assert_eq!(out, data);
+
+        libdeflate_free_compressor(c);
+        libdeflate_free_decompressor(d);
+        assert_eq!(FREES.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,104 @@
+use libdeflate::adler32::{adler32, adler32_combine};
+use libdeflate::crc32::{crc32, crc32_combine};
+
+/// Deterministic pseudo-random bytes.
+fn noise(len: usize, seed: u32) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 24) as u8
+        })
+        .collect()
+}
+
+/// Split points covering empty halves, SIMD block boundaries and random positions.
+fn split_points(len: usize, seed: u32) -> Vec<usize> {
+    let mut points = vec![0, len];
+    for p in [1, 15, 16, 17, 31, 32, 63, 64, 65, 255, 256, 5552, 5553] {
+        if p < len {
+            points.push(p);
+            points.push(len - p);
+        }
+    }
+    let mut state = seed;
+    for _ in 0..16 {
+        state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+        points.push(state as usize % (len + 1));
+    }
+    points
+}
+
+#[test]
+fn test_crc32_combine_matches_crc32() {
+    for (i, len) in [0, 1, 2, 100, 1000, 5553, 70_000, 300_000]
+        .into_iter()
+        .enumerate()
+    {
+        let data = noise(len, i as u32);
+        let expected = crc32(0, &data);
+        for split in split_points(len, i as u32 + 100) {
+            let (a, b) = data.split_at(split);
+            let combined = crc32_combine(crc32(0, a), crc32(0, b), b.len() as u64);
+            assert_eq!(combined, expected, "len {len} split {split}");
+        }
+    }
+}
+
+#[test]
+fn test_adler32_combine_matches_adler32() {
+    for (i, len) in [0, 1, 2, 100, 1000, 5553, 70_000, 300_000]
+        .into_iter()
+        .enumerate()
+    {
+        let data = noise(len, i as u32);
+        let expected = adler32(1, &data);
+        for split in split_points(len, i as u32 + 100) {
+            let (a, b) = data.split_at(split);
+            let combined = adler32_combine(adler32(1, a), adler32(1, b), b.len() as u64);
+            assert_eq!(combined, expected, "len {len} split {split}");
+        }
+    }
+}
+
+#[test]
+fn test_combine_many_chunks() {
+    // Folding chunk checksums left to right, as a parallel compressor would.
+    let data = noise(1 << 20, 7);
+    for chunk_size in [1000, 4096, 65536, 100_003] {
+        let mut crc = crc32(0, &[]);
+        let mut adler = adler32(1, &[]);
+        for chunk in data.chunks(chunk_size) {
+            crc = crc32_combine(crc, crc32(0, chunk), chunk.len() as u64);
+            adler = adler32_combine(adler, adler32(1, chunk), chunk.len() as u64);
+        }
+        assert_eq!(crc, crc32(0, &data), "chunk size {chunk_size}");
+        assert_eq!(adler, adler32(1, &data), "chunk size {chunk_size}");
+    }
+}
+
+#[test]
+fn test_combine_lengths_beyond_4gib() {
+    // Combining is associative, which checks the length handling past 32 bits
+    // without checksumming that much data.
+    let (a, b, c) = (crc32(0, b"a"), crc32(0, b"b"), crc32(0, b"c"));
+    for (len_b, len_c) in [
+        (1 << 32, 1),
+        ((1 << 33) + 5, (1 << 40) + 3),
+        (u64::MAX / 4, 12345),
+    ] {
+        let left = crc32_combine(crc32_combine(a, b, len_b), c, len_c);
+        let right = crc32_combine(a, crc32_combine(b, c, len_c), len_b + len_c);
+        assert_eq!(left, right, "{len_b} + {len_c}");
+    }
+
+    // Adler-32 of n zero bytes is 1 + ((n mod 65521) << 16); build it up by doubling.
+    let zeros = vec![0u8; 1 << 16];
+    let mut adler = adler32(1, &zeros);
+    let mut len = zeros.len() as u64;
+    while len < 1 << 34 {
+        adler = adler32_combine(adler, adler, len);
+        len *= 2;
+    }
+    assert_eq!(adler, 1 | (((len % 65521) as u32) << 16));
+}
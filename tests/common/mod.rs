@@ -0,0 +1,48 @@
+//! Test data shared by the integration tests. Each test crate uses only some of it.
+#![allow(dead_code)]
+
+/// Advances a xorshift32 generator, whose state must not be zero, and returns the
+/// new state.
+pub fn xorshift(state: &mut u32) -> u32 {
+    *state ^= *state << 13;
+    *state ^= *state >> 17;
+    *state ^= *state << 5;
+    *state
+}
+
+fn log_line(i: usize, state: &mut u32) -> Vec<u8> {
+    let state = xorshift(state);
+    format!(
+        "{i} level=info host=web-{} path=/api/v1/items/{} status={} ms={}\n",
+        state % 8,
+        state % 3000,
+        [200, 200, 204, 404][state as usize % 4],
+        state % 250
+    )
+    .into_bytes()
+}
+
+/// `count` lines of a web server log, each ending with a newline.
+pub fn log_lines(count: usize, seed: u32) -> Vec<Vec<u8>> {
+    let mut state = seed;
+    (0..count).map(|i| log_line(i, &mut state)).collect()
+}
+
+/// The lines of [`log_lines`] as one buffer of exactly `size` bytes.
+pub fn log_text(size: usize, seed: u32) -> Vec<u8> {
+    let mut state = seed;
+    let mut data = Vec::with_capacity(size);
+    let mut i = 0;
+    while data.len() < size {
+        data.extend(log_line(i, &mut state));
+        i += 1;
+    }
+    data.truncate(size);
+    data
+}
+
+/// `size` bytes that do not compress.
+pub fn noise(size: usize, seed: u32) -> Vec<u8> {
+    let mut state = seed;
+    (0..size).map(|_| xorshift(&mut state) as u8).collect()
+}
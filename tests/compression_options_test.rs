@@ -0,0 +1,162 @@
+mod common;
+
+use common::xorshift;
+use libdeflate::{CompressionOptions, Compressor, Decompressor, Error, MatchFinderKind};
+
+fn test_data(size: usize) -> Vec<u8> {
+    let words: [&[u8]; 6] = [
+        b"deflate ",
+        b"options ",
+        b"match ",
+        b"finder ",
+        b"block ",
+        b"\n",
+    ];
+    let mut state = 0x9e37_79b9u32;
+    let mut data = Vec::with_capacity(size);
+    while data.len() < size {
+        let state = xorshift(&mut state);
+        if state.is_multiple_of(5) {
+            data.push(state as u8);
+        } else {
+            data.extend_from_slice(words[state as usize % words.len()]);
+        }
+    }
+    data.truncate(size);
+    data
+}
+
+fn round_trip(options: CompressionOptions, data: &[u8]) -> Vec<u8> {
+    let mut compressor = Compressor::with_options(options).unwrap();
+    let compressed = compressor.compress_deflate(data).unwrap();
+    let decompressed = Decompressor::new()
+        .decompress_deflate(&compressed, data.len())
+        .unwrap();
+    assert_eq!(decompressed, data);
+    compressed
+}
+
+#[test]
+fn test_default_options_match_level() {
+    let data = test_data(200_000);
+    for level in 0..=12 {
+        let options = CompressionOptions::new(level).unwrap();
+        assert_eq!(options.level(), level as usize);
+        let mut with_options = Compressor::with_options(options).unwrap();
+        let mut with_level = Compressor::new(level).unwrap();
+        assert_eq!(
+            with_options.compress_gzip(&data).unwrap(),
+            with_level.compress_gzip(&data).unwrap(),
+            "level {level}"
+        );
+    }
+}
+
+#[test]
+fn test_level_defaults() {
+    let options = CompressionOptions::new(1).unwrap();
+    assert_eq!(options.match_finder(), MatchFinderKind::HashTable);
+    let options = CompressionOptions::new(6).unwrap();
+    assert_eq!(options.match_finder(), MatchFinderKind::HashChain);
+    assert_eq!(options.max_search_depth(), 35);
+    assert_eq!(options.nice_match_length(), 65);
+    assert_eq!(options.lazy_depth(), 1);
+    let options = CompressionOptions::new(12).unwrap();
+    assert_eq!(options.match_finder(), MatchFinderKind::BinaryTree);
+    assert_eq!(options.near_optimal_passes(), 1);
+    assert!(options.min_block_length() <= options.soft_max_block_length());
+}
+
+#[test]
+fn test_match_finders_with_each_parser() {
+    let data = test_data(300_000);
+    for level in [1, 4, 8, 10, 12] {
+        for match_finder in [
+            MatchFinderKind::HashTable,
+            MatchFinderKind::HashChain,
+            MatchFinderKind::BinaryTree,
+        ] {
+            let options = CompressionOptions::new(level)
+                .unwrap()
+                .with_match_finder(match_finder);
+            round_trip(options, &data);
+        }
+    }
+}
+
+#[test]
+fn test_tuned_parameters_round_trip() {
+    let data = test_data(400_000);
+    for level in [2, 6, 9, 11] {
+        for lazy_depth in 0..=2 {
+            let options = CompressionOptions::new(level)
+                .unwrap()
+                .with_max_search_depth(8)
+                .with_nice_match_length(258)
+                .with_lazy_depth(lazy_depth);
+            round_trip(options, &data);
+        }
+    }
+
+    let options = CompressionOptions::new(6)
+        .unwrap()
+        .with_min_block_length(1000)
+        .with_soft_max_block_length(4000);
+    let small_blocks = round_trip(options.clone(), &data);
+    assert_eq!(options.soft_max_block_length(), 4000);
+    let default_blocks = round_trip(CompressionOptions::new(6).unwrap(), &data);
+    assert_ne!(small_blocks, default_blocks);
+}
+
+#[test]
+fn test_near_optimal_passes() {
+    let data = test_data(300_000);
+    let one_pass = round_trip(CompressionOptions::new(12).unwrap(), &data);
+    let three_passes = round_trip(
+        CompressionOptions::new(12)
+            .unwrap()
+            .with_near_optimal_passes(3),
+        &data,
+    );
+    assert!(three_passes.len() <= one_pass.len() + one_pass.len() / 100);
+}
+
+#[test]
+fn test_invalid_options() {
+    assert!(matches!(
+        CompressionOptions::new(13),
+        Err(Error::InvalidLevel(13))
+    ));
+    assert!(matches!(
+        CompressionOptions::new(-1),
+        Err(Error::InvalidLevel(-1))
+    ));
+
+    let base = CompressionOptions::new(6).unwrap();
+    for options in [
+        base.clone().with_max_search_depth(0),
+        base.clone().with_nice_match_length(2),
+        base.clone().with_nice_match_length(259),
+        base.clone().with_lazy_depth(3),
+        base.clone().with_min_block_length(0),
+        base.clone()
+            .with_min_block_length(10_000)
+            .with_soft_max_block_length(5_000),
+        base.clone().with_near_optimal_passes(0),
+        CompressionOptions::new(12)
+            .unwrap()
+            .with_soft_max_block_length(usize::MAX),
+    ] {
+        assert!(matches!(
+            options.validate(),
+            Err(Error::InvalidParameter(_))
+        ));
+        assert!(matches!(
+            Compressor::with_options(options),
+            Err(Error::InvalidParameter(_))
+        ));
+    }
+
+    let stored = CompressionOptions::new(0).unwrap().with_max_search_depth(0);
+    assert!(Compressor::with_options(stored).is_ok());
+}
@@ -0,0 +1,283 @@
+mod common;
+
+use common::noise;
+use libdeflate::{Compressor, Decompressor};
+
+const DICT: &[u8] = br#"{"user_id": , "event": "click", "timestamp": , "payload": {"page": "/home"}}"#;
+const RECORD: &[u8] =
+    br#"{"user_id": 42, "event": "click", "timestamp": 1700000000, "payload": {"page": "/home"}}"#;
+
+fn generate_records(count: usize) -> Vec<Vec<u8>> {
+    (0..count)
+        .map(|i| {
+            format!(
+                r#"{{"user_id": {}, "event": "click", "timestamp": {}, "payload": {{"page": "/home"}}}}"#,
+                i * 7,
+                1700000000 + i * 13
+            )
+            .into_bytes()
+        })
+        .collect()
+}
+
+#[test]
+fn test_deflate_dictionary_round_trip_all_levels() {
+    for level in 0..=12 {
+        let mut compressor = Compressor::new(level).unwrap();
+        compressor.set_dictionary(DICT);
+        let mut decompressor = Decompressor::new();
+        decompressor.set_dictionary(DICT);
+
+        for record in generate_records(20) {
+            let compressed = compressor.compress_deflate(&record).unwrap();
+            // Reusing the compressor gives the same output as a fresh one.
+            let mut fresh = Compressor::new(level).unwrap();
+            fresh.set_dictionary(DICT);
+            assert_eq!(fresh.compress_deflate(&record).unwrap(), compressed);
+            let decompressed = decompressor
+                .decompress_deflate(&compressed, record.len())
+                .unwrap();
+            assert_eq!(decompressed, record, "level {}", level);
+        }
+    }
+}
+
+#[test]
+fn test_dictionary_improves_ratio() {
+    let mut plain = Compressor::new(6).unwrap();
+    let mut primed = Compressor::new(6).unwrap();
+    primed.set_dictionary(DICT);
+
+    let plain_size = plain.compress_deflate(RECORD).unwrap().len();
+    let primed_size = primed.compress_deflate(RECORD).unwrap().len();
+    assert!(
+        primed_size < plain_size,
+        "primed {} should be smaller than plain {}",
+        primed_size,
+        plain_size
+    );
+}
+
+#[test]
+fn test_deflate_dictionary_required_for_decoding() {
+    let mut compressor = Compressor::new(6).unwrap();
+    compressor.set_dictionary(DICT);
+    let compressed = compressor.compress_deflate(RECORD).unwrap();
+
+    let mut decompressor = Decompressor::new();
+    assert!(
+        decompressor
+            .decompress_deflate(&compressed, RECORD.len())
+            .is_err()
+    );
+}
+
+#[test]
+fn test_zlib_dictionary_header() {
+    let mut compressor = Compressor::new(6).unwrap();
+    compressor.set_dictionary(DICT);
+    let compressed = compressor.compress_zlib(RECORD).unwrap();
+
+    let hdr = u16::from_be_bytes([compressed[0], compressed[1]]);
+    assert_eq!(hdr % 31, 0);
+    assert_ne!(hdr & 0x20, 0, "FDICT must be set");
+    let dictid = u32::from_be_bytes([compressed[2], compressed[3], compressed[4], compressed[5]]);
+    assert_eq!(dictid, libdeflate::adler32(1, DICT));
+
+    let mut decompressor = Decompressor::new();
+    decompressor.set_dictionary(DICT);
+    let decompressed = decompressor
+        .decompress_zlib(&compressed, RECORD.len())
+        .unwrap();
+    assert_eq!(decompressed, RECORD);
+}
+
+#[test]
+fn test_zlib_dictionary_mismatch() {
+    let mut compressor = Compressor::new(6).unwrap();
+    compressor.set_dictionary(DICT);
+    let compressed = compressor.compress_zlib(RECORD).unwrap();
+
+    let mut decompressor = Decompressor::new();
+    assert!(decompressor.decompress_zlib(&compressed, RECORD.len()).is_err());
+
+    decompressor.set_dictionary(b"some other dictionary");
+    assert!(decompressor.decompress_zlib(&compressed, RECORD.len()).is_err());
+}
+
+#[test]
+fn test_zlib_without_fdict_ignores_dictionary() {
+    let mut compressor = Compressor::new(6).unwrap();
+    let compressed = compressor.compress_zlib(RECORD).unwrap();
+
+    let mut decompressor = Decompressor::new();
+    decompressor.set_dictionary(DICT);
+    let decompressed = decompressor
+        .decompress_zlib(&compressed, RECORD.len())
+        .unwrap();
+    assert_eq!(decompressed, RECORD);
+}
+
+#[test]
+fn test_zlib_dictionary_interop() {
+    // Produced by zlib: compressobj(9, DEFLATED, 15, 9, Z_DEFAULT_STRATEGY, zdict=DICT)
+    let zlib_output: [u8; 26] = [
+        120, 249, 146, 19, 23, 185, 171, 70, 50, 203, 196, 136, 160, 105, 134, 230, 6, 80, 64,
+        192, 92, 0, 138, 237, 26, 7,
+    ];
+
+    let mut decompressor = Decompressor::new();
+    decompressor.set_dictionary(DICT);
+    let decompressed = decompressor
+        .decompress_zlib(&zlib_output, RECORD.len())
+        .unwrap();
+    assert_eq!(decompressed, RECORD);
+}
+
+#[test]
+fn test_dictionary_larger_than_window() {
+    let mut dict = vec![0u8; 40000];
+    for (i, b) in dict.iter_mut().enumerate() {
+        *b = (i * 31 % 251) as u8;
+    }
+    let data = dict[dict.len() - 1000..].to_vec();
+
+    for level in [1, 6, 12] {
+        let mut compressor = Compressor::new(level).unwrap();
+        compressor.set_dictionary(&dict);
+        let compressed = compressor.compress_zlib(&data).unwrap();
+        assert!(compressed.len() < 100, "level {}: {}", level, compressed.len());
+
+        let mut decompressor = Decompressor::new();
+        decompressor.set_dictionary(&dict);
+        let decompressed = decompressor.decompress_zlib(&compressed, data.len()).unwrap();
+        assert_eq!(decompressed, data);
+    }
+}
+
+#[test]
+fn test_clear_dictionary() {
+    let mut compressor = Compressor::new(6).unwrap();
+    compressor.set_dictionary(DICT);
+    compressor.clear_dictionary();
+    let compressed = compressor.compress_zlib(RECORD).unwrap();
+    assert_eq!(compressed[1] & 0x20, 0);
+
+    let mut decompressor = Decompressor::new();
+    let decompressed = decompressor
+        .decompress_zlib(&compressed, RECORD.len())
+        .unwrap();
+    assert_eq!(decompressed, RECORD);
+}
+
+#[test]
+fn test_gzip_ignores_dictionary() {
+    let mut compressor = Compressor::new(6).unwrap();
+    compressor.set_dictionary(DICT);
+    let compressed = compressor.compress_gzip(RECORD).unwrap();
+
+    let mut decompressor = Decompressor::new();
+    let decompressed = decompressor
+        .decompress_gzip(&compressed, RECORD.len())
+        .unwrap();
+    assert_eq!(decompressed, RECORD);
+}
+
+#[test]
+fn test_dictionary_large_input() {
+    let mut data = Vec::new();
+    for record in generate_records(8000) {
+        data.extend_from_slice(&record);
+    }
+    assert!(data.len() > 256 * 1024);
+
+    let mut compressor = Compressor::new(6).unwrap();
+    compressor.set_dictionary(DICT);
+    let compressed = compressor.compress_zlib(&data).unwrap();
+
+    let mut decompressor = Decompressor::new();
+    decompressor.set_dictionary(DICT);
+    let decompressed = decompressor.decompress_zlib(&compressed, data.len()).unwrap();
+    assert_eq!(decompressed, data);
+}
+
+#[test]
+fn test_near_optimal_levels_with_dictionary() {
+    // Input mostly covered by matches into the dictionary leaves many symbols
+    // without statistics; the near-optimal levels must still beat level 9.
+    let records = generate_records(600);
+    let dict = records[..300].concat();
+    let data = records[300..].concat();
+    let size = |level| {
+        let mut compressor = Compressor::new(level).unwrap();
+        compressor.set_dictionary(&dict);
+        compressor.compress_deflate(&data).unwrap().len()
+    };
+    let level9 = size(9);
+    for level in 10..=12 {
+        let size = size(level);
+        assert!(size <= level9, "level {}: {} vs {}", level, size, level9);
+    }
+}
+
+#[test]
+fn test_matches_spanning_dictionary_and_output() {
+    // The first match starts in the dictionary and continues into the bytes it
+    // produces itself.
+    let dict = b"hello";
+    let data = dict.repeat(200);
+    for level in [1, 6, 12] {
+        let mut compressor = Compressor::new(level).unwrap();
+        compressor.set_dictionary(dict);
+        let compressed = compressor.compress_deflate(&data).unwrap();
+        assert!(
+            compressed.len() < 20,
+            "level {}: {}",
+            level,
+            compressed.len()
+        );
+
+        let mut decompressor = Decompressor::new();
+        decompressor.set_dictionary(dict);
+        let decompressed = decompressor
+            .decompress_deflate(&compressed, data.len())
+            .unwrap();
+        assert_eq!(decompressed, data, "level {}", level);
+        let decompressed = decompressor.decompress_deflate_to_vec(&compressed).unwrap();
+        assert_eq!(decompressed, data, "level {}", level);
+
+        // Too little output space fails cleanly.
+        let mut output = vec![0u8; 3];
+        assert!(
+            decompressor
+                .decompress_deflate_into(&compressed, &mut output)
+                .is_err()
+        );
+    }
+}
+
+#[test]
+fn test_match_beyond_dictionary_is_rejected() {
+    let dict = noise(32 * 1024, 0x2545_f491);
+    let data = dict[..1000].to_vec();
+    let mut compressor = Compressor::new(6).unwrap();
+    compressor.set_dictionary(&dict);
+    let compressed = compressor.compress_deflate(&data).unwrap();
+    assert!(compressed.len() < 100, "{}", compressed.len());
+
+    let mut decompressor = Decompressor::new();
+    decompressor.set_dictionary(&dict);
+    let decompressed = decompressor
+        .decompress_deflate(&compressed, data.len())
+        .unwrap();
+    assert_eq!(decompressed, data);
+
+    // The matches reach back to the start of the dictionary, so with its first
+    // bytes missing they reach too far.
+    decompressor.set_dictionary(&dict[100..]);
+    assert!(
+        decompressor
+            .decompress_deflate(&compressed, data.len())
+            .is_err()
+    );
+}
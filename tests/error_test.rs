@@ -0,0 +1,306 @@
+use libdeflate::stream::{DeflateDecoder, GzipDecoder};
+use libdeflate::{Compressor, Decompressor, Error, Position};
+use std::io::{self, Cursor, Read};
+
+/// Packs DEFLATE bit fields, least significant bit first.
+#[derive(Default)]
+struct Bits {
+    bytes: Vec<u8>,
+    nbits: usize,
+}
+
+impl Bits {
+    fn put(&mut self, value: u32, count: usize) -> &mut Self {
+        for i in 0..count {
+            if self.nbits.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if (value >> i) & 1 != 0 {
+                *self.bytes.last_mut().unwrap() |= 1 << (self.nbits % 8);
+            }
+            self.nbits += 1;
+        }
+        self
+    }
+
+    /// Writes a Huffman codeword, which DEFLATE packs most significant bit first.
+    fn code(&mut self, code: u32, len: usize) -> &mut Self {
+        for i in (0..len).rev() {
+            self.put((code >> i) & 1, 1);
+        }
+        self
+    }
+}
+
+fn test_data(len: usize) -> Vec<u8> {
+    (0..len as u32)
+        .map(|i| (i.wrapping_mul(2654435761) >> 28) as u8 + b'a')
+        .collect()
+}
+
+#[test]
+fn test_error_bad_block_type() {
+    let mut decompressor = Decompressor::new();
+    let err = decompressor.decompress_deflate(&[0x07], 100).unwrap_err();
+    assert_eq!(
+        err,
+        Error::BadBlockType {
+            at: Position {
+                bit_offset: 0,
+                block_index: 0
+            }
+        }
+    );
+
+    // An empty stored block followed by a block of the reserved type.
+    let data = [0x00, 0x00, 0x00, 0xFF, 0xFF, 0x07];
+    let err = decompressor.decompress_deflate(&data, 100).unwrap_err();
+    assert_eq!(
+        err,
+        Error::BadBlockType {
+            at: Position {
+                bit_offset: 40,
+                block_index: 1
+            }
+        }
+    );
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(err.position().unwrap().byte_offset(), 5);
+}
+
+#[test]
+fn test_error_invalid_stored_block() {
+    let mut decompressor = Decompressor::new();
+    let err = decompressor
+        .decompress_deflate(&[0x01, 0x05, 0x00, 0x00, 0x00], 100)
+        .unwrap_err();
+    assert_eq!(
+        err,
+        Error::InvalidStoredBlock {
+            at: Position {
+                bit_offset: 8,
+                block_index: 0
+            }
+        }
+    );
+}
+
+#[test]
+fn test_error_invalid_huffman_code() {
+    // A dynamic block whose four precode lengths are all 1: an over-subscribed code.
+    let mut bits = Bits::default();
+    bits.put(1, 1).put(2, 2).put(0, 5).put(0, 5).put(0, 4);
+    for _ in 0..4 {
+        bits.put(1, 3);
+    }
+    let mut decompressor = Decompressor::new();
+    let err = decompressor
+        .decompress_deflate(&bits.bytes, 100)
+        .unwrap_err();
+    match err {
+        Error::InvalidHuffmanCode { at } => assert_eq!(at.block_index, 0),
+        other => panic!("unexpected error: {other:?}"),
+    }
+}
+
+#[test]
+fn test_error_distance_too_far_back() {
+    // A static block starting with a length 3, distance 1 match.
+    let mut bits = Bits::default();
+    bits.put(1, 1)
+        .put(1, 2)
+        .code(0b0000001, 7)
+        .code(0, 5)
+        .code(0, 7);
+    let mut decompressor = Decompressor::new();
+    let err = decompressor
+        .decompress_deflate(&bits.bytes, 100)
+        .unwrap_err();
+    assert_eq!(
+        err,
+        Error::DistanceTooFarBack {
+            distance: 1,
+            available: 0,
+            at: Position {
+                bit_offset: 15,
+                block_index: 0
+            }
+        }
+    );
+
+    // Long enough to go through the fast decode loop: 40 literals, then a match
+    // reaching back 24577 bytes.
+    let mut bits = Bits::default();
+    bits.put(1, 1).put(1, 2);
+    for _ in 0..40 {
+        bits.code(0x30 + b'a' as u32, 8);
+    }
+    bits.code(0b0000001, 7).code(29, 5).put(0, 13);
+    for _ in 0..40 {
+        bits.code(0x30 + b'a' as u32, 8);
+    }
+    bits.code(0, 7);
+    let err = decompressor
+        .decompress_deflate(&bits.bytes, 1000)
+        .unwrap_err();
+    match err {
+        Error::DistanceTooFarBack {
+            distance,
+            available,
+            at,
+        } => {
+            assert_eq!((distance, available), (24577, 40));
+            assert_eq!(at.bit_offset, 3 + 40 * 8 + 7 + 5 + 13);
+        }
+        other => panic!("unexpected error: {other:?}"),
+    }
+}
+
+#[test]
+fn test_error_checksum_and_size_mismatch() {
+    let data = test_data(10_000);
+    let mut compressor = Compressor::new(6).unwrap();
+    let mut decompressor = Decompressor::new();
+
+    let mut gzip = compressor.compress_gzip(&data).unwrap();
+    let len = gzip.len();
+    let crc = libdeflate::crc32(0, &data);
+    gzip[len - 8] ^= 1;
+    match decompressor.decompress_gzip(&gzip, data.len()).unwrap_err() {
+        Error::ChecksumMismatch {
+            expected,
+            actual,
+            at,
+        } => {
+            assert_eq!(expected, crc ^ 1);
+            assert_eq!(actual, crc);
+            assert_eq!(at.byte_offset(), (len - 8) as u64);
+        }
+        other => panic!("unexpected error: {other:?}"),
+    }
+    gzip[len - 8] ^= 1;
+    gzip[len - 4] ^= 1;
+    match decompressor
+        .decompress_gzip(&gzip, data.len() + 1)
+        .unwrap_err()
+    {
+        Error::SizeMismatch {
+            expected,
+            actual,
+            at,
+        } => {
+            assert_eq!(expected, data.len() as u32 ^ 1);
+            assert_eq!(actual, data.len() as u32);
+            assert_eq!(at.byte_offset(), (len - 4) as u64);
+        }
+        other => panic!("unexpected error: {other:?}"),
+    }
+
+    let mut zlib = compressor.compress_zlib(&data).unwrap();
+    let len = zlib.len();
+    zlib[len - 1] ^= 1;
+    match decompressor.decompress_zlib(&zlib, data.len()).unwrap_err() {
+        Error::ChecksumMismatch {
+            expected,
+            actual,
+            at,
+        } => {
+            assert_eq!(actual, libdeflate::adler32(1, &data));
+            assert_eq!(expected, actual ^ 1);
+            assert_eq!(at.byte_offset(), (len - 4) as u64);
+        }
+        other => panic!("unexpected error: {other:?}"),
+    }
+}
+
+#[test]
+fn test_error_truncated_and_output_too_small() {
+    let data = test_data(50_000);
+    let compressed = Compressor::new(6).unwrap().compress_deflate(&data).unwrap();
+    let mut decompressor = Decompressor::new();
+
+    let half = &compressed[..compressed.len() / 2];
+    let err = decompressor
+        .decompress_deflate(half, data.len())
+        .unwrap_err();
+    assert!(matches!(err, Error::TruncatedInput { .. }), "{err:?}");
+    assert_eq!(err.position().unwrap().byte_offset(), half.len() as u64);
+
+    let mut output = vec![0u8; 1000];
+    let err = decompressor
+        .decompress_deflate_into(&compressed, &mut output)
+        .unwrap_err();
+    assert!(matches!(err, Error::OutputTooSmall { .. }), "{err:?}");
+}
+
+#[test]
+fn test_error_dictionary_mismatch() {
+    let dictionary = b"a preset dictionary with some words in it";
+    let mut compressor = Compressor::new(6).unwrap();
+    compressor.set_dictionary(dictionary);
+    let compressed = compressor.compress_zlib(b"some words in it").unwrap();
+
+    let mut decompressor = Decompressor::new();
+    let err = decompressor.decompress_zlib(&compressed, 100).unwrap_err();
+    assert_eq!(
+        err,
+        Error::DictionaryMismatch {
+            expected: libdeflate::adler32(1, dictionary),
+            actual: None,
+            at: Position {
+                bit_offset: 16,
+                block_index: 0
+            }
+        }
+    );
+}
+
+#[test]
+fn test_error_into_io_error() {
+    let mut decompressor = Decompressor::new();
+    let err = decompressor.decompress_deflate(&[0x07], 100).unwrap_err();
+    let message = err.to_string();
+    let io_err: io::Error = err.clone().into();
+    assert_eq!(io_err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(io_err.to_string(), message);
+    assert_eq!(
+        io_err.get_ref().unwrap().downcast_ref::<Error>(),
+        Some(&err)
+    );
+
+    let err = Compressor::new(42).err().unwrap();
+    assert_eq!(err, Error::InvalidLevel(42));
+    assert_eq!(io::Error::from(err).kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_error_from_stream_decoders() {
+    let data = [0x00, 0x00, 0x00, 0xFF, 0xFF, 0x07];
+    let err = DeflateDecoder::new(Cursor::new(&data))
+        .read_to_end(&mut Vec::new())
+        .unwrap_err();
+    let inner = err.get_ref().unwrap().downcast_ref::<Error>().unwrap();
+    assert_eq!(
+        inner,
+        &Error::BadBlockType {
+            at: Position {
+                bit_offset: 40,
+                block_index: 1
+            }
+        }
+    );
+
+    let payload = test_data(5000);
+    let mut gzip = Compressor::new(6).unwrap().compress_gzip(&payload).unwrap();
+    let len = gzip.len();
+    gzip[len - 8] ^= 1;
+    let err = GzipDecoder::new(Cursor::new(&gzip))
+        .read_to_end(&mut Vec::new())
+        .unwrap_err();
+    match err.get_ref().unwrap().downcast_ref::<Error>().unwrap() {
+        Error::ChecksumMismatch { at, .. } => {
+            assert_eq!(at.byte_offset(), (len - 8) as u64)
+        }
+        other => panic!("unexpected error: {other:?}"),
+    }
+}
@@ -0,0 +1,374 @@
+use libdeflate::stream::{GzipDecoder, GzipEncoder};
+use libdeflate::{Compressor, Decompressor, GzipHeader};
+use std::io::{Cursor, Read, Write};
+
+fn test_data(len: usize, seed: u32) -> Vec<u8> {
+    (0..len as u32)
+        .map(|i| (i.wrapping_add(seed).wrapping_mul(2654435761) >> 27) as u8 ^ (i / 4096) as u8)
+        .collect()
+}
+
+struct OneByteReader<R>(R);
+
+impl<R: Read> Read for OneByteReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(1);
+        self.0.read(&mut buf[..len])
+    }
+}
+
+/// Three members from different encoders, concatenated as `cat` would.
+fn concatenated_members() -> (Vec<Vec<u8>>, Vec<Vec<u8>>) {
+    let parts = vec![test_data(70_000, 1), test_data(0, 2), test_data(300_000, 3)];
+
+    let mut members = Vec::new();
+    let mut compressor = Compressor::new(6).unwrap();
+    members.push(compressor.compress_gzip(&parts[0]).unwrap());
+
+    let mut encoder = GzipEncoder::new(Vec::new(), 1);
+    encoder.write_all(&parts[1]).unwrap();
+    members.push(encoder.finish().unwrap());
+
+    let mut c = libdeflater::Compressor::new(libdeflater::CompressionLvl::new(9).unwrap());
+    let mut out = vec![0u8; c.gzip_compress_bound(parts[2].len())];
+    let n = c.gzip_compress(&parts[2], &mut out).unwrap();
+    out.truncate(n);
+    members.push(out);
+
+    (parts, members)
+}
+
+#[test]
+fn test_gzip_multi_member_one_shot() {
+    let (parts, members) = concatenated_members();
+    let input = members.concat();
+    let expected = parts.concat();
+
+    let mut decompressor = Decompressor::new();
+    assert_eq!(decompressor.decompress_gzip(&input, expected.len()).unwrap(), expected);
+
+    let mut output = vec![0u8; expected.len()];
+    let n = decompressor.decompress_gzip_into(&input, &mut output).unwrap();
+    assert_eq!(n, expected.len());
+    assert_eq!(output, expected);
+
+    let mut offset = 0;
+    for (part, member) in parts.iter().zip(&members) {
+        let (data, consumed) = decompressor
+            .decompress_gzip_member(&input[offset..], part.len())
+            .unwrap();
+        assert_eq!(&data, part);
+        assert_eq!(consumed, member.len());
+        offset += consumed;
+    }
+    assert_eq!(offset, input.len());
+
+    let mut output = vec![0u8; parts[0].len()];
+    let (size, consumed) = decompressor
+        .decompress_gzip_member_into(&input, &mut output)
+        .unwrap();
+    assert_eq!(size, parts[0].len());
+    assert_eq!(consumed, members[0].len());
+    assert_eq!(output, parts[0]);
+}
+
+#[test]
+fn test_gzip_multi_member_trailing_garbage() {
+    let (parts, members) = concatenated_members();
+    let mut input = members[0].clone();
+    input.extend_from_slice(b"not a gzip member");
+
+    let mut decompressor = Decompressor::new();
+    assert!(decompressor.decompress_gzip(&input, parts[0].len() + 100).is_err());
+
+    let (data, consumed) = decompressor
+        .decompress_gzip_member(&input, parts[0].len())
+        .unwrap();
+    assert_eq!(data, parts[0]);
+    assert_eq!(&input[consumed..], b"not a gzip member");
+}
+
+#[test]
+fn test_gzip_multi_member_zero_padding() {
+    let (parts, members) = concatenated_members();
+    let expected = parts.concat();
+    let mut input = members.concat();
+    input.resize(input.len() + 1000, 0);
+
+    let mut decompressor = Decompressor::new();
+    assert_eq!(
+        decompressor
+            .decompress_gzip(&input, expected.len())
+            .unwrap(),
+        expected
+    );
+    assert_eq!(
+        decompressor.decompress_gzip_to_vec(&input).unwrap(),
+        expected
+    );
+
+    let mut decoder = GzipDecoder::new(OneByteReader(Cursor::new(&input)));
+    let mut decompressed = Vec::new();
+    decoder.read_to_end(&mut decompressed).unwrap();
+    assert_eq!(decompressed, expected);
+    assert_eq!(decoder.input_offset(), input.len() as u64);
+
+    // Only zeros are padding.
+    input.push(1);
+    assert!(
+        decompressor
+            .decompress_gzip(&input, expected.len())
+            .is_err()
+    );
+    let mut decoder = GzipDecoder::new(Cursor::new(&input));
+    let err = decoder.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_gzip_multi_member_stream() {
+    let (parts, members) = concatenated_members();
+    let input = members.concat();
+
+    let mut decoder = GzipDecoder::new(OneByteReader(Cursor::new(&input)));
+    let mut decompressed = Vec::new();
+    decoder.read_to_end(&mut decompressed).unwrap();
+    assert_eq!(decompressed, parts.concat());
+    assert_eq!(decoder.input_offset(), input.len() as u64);
+
+    let mut decoder = GzipDecoder::new(Cursor::new(&input)).with_multi_member(false);
+    let mut decompressed = Vec::new();
+    decoder.read_to_end(&mut decompressed).unwrap();
+    assert_eq!(decompressed, parts[0]);
+    assert_eq!(decoder.input_offset(), members[0].len() as u64);
+}
+
+#[test]
+fn test_gzip_multi_member_stream_bad_second_member() {
+    let (parts, members) = concatenated_members();
+    let mut input = members[0].clone();
+    let mut second = members[2].clone();
+    let crc_pos = second.len() - 8;
+    second[crc_pos] ^= 0xFF;
+    input.extend_from_slice(&second);
+
+    let mut decoder = GzipDecoder::new(Cursor::new(&input));
+    let mut decompressed = Vec::new();
+    let err = decoder.read_to_end(&mut decompressed).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(decompressed.starts_with(&parts[0]));
+}
+
+fn full_header() -> GzipHeader {
+    GzipHeader::new()
+        .with_text(true)
+        .with_mtime(1_700_000_000)
+        .with_os(3)
+        .with_extra_field(*b"AB", b"subfield")
+        .with_extra_field(*b"CD", &[])
+        .with_filename(b"data.bin")
+        .with_comment(b"made by gzip_test")
+        .with_header_crc(true)
+}
+
+#[test]
+fn test_gzip_header_fields_written() {
+    let data = test_data(100_000, 4);
+    let mut compressor = Compressor::new(9).unwrap();
+    compressor.set_gzip_header(full_header()).unwrap();
+    let compressed = compressor.compress_gzip(&data).unwrap();
+
+    let header_len = full_header().encoded_len();
+    let header = &compressed[..header_len];
+    assert_eq!(header[3], 0x01 | 0x02 | 0x04 | 0x08 | 0x10);
+    assert_eq!(&header[4..8], &1_700_000_000u32.to_le_bytes());
+    assert_eq!(header[8], 0x02);
+    assert_eq!(header[9], 3);
+    assert_eq!(&header[10..12], &16u16.to_le_bytes());
+    assert_eq!(&header[12..28], b"AB\x08\x00subfieldCD\x00\x00");
+    assert_eq!(&header[28..37], b"data.bin\0");
+    assert_eq!(&header[37..55], b"made by gzip_test\0");
+    let crc16 = libdeflate::crc32(0, &header[..55]) as u16;
+    assert_eq!(&header[55..57], &crc16.to_le_bytes());
+
+    let mut decompressor = Decompressor::new();
+    assert_eq!(decompressor.decompress_gzip(&compressed, data.len()).unwrap(), data);
+
+    let mut reference = libdeflater::Decompressor::new();
+    let mut out = vec![0u8; data.len()];
+    assert_eq!(reference.gzip_decompress(&compressed, &mut out).unwrap(), data.len());
+    assert_eq!(out, data);
+
+    compressor.clear_gzip_header();
+    let plain = compressor.compress_gzip(&data).unwrap();
+    assert_eq!(plain.len() + header_len - 10, compressed.len());
+    assert_eq!(&plain[10..], &compressed[header_len..]);
+}
+
+#[test]
+fn test_gzip_header_bound_accounts_for_fields() {
+    let data = test_data(5000, 5);
+    let header = GzipHeader::new()
+        .with_extra(&[7u8; 60_000])
+        .with_comment(&[b'c'; 1000]);
+
+    let mut compressor = Compressor::new(0).unwrap();
+    let plain_bound = compressor.gzip_compress_bound(data.len());
+    compressor.set_gzip_header(header.clone()).unwrap();
+    let bound = compressor.gzip_compress_bound(data.len());
+    assert_eq!(bound, plain_bound + header.encoded_len() - 10);
+
+    let mut output = vec![0u8; bound];
+    let n = compressor.compress_gzip_into(&data, &mut output).unwrap();
+    let mut decompressor = Decompressor::new();
+    assert_eq!(decompressor.decompress_gzip(&output[..n], data.len()).unwrap(), data);
+
+    let mut short = vec![0u8; header.encoded_len()];
+    assert!(compressor.compress_gzip_into(&data, &mut short).is_err());
+}
+
+#[test]
+fn test_gzip_header_validation() {
+    let mut compressor = Compressor::new(6).unwrap();
+    let err = compressor
+        .set_gzip_header(GzipHeader::new().with_filename(b"a\0b"))
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(compressor
+        .set_gzip_header(GzipHeader::new().with_extra(&vec![0; 65_536]))
+        .is_err());
+    assert!(compressor
+        .set_gzip_header(GzipHeader::new().with_extra_field(*b"XX", &vec![0; 65_532]))
+        .is_err());
+    assert!(compressor
+        .set_gzip_header(GzipHeader::new().with_extra_field(*b"XX", &vec![0; 65_531]))
+        .is_ok());
+
+    let mut encoder = GzipEncoder::new(Vec::new(), 6)
+        .with_header(GzipHeader::new().with_comment(b"bad\0comment"));
+    let err = encoder.write_all(b"data").unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_gzip_encoder_header_matches_one_shot() {
+    let data = test_data(400_000, 6);
+    let mut encoder = GzipEncoder::new(Vec::new(), 6).with_header(full_header());
+    encoder.write_all(&data).unwrap();
+    let streamed = encoder.finish().unwrap();
+
+    let mut compressor = Compressor::new(6).unwrap();
+    compressor.set_gzip_header(full_header()).unwrap();
+    let one_shot = compressor.compress_gzip(&data).unwrap();
+
+    let header_len = full_header().encoded_len();
+    assert_eq!(&streamed[..header_len], &one_shot[..header_len]);
+
+    let mut decoder = GzipDecoder::new(Cursor::new(&streamed));
+    let mut decompressed = Vec::new();
+    decoder.read_to_end(&mut decompressed).unwrap();
+    assert_eq!(decompressed, data);
+}
+
+#[test]
+fn test_gzip_decompress_with_header() {
+    let data = test_data(50_000, 7);
+    let mut compressor = Compressor::new(9).unwrap();
+    compressor.set_gzip_header(full_header()).unwrap();
+    let compressed = compressor.compress_gzip(&data).unwrap();
+
+    let mut decompressor = Decompressor::new();
+    decompressor.set_verify_gzip_header_crc(true);
+    let (output, header) = decompressor
+        .decompress_gzip_with_header(&compressed, data.len())
+        .unwrap();
+    assert_eq!(output, data);
+    assert_eq!(header, full_header().with_xfl(2));
+    assert_eq!(header.filename(), Some(&b"data.bin"[..]));
+    assert_eq!(header.comment(), Some(&b"made by gzip_test"[..]));
+    assert_eq!(header.mtime(), 1_700_000_000);
+    assert_eq!(header.os(), 3);
+    let fields: Vec<_> = header.extra_fields().collect();
+    assert_eq!(fields, vec![(*b"AB", &b"subfield"[..]), (*b"CD", &b""[..])]);
+
+    let mut output = vec![0u8; data.len()];
+    let (size, header) = decompressor
+        .decompress_gzip_into_with_header(&compressed, &mut output)
+        .unwrap();
+    assert_eq!(size, data.len());
+    assert_eq!(header.filename(), Some(&b"data.bin"[..]));
+
+    let (parsed, len) = GzipHeader::parse(&compressed, true).unwrap();
+    assert_eq!(parsed, header);
+    assert_eq!(len, full_header().encoded_len());
+
+    let plain = Compressor::new(1).unwrap().compress_gzip(&data).unwrap();
+    let (_, header) = decompressor.decompress_gzip_with_header(&plain, data.len()).unwrap();
+    assert_eq!(header, GzipHeader::new().with_xfl(4));
+    assert_eq!(header.extra_fields().count(), 0);
+}
+
+#[test]
+fn test_gzip_header_crc_verification() {
+    let data = test_data(10_000, 8);
+    let mut compressor = Compressor::new(6).unwrap();
+    compressor.set_gzip_header(full_header()).unwrap();
+    let mut compressed = compressor.compress_gzip(&data).unwrap();
+    let crc_pos = full_header().encoded_len() - 2;
+    compressed[crc_pos] ^= 0x55;
+
+    let mut decompressor = Decompressor::new();
+    assert_eq!(decompressor.decompress_gzip(&compressed, data.len()).unwrap(), data);
+    decompressor.set_verify_gzip_header_crc(true);
+    assert!(decompressor.decompress_gzip(&compressed, data.len()).is_err());
+    assert!(decompressor.decompress_gzip_member(&compressed, data.len()).is_err());
+
+    assert!(GzipHeader::parse(&compressed, false).is_ok());
+    let err = GzipHeader::parse(&compressed, true).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+    let mut decompressed = Vec::new();
+    GzipDecoder::new(Cursor::new(&compressed))
+        .read_to_end(&mut decompressed)
+        .unwrap();
+    assert_eq!(decompressed, data);
+    let err = GzipDecoder::new(Cursor::new(&compressed))
+        .with_verify_header_crc(true)
+        .read_to_end(&mut Vec::new())
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_gzip_truncated_extra_field() {
+    let data = test_data(1000, 9);
+    let mut compressed = Compressor::new(6).unwrap().compress_gzip(&data).unwrap();
+    // Claim a 60000-byte FEXTRA that extends far past the end of the input.
+    compressed[3] |= 0x04;
+    compressed.splice(10..10, 60_000u16.to_le_bytes());
+
+    let mut decompressor = Decompressor::new();
+    assert!(decompressor.decompress_gzip(&compressed, data.len()).is_err());
+    assert!(decompressor.decompress_gzip_member(&compressed, data.len()).is_err());
+    let err = GzipHeader::parse(&compressed, false).unwrap_err();
+    assert!(matches!(err, libdeflate::Error::TruncatedInput { .. }));
+
+    let header = GzipHeader::new().with_extra(b"AB\x10\x00short");
+    assert_eq!(header.extra_fields().count(), 0);
+}
+
+#[test]
+fn test_gzip_decoder_header() {
+    let data = test_data(20_000, 10);
+    let mut compressor = Compressor::new(6).unwrap();
+    compressor.set_gzip_header(full_header()).unwrap();
+    let compressed = compressor.compress_gzip(&data).unwrap();
+
+    let mut decoder = GzipDecoder::new(OneByteReader(Cursor::new(&compressed)))
+        .with_verify_header_crc(true);
+    assert!(decoder.header().is_none());
+    let mut decompressed = Vec::new();
+    decoder.read_to_end(&mut decompressed).unwrap();
+    assert_eq!(decompressed, data);
+    assert_eq!(decoder.header(), Some(&full_header().with_xfl(0)));
+}
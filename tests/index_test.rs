@@ -0,0 +1,190 @@
+mod common;
+
+use common::log_text;
+use libdeflate::index::{GzipIndex, IndexBuilder, IndexFormat, SeekableGzipReader};
+use libdeflate::{Compressor, Error};
+use std::io::{Cursor, ErrorKind, Read, Seek, SeekFrom};
+
+/// Reads `len` bytes at `offset` and checks them against `data`.
+fn check_range<R: Read + Seek>(reader: &mut R, data: &[u8], offset: usize, len: usize) {
+    reader.seek(SeekFrom::Start(offset as u64)).unwrap();
+    let end = (offset + len).min(data.len());
+    let mut buf = vec![0; end - offset];
+    reader.read_exact(&mut buf).unwrap();
+    assert!(buf == data[offset..end], "range at {offset}");
+}
+
+#[test]
+fn test_index_random_access_gzip() {
+    let data = log_text(6 * 1024 * 1024, 0x2545_f491);
+    for level in [0, 1, 6, 12] {
+        let gzip = Compressor::new(level)
+            .unwrap()
+            .compress_gzip(&data)
+            .unwrap();
+        let index = IndexBuilder::new(IndexFormat::Gzip)
+            .with_span(256 * 1024)
+            .build(Cursor::new(&gzip))
+            .unwrap();
+        assert_eq!(index.uncompressed_size(), data.len() as u64);
+        assert_eq!(index.compressed_size(), gzip.len() as u64);
+        assert!(index.checkpoints().len() >= 10, "level {level}");
+
+        let mut reader = SeekableGzipReader::new(Cursor::new(&gzip), index).unwrap();
+        for offset in [5_000_000, 17, 3_333_333, 0, 6 * 1024 * 1024 - 10, 1_048_576] {
+            check_range(&mut reader, &data, offset, 100_000);
+        }
+    }
+}
+
+#[test]
+fn test_index_other_encoder_and_members() {
+    // Members from another encoder, so that block boundaries fall where this crate
+    // would not put them, and an empty member in between.
+    let mut gzip = Vec::new();
+    let mut data = Vec::new();
+    for (i, size) in [2_000_000, 0, 1_500_000].into_iter().enumerate() {
+        let member = log_text(size, 7 + i as u32);
+        let mut compressor =
+            libdeflater::Compressor::new(libdeflater::CompressionLvl::new(6).unwrap());
+        let mut out = vec![0; compressor.gzip_compress_bound(member.len())];
+        let len = compressor.gzip_compress(&member, &mut out).unwrap();
+        gzip.extend_from_slice(&out[..len]);
+        data.extend_from_slice(&member);
+    }
+    let index = IndexBuilder::new(IndexFormat::Gzip)
+        .with_span(100_000)
+        .build(Cursor::new(&gzip))
+        .unwrap();
+    assert_eq!(index.uncompressed_size(), data.len() as u64);
+
+    let mut reader = SeekableGzipReader::new(Cursor::new(&gzip), index).unwrap();
+    // A range spanning the member boundary.
+    check_range(&mut reader, &data, 1_990_000, 20_000);
+    check_range(&mut reader, &data, 3_000_000, 10_000);
+    check_range(&mut reader, &data, 10, 10);
+
+    let mut all = Vec::new();
+    reader.rewind().unwrap();
+    reader.read_to_end(&mut all).unwrap();
+    assert!(all == data);
+}
+
+#[test]
+fn test_index_deflate() {
+    let data = log_text(3 * 1024 * 1024, 99);
+    let mut deflate = Compressor::new(9).unwrap().compress_deflate(&data).unwrap();
+    deflate.extend_from_slice(b"trailing data");
+    let index = IndexBuilder::new(IndexFormat::Deflate)
+        .with_span(200_000)
+        .build(Cursor::new(&deflate))
+        .unwrap();
+    assert_eq!(index.compressed_size(), deflate.len() as u64 - 13);
+
+    let mut reader = SeekableGzipReader::new(Cursor::new(&deflate), index).unwrap();
+    check_range(&mut reader, &data, 2_500_000, 600_000);
+    check_range(&mut reader, &data, 700_000, 1000);
+}
+
+#[test]
+fn test_index_seek_positions() {
+    let data = log_text(1024 * 1024, 3);
+    let gzip = Compressor::new(6).unwrap().compress_gzip(&data).unwrap();
+    let index = IndexBuilder::new(IndexFormat::Gzip)
+        .with_span(64 * 1024)
+        .build(Cursor::new(&gzip))
+        .unwrap();
+    let mut reader = SeekableGzipReader::new(Cursor::new(&gzip), index).unwrap();
+
+    assert_eq!(
+        reader.seek(SeekFrom::End(-100)).unwrap(),
+        data.len() as u64 - 100
+    );
+    let mut tail = Vec::new();
+    reader.read_to_end(&mut tail).unwrap();
+    assert!(tail == data[data.len() - 100..]);
+
+    reader.seek(SeekFrom::Start(500_000)).unwrap();
+    assert_eq!(reader.seek(SeekFrom::Current(-1000)).unwrap(), 499_000);
+    let mut buf = [0; 10];
+    reader.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, data[499_000..499_010]);
+    assert_eq!(reader.stream_position().unwrap(), 499_010);
+
+    reader.seek(SeekFrom::Start(data.len() as u64 + 5)).unwrap();
+    assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    assert_eq!(
+        reader
+            .seek(SeekFrom::Current(-(data.len() as i64) - 10))
+            .unwrap_err()
+            .kind(),
+        ErrorKind::InvalidInput
+    );
+}
+
+#[test]
+fn test_index_serialization() {
+    let data = log_text(2 * 1024 * 1024, 11);
+    let gzip = Compressor::new(6).unwrap().compress_gzip(&data).unwrap();
+    let index = IndexBuilder::new(IndexFormat::Gzip)
+        .with_span(128 * 1024)
+        .build(Cursor::new(&gzip))
+        .unwrap();
+
+    let mut bytes = Vec::new();
+    index.write_to(&mut bytes).unwrap();
+    let loaded = GzipIndex::read_from(bytes.as_slice()).unwrap();
+    assert_eq!(loaded, index);
+
+    let mut reader = SeekableGzipReader::new(Cursor::new(&gzip), loaded).unwrap();
+    check_range(&mut reader, &data, 1_234_567, 50_000);
+
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] ^= 1;
+    assert_eq!(
+        GzipIndex::read_from(bad_magic.as_slice())
+            .unwrap_err()
+            .kind(),
+        ErrorKind::InvalidData
+    );
+    assert_eq!(
+        GzipIndex::read_from(&bytes[..bytes.len() - 1])
+            .unwrap_err()
+            .kind(),
+        ErrorKind::UnexpectedEof
+    );
+
+    // The index belongs to a longer stream than the one given.
+    assert_eq!(
+        SeekableGzipReader::new(Cursor::new(&gzip[..1000]), index)
+            .err()
+            .unwrap()
+            .kind(),
+        ErrorKind::InvalidInput
+    );
+}
+
+#[test]
+fn test_index_build_errors() {
+    let data = log_text(500_000, 5);
+    let gzip = Compressor::new(6).unwrap().compress_gzip(&data).unwrap();
+    let build = |input: &[u8]| IndexBuilder::new(IndexFormat::Gzip).build(Cursor::new(input));
+
+    let mut bad_crc = gzip.clone();
+    let len = bad_crc.len();
+    bad_crc[len - 8] ^= 1;
+    let err = build(&bad_crc).unwrap_err();
+    assert!(matches!(
+        err.get_ref().unwrap().downcast_ref::<Error>(),
+        Some(Error::ChecksumMismatch { .. })
+    ));
+
+    assert_eq!(
+        build(&gzip[..len - 100]).unwrap_err().kind(),
+        ErrorKind::UnexpectedEof
+    );
+    assert_eq!(
+        build(b"not gzip data").unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+}
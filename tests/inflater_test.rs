@@ -0,0 +1,171 @@
+mod common;
+
+use common::log_text;
+use libdeflate::compress::{CompressResult, FlushMode, StreamCompressor};
+use libdeflate::decompress::{Inflater, Status};
+use libdeflate::{Compressor, Error};
+
+/// Feeds `input` in pieces of `in_step` bytes, passing back whatever was not consumed,
+/// into outputs of `out_step` bytes. Returns the output and the input consumed.
+fn inflate_in_steps(
+    inflater: &mut Inflater,
+    input: &[u8],
+    in_step: usize,
+    out_step: usize,
+) -> (Vec<u8>, usize) {
+    let mut output = Vec::new();
+    let mut buf = vec![0; out_step];
+    let mut pos = 0;
+    let mut end = 0;
+    loop {
+        end = (end + in_step).min(input.len()).max(pos);
+        let (consumed, produced, status) = inflater.inflate(&input[pos..end], &mut buf).unwrap();
+        pos += consumed;
+        output.extend_from_slice(&buf[..produced]);
+        match status {
+            Status::StreamEnd => return (output, pos),
+            Status::NeedsInput => {
+                assert_eq!(pos, end);
+                assert!(end < input.len(), "truncated");
+            }
+            Status::NeedsOutput => assert_eq!(produced, out_step),
+        }
+    }
+}
+
+#[test]
+fn test_inflater_round_trip() {
+    let mut data = log_text(300_000, 1);
+    // An incompressible stretch, so that some levels emit stored blocks.
+    let mut state = 0x2545_f491u32;
+    data.extend((0..70_000).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as u8
+    }));
+    data.extend(log_text(100_000, 2));
+
+    for level in [0, 1, 6, 12] {
+        let compressed = Compressor::new(level)
+            .unwrap()
+            .compress_deflate(&data)
+            .unwrap();
+        let mut inflater = Inflater::new();
+        for (in_step, out_step) in [(1, 100_000), (1000, 1), (7, 13), (1 << 20, 65_536)] {
+            if level > 1 && (in_step == 1 || out_step == 1) {
+                continue;
+            }
+            inflater.reset();
+            let (output, consumed) =
+                inflate_in_steps(&mut inflater, &compressed, in_step, out_step);
+            assert!(output == data, "level {level}, steps {in_step}/{out_step}");
+            assert_eq!(consumed, compressed.len());
+            assert_eq!(inflater.total_in(), compressed.len() as u64);
+            assert_eq!(inflater.total_out(), data.len() as u64);
+        }
+    }
+}
+
+#[test]
+fn test_inflater_leaves_trailing_input() {
+    let data = log_text(50_000, 3);
+    let trailer = b"TRAILER!";
+    for level in [0, 1, 9] {
+        let mut input = Compressor::new(level)
+            .unwrap()
+            .compress_deflate(&data)
+            .unwrap();
+        let stream_len = input.len();
+        input.extend_from_slice(trailer);
+
+        // However the input and output are cut, the stream ends at the same byte,
+        // even when the decompressor has read past it.
+        let mut inflater = Inflater::new();
+        for (in_step, out_step) in [(input.len(), 50_000), (input.len(), 3), (5, 1000), (3, 7)] {
+            inflater.reset();
+            let (output, consumed) = inflate_in_steps(&mut inflater, &input, in_step, out_step);
+            assert!(output == data, "level {level}, steps {in_step}/{out_step}");
+            assert_eq!(
+                consumed, stream_len,
+                "level {level}, steps {in_step}/{out_step}"
+            );
+        }
+
+        // Once the stream has ended, nothing more is consumed or produced.
+        assert!(inflater.is_done());
+        let mut buf = [0; 16];
+        assert_eq!(
+            inflater.inflate(trailer, &mut buf).unwrap(),
+            (0, 0, Status::StreamEnd)
+        );
+    }
+}
+
+#[test]
+fn test_inflater_truncated_and_empty_buffers() {
+    let data = log_text(20_000, 4);
+    let compressed = Compressor::new(6).unwrap().compress_deflate(&data).unwrap();
+    let mut inflater = Inflater::new();
+    let mut output = vec![0; data.len()];
+
+    let half = compressed.len() / 2;
+    let (mut consumed, produced, status) =
+        inflater.inflate(&compressed[..half], &mut output).unwrap();
+    assert_eq!(status, Status::NeedsInput);
+    assert_eq!(consumed, half);
+    assert_eq!(
+        inflater.inflate(&[], &mut output[produced..]).unwrap(),
+        (0, 0, Status::NeedsInput)
+    );
+
+    // Without room for output, input is still decoded ahead into the window.
+    let (n, produced_none, status) = inflater.inflate(&compressed[half..], &mut []).unwrap();
+    assert_eq!((produced_none, status), (0, Status::NeedsOutput));
+    consumed += n;
+
+    let (rest, produced_rest, status) = inflater
+        .inflate(&compressed[consumed..], &mut output[produced..])
+        .unwrap();
+    assert_eq!(status, Status::StreamEnd);
+    assert_eq!(consumed + rest, compressed.len());
+    assert_eq!(produced + produced_rest, data.len());
+    assert!(output == data);
+}
+
+#[test]
+fn test_inflater_invalid_data() {
+    let mut output = vec![0; 4096];
+    let mut inflater = Inflater::new();
+    // Block type 3 is reserved.
+    let err = inflater.inflate(&[0x07], &mut output).unwrap_err();
+    assert!(matches!(err, Error::BadBlockType { .. }), "{err:?}");
+
+    // A bad block after a sync flush is located relative to the whole stream, not to
+    // the slice passed to the failing call.
+    let data = log_text(100_000, 5);
+    let mut compressed = Vec::new();
+    let mut stream = StreamCompressor::new(6);
+    assert_eq!(
+        stream.compress(&data, &mut compressed, FlushMode::Sync),
+        CompressResult::Success
+    );
+    let bad_block = compressed.len();
+    compressed.push(0x07);
+
+    inflater.reset();
+    let mut pos = 0;
+    let err = loop {
+        let end = (pos + 1000).min(compressed.len());
+        match inflater.inflate(&compressed[pos..end], &mut output) {
+            Ok((consumed, _, status)) => {
+                assert_ne!(status, Status::StreamEnd);
+                assert!(consumed > 0 || status == Status::NeedsOutput);
+                pos += consumed;
+            }
+            Err(err) => break err,
+        }
+    };
+    assert!(matches!(err, Error::BadBlockType { .. }), "{err:?}");
+    assert_eq!(err.position().unwrap().byte_offset(), bad_block as u64);
+}
@@ -170,3 +170,46 @@ fn test_gzip_interop() {
         }
     }
 }
+
+#[test]
+fn test_structured_data_interop() {
+    // Large, record-like input gives dynamic blocks with long (subtable) codewords,
+    // including for the end-of-block symbol.
+    let mut data = Vec::new();
+    for i in 0..3000 {
+        data.extend_from_slice(
+            format!(
+                r#"{{"user_id": {}, "event": "click", "timestamp": {}, "payload": {{"page": "/home"}}}}"#,
+                i * 7,
+                1700000000 + i * 13
+            )
+            .as_bytes(),
+        );
+    }
+
+    let mut our_decompressor = libdeflate::api::Decompressor::new();
+    let mut their_decompressor = libdeflater::Decompressor::new();
+
+    for level in 1..=12 {
+        let mut our_compressor = libdeflate::api::Compressor::new(level).unwrap();
+        let mut their_compressor =
+            libdeflater::Compressor::new(libdeflater::CompressionLvl::new(level).unwrap());
+
+        let comp1 = our_compressor.compress_zlib(&data).unwrap();
+        let mut decomp1 = vec![0u8; data.len()];
+        let decomp_sz = their_decompressor
+            .zlib_decompress(&comp1, &mut decomp1)
+            .unwrap();
+        assert_eq!(decomp_sz, data.len());
+        assert_eq!(decomp1, data, "level {}", level);
+
+        let their_bound = their_compressor.zlib_compress_bound(data.len());
+        let mut comp2 = vec![0u8; their_bound];
+        let comp2_sz = their_compressor.zlib_compress(&data, &mut comp2).unwrap();
+        comp2.truncate(comp2_sz);
+        let decomp2 = our_decompressor
+            .decompress_zlib(&comp2, data.len())
+            .unwrap();
+        assert_eq!(decomp2, data, "level {}", level);
+    }
+}
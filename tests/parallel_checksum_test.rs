@@ -0,0 +1,111 @@
+use libdeflate::{Compressor, Decompressor, Error};
+
+fn test_data(size: usize) -> Vec<u8> {
+    let mut state = 0x1234_5678u32;
+    (0..size)
+        .map(|i| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            if i % 7 == 0 {
+                (state >> 24) as u8
+            } else {
+                (i % 251) as u8
+            }
+        })
+        .collect()
+}
+
+fn trailer(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+}
+
+#[test]
+fn test_parallel_gzip_checksum() {
+    for size in [256 * 1024 + 1, 3 * 1024 * 1024 + 17] {
+        let data = test_data(size);
+        let mut compressor = Compressor::new(6).unwrap();
+        let parallel = compressor.compress_gzip(&data).unwrap();
+        compressor.set_parallel(false);
+        let sequential = compressor.compress_gzip(&data).unwrap();
+        assert_eq!(parallel, sequential);
+
+        let len = parallel.len();
+        assert_eq!(trailer(&parallel, len - 8), libdeflate::crc32(0, &data));
+        assert_eq!(trailer(&parallel, len - 4), size as u32);
+
+        let mut decompressor = Decompressor::new();
+        assert_eq!(decompressor.decompress_gzip(&parallel, size).unwrap(), data);
+    }
+}
+
+#[test]
+fn test_parallel_zlib_checksum() {
+    let data = test_data(2 * 1024 * 1024 + 5);
+    let dictionary = test_data(40_000);
+    let mut compressor = Compressor::new(6).unwrap();
+    let mut decompressor = Decompressor::new();
+
+    let parallel = compressor.compress_zlib(&data).unwrap();
+    compressor.set_parallel(false);
+    let sequential = compressor.compress_zlib(&data).unwrap();
+    assert_eq!(parallel, sequential);
+    let len = parallel.len();
+    assert_eq!(
+        u32::from_be_bytes(parallel[len - 4..].try_into().unwrap()),
+        libdeflate::adler32(1, &data)
+    );
+    assert_eq!(
+        decompressor.decompress_zlib(&parallel, data.len()).unwrap(),
+        data
+    );
+
+    compressor.set_parallel(true);
+    compressor.set_dictionary(&dictionary);
+    let with_dictionary = compressor.compress_zlib(&data).unwrap();
+    let len = with_dictionary.len();
+    assert_eq!(
+        u32::from_be_bytes(with_dictionary[len - 4..].try_into().unwrap()),
+        libdeflate::adler32(1, &data)
+    );
+    decompressor.set_dictionary(&dictionary);
+    assert_eq!(
+        decompressor
+            .decompress_zlib(&with_dictionary, data.len())
+            .unwrap(),
+        data
+    );
+}
+
+#[test]
+fn test_parallel_checksum_mismatch() {
+    let data = test_data(3 * 1024 * 1024);
+    let mut compressor = Compressor::new(1).unwrap();
+    let mut gzip = compressor.compress_gzip(&data).unwrap();
+    let len = gzip.len();
+    gzip[len - 8] ^= 0x80;
+    let mut zlib = compressor.compress_zlib(&data).unwrap();
+    let len = zlib.len();
+    zlib[len - 1] ^= 1;
+
+    for parallel in [false, true] {
+        let mut decompressor = Decompressor::new();
+        decompressor.set_parallel(parallel);
+        match decompressor.decompress_gzip(&gzip, data.len()).unwrap_err() {
+            Error::ChecksumMismatch {
+                expected, actual, ..
+            } => {
+                assert_eq!(actual, libdeflate::crc32(0, &data));
+                assert_eq!(expected, actual ^ 0x80);
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+        match decompressor.decompress_zlib(&zlib, data.len()).unwrap_err() {
+            Error::ChecksumMismatch {
+                expected, actual, ..
+            } => {
+                assert_eq!(actual, libdeflate::adler32(1, &data));
+                assert_eq!(expected, actual ^ 1);
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+    }
+}
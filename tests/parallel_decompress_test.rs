@@ -0,0 +1,204 @@
+mod common;
+
+use common::log_text;
+use libdeflate::{Compressor, Decompressor, Error};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Runs `f` on a pool of several threads, which parallel decompression needs to be used
+/// at all.
+fn with_threads<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap()
+        .install(f)
+}
+
+fn decompressor() -> Decompressor {
+    let mut decompressor = Decompressor::new();
+    decompressor.set_parallel_chunk_size(CHUNK_SIZE);
+    decompressor
+}
+
+#[test]
+fn test_parallel_gzip_matches_sequential() {
+    with_threads(|| {
+        let data = log_text(4 * 1024 * 1024, 0x2545_f491);
+        for level in [0, 1, 6, 12] {
+            let mut compressor = Compressor::new(level).unwrap();
+            let gzip = compressor.compress_gzip(&data).unwrap();
+            assert!(gzip.len() > 2 * CHUNK_SIZE, "level {level}");
+            let decompressed = decompressor().decompress_gzip_parallel(&gzip).unwrap();
+            assert!(decompressed == data, "level {level}");
+        }
+    });
+}
+
+#[test]
+fn test_parallel_gzip_from_other_encoders() {
+    with_threads(|| {
+        let data = log_text(3 * 1024 * 1024, 0x9e37_79b9);
+        for level in [1, 6, 12] {
+            let mut compressor =
+                libdeflater::Compressor::new(libdeflater::CompressionLvl::new(level).unwrap());
+            let mut gzip = vec![0; compressor.gzip_compress_bound(data.len())];
+            let len = compressor.gzip_compress(&data, &mut gzip).unwrap();
+            gzip.truncate(len);
+            let decompressed = decompressor().decompress_gzip_parallel(&gzip).unwrap();
+            assert!(decompressed == data, "level {level}");
+        }
+    });
+}
+
+#[test]
+fn test_parallel_gzip_multiple_members() {
+    with_threads(|| {
+        // Members of varied sizes, so that member boundaries fall inside and at the edges
+        // of chunks, and a few empty members in between.
+        let mut compressor = Compressor::new(6).unwrap();
+        let mut gzip = Vec::new();
+        let mut expected = Vec::new();
+        for (i, size) in [300_000, 0, 10, 1_500_000, 70_000, 0, 900_000]
+            .into_iter()
+            .enumerate()
+        {
+            let member = log_text(size, 0x1234_5678 + i as u32);
+            gzip.extend_from_slice(&compressor.compress_gzip(&member).unwrap());
+            expected.extend_from_slice(&member);
+        }
+        let decompressed = decompressor().decompress_gzip_parallel(&gzip).unwrap();
+        assert!(decompressed == expected);
+        assert!(Decompressor::new().decompress_gzip_parallel(&gzip).unwrap() == expected);
+    });
+}
+
+#[test]
+fn test_parallel_gzip_zero_padding() {
+    with_threads(|| {
+        let data = log_text(1024 * 1024, 3);
+        let mut gzip = Compressor::new(6).unwrap().compress_gzip(&data).unwrap();
+        // More than a chunk of padding, so that some chunks hold nothing else.
+        gzip.resize(gzip.len() + 3 * CHUNK_SIZE, 0);
+        assert!(decompressor().decompress_gzip_parallel(&gzip).unwrap() == data);
+
+        gzip.push(1);
+        assert!(decompressor().decompress_gzip_parallel(&gzip).is_err());
+    });
+}
+
+#[test]
+fn test_parallel_deflate_with_dictionary() {
+    with_threads(|| {
+        let dictionary = log_text(50_000, 7);
+        let data = log_text(2 * 1024 * 1024, 7);
+        let mut compressor = Compressor::new(6).unwrap();
+        compressor.set_dictionary(&dictionary);
+        let mut deflate = compressor.compress_deflate(&data).unwrap();
+
+        let mut decompressor = decompressor();
+        decompressor.set_dictionary(&dictionary);
+        assert!(decompressor.decompress_deflate_parallel(&deflate).unwrap() == data);
+
+        // Data after the final block is not part of the stream.
+        deflate.extend_from_slice(&[0xAB; 100]);
+        assert!(decompressor.decompress_deflate_parallel(&deflate).unwrap() == data);
+
+        decompressor.clear_dictionary();
+        assert!(matches!(
+            decompressor.decompress_deflate_parallel(&deflate),
+            Err(Error::DistanceTooFarBack { .. })
+        ));
+    });
+}
+
+#[test]
+fn test_parallel_gzip_corrupt_input() {
+    with_threads(|| {
+        let data = log_text(2 * 1024 * 1024, 99);
+        let gzip = Compressor::new(6).unwrap().compress_gzip(&data).unwrap();
+        let len = gzip.len();
+
+        let mut bad_crc = gzip.clone();
+        bad_crc[len - 8] ^= 1;
+        match decompressor()
+            .decompress_gzip_parallel(&bad_crc)
+            .unwrap_err()
+        {
+            Error::ChecksumMismatch {
+                expected,
+                actual,
+                at,
+            } => {
+                assert_eq!(actual, libdeflate::crc32(0, &data));
+                assert_eq!(expected, actual ^ 1);
+                assert_eq!(at.byte_offset(), len as u64 - 8);
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+
+        let mut bad_size = gzip.clone();
+        bad_size[len - 1] ^= 1;
+        assert!(matches!(
+            decompressor().decompress_gzip_parallel(&bad_size),
+            Err(Error::SizeMismatch { .. })
+        ));
+
+        let truncated = &gzip[..len - 100];
+        assert!(matches!(
+            decompressor().decompress_gzip_parallel(truncated),
+            Err(Error::TruncatedInput { .. })
+        ));
+
+        let mut trailing = gzip.clone();
+        trailing.extend_from_slice(b"trailing bytes that are not gzip");
+        assert!(matches!(
+            decompressor().decompress_gzip_parallel(&trailing),
+            Err(Error::InvalidHeader { .. })
+        ));
+
+        let mut bad_header = gzip.clone();
+        bad_header[0] = 0;
+        assert!(matches!(
+            decompressor().decompress_gzip_parallel(&bad_header),
+            Err(Error::InvalidHeader { .. })
+        ));
+    });
+}
+
+#[test]
+fn test_parallel_gzip_limits() {
+    with_threads(|| {
+        let data = vec![0u8; 64 * 1024 * 1024];
+        let gzip = Compressor::new(1).unwrap().compress_gzip(&data).unwrap();
+        let mut decompressor = Decompressor::new();
+        decompressor.set_parallel_chunk_size(1024);
+        decompressor.set_max_memory_limit(1024 * 1024);
+        assert!(matches!(
+            decompressor.decompress_gzip_parallel(&gzip),
+            Err(Error::MemoryLimitExceeded { .. })
+        ));
+
+        decompressor.set_max_memory_limit(usize::MAX);
+        decompressor.set_limit_ratio(10);
+        assert!(matches!(
+            decompressor.decompress_gzip_parallel(&gzip),
+            Err(Error::ExpectedSizeTooLarge { .. })
+        ));
+
+        decompressor.set_limit_ratio(2000);
+        assert!(decompressor.decompress_gzip_parallel(&gzip).unwrap() == data);
+    });
+}
+
+#[test]
+fn test_parallel_gzip_single_thread() {
+    let data = log_text(1024 * 1024, 5);
+    let gzip = Compressor::new(6).unwrap().compress_gzip(&data).unwrap();
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .unwrap();
+    let decompressed = pool.install(|| decompressor().decompress_gzip_parallel(&gzip));
+    assert!(decompressed.unwrap() == data);
+}
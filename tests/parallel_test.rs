@@ -115,3 +115,105 @@ fn test_parallel_insufficient_space() {
             || err.to_string().contains("Compression failed")
     );
 }
+
+fn repeated_noise(block: usize, size: usize) -> Vec<u8> {
+    let mut state = 12345u32;
+    let noise: Vec<u8> = (0..block)
+        .map(|_| {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 24) as u8
+        })
+        .collect();
+    noise.iter().copied().cycle().take(size).collect()
+}
+
+#[test]
+fn test_parallel_chunk_history_ratio() {
+    // Incompressible 16 KiB block repeated, so every chunk boundary falls inside
+    // a run that can only be matched against the previous chunk.
+    let size = 4 * 1024 * 1024;
+    let data = repeated_noise(16 * 1024, size);
+
+    let mut primed = Compressor::new(6).unwrap();
+    let mut independent = Compressor::new(6).unwrap();
+    independent.set_chunk_history(false);
+
+    let primed_out = primed.compress_deflate(&data).unwrap();
+    let independent_out = independent.compress_deflate(&data).unwrap();
+    assert!(
+        primed_out.len() * 4 < independent_out.len(),
+        "primed {} vs independent {}",
+        primed_out.len(),
+        independent_out.len()
+    );
+
+    let mut decompressor = Decompressor::new();
+    assert_eq!(decompressor.decompress_deflate(&primed_out, size).unwrap(), data);
+    assert_eq!(
+        decompressor
+            .decompress_deflate(&independent_out, size)
+            .unwrap(),
+        data
+    );
+}
+
+#[test]
+fn test_parallel_chunk_history_all_formats_and_levels() {
+    let size = 768 * 1024 + 123;
+    let data = repeated_noise(20000, size);
+
+    for level in [0, 1, 6, 9, 12] {
+        let mut compressor = Compressor::new(level).unwrap();
+        let mut decompressor = Decompressor::new();
+
+        let deflate = compressor.compress_deflate(&data).unwrap();
+        assert_eq!(decompressor.decompress_deflate(&deflate, size).unwrap(), data);
+        let zlib = compressor.compress_zlib(&data).unwrap();
+        assert_eq!(decompressor.decompress_zlib(&zlib, size).unwrap(), data);
+        let gzip = compressor.compress_gzip(&data).unwrap();
+        assert_eq!(decompressor.decompress_gzip(&gzip, size).unwrap(), data);
+    }
+}
+
+#[test]
+fn test_sequential_matches_parallel() {
+    let size = 3 * 1024 * 1024 + 17;
+    let data = repeated_noise(30000, size);
+
+    for level in [1, 6, 12] {
+        let mut parallel = Compressor::new(level).unwrap();
+        let mut sequential = Compressor::new(level).unwrap();
+        sequential.set_parallel(false);
+
+        let expected = parallel.compress_gzip(&data).unwrap();
+        let output = sequential.compress_gzip(&data).unwrap();
+        assert_eq!(output, expected, "level {level}");
+        assert_eq!(
+            Decompressor::new().decompress_gzip(&output, size).unwrap(),
+            data
+        );
+    }
+}
+
+#[test]
+fn test_sequential_stream_encoder() {
+    use libdeflate::stream::ZlibEncoder;
+    use std::io::Write;
+
+    let size = 2 * 1024 * 1024;
+    let data = repeated_noise(16 * 1024, size);
+    let encode = |parallel: bool| {
+        let mut encoder = ZlibEncoder::new(Vec::new(), 6)
+            .with_buffer_size(1024 * 1024)
+            .with_parallel(parallel);
+        encoder.write_all(&data).unwrap();
+        encoder.finish().unwrap()
+    };
+
+    let output = encode(false);
+    assert_eq!(output, encode(true));
+    assert_eq!(
+        Decompressor::new().decompress_zlib(&output, size).unwrap(),
+        data
+    );
+}
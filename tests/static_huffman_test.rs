@@ -0,0 +1,25 @@
+use libdeflate::{Compressor, Decompressor};
+
+#[test]
+fn test_static_huffman_decode() {
+    // zlib's raw DEFLATE output for "hello, hello, hello!" with Z_FIXED: one final
+    // static Huffman block (BFINAL = 1, BTYPE = 01) containing a match.
+    let compressed = [
+        0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0xd7, 0x51, 0xc8, 0x40, 0xa2, 0x14, 0x01,
+    ];
+    assert_eq!(compressed[0] & 7, 0b011);
+    let mut decompressor = Decompressor::new();
+    let output = decompressor.decompress_deflate(&compressed, 20).unwrap();
+    assert_eq!(output, b"hello, hello, hello!");
+}
+
+#[test]
+fn test_static_huffman_round_trip() {
+    let data = &b"abc".repeat(30)[..];
+    let compressed = Compressor::new(1).unwrap().compress_deflate(data).unwrap();
+    assert_eq!(compressed[0] & 7, 0b011);
+    let output = Decompressor::new()
+        .decompress_deflate(&compressed, data.len())
+        .unwrap();
+    assert_eq!(output, data);
+}
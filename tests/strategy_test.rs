@@ -0,0 +1,129 @@
+mod common;
+
+use common::xorshift;
+use libdeflate::{CompressionOptions, Compressor, Decompressor, Strategy};
+
+const STRATEGIES: [Strategy; 5] = [
+    Strategy::Default,
+    Strategy::Filtered,
+    Strategy::HuffmanOnly,
+    Strategy::Rle,
+    Strategy::Fixed,
+];
+
+fn scanlines(size: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491u32;
+    let mut data = Vec::with_capacity(size);
+    while data.len() < size {
+        let state = xorshift(&mut state);
+        let run = 1 + (state >> 28) as usize;
+        let value = if state & 0x100 == 0 { 0 } else { state as u8 };
+        data.extend(std::iter::repeat_n(value, run));
+    }
+    data.truncate(size);
+    data
+}
+
+fn compress(level: i32, strategy: Strategy, data: &[u8]) -> Vec<u8> {
+    let mut compressor = Compressor::new(level).unwrap();
+    compressor.set_strategy(strategy);
+    let compressed = compressor.compress_deflate(data).unwrap();
+    let decompressed = Decompressor::new()
+        .decompress_deflate(&compressed, data.len())
+        .unwrap();
+    assert_eq!(decompressed, data, "level {level}, {strategy:?}");
+    compressed
+}
+
+#[test]
+fn test_strategies_round_trip() {
+    let mut data = scanlines(150_000);
+    data.extend_from_slice(&std::fs::read("src/compress/mod.rs").unwrap());
+    for level in [0, 1, 2, 5, 9, 10, 12] {
+        for strategy in STRATEGIES {
+            compress(level, strategy, &data);
+
+            let options = CompressionOptions::new(level)
+                .unwrap()
+                .with_strategy(strategy);
+            let mut compressor = Compressor::with_options(options).unwrap();
+            let gzip = compressor.compress_gzip(&data).unwrap();
+            let decompressed = Decompressor::new()
+                .decompress_gzip(&gzip, data.len())
+                .unwrap();
+            assert_eq!(decompressed, data);
+        }
+    }
+}
+
+#[test]
+fn test_huffman_only_emits_no_matches() {
+    let data = b"abc".repeat(100_000);
+    for level in [1, 6, 12] {
+        let default = compress(level, Strategy::Default, &data);
+        let huffman_only = compress(level, Strategy::HuffmanOnly, &data);
+        assert!(default.len() < data.len() / 50, "level {level}");
+        // Three equally likely literals need at least 1.5 bits each.
+        assert!(huffman_only.len() >= data.len() * 3 / 16, "level {level}");
+        assert!(huffman_only.len() < data.len() * 5 / 16, "level {level}");
+    }
+}
+
+#[test]
+fn test_rle_only_matches_runs() {
+    let runs = scanlines(300_000);
+    let repeats = b"abcdefgh".repeat(40_000);
+    for level in [1, 6, 12] {
+        let rle_runs = compress(level, Strategy::Rle, &runs);
+        let huffman_only = compress(level, Strategy::HuffmanOnly, &runs);
+        assert!(rle_runs.len() < huffman_only.len() / 2, "level {level}");
+
+        // Without runs there is nothing at distance 1, so only literals remain.
+        let rle_repeats = compress(level, Strategy::Rle, &repeats);
+        assert!(rle_repeats.len() >= repeats.len() * 3 / 8, "level {level}");
+    }
+}
+
+#[test]
+fn test_fixed_writes_static_blocks() {
+    let data = std::fs::read("src/compress/mod.rs").unwrap();
+    let data = &data[..4_000];
+    for level in [1, 2, 6, 9, 10, 12] {
+        let fixed = compress(level, Strategy::Fixed, data);
+        assert_eq!(fixed[0] & 0x07, 0x03, "level {level}: BFINAL with BTYPE 01");
+    }
+    let dynamic = compress(6, Strategy::Default, data);
+    assert_eq!(dynamic[0] & 0x07, 0x05);
+
+    let large = std::fs::read("src/compress/mod.rs").unwrap();
+    for level in [2, 12] {
+        let fixed = compress(level, Strategy::Fixed, &large);
+        let default = compress(level, Strategy::Default, &large);
+        assert!(fixed.len() > default.len(), "level {level}");
+    }
+}
+
+#[test]
+fn test_filtered_drops_short_matches() {
+    let mut state = 0x1234_5678u32;
+    let data: Vec<u8> = (0..200_000)
+        .map(|_| b"abcd"[(xorshift(&mut state) >> 30) as usize])
+        .collect();
+    for level in [2, 6, 12] {
+        let default = compress(level, Strategy::Default, &data);
+        let filtered = compress(level, Strategy::Filtered, &data);
+        assert_ne!(default, filtered, "level {level}");
+    }
+}
+
+#[test]
+fn test_strategy_applies_to_parallel_chunks() {
+    let data = b"abc".repeat(400_000);
+    let mut compressor = Compressor::new(6).unwrap();
+    compressor.set_strategy(Strategy::HuffmanOnly);
+    let parallel = compressor.compress_deflate(&data).unwrap();
+    compressor.set_parallel(false);
+    let sequential = compressor.compress_deflate(&data).unwrap();
+    assert_eq!(parallel, sequential);
+    assert!(parallel.len() >= data.len() * 3 / 16);
+}
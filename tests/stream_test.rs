@@ -1,4 +1,6 @@
-use libdeflate::stream::{DeflateDecoder, DeflateEncoder};
+use libdeflate::stream::{
+    DeflateDecoder, DeflateEncoder, GzipDecoder, GzipEncoder, ZlibDecoder, ZlibEncoder,
+};
 use std::io::{Cursor, Read, Write};
 use std::sync::{Arc, Mutex};
 
@@ -107,3 +109,356 @@ fn test_encoder_flush_error() {
     // flush() should fail because the underlying writer returns an error
     assert!(encoder.flush().is_err());
 }
+
+#[test]
+fn test_stream_history_across_flushes() {
+    let mut state = 777u32;
+    let block: Vec<u8> = (0..8192)
+        .map(|_| {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 24) as u8
+        })
+        .collect();
+
+    let compress = |chunk_history: bool| {
+        let mut encoder = DeflateEncoder::new(Vec::new(), 6)
+            .with_buffer_size(8192)
+            .with_chunk_history(chunk_history);
+        for _ in 0..64 {
+            encoder.write_all(&block).unwrap();
+            encoder.flush().unwrap();
+        }
+        encoder.finish().unwrap()
+    };
+
+    let primed = compress(true);
+    let independent = compress(false);
+    assert!(
+        primed.len() * 8 < independent.len(),
+        "primed {} vs independent {}",
+        primed.len(),
+        independent.len()
+    );
+
+    let expected: Vec<u8> = block.iter().copied().cycle().take(block.len() * 64).collect();
+    for compressed in [primed, independent] {
+        let mut decoder = DeflateDecoder::new(Cursor::new(compressed));
+        let mut decompressed = Vec::new();
+        decoder.read_to_end(&mut decompressed).unwrap();
+        assert_eq!(decompressed, expected);
+    }
+}
+
+#[test]
+fn test_stream_parallel_buffer_round_trip() {
+    let mut data = Vec::with_capacity(3 * 1024 * 1024);
+    for i in 0..3 * 1024 * 1024u32 {
+        data.push((i.wrapping_mul(2654435761) >> 27) as u8 ^ (i / 4096) as u8);
+    }
+
+    let mut encoder = DeflateEncoder::new(Vec::new(), 6).with_buffer_size(1024 * 1024 + 7);
+    for piece in data.chunks(100_000) {
+        encoder.write_all(piece).unwrap();
+    }
+    let compressed = encoder.finish().unwrap();
+
+    let mut decoder = DeflateDecoder::new(Cursor::new(compressed));
+    let mut decompressed = Vec::new();
+    decoder.read_to_end(&mut decompressed).unwrap();
+    assert_eq!(decompressed, data);
+}
+
+struct OneByteReader<R>(R);
+
+impl<R: Read> Read for OneByteReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(1);
+        self.0.read(&mut buf[..len])
+    }
+}
+
+#[test]
+fn test_stream_decoder_one_byte_input() {
+    let mut data = Vec::with_capacity(300_000);
+    for i in 0..300_000u32 {
+        data.push((i.wrapping_mul(2654435761) >> 27) as u8 ^ (i / 4096) as u8);
+    }
+
+    for level in [0, 1, 6, 12] {
+        let mut compressor = libdeflate::Compressor::new(level).unwrap();
+        let compressed = compressor.compress_deflate(&data).unwrap();
+
+        let mut decoder = DeflateDecoder::new(OneByteReader(Cursor::new(compressed)));
+        let mut decompressed = Vec::new();
+        decoder.read_to_end(&mut decompressed).unwrap();
+        assert_eq!(decompressed, data, "level {}", level);
+    }
+}
+
+#[test]
+fn test_stream_decoder_long_matches_small_reads() {
+    // Long matches keep reaching the end of the decoder's window, so some of them
+    // have to be resumed on the next call.
+    let mut data = Vec::with_capacity(500_000);
+    for i in 0..2000u32 {
+        let byte = (i.wrapping_mul(2654435761) >> 24) as u8;
+        data.extend(std::iter::repeat_n(byte, 100 + (i as usize * 37) % 400));
+    }
+
+    for level in [1, 6, 12] {
+        let mut compressor = libdeflate::Compressor::new(level).unwrap();
+        let compressed = compressor.compress_deflate(&data).unwrap();
+
+        let mut decoder = DeflateDecoder::new(Cursor::new(compressed));
+        let mut decompressed = Vec::new();
+        let mut buf = [0u8; 7];
+        loop {
+            let n = decoder.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            decompressed.extend_from_slice(&buf[..n]);
+        }
+        assert!(decompressed == data, "level {}", level);
+    }
+}
+
+fn gzip_test_data(len: usize) -> Vec<u8> {
+    (0..len as u32)
+        .map(|i| (i.wrapping_mul(2654435761) >> 27) as u8 ^ (i / 4096) as u8)
+        .collect()
+}
+
+#[test]
+fn test_gzip_stream_round_trip() {
+    for len in [0, 1, 10_000, 3 * 1024 * 1024] {
+        let data = gzip_test_data(len);
+
+        let mut encoder = GzipEncoder::new(Vec::new(), 6).with_buffer_size(1024 * 1024);
+        for piece in data.chunks(100_000) {
+            encoder.write_all(piece).unwrap();
+        }
+        let compressed = encoder.finish().unwrap();
+
+        let mut decompressor = libdeflate::Decompressor::new();
+        let one_shot = decompressor.decompress_gzip(&compressed, data.len()).unwrap();
+        assert_eq!(one_shot, data);
+
+        let mut decoder = GzipDecoder::new(OneByteReader(Cursor::new(&compressed)));
+        let mut decompressed = Vec::new();
+        decoder.read_to_end(&mut decompressed).unwrap();
+        assert_eq!(decompressed, data);
+    }
+}
+
+#[test]
+fn test_gzip_decoder_optional_header_fields() {
+    let data = gzip_test_data(50_000);
+    let mut compressor = libdeflate::Compressor::new(6).unwrap();
+    let plain = compressor.compress_gzip(&data).unwrap();
+
+    // FEXTRA, FNAME, FCOMMENT and FHCRC spliced into a bare header.
+    let mut compressed = plain[..10].to_vec();
+    compressed[3] = 0x04 | 0x08 | 0x10 | 0x02;
+    compressed.extend_from_slice(&[3, 0, b'a', b'b', b'c']);
+    compressed.extend_from_slice(b"name.txt\0");
+    compressed.extend_from_slice(b"a comment\0");
+    compressed.extend_from_slice(&[0, 0]);
+    compressed.extend_from_slice(&plain[10..]);
+
+    let mut decoder = GzipDecoder::new(OneByteReader(Cursor::new(compressed)));
+    let mut decompressed = Vec::new();
+    decoder.read_to_end(&mut decompressed).unwrap();
+    assert_eq!(decompressed, data);
+}
+
+#[test]
+fn test_gzip_decoder_rejects_bad_trailer() {
+    let data = gzip_test_data(20_000);
+    let mut encoder = GzipEncoder::new(Vec::new(), 6);
+    encoder.write_all(&data).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let mut bad_crc = compressed.clone();
+    let crc_pos = bad_crc.len() - 8;
+    bad_crc[crc_pos] ^= 1;
+    let err = GzipDecoder::new(Cursor::new(bad_crc))
+        .read_to_end(&mut Vec::new())
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+    let truncated = &compressed[..compressed.len() - 3];
+    let err = GzipDecoder::new(Cursor::new(truncated))
+        .read_to_end(&mut Vec::new())
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+
+    let err = GzipDecoder::new(Cursor::new(&compressed[1..]))
+        .read_to_end(&mut Vec::new())
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_gzip_encoder_drop_writes_trailer() {
+    let data = gzip_test_data(20_000);
+    let out = Arc::new(Mutex::new(Vec::new()));
+    {
+        let writer = FlushTrackingWriter {
+            data: out.clone(),
+            flush_count: Arc::new(Mutex::new(0)),
+        };
+        let mut encoder = GzipEncoder::new(writer, 6);
+        encoder.write_all(&data).unwrap();
+    }
+    let compressed = out.lock().unwrap().clone();
+    let mut decompressor = libdeflate::Decompressor::new();
+    assert_eq!(decompressor.decompress_gzip(&compressed, data.len()).unwrap(), data);
+}
+
+#[test]
+fn test_zlib_stream_round_trip() {
+    for level in [0, 1, 6, 12] {
+        for len in [0, 10_000, 3 * 1024 * 1024] {
+            let data = gzip_test_data(len);
+
+            let mut encoder = ZlibEncoder::new(Vec::new(), level).with_buffer_size(1024 * 1024);
+            for piece in data.chunks(100_000) {
+                encoder.write_all(piece).unwrap();
+            }
+            let compressed = encoder.finish().unwrap();
+
+            let mut decompressor = libdeflater::Decompressor::new();
+            let mut one_shot = vec![0u8; data.len()];
+            let n = decompressor
+                .zlib_decompress(&compressed, &mut one_shot)
+                .unwrap_or_else(|e| panic!("level {} len {}: {:?}", level, len, e));
+            assert_eq!(n, data.len());
+            assert_eq!(one_shot, data);
+
+            let mut decoder = ZlibDecoder::new(OneByteReader(Cursor::new(&compressed)));
+            let mut decompressed = Vec::new();
+            decoder.read_to_end(&mut decompressed).unwrap();
+            assert_eq!(decompressed, data, "level {} len {}", level, len);
+        }
+    }
+}
+
+#[test]
+fn test_zlib_decoder_rejects_bad_streams() {
+    let data = gzip_test_data(20_000);
+    let mut compressor = libdeflate::Compressor::new(6).unwrap();
+    let compressed = compressor.compress_zlib(&data).unwrap();
+
+    let mut bad_adler = compressed.clone();
+    let last = bad_adler.len() - 1;
+    bad_adler[last] ^= 1;
+    let err = ZlibDecoder::new(Cursor::new(bad_adler))
+        .read_to_end(&mut Vec::new())
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+    let err = ZlibDecoder::new(Cursor::new(&compressed[..compressed.len() - 2]))
+        .read_to_end(&mut Vec::new())
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+
+    let mut bad_header = compressed.clone();
+    bad_header[1] ^= 1;
+    let err = ZlibDecoder::new(Cursor::new(bad_header))
+        .read_to_end(&mut Vec::new())
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+    compressor.set_dictionary(b"dictionary");
+    let with_dict = compressor.compress_zlib(&data).unwrap();
+    let err = ZlibDecoder::new(Cursor::new(with_dict))
+        .read_to_end(&mut Vec::new())
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+/// Decodes the stream at the start of `reader` and returns the output, the reader and
+/// the bytes the decoder read past the end of the stream.
+fn decode_with_remaining<R: Read>(format: &str, reader: R) -> (Vec<u8>, R, Vec<u8>) {
+    let mut output = Vec::new();
+    match format {
+        "deflate" => {
+            let mut decoder = DeflateDecoder::new(reader);
+            decoder.read_to_end(&mut output).unwrap();
+            let (reader, remaining) = decoder.into_inner_with_remaining();
+            (output, reader, remaining)
+        }
+        "gzip" => {
+            let mut decoder = GzipDecoder::new(reader).with_multi_member(false);
+            decoder.read_to_end(&mut output).unwrap();
+            let (reader, remaining) = decoder.into_inner_with_remaining();
+            (output, reader, remaining)
+        }
+        _ => {
+            let mut decoder = ZlibDecoder::new(reader);
+            decoder.read_to_end(&mut output).unwrap();
+            let (reader, remaining) = decoder.into_inner_with_remaining();
+            (output, reader, remaining)
+        }
+    }
+}
+
+#[test]
+fn test_decoders_return_remaining_input() {
+    let data = gzip_test_data(200_000);
+    let trailer: Vec<u8> = (0..50_000u32).map(|i| (i % 251) as u8).collect();
+    let mut compressor = libdeflate::Compressor::new(6).unwrap();
+    for format in ["deflate", "gzip", "zlib"] {
+        let mut input = match format {
+            "deflate" => compressor.compress_deflate(&data),
+            "gzip" => compressor.compress_gzip(&data),
+            _ => compressor.compress_zlib(&data),
+        }
+        .unwrap();
+        let stream_len = input.len();
+        input.extend_from_slice(&trailer);
+
+        // The remaining bytes followed by the rest of the reader are the trailer, so
+        // nothing is lost to the decoder's read-ahead.
+        let (output, mut reader, mut rest) = decode_with_remaining(format, Cursor::new(&input));
+        assert_eq!(output, data, "{format}");
+        assert_eq!(reader.position() as usize, stream_len + rest.len());
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, trailer, "{format}");
+
+        let (output, reader, remaining) =
+            decode_with_remaining(format, OneByteReader(Cursor::new(&input)));
+        assert_eq!(output, data, "{format}");
+        assert!(remaining.is_empty(), "{format}");
+        assert_eq!(reader.0.position() as usize, stream_len);
+    }
+}
+
+#[test]
+fn test_zlib_decoder_back_to_back_streams() {
+    // Objects stored one after another, as in a git packfile.
+    let mut compressor = libdeflate::Compressor::new(6).unwrap();
+    let objects: Vec<Vec<u8>> = (0..20).map(|i| gzip_test_data(i * 3000)).collect();
+    let mut pack = Vec::new();
+    for object in &objects {
+        pack.extend(compressor.compress_zlib(object).unwrap());
+    }
+
+    let mut reader = Cursor::new(pack);
+    let mut buffered = Vec::new();
+    for object in &objects {
+        let mut decoder = ZlibDecoder::new(Cursor::new(buffered).chain(reader));
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(&output, object);
+        let (chain, remaining) = decoder.into_inner_with_remaining();
+        let (head, tail) = chain.into_inner();
+        // Bytes of the previous remainder the decoder never asked for come first.
+        buffered = head.get_ref()[head.position() as usize..].to_vec();
+        buffered.extend(remaining);
+        reader = tail;
+    }
+    assert!(buffered.is_empty());
+    assert_eq!(reader.position() as usize, reader.get_ref().len());
+}
@@ -0,0 +1,296 @@
+mod common;
+
+use common::{log_lines, noise};
+use libdeflate::Decompressor;
+use libdeflate::compress::{CompressResult, FlushMode, StreamCompressor};
+use libdeflate::stream::{DeflateDecoder, DeflateEncoder};
+use std::io::{Cursor, Read, Write};
+
+const SYNC_MARKER: [u8; 4] = [0, 0, 0xff, 0xff];
+
+fn compress(stream: &mut StreamCompressor, input: &[u8], mode: FlushMode) -> Vec<u8> {
+    let mut output = Vec::new();
+    assert_eq!(
+        stream.compress(input, &mut output, mode),
+        CompressResult::Success
+    );
+    output
+}
+
+#[test]
+fn test_stream_compressor_round_trip() {
+    let lines = log_lines(1500, 1);
+    let noise = noise(300_000, 0x2545_f491);
+    for level in [0, 1, 6, 9, 12] {
+        let mut stream = StreamCompressor::new(level);
+        let mut compressed = Vec::new();
+        let mut data = Vec::new();
+        for (i, line) in lines.iter().enumerate() {
+            // Pieces of every kind, including empty ones, a large incompressible one
+            // and one larger than a parallel chunk.
+            let piece: &[u8] = match i % 500 {
+                100 => &noise[..(i % 7) * 1000],
+                499 => &noise,
+                _ if i % 10 == 3 => &[],
+                _ => line,
+            };
+            let mode = if i % 37 == 0 {
+                FlushMode::Sync
+            } else {
+                FlushMode::None
+            };
+            compressed.extend(compress(&mut stream, piece, mode));
+            data.extend_from_slice(piece);
+        }
+        compressed.extend(compress(&mut stream, b"end", FlushMode::Finish));
+        data.extend_from_slice(b"end");
+
+        let output = Decompressor::new()
+            .decompress_deflate_to_vec(&compressed)
+            .unwrap();
+        assert!(output == data, "level {level}");
+    }
+}
+
+#[test]
+fn test_stream_compressor_sync_flush() {
+    let lines = log_lines(1200, 2);
+    for level in [0, 1, 6, 12] {
+        let mut stream = StreamCompressor::new(level);
+        let mut compressed = Vec::new();
+        let mut data = Vec::new();
+        for batch in lines.chunks(150) {
+            for line in batch {
+                compressed.extend(compress(&mut stream, line, FlushMode::None));
+                data.extend_from_slice(line);
+            }
+            compressed.extend(compress(&mut stream, &[], FlushMode::Sync));
+            assert!(stream.is_byte_aligned());
+            assert!(compressed.ends_with(&SYNC_MARKER), "level {level}");
+
+            // Everything written so far can be decoded without the rest of the stream.
+            let mut decoder = DeflateDecoder::new(Cursor::new(&compressed));
+            let mut output = vec![0; data.len()];
+            decoder.read_exact(&mut output).unwrap();
+            assert!(output == data, "level {level}");
+        }
+
+        // A sync flush keeps the window: a repeat of earlier input compresses to a
+        // fraction of its size.
+        let repeat = lines[lines.len() - 150..].concat();
+        let size = compress(&mut stream, &repeat, FlushMode::Sync).len();
+        if level > 0 {
+            assert!(size * 10 < repeat.len(), "level {level}: {size}");
+        }
+    }
+}
+
+#[test]
+fn test_stream_compressor_history() {
+    let lines = log_lines(2000, 3);
+    let dictionary = lines[..1000].concat();
+    let data = lines[1000..].concat();
+
+    let mut primed = StreamCompressor::new(6);
+    primed.set_history(&dictionary);
+    assert_eq!(primed.history().len(), 32 * 1024);
+    let with_history = compress(&mut primed, &data, FlushMode::Finish);
+    // Finishing the stream forgets the window.
+    assert!(primed.history().is_empty());
+    let without_history = compress(&mut primed, &data, FlushMode::Finish);
+    assert!(with_history.len() < without_history.len());
+
+    let mut decompressor = Decompressor::new();
+    decompressor.set_dictionary(&dictionary);
+    let output = decompressor
+        .decompress_deflate(&with_history, data.len())
+        .unwrap();
+    assert!(output == data);
+}
+
+#[test]
+fn test_encoder_frequent_flushes() {
+    let lines = log_lines(20_000, 4);
+    let data = lines.concat();
+    let one_shot = libdeflate::Compressor::new(6)
+        .unwrap()
+        .compress_deflate(&data)
+        .unwrap();
+
+    let mut encoder = DeflateEncoder::new(Vec::new(), 6);
+    let mut flushes = 0;
+    for batch in lines.chunks(20) {
+        for line in batch {
+            encoder.write_all(line).unwrap();
+        }
+        encoder.flush().unwrap();
+        flushes += 1;
+        assert!(encoder.get_ref().ends_with(&SYNC_MARKER));
+    }
+    let compressed = encoder.finish().unwrap();
+
+    // Each flush costs a sync marker and a block header, but nothing more: matches
+    // still reach back into earlier flushes.
+    assert!(
+        compressed.len() < one_shot.len() + flushes * 60,
+        "{} vs {} with {flushes} flushes",
+        compressed.len(),
+        one_shot.len()
+    );
+    let output = Decompressor::new()
+        .decompress_deflate_to_vec(&compressed)
+        .unwrap();
+    assert!(output == data);
+}
+
+#[test]
+fn test_encoder_full_buffers_then_large_write() {
+    let data = log_lines(40_000, 5).concat();
+    for parallel in [true, false] {
+        // Full buffers are compressed without a marker, so the output may end in
+        // the middle of a byte when the large write arrives.
+        let mut encoder = DeflateEncoder::new(Vec::new(), 6)
+            .with_buffer_size(10_000)
+            .with_parallel(parallel);
+        for piece in data[..100_000].chunks(777) {
+            encoder.write_all(piece).unwrap();
+        }
+        encoder.write_all(&data[100_000..]).unwrap();
+        encoder.write_all(b"tail").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let output = Decompressor::new()
+            .decompress_deflate_to_vec(&compressed)
+            .unwrap();
+        assert!(output[..data.len()] == data, "parallel {parallel}");
+        assert_eq!(&output[data.len()..], b"tail");
+    }
+}
+
+#[test]
+fn test_stream_compressor_full_flush() {
+    let lines = log_lines(1500, 6);
+    for level in [0, 1, 6, 12] {
+        let mut stream = StreamCompressor::new(level);
+        let mut compressed = Vec::new();
+        let mut data = Vec::new();
+        // (compressed offset, uncompressed offset) of each full flush point
+        let mut points = Vec::new();
+        for (i, batch) in lines.chunks(125).enumerate() {
+            for line in batch {
+                compressed.extend(compress(&mut stream, line, FlushMode::None));
+                data.extend_from_slice(line);
+            }
+            if i % 3 == 2 {
+                compressed.extend(compress(&mut stream, &[], FlushMode::Full));
+                assert!(compressed.ends_with(&SYNC_MARKER));
+                assert!(stream.history().is_empty());
+                points.push((compressed.len(), data.len()));
+            }
+        }
+        compressed.extend(compress(&mut stream, &[], FlushMode::Finish));
+
+        for &(offset, data_offset) in &points {
+            // Corrupting everything before the point does not matter.
+            let mut damaged = compressed.clone();
+            damaged[..offset - SYNC_MARKER.len()].fill(0xaa);
+            let output = Decompressor::new()
+                .decompress_deflate_to_vec(&damaged[offset..])
+                .unwrap();
+            assert!(output == data[data_offset..], "level {level}");
+        }
+    }
+
+    // A one-shot compressor keeps no window, so a full flush is a sync flush.
+    let mut compressor = libdeflate::compress::Compressor::new(6);
+    let data = lines.concat();
+    let mut output =
+        vec![0; libdeflate::compress::Compressor::deflate_compress_bound(data.len()) + 5];
+    let uninit = libdeflate::common::slice_as_uninit_mut(&mut output);
+    let (res, size, _) = compressor.compress(&data, uninit, FlushMode::Full);
+    assert_eq!(res, CompressResult::Success);
+    assert!(output[..size].ends_with(&SYNC_MARKER));
+}
+
+#[test]
+fn test_encoder_full_flush_offsets() {
+    let lines = log_lines(30_000, 7);
+    for parallel in [true, false] {
+        let mut encoder = DeflateEncoder::new(Vec::new(), 6).with_parallel(parallel);
+        let mut data = Vec::new();
+        let mut points = Vec::new();
+        // Small batches continue the stream; the large one is a single write of more than
+        // a chunk and goes through the chunked path.
+        for (i, batch) in [&lines[..100], &lines[100..200], &lines[200..], &lines[..50]]
+            .into_iter()
+            .enumerate()
+        {
+            if i == 2 {
+                let joined = batch.concat();
+                encoder.write_all(&joined).unwrap();
+                data.extend_from_slice(&joined);
+            } else {
+                for line in batch {
+                    encoder.write_all(line).unwrap();
+                    data.extend_from_slice(line);
+                }
+            }
+            if i == 1 {
+                encoder.flush().unwrap();
+            }
+            let offset = encoder.full_flush().unwrap();
+            assert_eq!(offset, encoder.get_ref().len() as u64);
+            assert!(encoder.get_ref().ends_with(&SYNC_MARKER));
+            points.push((offset as usize, data.len()));
+        }
+        // A second full flush with nothing new still marks a point.
+        let offset = encoder.full_flush().unwrap();
+        assert_eq!(offset as usize, points.last().unwrap().0 + 5);
+        encoder.write_all(b"last").unwrap();
+        data.extend_from_slice(b"last");
+        assert_eq!(encoder.total_out(), offset);
+        let compressed = encoder.finish().unwrap();
+
+        let mut starts = vec![(0, 0)];
+        starts.extend(&points);
+        for &(offset, data_offset) in &starts {
+            let mut decoder = DeflateDecoder::new(Cursor::new(&compressed[offset..]));
+            let mut output = Vec::new();
+            decoder.read_to_end(&mut output).unwrap();
+            assert!(output == data[data_offset..], "parallel {parallel}");
+        }
+    }
+}
+
+#[test]
+fn test_encoder_buffers_up_to_one_chunk_continue_the_stream() {
+    use libdeflate::compress::PARALLEL_CHUNK_SIZE;
+
+    let data = log_lines(20_000, 8).concat();
+    assert!(data.len() > 4 * PARALLEL_CHUNK_SIZE);
+
+    // The same output as one StreamCompressor given every buffer with
+    // FlushMode::None: no sync markers, and the match finder carries over.
+    let mut stream = StreamCompressor::new(6);
+    let mut expected = Vec::new();
+    let full = data.len() / PARALLEL_CHUNK_SIZE * PARALLEL_CHUNK_SIZE;
+    for chunk in data[..full].chunks(PARALLEL_CHUNK_SIZE) {
+        expected.extend(compress(&mut stream, chunk, FlushMode::None));
+    }
+    expected.extend(compress(&mut stream, &data[full..], FlushMode::Finish));
+
+    // The default buffer size is one chunk.
+    let encoders = [
+        DeflateEncoder::new(Vec::new(), 6),
+        DeflateEncoder::new(Vec::new(), 6).with_parallel(false),
+        DeflateEncoder::new(Vec::new(), 6).with_buffer_size(PARALLEL_CHUNK_SIZE),
+    ];
+    for (i, mut encoder) in encoders.into_iter().enumerate() {
+        // Writes that do not divide the buffer size still fill it exactly.
+        for piece in data.chunks(777) {
+            encoder.write_all(piece).unwrap();
+        }
+        let compressed = encoder.finish().unwrap();
+        assert!(compressed == expected, "encoder {i}");
+    }
+}
@@ -0,0 +1,100 @@
+use libdeflate::{Compressor, Decompressor, Error};
+
+fn test_data(len: usize) -> Vec<u8> {
+    (0..len as u32)
+        .map(|i| (i.wrapping_mul(2654435761) >> 28) as u8 + b'a')
+        .collect()
+}
+
+#[test]
+fn test_to_vec_round_trip() {
+    let mut compressor = Compressor::new(6).unwrap();
+    let mut decompressor = Decompressor::new();
+    for len in [0, 1, 100, 4096, 100_000, 1_000_000] {
+        let data = test_data(len);
+        let deflate = compressor.compress_deflate(&data).unwrap();
+        assert_eq!(
+            decompressor.decompress_deflate_to_vec(&deflate).unwrap(),
+            data
+        );
+        let zlib = compressor.compress_zlib(&data).unwrap();
+        assert_eq!(decompressor.decompress_zlib_to_vec(&zlib).unwrap(), data);
+        let gzip = compressor.compress_gzip(&data).unwrap();
+        assert_eq!(decompressor.decompress_gzip_to_vec(&gzip).unwrap(), data);
+    }
+}
+
+#[test]
+fn test_to_vec_highly_compressible() {
+    // Expands far beyond the initial guess of four times the input size.
+    let data = vec![b'x'; 5_000_000];
+    let compressed = Compressor::new(9).unwrap().compress_zlib(&data).unwrap();
+    let mut decompressor = Decompressor::new();
+    assert_eq!(
+        decompressor.decompress_zlib_to_vec(&compressed).unwrap(),
+        data
+    );
+}
+
+#[test]
+fn test_to_vec_gzip_multi_member() {
+    // The ISIZE of the last member underestimates the total size.
+    let first = test_data(200_000);
+    let second = test_data(10);
+    let mut compressor = Compressor::new(6).unwrap();
+    let mut gzip = compressor.compress_gzip(&first).unwrap();
+    gzip.extend(compressor.compress_gzip(&second).unwrap());
+
+    let mut decompressor = Decompressor::new();
+    let output = decompressor.decompress_gzip_to_vec(&gzip).unwrap();
+    assert_eq!(output, [first, second].concat());
+}
+
+#[test]
+fn test_to_vec_limits() {
+    let data = vec![0u8; 1_000_000];
+    let compressed = Compressor::new(6).unwrap().compress_deflate(&data).unwrap();
+
+    let mut decompressor = Decompressor::new();
+    decompressor.set_max_memory_limit(100_000);
+    match decompressor
+        .decompress_deflate_to_vec(&compressed)
+        .unwrap_err()
+    {
+        Error::MemoryLimitExceeded { expected, limit } => {
+            assert_eq!((expected, limit), (100_001, 100_000));
+        }
+        other => panic!("unexpected error: {other:?}"),
+    }
+
+    let mut decompressor = Decompressor::new();
+    decompressor.set_limit_ratio(10);
+    let err = decompressor
+        .decompress_deflate_to_vec(&compressed)
+        .unwrap_err();
+    assert!(matches!(err, Error::ExpectedSizeTooLarge { .. }), "{err:?}");
+
+    // Exactly at the limit is fine.
+    let mut decompressor = Decompressor::new();
+    decompressor.set_max_memory_limit(data.len());
+    assert_eq!(
+        decompressor.decompress_deflate_to_vec(&compressed).unwrap(),
+        data
+    );
+}
+
+#[test]
+fn test_to_vec_corrupt_input() {
+    let data = test_data(50_000);
+    let mut gzip = Compressor::new(6).unwrap().compress_gzip(&data).unwrap();
+    let len = gzip.len();
+    gzip[len - 8] ^= 1;
+    let mut decompressor = Decompressor::new();
+    let err = decompressor.decompress_gzip_to_vec(&gzip).unwrap_err();
+    assert!(matches!(err, Error::ChecksumMismatch { .. }), "{err:?}");
+
+    let err = decompressor
+        .decompress_deflate_to_vec(&gzip[10..len / 2])
+        .unwrap_err();
+    assert!(matches!(err, Error::TruncatedInput { .. }), "{err:?}");
+}
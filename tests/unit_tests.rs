@@ -441,3 +441,98 @@ fn test_zlib_compress_bound() {
         );
     }
 }
+
+/// Canonical Huffman codewords for `lens`, as in RFC 1951 section 3.2.2.
+fn canonical_codes(lens: &[u8]) -> Vec<u32> {
+    let mut bl_count = [0u32; 16];
+    for &len in lens {
+        bl_count[len as usize] += 1;
+    }
+    bl_count[0] = 0;
+    let mut next_code = [0u32; 16];
+    let mut code = 0;
+    for bits in 1..16 {
+        code = (code + bl_count[bits - 1]) << 1;
+        next_code[bits] = code;
+    }
+    lens.iter()
+        .map(|&len| {
+            let code = next_code[len as usize];
+            next_code[len as usize] += 1;
+            code
+        })
+        .collect()
+}
+
+/// Writes a dynamic Huffman block with the given literal/length codeword lengths
+/// (257 of them) that encodes `literals` followed by the end-of-block symbol.
+fn write_dynamic_block(
+    writer: &mut BitWriter,
+    is_final: bool,
+    litlen_lens: &[u8],
+    literals: &[u8],
+) {
+    const PRECODE_ORDER: [usize; 19] = [
+        16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+    ];
+    writer.write_bits(is_final as u32, 1);
+    writer.write_bits(2, 2);
+    writer.write_bits(0, 5); // HLIT: 257 codes
+    writer.write_bits(1, 5); // HDIST: 2 codes
+    writer.write_bits(15, 4); // HCLEN: 19 codes
+    // Code lengths 0..=15 all get 4-bit precode codewords equal to their value.
+    for sym in PRECODE_ORDER {
+        writer.write_bits(if sym < 16 { 4 } else { 0 }, 3);
+    }
+    for &len in litlen_lens.iter().chain(&[1, 1]) {
+        writer.write_huffman(len as u32, 4);
+    }
+    let codes = canonical_codes(litlen_lens);
+    for sym in literals.iter().map(|&b| b as usize).chain([256]) {
+        writer.write_huffman(codes[sym], litlen_lens[sym] as u32);
+    }
+}
+
+fn check_dynamic_blocks(litlen_lens: &[u8], used: &[u8]) {
+    let literals: Vec<u8> = used.iter().copied().cycle().take(5000).collect();
+    let mut writer = BitWriter::new();
+    write_dynamic_block(&mut writer, false, litlen_lens, &literals);
+    write_dynamic_block(&mut writer, true, litlen_lens, &literals);
+    let input = writer.flush();
+    let expected = [&literals[..], &literals[..]].concat();
+
+    let output = Decompressor::new()
+        .decompress_deflate(&input, expected.len())
+        .unwrap();
+    assert!(output == expected);
+
+    let mut decoder = libdeflate::stream::DeflateDecoder::new(std::io::Cursor::new(&input));
+    let mut output = Vec::new();
+    std::io::Read::read_to_end(&mut decoder, &mut output).unwrap();
+    assert!(output == expected);
+}
+
+#[test]
+fn test_dynamic_codeword_lengths_skip_past_table_bits() {
+    // Codewords of lengths 1 to 11 and 13 but none of 12, one past the main table.
+    let mut lens = vec![0u8; 257];
+    lens[256] = 1;
+    for (sym, len) in lens.iter_mut().take(10).enumerate() {
+        *len = sym as u8 + 2;
+    }
+    lens[10..14].fill(13);
+    check_dynamic_blocks(&lens, &[0, 10, 1, 11, 9, 12, 13, 5]);
+}
+
+#[test]
+fn test_dynamic_end_of_block_in_subtable() {
+    // The end-of-block codeword is longer than the main table, so it is decoded
+    // through a subtable.
+    let mut lens = vec![0u8; 257];
+    for (sym, len) in lens.iter_mut().take(11).enumerate() {
+        *len = sym as u8 + 1;
+    }
+    lens[11] = 12;
+    lens[256] = 12;
+    check_dynamic_blocks(&lens, &[0, 11, 1, 10, 2, 0, 0, 3]);
+}
@@ -0,0 +1,190 @@
+mod common;
+
+use common::{log_text, noise};
+use libdeflate::zip::{CompressionMethod, ZipArchive, ZipWriter};
+use std::io::{Cursor, ErrorKind, Read};
+
+fn sample_files() -> Vec<(String, Vec<u8>)> {
+    let mut files = vec![
+        ("empty.txt".to_string(), Vec::new()),
+        ("noise.bin".to_string(), noise(100_000, 0x2545_f491)),
+        ("données/é.txt".to_string(), log_text(5000, 1)),
+    ];
+    for i in 0..40 {
+        files.push((
+            format!("src/file{i}.rs"),
+            log_text(1000 + i * 3000, i as u32 + 2),
+        ));
+    }
+    files
+}
+
+fn write_archive(files: &[(String, Vec<u8>)], writer: ZipWriter<Vec<u8>>) -> Vec<u8> {
+    let mut writer = writer;
+    writer.add_directory("src").unwrap();
+    for (name, data) in files {
+        writer
+            .add_file(name, data, CompressionMethod::Deflated)
+            .unwrap();
+    }
+    writer.finish().unwrap()
+}
+
+fn check_archive(archive: &[u8], files: &[(String, Vec<u8>)]) {
+    let mut zip = ZipArchive::new(Cursor::new(archive)).unwrap();
+    assert_eq!(zip.len(), files.len() + 1);
+    assert!(zip.entry(0).unwrap().is_dir());
+    assert_eq!(zip.entry(0).unwrap().name(), "src/");
+
+    for (i, (name, data)) in files.iter().enumerate() {
+        let entry = zip.entry(i + 1).unwrap().clone();
+        assert_eq!(entry.name(), name);
+        assert_eq!(entry.size(), data.len() as u64);
+        assert_eq!(entry.crc32(), libdeflate::crc32(0, data));
+        assert_eq!(zip.read_entry(i + 1).unwrap(), *data, "{name}");
+
+        let mut streamed = Vec::new();
+        zip.entry_reader(i + 1)
+            .unwrap()
+            .read_to_end(&mut streamed)
+            .unwrap();
+        assert!(streamed == *data, "{name}");
+    }
+    // Entries can be read in any order.
+    for (name, data) in files.iter().rev().step_by(7) {
+        assert_eq!(zip.read_entry_by_name(name).unwrap(), *data);
+    }
+}
+
+#[test]
+fn test_zip_round_trip() {
+    let files = sample_files();
+    for level in [0, 1, 6, 12] {
+        // A small batch size, so that the entries are compressed in several batches.
+        let writer = ZipWriter::new(Vec::new(), level).with_batch_size(200_000);
+        let archive = write_archive(&files, writer);
+        check_archive(&archive, &files);
+
+        let zip = ZipArchive::new(Cursor::new(&archive)).unwrap();
+        let method = |name: &str| zip.entry(zip.index_of(name).unwrap()).unwrap().method();
+        assert_eq!(method("empty.txt"), CompressionMethod::Stored);
+        // Deflate does not shrink noise, so it is stored.
+        assert_eq!(method("noise.bin"), CompressionMethod::Stored);
+        let expected = if level == 0 {
+            CompressionMethod::Stored
+        } else {
+            CompressionMethod::Deflated
+        };
+        assert_eq!(method("src/file3.rs"), expected, "level {level}");
+    }
+}
+
+#[test]
+fn test_zip_data_descriptors_and_zip64() {
+    let files = sample_files();
+    for (descriptors, zip64) in [(true, false), (false, true), (true, true)] {
+        let writer = ZipWriter::new(Vec::new(), 6)
+            .with_data_descriptors(descriptors)
+            .with_zip64(zip64);
+        let archive = write_archive(&files, writer);
+        check_archive(&archive, &files);
+
+        let zip = ZipArchive::new(Cursor::new(&archive)).unwrap();
+        assert!(
+            zip.entries()
+                .iter()
+                .all(|e| e.has_data_descriptor() == descriptors)
+        );
+        let has_zip64_end = archive.windows(4).any(|w| w == [0x50, 0x4b, 0x06, 0x06]);
+        assert_eq!(has_zip64_end, zip64);
+    }
+
+    // More entries than the original end record can count.
+    let mut writer = ZipWriter::new(Vec::new(), 6);
+    for i in 0..70_000 {
+        writer
+            .add_file(&format!("{i}"), &[], CompressionMethod::Deflated)
+            .unwrap();
+    }
+    writer
+        .add_file("last", b"last entry", CompressionMethod::Deflated)
+        .unwrap();
+    let archive = writer.finish().unwrap();
+    let mut zip = ZipArchive::new(Cursor::new(&archive)).unwrap();
+    assert_eq!(zip.len(), 70_001);
+    assert_eq!(zip.read_entry_by_name("last").unwrap(), b"last entry");
+    assert_eq!(zip.entry(69_999).unwrap().name(), "69999");
+}
+
+#[test]
+fn test_zip_archive_comment() {
+    let files = sample_files();
+    let mut archive = write_archive(&files, ZipWriter::new(Vec::new(), 6));
+    // A comment that itself contains an end record signature.
+    let comment = b"PK\x05\x06 is the signature";
+    let len = archive.len();
+    archive[len - 2..].copy_from_slice(&(comment.len() as u16).to_le_bytes());
+    archive.extend_from_slice(comment);
+    check_archive(&archive, &files);
+}
+
+#[test]
+fn test_zip_invalid_archives() {
+    let files = sample_files();
+    let archive = write_archive(&files, ZipWriter::new(Vec::new(), 0));
+
+    assert_eq!(
+        ZipArchive::new(Cursor::new(b"not a zip file"))
+            .err()
+            .unwrap()
+            .kind(),
+        ErrorKind::InvalidData
+    );
+    assert!(ZipArchive::new(Cursor::new(&archive[..archive.len() - 30])).is_err());
+    // Too short to hold an end record at all.
+    for len in [0, 3, 21] {
+        assert_eq!(
+            ZipArchive::new(Cursor::new(&archive[archive.len() - len..]))
+                .err()
+                .unwrap()
+                .kind(),
+            ErrorKind::InvalidData,
+            "{len} bytes"
+        );
+    }
+
+    let mut zip = ZipArchive::new(Cursor::new(&archive)).unwrap();
+    assert_eq!(
+        zip.read_entry_by_name("missing").unwrap_err().kind(),
+        ErrorKind::NotFound
+    );
+
+    // Damage the stored data of one entry: both ways of reading it notice.
+    let index = zip.index_of("src/file5.rs").unwrap();
+    let offset = zip.entry(index).unwrap().header_offset() as usize + 30 + 12 + 100;
+    let mut damaged = archive.clone();
+    damaged[offset] ^= 1;
+    let mut zip = ZipArchive::new(Cursor::new(&damaged)).unwrap();
+    let err = zip.read_entry(index).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(matches!(
+        err.get_ref().unwrap().downcast_ref::<libdeflate::Error>(),
+        Some(libdeflate::Error::ChecksumMismatch { .. })
+    ));
+    let err = zip
+        .entry_reader(index)
+        .unwrap()
+        .read_to_end(&mut Vec::new())
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(zip.read_entry(index + 1).is_ok());
+
+    let mut writer = ZipWriter::new(Vec::new(), 6);
+    assert_eq!(
+        writer
+            .add_file("x", b"", CompressionMethod::Other(12))
+            .unwrap_err()
+            .kind(),
+        ErrorKind::InvalidInput
+    );
+}